@@ -22,8 +22,10 @@ fn build_controller(cache: bool) -> NNController {
                 init_mode: convolution::ConvolutionInitMode::HeNormal(),
                 out_channels: 2,
                 padding: 0,
+                dilation: 1,
                 lr_calc: LrCalc::Constant(ConstantLrConfig::default()),
                 cache,
+                train_options: Default::default(),
             }),
             Layer::Relu,
             Layer::MaxPool(filtering::max_pool::MaxPoolConfig{
@@ -38,8 +40,10 @@ fn build_controller(cache: bool) -> NNController {
                 weights_lr_calc: LrCalc::Constant(ConstantLrConfig::default()),
                 out_values: 1,
                 in_values: 4 * 4 * 2,
+                train_options: Default::default(),
             }),
         ],
+        train_options: Default::default(),
     }), LossFunc::Mse).unwrap()
 }
 
@@ -22,7 +22,9 @@ fn criterion_benchmark(c: &mut Criterion) {
         in_channels: 32,
         out_channels: 64,
         padding: 2,
+        dilation: 1,
         cache: false,
+        train_options: Default::default(),
     };
     let dist = Normal::new(0.0, 1.0).unwrap();
     let mut storage = GenericStorage::new();
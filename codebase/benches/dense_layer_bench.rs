@@ -18,6 +18,7 @@ fn criterion_benchmark(c: &mut Criterion) {
         init_mode: dense_layer::DenseLayerInit::Random(),
         weights_lr_calc: LrCalc::Constant(ConstantLrConfig::default()),
         biases_lr_calc: LrCalc::Constant(ConstantLrConfig::default()),
+        train_options: Default::default(),
     };
     let dist = Normal::new(0.0, 1.0).unwrap();
     let mut storage = GenericStorage::new();
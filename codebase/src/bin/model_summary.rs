@@ -0,0 +1,24 @@
+use std::fs;
+use codebase::integration::layers_loading::load_model_xml;
+use codebase::nn::controller::NNController;
+use codebase::utils::GenericResult;
+
+/// Print the summary of a model config, like:
+/// `cargo run --bin model_summary -- ../docs/digits/config.xml 28,28`
+/// The input shape doesn't include the batch dimension
+fn main() -> GenericResult<()> {
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() != 3 {
+        return Err(anyhow::anyhow!("Usage: model_summary <config.xml> <input shape, like 28,28>"));
+    }
+
+    let input_shape = args[2].split(',')
+        .map(|o| o.trim().parse::<usize>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| anyhow::anyhow!("Invalid input shape '{}': {}", args[2], e))?;
+
+    let config = load_model_xml(&fs::read(&args[1])?)?;
+    let controller = NNController::new_with_heads(config.main_layer, config.loss_func, config.heads)?;
+    print!("{}", controller.summary(&input_shape)?);
+    Ok(())
+}
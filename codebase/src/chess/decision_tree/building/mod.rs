@@ -289,8 +289,10 @@ mod tests {
                     init_mode: convolution::ConvolutionInitMode::HeNormal(),
                     out_channels: 2,
                     padding: 0,
+                    dilation: 1,
                     lr_calc: LrCalc::Constant(ConstantLrConfig::default()),
                     cache: false,
+                    train_options: Default::default(),
                 }),
                 Layer::Debug(DebugLayerConfig {
                     action: DebugAction::PrintShape,
@@ -307,12 +309,14 @@ mod tests {
                     weights_lr_calc: LrCalc::Constant(ConstantLrConfig::default()),
                     out_values: 1,
                     in_values: 6 * 6 * 2,
+                    train_options: Default::default(),
                 }),
                 Layer::Debug(DebugLayerConfig {
                     action: DebugAction::PrintShape,
                     tag: "after_dense".to_owned(),
                 }),
             ],
+            train_options: Default::default(),
         }), LossFunc::Mse).unwrap();
 
         let (trees, _) = builder.build(&controller);
@@ -45,6 +45,15 @@ pub mod relu_forward {
     }
 }
 
+pub mod leaky_relu_forward {
+    pub const BLOCK_SIZE: [u32; 3] = [8, 1, 1];
+
+    vulkano_shaders::shader! {
+        ty: "compute",
+        path: "./src/gpu/shaders/leaky_relu_forward.glsl"
+    }
+}
+
 pub mod max_pool_forward {
     pub const BLOCK_SIZE: [u32; 3] = [8, 2, 2];
 
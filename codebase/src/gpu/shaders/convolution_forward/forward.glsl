@@ -30,6 +30,7 @@ layout(constant_id = 7) const uint out_height = 0;
 
 layout(constant_id = 8) const uint padding = 0;
 layout(constant_id = 9) const uint cache_enabled = 0;
+layout(constant_id = 10) const uint dilation = 1;
 
 void main() {
     const uint b_and_in_c = gl_GlobalInvocationID.x;
@@ -54,12 +55,12 @@ void main() {
     const uint kernel_sections_1 = kernel_size*kernel_size;
 
     for (uint kh = 0; kh < kernel_size; kh++) {
-        const uint input_h = h_offset + kh - padding;
+        const uint input_h = h_offset + kh * dilation - padding;
         const uint partial_input_index = batch_section + input_h * input_width;
         const uint partial_kernel_index = out_c_section + kh * kernel_size;
 
         for (uint kw = 0; kw < kernel_size; kw++) {
-            const uint input_w = w_offset + kw - padding;
+            const uint input_w = w_offset + kw * dilation - padding;
             const float in_bounds_factor = float(input_h < input_height && input_w < input_width);
 
             for (uint in_c = 0; in_c < in_channels; in_c++) {
@@ -83,13 +84,13 @@ Array4F::from_shape_fn((batch_size, layer_config.out_channels, new_height, new_w
     for kh in 0..*kernel_size {
         for kw in 0..*kernel_size {
             for in_c in 0..layer_config.in_channels {
-                let result_h = h_offset + kh - padding;
-                let result_w = w_offset + kw - padding;
+                let result_h = h_offset + kh * dilation - padding;
+                let result_w = w_offset + kw * dilation - padding;
 
                 let i = if result_h < 0 || result_w < 0 || result_h >= height || result_w >= height {
                     0.0
                 } else {
-                    inputs[(b, in_c, h_offset + kh, w_offset + kw)];
+                    inputs[(b, in_c, result_h, result_w)];
                 };
                 result += i * kernel[(out_c, in_c, kh, kw)];
             }
@@ -21,6 +21,7 @@ layout(constant_id = 6) const uint out_width = 0;
 layout(constant_id = 7) const uint out_height = 0;
 
 layout(constant_id = 8) const uint padding = 0;
+layout(constant_id = 10) const uint dilation = 1;
 
 void main() {
     const uint b = gl_GlobalInvocationID.x;
@@ -33,8 +34,8 @@ void main() {
 
     for (uint kh = 0; kh < kernel_size; kh++) {
         for (uint kw = 0; kw < kernel_size; kw++) {
-            const uint input_h = h_offset + kh - padding;
-            const uint input_w = w_offset + kw - padding;
+            const uint input_h = h_offset + kh * dilation - padding;
+            const uint input_w = w_offset + kw * dilation - padding;
 
             const bool in_bounds = (input_h < input_height) && (input_w < input_width);
             const bool invalid = in_bounds && inputs.data[b*input_height*input_width + input_h*input_width + input_w];
@@ -0,0 +1,13 @@
+#version 450
+layout(local_size_x = 8, local_size_y = 1, local_size_z = 1) in;
+layout(set = 0, binding = 0) buffer ResultData {
+    float data[];
+} result_data;
+
+layout(constant_id = 0) const float alpha = 0.01;
+
+void main() {
+    const uint index = gl_GlobalInvocationID.x;
+    const float value = result_data.data[index];
+    result_data.data[index] = value > 0.0 ? value : value * alpha;
+}
@@ -0,0 +1,284 @@
+use anyhow::bail;
+use ndarray::{Array2, Array4, ArrayView2, ArrayViewMut2, Axis};
+use ndarray_rand::rand::Rng;
+use ndarray_rand::rand_distr::{Distribution, Normal};
+use crate::ArrayDynF;
+use crate::utils::{Array2F, GenericResult};
+
+/// Random changes made to each image of a batch. Changes with a value of 0 are disabled
+#[derive(Clone, Debug)]
+pub struct AugmentConfig {
+    /// Maximum shift in pixels, in each direction
+    pub max_shift: f32,
+    pub max_rotation_degrees: f32,
+    /// Maximum relative change of the size, like 0.1 for sizes between 90% and 110%
+    pub max_scale: f32,
+    /// Strength of the elastic distortion. The pixels are moved by a random field, smoothed with a
+    /// gaussian of **elastic_sigma**, multiplied by this
+    pub elastic_alpha: f32,
+    pub elastic_sigma: f32,
+    /// Standard deviation of the gaussian noise added to every value
+    pub noise_std_dev: f32,
+    /// Move the center of mass of each image to its center before the other changes
+    pub recenter: bool,
+}
+
+impl Default for AugmentConfig {
+    fn default() -> Self {
+        Self {
+            max_shift: 0.0,
+            max_rotation_degrees: 0.0,
+            max_scale: 0.0,
+            elastic_alpha: 0.0,
+            elastic_sigma: 4.0,
+            noise_std_dev: 0.0,
+            recenter: false,
+        }
+    }
+}
+
+/// Maps each pixel of the result to the position that is sampled in the original image
+struct Transform {
+    /// Point of the original image that ends up in the center, before the shift
+    origin: (f32, f32),
+    center: (f32, f32),
+    shift: (f32, f32),
+    /// Sine and cosine of the rotation, divided by the scale
+    sin: f32,
+    cos: f32,
+    displacement: Option<(Array2F, Array2F)>,
+}
+
+impl Transform {
+    fn identity(height: usize, width: usize) -> Self {
+        let center = ((height as f32 - 1.0) / 2.0, (width as f32 - 1.0) / 2.0);
+        Self { origin: center, center, shift: (0.0, 0.0), sin: 0.0, cos: 1.0, displacement: None }
+    }
+
+    fn source(&self, y: usize, x: usize) -> (f32, f32) {
+        let (mut dy, mut dx) = (y as f32 - self.center.0 - self.shift.0, x as f32 - self.center.1 - self.shift.1);
+        if let Some((field_y, field_x)) = &self.displacement {
+            dy += field_y[(y, x)];
+            dx += field_x[(y, x)];
+        }
+        (
+            self.cos * dy + self.sin * dx + self.origin.0,
+            -self.sin * dy + self.cos * dx + self.origin.1,
+        )
+    }
+
+    fn apply(&self, image: ArrayView2<f32>, mut result: ArrayViewMut2<f32>) {
+        for ((y, x), value) in result.indexed_iter_mut() {
+            let (source_y, source_x) = self.source(y, x);
+            *value = sample(image, source_y, source_x);
+        }
+    }
+}
+
+/// Bilinear interpolation, with 0 outside of the image
+fn sample(image: ArrayView2<f32>, y: f32, x: f32) -> f32 {
+    let (y0, x0) = (y.floor(), x.floor());
+    let (fy, fx) = (y - y0, x - x0);
+    let get = |y: f32, x: f32| {
+        if y < 0.0 || x < 0.0 {
+            return 0.0;
+        }
+        image.get((y as usize, x as usize)).copied().unwrap_or(0.0)
+    };
+
+    get(y0, x0) * (1.0 - fy) * (1.0 - fx)
+        + get(y0, x0 + 1.0) * (1.0 - fy) * fx
+        + get(y0 + 1.0, x0) * fy * (1.0 - fx)
+        + get(y0 + 1.0, x0 + 1.0) * fy * fx
+}
+
+/// Center of mass of all the channels of an image, or None if it's empty
+fn center_of_mass(channels: &[ArrayView2<f32>]) -> Option<(f32, f32)> {
+    let (mut total, mut sum_y, mut sum_x) = (0.0, 0.0, 0.0);
+    for channel in channels {
+        for ((y, x), value) in channel.indexed_iter() {
+            total += value;
+            sum_y += value * y as f32;
+            sum_x += value * x as f32;
+        }
+    }
+    if total > 0.0 { Some((sum_y / total, sum_x / total)) } else { None }
+}
+
+fn gaussian_blur(field: &Array2F, sigma: f32) -> Array2F {
+    let radius = (sigma * 3.0).ceil() as isize;
+    let kernel: Vec<_> = (-radius..=radius)
+        .map(|o| (-((o * o) as f32) / (2.0 * sigma * sigma)).exp())
+        .collect();
+
+    let mut result = field.clone();
+    for axis in 0..2 {
+        let source = result.clone();
+        let len = source.len_of(Axis(axis)) as isize;
+        for ((y, x), value) in result.indexed_iter_mut() {
+            let position = if axis == 0 { y } else { x } as isize;
+            let (mut sum, mut weights) = (0.0, 0.0);
+            for (offset, weight) in (-radius..=radius).zip(kernel.iter()) {
+                let index = position + offset;
+                if (0..len).contains(&index) {
+                    let index = index as usize;
+                    sum += weight * if axis == 0 { source[(index, x)] } else { source[(y, index)] };
+                    weights += weight;
+                }
+            }
+            *value = sum / weights;
+        }
+    }
+    result
+}
+
+fn random_displacement(height: usize, width: usize, config: &AugmentConfig, rng: &mut impl Rng) -> Array2F {
+    let field = Array2::from_shape_simple_fn((height, width), || rng.gen_range(-1.0..=1.0));
+    gaussian_blur(&field, config.elastic_sigma) * config.elastic_alpha
+}
+
+fn random_range(max: f32, rng: &mut impl Rng) -> f32 {
+    if max > 0.0 { rng.gen_range(-max..=max) } else { 0.0 }
+}
+
+/// View the images as [items, channels, height, width]. The last 2 axes are the height and width,
+/// and when there are more than 2 the first one is the batch
+fn as_items(images: &ArrayDynF) -> GenericResult<Array4<f32>> {
+    let shape = images.shape();
+    if shape.len() < 2 {
+        bail!("Images need at least 2 dimensions, got shape {:?}", shape);
+    }
+
+    let (height, width) = (shape[shape.len() - 2], shape[shape.len() - 1]);
+    let items = if shape.len() > 2 { shape[0] } else { 1 };
+    let channels = shape[..shape.len() - 2].iter().product::<usize>() / items.max(1);
+    Ok(images.as_standard_layout().into_owned().into_shape((items, channels, height, width))?)
+}
+
+fn transform_items(images: &ArrayDynF, mut get_transform: impl FnMut(&[ArrayView2<f32>]) -> Transform)
+                   -> GenericResult<ArrayDynF> {
+    let items = as_items(images)?;
+    let mut result = Array4::zeros(items.raw_dim());
+
+    for (item, mut result_item) in items.outer_iter().zip(result.outer_iter_mut()) {
+        let channels: Vec<_> = item.outer_iter().collect();
+        let transform = get_transform(&channels);
+        for (channel, result_channel) in channels.iter().zip(result_item.outer_iter_mut()) {
+            transform.apply(*channel, result_channel);
+        }
+    }
+    Ok(result.into_shape(images.shape())?)
+}
+
+/// Move the center of mass of each image to its center, like the digits of MNIST. Takes a single
+/// image or a batch, as described in **augment**
+pub fn recenter(images: &ArrayDynF) -> GenericResult<ArrayDynF> {
+    transform_items(images, |channels| {
+        let (height, width) = channels[0].dim();
+        let mut transform = Transform::identity(height, width);
+        if let Some(center) = center_of_mass(channels) {
+            transform.origin = center;
+        }
+        transform
+    })
+}
+
+/// Apply random changes to each image. The last 2 axes of **images** are the height and width,
+/// and when there are more than 2 the first one is the batch. Every channel of an image gets
+/// the same changes
+pub fn augment(images: &ArrayDynF, config: &AugmentConfig, rng: &mut impl Rng) -> GenericResult<ArrayDynF> {
+    let mut result = transform_items(images, |channels| {
+        let (height, width) = channels[0].dim();
+        let mut transform = Transform::identity(height, width);
+        if config.recenter {
+            if let Some(center) = center_of_mass(channels) {
+                transform.origin = center;
+            }
+        }
+
+        transform.shift = (random_range(config.max_shift, rng), random_range(config.max_shift, rng));
+        let angle = random_range(config.max_rotation_degrees, rng).to_radians();
+        let scale = 1.0 + random_range(config.max_scale, rng);
+        transform.sin = angle.sin() / scale;
+        transform.cos = angle.cos() / scale;
+
+        if config.elastic_alpha > 0.0 {
+            transform.displacement = Some((
+                random_displacement(height, width, config, rng),
+                random_displacement(height, width, config, rng),
+            ));
+        }
+        transform
+    })?;
+
+    if config.noise_std_dev > 0.0 {
+        let normal = Normal::new(0.0, config.noise_std_dev)?;
+        result.mapv_inplace(|o| o + normal.sample(rng));
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::{Array3, array};
+    use ndarray_rand::rand::thread_rng;
+    use crate::utils::arrays_almost_equal;
+    use super::*;
+
+    fn get_image() -> ArrayDynF {
+        let mut image = Array3::zeros((1, 7, 7));
+        image[(0, 1, 1)] = 1.0;
+        image[(0, 1, 2)] = 1.0;
+        image.into_dyn()
+    }
+
+    #[test]
+    fn test_recenter() {
+        let result = recenter(&get_image()).unwrap();
+        assert_eq!(result.shape(), &[1, 7, 7]);
+        assert!(arrays_almost_equal(&result.slice(ndarray::s![0, 3, 2..5]).to_owned(), &array![0.5, 1.0, 0.5]));
+        assert!((result.sum() - 2.0).abs() < 1e-5);
+
+        let single = recenter(&get_image().index_axis(Axis(0), 0).to_owned()).unwrap();
+        assert_eq!(single, result.index_axis(Axis(0), 0));
+    }
+
+    #[test]
+    fn test_identity() {
+        let images = ArrayDynF::from_shape_fn(vec![2, 3, 5, 4], |o| (o[0] + o[1] * 2 + o[2] * o[3]) as f32);
+        let result = augment(&images, &AugmentConfig::default(), &mut thread_rng()).unwrap();
+        assert!(arrays_almost_equal(&result, &images));
+    }
+
+    #[test]
+    fn test_channels_together() {
+        let image = get_image().index_axis(Axis(0), 0).to_owned();
+        let images = ndarray::stack(Axis(0), &[image.view(), image.view()]).unwrap().insert_axis(Axis(0));
+        let config = AugmentConfig {
+            max_shift: 2.0,
+            max_rotation_degrees: 15.0,
+            max_scale: 0.1,
+            elastic_alpha: 8.0,
+            noise_std_dev: 0.0,
+            ..AugmentConfig::default()
+        };
+
+        let result = augment(&images, &config, &mut thread_rng()).unwrap();
+        assert_eq!(result.shape(), &[1, 2, 7, 7]);
+        assert_eq!(result.slice(ndarray::s![0, 0, .., ..]), result.slice(ndarray::s![0, 1, .., ..]));
+    }
+
+    #[test]
+    fn test_noise() {
+        let images = ArrayDynF::zeros(vec![4, 10, 10]);
+        let config = AugmentConfig { noise_std_dev: 0.1, ..AugmentConfig::default() };
+        let result = augment(&images, &config, &mut thread_rng()).unwrap();
+        let std_dev = (result.mapv(|o| o * o).mean().unwrap()).sqrt();
+        assert!((std_dev - 0.1).abs() < 0.02);
+    }
+
+    #[test]
+    fn test_invalid_shape() {
+        assert!(recenter(&ArrayDynF::zeros(vec![5])).is_err());
+    }
+}
@@ -0,0 +1,280 @@
+use std::collections::BTreeMap;
+use std::io;
+use crate::integration::compression::{Compression, ValuesFormat};
+use crate::integration::deserialization::read_array;
+use crate::integration::serde_utils::*;
+use crate::integration::serialization::write_array;
+use crate::nn::layers::nn_layers::GenericStorage;
+use crate::nn::lr_calculators::lr_schedule::get_global_step;
+
+const MAGIC: &[u8; 8] = b"AIPCKPT\0";
+const FORMAT_VERSION: u32 = 3;
+
+/// Everything needed to use a model or resume its training, in a single file.
+/// ### Format
+/// * Magic bytes and format version
+/// * Header with the config and the metadata, followed by its CRC32
+/// * Each key of the storage with its number of arrays, followed by its CRC32
+/// * Each array of the key, with its format (dtype), shape and values, followed by its CRC32
+#[derive(Clone, Debug, PartialEq)]
+pub struct Checkpoint {
+    /// XML that defines the structure of the model
+    pub config_xml: String,
+    /// Parameters of the model, the state of the optimizers (like the moments of **Adam**) and the
+    /// global training step
+    pub storage: GenericStorage,
+    /// Free-form information, like the loss of the version
+    pub metadata: BTreeMap<String, String>,
+}
+
+impl Checkpoint {
+    /// Number of training steps applied to the storage
+    pub fn step(&self) -> u32 {
+        get_global_step(&self.storage)
+    }
+}
+
+fn write_checksum(result: &mut Vec<u8>, data: &[u8]) {
+    write_u32(result, crc32fast::hash(data));
+}
+
+/// Write the data prefixed by its length and followed by its checksum
+fn write_section(result: &mut Vec<u8>, data: &[u8]) {
+    write_u32(result, data.len() as u32);
+    result.extend(data);
+    write_checksum(result, data);
+}
+
+/// Serialize the checkpoint with the values of the arrays in **format**.
+/// The keys are sorted, so the same checkpoint always produces the same bytes
+pub fn serialize_checkpoint(checkpoint: &Checkpoint, format: ValuesFormat) -> Vec<u8> {
+    let mut result = Vec::new();
+    result.extend(MAGIC);
+    write_u32(&mut result, FORMAT_VERSION);
+
+    let mut header = Vec::new();
+    write_string(&mut header, &checkpoint.config_xml);
+    write_u32(&mut header, checkpoint.metadata.len() as u32);
+    for (key, value) in checkpoint.metadata.iter() {
+        write_string(&mut header, key);
+        write_string(&mut header, value);
+    }
+    write_section(&mut result, &header);
+
+    let mut keys: Vec<_> = checkpoint.storage.keys().collect();
+    keys.sort();
+    write_u32(&mut result, keys.len() as u32);
+    for key in keys {
+        let arrays = &checkpoint.storage[key];
+        let mut key_header = Vec::new();
+        write_string(&mut key_header, key);
+        write_u32(&mut key_header, arrays.len() as u32);
+        write_section(&mut result, &key_header);
+        for array in arrays {
+            let format = format.for_shape(array.shape());
+            let mut data = vec![Compression { format, deflate: false }.to_byte()];
+            write_array(&mut data, array, format);
+            write_section(&mut result, &data);
+        }
+    }
+
+    result
+}
+
+fn take<'a>(source: &mut &'a [u8], len: usize) -> io::Result<&'a [u8]> {
+    if source.len() < len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    let (result, rest) = source.split_at(len);
+    *source = rest;
+    Ok(result)
+}
+
+/// Read a section written by **write_section** and verify its checksum
+fn read_section<'a>(source: &mut &'a [u8]) -> DeserResult<&'a [u8]> {
+    let len = read_u32(source)? as usize;
+    let data = take(source, len)?;
+    let checksum = read_u32(source)?;
+    if crc32fast::hash(data) != checksum {
+        return Err(StorageDeserError::new(ErrorKind::ChecksumMismatch));
+    }
+    Ok(data)
+}
+
+fn read_header(source: &mut &[u8]) -> DeserResult<(String, BTreeMap<String, String>)> {
+    let mut header = read_section(source)?;
+    let config_xml = read_string(&mut header)?;
+    let metadata_len = read_u32(&mut header)?;
+    let mut metadata = BTreeMap::new();
+    for _ in 0..metadata_len {
+        let key = read_string(&mut header)?;
+        let value = read_string(&mut header)?;
+        metadata.insert(key, value);
+    }
+    Ok((config_xml, metadata))
+}
+
+/// Read the name of a key and its number of arrays
+fn read_key_header(source: &mut &[u8]) -> DeserResult<(String, u32)> {
+    let mut header = read_section(source)?;
+    let key = read_string(&mut header)?;
+    let arrays_len = read_u32(&mut header)?;
+    if !header.is_empty() {
+        return Err(StorageDeserError::new(ErrorKind::TrailingBytes(header.len())));
+    }
+    Ok((key, arrays_len))
+}
+
+fn read_tensor(source: &mut &[u8]) -> DeserResult<crate::ArrayDynF> {
+    let mut data = read_section(source)?;
+    let byte = read_u8(&mut data)?;
+    let format = match Compression::from_byte(byte) {
+        Some(Compression { format, deflate: false }) => format,
+        _ => return Err(StorageDeserError::new(ErrorKind::UnknownCompression(byte))),
+    };
+
+    let array = read_array(&mut data, format)?;
+    if !data.is_empty() {
+        return Err(StorageDeserError::new(ErrorKind::TrailingBytes(data.len())));
+    }
+    Ok(array)
+}
+
+/// Read a checkpoint written by **serialize_checkpoint**. Checks the magic bytes, the version and
+/// the checksums, and describes where the data is invalid in the error
+pub fn deserialize_checkpoint(mut bytes: &[u8]) -> DeserResult<Checkpoint> {
+    let source = &mut bytes;
+    let magic = take(source, MAGIC.len()).map_err(|e| StorageDeserError::from(e).with_context("magic bytes"))?;
+    if magic != MAGIC {
+        return Err(StorageDeserError::new(ErrorKind::InvalidMagic));
+    }
+
+    let version = read_u32(source).map_err(|e| StorageDeserError::from(e).with_context("format version"))?;
+    if version != FORMAT_VERSION {
+        return Err(StorageDeserError::new(ErrorKind::UnsupportedVersion(version)));
+    }
+
+    let (config_xml, metadata) = read_header(source).map_err(|e| e.with_context("header"))?;
+
+    let keys_len = read_u32(source).map_err(|e| StorageDeserError::from(e).with_context("keys count"))?;
+    let mut storage = GenericStorage::new();
+    for _ in 0..keys_len {
+        let (key, arrays_len) = read_key_header(source).map_err(|e| e.with_context("a key"))?;
+        // Each array takes more than a byte, so a larger count can't be valid
+        let mut arrays = Vec::with_capacity((arrays_len as usize).min(source.len()));
+        for index in 0..arrays_len {
+            arrays.push(read_tensor(source).map_err(|e| e.with_context(format!("array {} of key {}", index, key)))?);
+        }
+        storage.insert(key, arrays);
+    }
+
+    if !source.is_empty() {
+        return Err(StorageDeserError::new(ErrorKind::TrailingBytes(source.len())));
+    }
+
+    Ok(Checkpoint { config_xml, storage, metadata })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ArrayDynF;
+    use crate::nn::lr_calculators::lr_schedule::increment_global_step;
+    use crate::nn::precision::Precision;
+    use super::*;
+
+    fn get_checkpoint() -> Checkpoint {
+        let mut storage = GenericStorage::new();
+        storage.insert("dense_4_2_0".to_owned(), vec![
+            ArrayDynF::from_shape_fn(vec![2, 4], |o| o[0] as f32 - o[1] as f32 * 0.5),
+            ArrayDynF::from_shape_fn(vec![2], |o| o[0] as f32),
+        ]);
+        storage.insert("adam_0".to_owned(), vec![ArrayDynF::ones(vec![2, 4]), ArrayDynF::from_elem(vec![], 3.0)]);
+        increment_global_step(&mut storage);
+        increment_global_step(&mut storage);
+
+        let mut metadata = BTreeMap::new();
+        metadata.insert("loss".to_owned(), "0.25".to_owned());
+        Checkpoint {
+            config_xml: "<AIModel><Layer><Relu/></Layer></AIModel>".to_owned(),
+            storage,
+            metadata,
+        }
+    }
+
+    #[test]
+    fn test_integrity() {
+        let checkpoint = get_checkpoint();
+        let serialized = serialize_checkpoint(&checkpoint, ValuesFormat::Float(Precision::F32));
+        assert_eq!(serialized, serialize_checkpoint(&checkpoint, ValuesFormat::Float(Precision::F32)));
+
+        let result = deserialize_checkpoint(&serialized).unwrap();
+        assert_eq!(result, checkpoint);
+        assert_eq!(result.step(), 2);
+
+        let result = deserialize_checkpoint(&serialize_checkpoint(&checkpoint, ValuesFormat::Float(Precision::F16))).unwrap();
+        assert_eq!(result.storage["dense_4_2_0"], checkpoint.storage["dense_4_2_0"]);
+        assert_eq!(result.step(), 2);
+    }
+
+    #[test]
+    fn test_truncated() {
+        let serialized = serialize_checkpoint(&get_checkpoint(), ValuesFormat::Float(Precision::F32));
+        for len in [0, 4, 20, serialized.len() - 1] {
+            let error = deserialize_checkpoint(&serialized[..len]).unwrap_err();
+            assert!(matches!(error.kind(), ErrorKind::NotEnoughBytes), "{}", error);
+        }
+
+        let error = deserialize_checkpoint(&serialized[..serialized.len() - 1]).unwrap_err();
+        assert!(error.to_string().contains("while reading array 1 of key global_step"), "{}", error);
+    }
+
+    #[test]
+    fn test_corrupted() {
+        let serialized = serialize_checkpoint(&get_checkpoint(), ValuesFormat::Float(Precision::F32));
+
+        let mut corrupted = serialized.clone();
+        corrupted[0] = b'X';
+        assert!(matches!(deserialize_checkpoint(&corrupted).unwrap_err().kind(), ErrorKind::InvalidMagic));
+
+        let mut corrupted = serialized.clone();
+        corrupted[11] = 4;
+        assert!(matches!(deserialize_checkpoint(&corrupted).unwrap_err().kind(), ErrorKind::UnsupportedVersion(4)));
+
+        // The value of the global step, which is the last key
+        let mut corrupted = serialized.clone();
+        let index = serialized.len() - 6;
+        corrupted[index] ^= 1;
+        let error = deserialize_checkpoint(&corrupted).unwrap_err();
+        assert!(matches!(error.kind(), ErrorKind::ChecksumMismatch));
+        assert!(error.to_string().contains("array 1 of key global_step"), "{}", error);
+
+        // Inside the name of a key
+        let mut corrupted = serialized.clone();
+        let index = serialized.windows(11).position(|o| o == b"dense_4_2_0").unwrap();
+        corrupted[index] ^= 1;
+        let error = deserialize_checkpoint(&corrupted).unwrap_err();
+        assert!(matches!(error.kind(), ErrorKind::ChecksumMismatch));
+        assert!(error.to_string().contains("a key"), "{}", error);
+
+        // The format of the first array of adam_0, from F32 to Bf16. It's after the name, the number
+        // of arrays, the CRC32 of the key and the length of the array
+        let mut corrupted = serialized.clone();
+        let index = serialized.windows(6).position(|o| o == b"adam_0").unwrap() + 6 + 4 + 4 + 4;
+        assert_eq!(corrupted[index], 0);
+        corrupted[index] = 2;
+        let error = deserialize_checkpoint(&corrupted).unwrap_err();
+        assert!(matches!(error.kind(), ErrorKind::ChecksumMismatch), "{}", error);
+        assert!(error.to_string().contains("array 0 of key adam_0"), "{}", error);
+
+        // Inside the config
+        let mut corrupted = serialized.clone();
+        corrupted[20] ^= 1;
+        let error = deserialize_checkpoint(&corrupted).unwrap_err();
+        assert!(matches!(error.kind(), ErrorKind::ChecksumMismatch));
+        assert!(error.to_string().contains("header"), "{}", error);
+
+        let mut extended = serialized;
+        extended.push(0);
+        assert!(matches!(deserialize_checkpoint(&extended).unwrap_err().kind(), ErrorKind::TrailingBytes(1)));
+    }
+}
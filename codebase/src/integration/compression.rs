@@ -0,0 +1,159 @@
+use std::io::{Read, Write};
+use flate2::Compression as DeflateLevel;
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use crate::integration::serde_utils::{DeserResult, ErrorKind, StorageDeserError};
+use crate::nn::precision::Precision;
+
+/// Largest size of decompressed data that is accepted, so a small upload of highly compressible
+/// data can't use all the memory
+pub const MAX_DECOMPRESSED_LEN: usize = 256 * 1024 * 1024;
+
+/// How each value of the arrays is written
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ValuesFormat {
+    /// Floats with the specified precision
+    Float(Precision),
+    /// Values converted to int8, with one f32 scale per index of the first axis (or a single one
+    /// for arrays with 1 dimension). Lossy, so it's meant for values that don't need to be
+    /// exact, like the outputs of **export_deltas**
+    Int8,
+}
+
+impl ValuesFormat {
+    /// Format used for an array of this shape. Like with **Precision**, scalars are kept in f32
+    pub fn for_shape(&self, shape: &[usize]) -> ValuesFormat {
+        match self {
+            ValuesFormat::Float(precision) => ValuesFormat::Float(precision.for_shape(shape)),
+            ValuesFormat::Int8 if shape.is_empty() => ValuesFormat::Float(Precision::F32),
+            ValuesFormat::Int8 => ValuesFormat::Int8,
+        }
+    }
+}
+
+/// Number of scales used to write an array of this shape in **ValuesFormat::Int8**
+pub fn int8_channels(shape: &[usize]) -> usize {
+    if shape.len() >= 2 { shape[0] } else { 1 }
+}
+
+/// Described by the first byte of serialized data. The 2 lowest bits select the **ValuesFormat**
+/// and the highest bit is set when the rest of the data is compressed with deflate
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Compression {
+    pub format: ValuesFormat,
+    pub deflate: bool,
+}
+
+const FORMAT_MASK: u8 = 0b0000_0011;
+const DEFLATE_FLAG: u8 = 0b1000_0000;
+
+impl Compression {
+    pub fn none() -> Self {
+        Self::from_precision(Precision::F32)
+    }
+
+    pub fn from_precision(precision: Precision) -> Self {
+        Self { format: ValuesFormat::Float(precision), deflate: false }
+    }
+
+    /// General and lossless
+    pub fn deflate() -> Self {
+        Self { format: ValuesFormat::Float(Precision::F32), deflate: true }
+    }
+
+    /// For deltas between storages, which are small and can be rounded
+    pub fn delta_quantized() -> Self {
+        Self { format: ValuesFormat::Int8, deflate: true }
+    }
+
+    pub fn to_byte(&self) -> u8 {
+        let format = match self.format {
+            ValuesFormat::Float(Precision::F32) => 0,
+            ValuesFormat::Float(Precision::F16) => 1,
+            ValuesFormat::Float(Precision::Bf16) => 2,
+            ValuesFormat::Int8 => 3,
+        };
+        if self.deflate { format | DEFLATE_FLAG } else { format }
+    }
+
+    /// Returns None if the byte has unknown bits set
+    pub fn from_byte(byte: u8) -> Option<Self> {
+        if byte & !(FORMAT_MASK | DEFLATE_FLAG) != 0 {
+            return None;
+        }
+
+        let format = match byte & FORMAT_MASK {
+            0 => ValuesFormat::Float(Precision::F32),
+            1 => ValuesFormat::Float(Precision::F16),
+            2 => ValuesFormat::Float(Precision::Bf16),
+            _ => ValuesFormat::Int8,
+        };
+        Some(Self { format, deflate: byte & DEFLATE_FLAG != 0 })
+    }
+
+    /// Apply deflate to **data**, if enabled
+    pub fn compress(&self, data: Vec<u8>) -> Vec<u8> {
+        if !self.deflate {
+            return data;
+        }
+
+        let mut encoder = DeflateEncoder::new(Vec::new(), DeflateLevel::default());
+        // Writing to a Vec never fails
+        encoder.write_all(&data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    /// The opposite of **compress**. Fails if the result would be larger than **max_len**
+    pub fn decompress(&self, data: &[u8], max_len: usize) -> DeserResult<Vec<u8>> {
+        if !self.deflate {
+            return Ok(data.to_vec());
+        }
+
+        let mut result = Vec::new();
+        DeflateDecoder::new(data).take(max_len as u64 + 1).read_to_end(&mut result)
+            .map_err(|_| StorageDeserError::new(ErrorKind::InvalidCompressedData))?;
+        if result.len() > max_len {
+            return Err(StorageDeserError::new(ErrorKind::DecompressedTooLarge(max_len)));
+        }
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_byte() {
+        // Values written before compression existed
+        assert_eq!(Compression::none().to_byte(), 0);
+        assert_eq!(Compression::from_precision(Precision::F16).to_byte(), 1);
+        assert_eq!(Compression::from_precision(Precision::Bf16).to_byte(), 2);
+
+        for compression in [Compression::none(), Compression::deflate(), Compression::delta_quantized(),
+            Compression { format: ValuesFormat::Float(Precision::Bf16), deflate: true }] {
+            assert_eq!(Compression::from_byte(compression.to_byte()), Some(compression));
+        }
+        assert_eq!(Compression::from_byte(0b0100_0000), None);
+    }
+
+    #[test]
+    fn test_deflate() {
+        let data: Vec<u8> = (0..1000).map(|o| (o % 7) as u8).collect();
+        let compressed = Compression::deflate().compress(data.clone());
+        assert!(compressed.len() < data.len() / 4);
+        assert_eq!(Compression::deflate().decompress(&compressed, data.len()).unwrap(), data);
+        assert_eq!(Compression::none().compress(data.clone()), data);
+    }
+
+    #[test]
+    fn test_decompressed_too_large() {
+        let data = vec![0; 16 * 1024 * 1024];
+        let compressed = Compression::deflate().compress(data);
+        assert!(compressed.len() < 32 * 1024);
+
+        let error = Compression::deflate().decompress(&compressed, 1024 * 1024).unwrap_err();
+        assert!(matches!(error.kind(), ErrorKind::DecompressedTooLarge(_)), "{}", error);
+        assert_eq!(Compression::deflate().decompress(&compressed, 16 * 1024 * 1024).unwrap().len(), 16 * 1024 * 1024);
+    }
+}
@@ -0,0 +1,353 @@
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{sync_channel, Receiver};
+use std::thread;
+use anyhow::{anyhow, bail};
+use ndarray::Axis;
+use ndarray_rand::rand::Rng;
+use ndarray_rand::rand::seq::SliceRandom;
+use crate::ArrayDynF;
+use crate::integration::compression::{Compression, ValuesFormat};
+use crate::integration::deserialization::deserialize_pairs;
+use crate::integration::serde_utils::Pairs;
+use crate::nn::precision::Precision;
+use crate::utils::GenericResult;
+
+/// Input/expected pairs that are read in batches by index, so they don't all need to be in memory
+pub trait Dataset: Send + Sync {
+    /// Number of pairs
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Read the pairs at **indexes**, in that order, as a single batch
+    fn get_batch(&self, indexes: &[usize]) -> GenericResult<Pairs>;
+}
+
+fn check_indexes(indexes: &[usize], len: usize) -> GenericResult<()> {
+    match indexes.iter().find(|o| **o >= len) {
+        Some(index) => bail!("Index {} is out of bounds for a dataset with {} pairs", index, len),
+        None => Ok(()),
+    }
+}
+
+impl Dataset for Pairs {
+    fn len(&self) -> usize {
+        self.inputs.len_of(Axis(0))
+    }
+
+    fn get_batch(&self, indexes: &[usize]) -> GenericResult<Pairs> {
+        check_indexes(indexes, self.len())?;
+        Ok(Pairs {
+            inputs: self.inputs.select(Axis(0), indexes),
+            expected: self.expected.select(Axis(0), indexes),
+        })
+    }
+}
+
+/// Location of an array of a pairs file, whose first axis is the pair index
+struct StoredArray {
+    /// Where the values start in the file
+    offset: u64,
+    len: usize,
+    item_shape: Vec<usize>,
+    precision: Precision,
+}
+
+fn read_file_u32(file: &mut File) -> io::Result<u32> {
+    let mut buffer = [0; 4];
+    file.read_exact(&mut buffer)?;
+    Ok(u32::from_be_bytes(buffer))
+}
+
+impl StoredArray {
+    fn read_header(file: &mut File, precision: Precision) -> GenericResult<Self> {
+        let shape_len = read_file_u32(file)? as usize;
+        if shape_len == 0 {
+            bail!("Pairs can't be scalars");
+        }
+        let mut shape = Vec::with_capacity(shape_len);
+        for _ in 0..shape_len {
+            shape.push(read_file_u32(file)? as usize);
+        }
+
+        Ok(Self {
+            offset: file.stream_position()?,
+            len: shape[0],
+            precision: precision.for_shape(&shape),
+            item_shape: shape[1..].to_vec(),
+        })
+    }
+
+    fn item_bytes(&self) -> usize {
+        self.item_shape.iter().product::<usize>() * self.precision.bytes_per_value()
+    }
+
+    fn end(&self) -> u64 {
+        self.offset + (self.len * self.item_bytes()) as u64
+    }
+
+    fn read_items(&self, file: &mut File, indexes: &[usize]) -> io::Result<ArrayDynF> {
+        let item_bytes = self.item_bytes();
+        let mut buffer = vec![0; item_bytes];
+        let mut values = Vec::with_capacity(indexes.len() * self.item_shape.iter().product::<usize>());
+
+        for index in indexes {
+            file.seek(SeekFrom::Start(self.offset + (index * item_bytes) as u64))?;
+            file.read_exact(&mut buffer)?;
+            values.extend(buffer
+                .chunks_exact(self.precision.bytes_per_value())
+                .map(|o| self.precision.from_be_bytes(o)));
+        }
+
+        let mut shape = vec![indexes.len()];
+        shape.extend(&self.item_shape);
+        Ok(ArrayDynF::from_shape_vec(shape, values).unwrap())
+    }
+}
+
+/// Precision of the values when the pairs can be read individually from the file, which isn't
+/// possible when it's deflated or in int8
+fn streamed_precision(compression: Compression) -> Option<Precision> {
+    match compression {
+        Compression { deflate: false, format: ValuesFormat::Float(precision) } => Some(precision),
+        _ => None,
+    }
+}
+
+fn read_file_compression(file: &mut File) -> GenericResult<Compression> {
+    let mut byte = [0];
+    file.read_exact(&mut byte)?;
+    Compression::from_byte(byte[0]).ok_or_else(|| anyhow!("Unknown compression byte {}", byte[0]))
+}
+
+/// Pairs file written by **serialize_pairs** where only the pairs of each batch are read from disk
+pub struct FileDataset {
+    file: Mutex<File>,
+    inputs: StoredArray,
+    expected: StoredArray,
+}
+
+impl FileDataset {
+    pub fn open(path: impl AsRef<Path>) -> GenericResult<Self> {
+        let mut file = File::open(path)?;
+        let compression = read_file_compression(&mut file)?;
+        let precision = streamed_precision(compression)
+            .ok_or_else(|| anyhow!("Can't stream pairs stored with {:?}", compression))?;
+
+        let inputs = StoredArray::read_header(&mut file, precision)?;
+        file.seek(SeekFrom::Start(inputs.end()))?;
+        let expected = StoredArray::read_header(&mut file, precision)?;
+
+        if inputs.len != expected.len {
+            bail!("There are {} inputs but {} expected values", inputs.len, expected.len);
+        }
+        if file.metadata()?.len() < expected.end() {
+            bail!("The pairs file is truncated");
+        }
+
+        Ok(Self { file: Mutex::new(file), inputs, expected })
+    }
+}
+
+impl Dataset for FileDataset {
+    fn len(&self) -> usize {
+        self.inputs.len
+    }
+
+    fn get_batch(&self, indexes: &[usize]) -> GenericResult<Pairs> {
+        check_indexes(indexes, self.len())?;
+        let mut file = self.file.lock().unwrap();
+        Ok(Pairs {
+            inputs: self.inputs.read_items(&mut file, indexes)?,
+            expected: self.expected.read_items(&mut file, indexes)?,
+        })
+    }
+}
+
+/// Stream the pairs of the file when its compression allows it, otherwise load all of them in memory
+pub fn open_dataset(path: impl AsRef<Path>) -> GenericResult<Arc<dyn Dataset>> {
+    let mut file = File::open(&path)?;
+    if streamed_precision(read_file_compression(&mut file)?).is_some() {
+        return Ok(Arc::new(FileDataset::open(path)?));
+    }
+
+    let mut bytes = Vec::new();
+    file.seek(SeekFrom::Start(0))?;
+    file.read_to_end(&mut bytes)?;
+    Ok(Arc::new(deserialize_pairs(&bytes)?))
+}
+
+/// Some of the pairs of another dataset
+pub struct Subset {
+    source: Arc<dyn Dataset>,
+    indexes: Vec<usize>,
+}
+
+impl Dataset for Subset {
+    fn len(&self) -> usize {
+        self.indexes.len()
+    }
+
+    fn get_batch(&self, indexes: &[usize]) -> GenericResult<Pairs> {
+        check_indexes(indexes, self.len())?;
+        let indexes: Vec<_> = indexes.iter().map(|o| self.indexes[*o]).collect();
+        self.source.get_batch(&indexes)
+    }
+}
+
+/// Randomly split the pairs into (train, validation), with **validation_fraction** of them in validation
+pub fn split(dataset: Arc<dyn Dataset>, validation_fraction: f32, rng: &mut impl Rng)
+             -> GenericResult<(Subset, Subset)> {
+    if !(0.0..=1.0).contains(&validation_fraction) {
+        bail!("The validation fraction must be between 0 and 1, got {}", validation_fraction);
+    }
+
+    let mut indexes: Vec<_> = (0..dataset.len()).collect();
+    indexes.shuffle(rng);
+    let validation_len = (dataset.len() as f32 * validation_fraction).round() as usize;
+    let validation = indexes.split_off(indexes.len() - validation_len);
+
+    Ok((
+        Subset { source: dataset.clone(), indexes },
+        Subset { source: dataset, indexes: validation },
+    ))
+}
+
+/// Batches of one epoch. They're read in a background thread, which reads the next batch while
+/// the current one is used
+pub struct EpochBatches {
+    receiver: Receiver<GenericResult<Pairs>>,
+}
+
+impl Iterator for EpochBatches {
+    type Item = GenericResult<Pairs>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.receiver.recv().ok()
+    }
+}
+
+fn prefetch_batches(dataset: Arc<dyn Dataset>, order: Vec<usize>, batch_size: usize) -> EpochBatches {
+    let (sender, receiver) = sync_channel(0);
+    thread::spawn(move || {
+        for indexes in order.chunks(batch_size.max(1)) {
+            let batch = dataset.get_batch(indexes);
+            let failed = batch.is_err();
+            // Sending fails when the iterator was dropped
+            if sender.send(batch).is_err() || failed {
+                break;
+            }
+        }
+    });
+    EpochBatches { receiver }
+}
+
+/// Go through every pair once, in order, in batches of **batch_size**. The last batch is smaller
+/// when the pairs can't be divided evenly
+pub fn sequential_batches(dataset: Arc<dyn Dataset>, batch_size: usize) -> EpochBatches {
+    let order = (0..dataset.len()).collect();
+    prefetch_batches(dataset, order, batch_size)
+}
+
+/// Same as **sequential_batches**, but the pairs are shuffled
+pub fn shuffled_batches(dataset: Arc<dyn Dataset>, batch_size: usize, rng: &mut impl Rng) -> EpochBatches {
+    let mut order: Vec<_> = (0..dataset.len()).collect();
+    order.shuffle(rng);
+    prefetch_batches(dataset, order, batch_size)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use ndarray::Array;
+    use ndarray_rand::rand::thread_rng;
+    use crate::integration::serialization::{serialize_pairs, serialize_pairs_compressed};
+    use super::*;
+
+    fn get_pairs(len: usize) -> Pairs {
+        Pairs {
+            inputs: Array::range(0.0, (len * 3) as f32, 1.0).into_shape((len, 3)).unwrap().into_dyn(),
+            expected: Array::range(0.0, len as f32, 1.0).into_dyn(),
+        }
+    }
+
+    /// First value of each input, which is unique
+    fn input_ids(pairs: &Pairs) -> Vec<usize> {
+        pairs.inputs.outer_iter().map(|o| o[0] as usize / 3).collect()
+    }
+
+    fn write_temp(name: &str, bytes: &[u8]) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("codebase_dataset_{}_{}.dat", name, std::process::id()));
+        fs::write(&path, bytes).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_in_memory() {
+        let pairs = get_pairs(5);
+        let batch = pairs.get_batch(&[3, 1]).unwrap();
+        assert_eq!(input_ids(&batch), vec![3, 1]);
+        assert_eq!(batch.expected.into_raw_vec(), vec![3.0, 1.0]);
+        assert!(pairs.get_batch(&[5]).is_err());
+    }
+
+    #[test]
+    fn test_file_dataset() {
+        let pairs = get_pairs(6);
+        let path = write_temp("plain", &serialize_pairs(&pairs));
+        let dataset = FileDataset::open(&path).unwrap();
+
+        assert_eq!(dataset.len(), 6);
+        let batch = dataset.get_batch(&[4, 0, 2]).unwrap();
+        let expected = pairs.get_batch(&[4, 0, 2]).unwrap();
+        assert_eq!(batch.inputs, expected.inputs);
+        assert_eq!(batch.expected, expected.expected);
+        assert!(dataset.get_batch(&[6]).is_err());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_open_deflated() {
+        let pairs = get_pairs(4);
+        let path = write_temp("deflated", &serialize_pairs_compressed(&pairs, Compression::deflate()));
+        assert!(FileDataset::open(&path).is_err());
+
+        let dataset = open_dataset(&path).unwrap();
+        assert_eq!(input_ids(&dataset.get_batch(&[2, 3]).unwrap()), vec![2, 3]);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_split() {
+        let (train, validation) = split(Arc::new(get_pairs(10)), 0.3, &mut thread_rng()).unwrap();
+        assert_eq!(train.len(), 7);
+        assert_eq!(validation.len(), 3);
+
+        let mut ids = input_ids(&train.get_batch(&(0..7).collect::<Vec<_>>()).unwrap());
+        ids.extend(input_ids(&validation.get_batch(&[0, 1, 2]).unwrap()));
+        ids.sort();
+        assert_eq!(ids, (0..10).collect::<Vec<_>>());
+        assert!(validation.get_batch(&[3]).is_err());
+        assert!(split(Arc::new(get_pairs(10)), 1.5, &mut thread_rng()).is_err());
+    }
+
+    #[test]
+    fn test_epoch_batches() {
+        let dataset: Arc<dyn Dataset> = Arc::new(get_pairs(10));
+        let sizes: Vec<_> = sequential_batches(dataset.clone(), 4)
+            .map(|o| o.unwrap().inputs.shape()[0])
+            .collect();
+        assert_eq!(sizes, vec![4, 4, 2]);
+
+        let mut ids: Vec<_> = shuffled_batches(dataset, 3, &mut thread_rng())
+            .flat_map(|o| input_ids(&o.unwrap()))
+            .collect();
+        ids.sort();
+        assert_eq!(ids, (0..10).collect::<Vec<_>>());
+    }
+}
@@ -3,42 +3,66 @@ use std::io::Read;
 use crate::ArrayDynF;
 use crate::integration::serde_utils::*;
 use crate::nn::layers::nn_layers::GenericStorage;
+use crate::integration::compression::{int8_channels, ValuesFormat};
+use crate::nn::precision::Precision;
 
-fn read_num_vec(source: &mut &[u8], shape: &[usize]) -> io::Result<ArrayDynF> {
-    let length = shape.iter().copied().reduce(|a, b| a * b).unwrap_or(1);
-    let mut buffer = vec![0; length * 4];
-    source.read_exact(&mut buffer)?;
+fn read_values(source: &mut &[u8], shape: &[usize], format: ValuesFormat) -> io::Result<ArrayDynF> {
+    let length = shape.iter().try_fold(1usize, |a, b| a.checked_mul(*b));
+    let nums = match format.for_shape(shape) {
+        ValuesFormat::Float(precision) => {
+            check_remaining(source, length.and_then(|o| o.checked_mul(precision.bytes_per_value())))?;
+            let length = length.unwrap();
+            let mut buffer = vec![0; length * precision.bytes_per_value()];
+            source.read_exact(&mut buffer)?;
+            buffer
+                .chunks_exact(precision.bytes_per_value())
+                .map(|arr| precision.from_be_bytes(arr))
+                .collect()
+        }
+        ValuesFormat::Int8 if length == Some(0) => Vec::new(),
+        ValuesFormat::Int8 => {
+            let channels = int8_channels(shape);
+            check_remaining(source, length.and_then(|o| o.checked_add(channels.saturating_mul(4))))?;
+            let length = length.unwrap();
+            let mut scales = Vec::with_capacity(channels);
+            for _ in 0..channels {
+                scales.push(read_f32(source)?);
+            }
 
-    let nums = buffer
-        .chunks_exact(4)
-        .map(|arr| f32::from_be_bytes([arr[0], arr[1], arr[2], arr[3]]))
-        .collect();
+            let mut buffer = vec![0; length];
+            source.read_exact(&mut buffer)?;
+            let channel_len = length / channels;
+            buffer.iter()
+                .enumerate()
+                .map(|(i, o)| *o as i8 as f32 * scales[i / channel_len])
+                .collect()
+        }
+    };
     Ok(ArrayDynF::from_shape_vec(shape, nums).unwrap())
 }
 
-fn read_array(source: &mut &[u8]) -> io::Result<ArrayDynF> {
+pub(crate) fn read_array(source: &mut &[u8], format: ValuesFormat) -> io::Result<ArrayDynF> {
     let shape_len = read_u32(source)? as usize;
+    check_remaining(source, shape_len.checked_mul(4))?;
     let mut shape = vec![0; shape_len];
     for i in 0..shape_len {
         shape[i] = read_u32(source)? as usize;
     }
 
-    read_num_vec(source, &shape)
+    read_values(source, &shape, format)
 }
 
-fn read_storage(source: &mut &[u8]) -> DeserResult<GenericStorage> {
+fn read_storage(source: &mut &[u8], format: ValuesFormat) -> DeserResult<GenericStorage> {
     let mut result = GenericStorage::new();
 
     while !source.is_empty() {
-        let key_len = read_u32(source)?;
-        let mut key_bytes = vec![0; key_len as usize];
-        source.read_exact(&mut key_bytes)?;
-        let key = String::from_utf8(key_bytes)?;
-
-        let vec_len = read_u32(source)? as usize;
-        let mut arrays = Vec::with_capacity(vec_len);
-        for _ in 0..vec_len {
-            arrays.push(read_array(source)?);
+        let key = read_string(source).map_err(|e| e.with_context("a key"))?;
+
+        let vec_len = read_u32(source).map_err(|e| StorageDeserError::from(e).with_context(format!("key {}", key)))? as usize;
+        let mut arrays = Vec::with_capacity(vec_len.min(source.len()));
+        for index in 0..vec_len {
+            arrays.push(read_array(source, format)
+                .map_err(|e| StorageDeserError::from(e).with_context(format!("array {} of key {}", index, key)))?);
         }
 
         result.insert(key, arrays);
@@ -48,28 +72,33 @@ fn read_storage(source: &mut &[u8]) -> DeserResult<GenericStorage> {
 }
 
 pub fn deserialize_array(mut bytes: &[u8]) -> DeserResult<ArrayDynF> {
-    Ok(read_array(&mut bytes)?)
+    Ok(read_array(&mut bytes, ValuesFormat::Float(Precision::F32))?)
 }
 
+/// Any compression is accepted. Values in reduced precision are converted back to f32
 pub fn deserialize_storage(mut bytes: &[u8]) -> DeserResult<GenericStorage> {
-    let _compression = read_u8(&mut bytes)?;
-    let result = read_storage(&mut bytes)?;
+    let (compression, data) = read_decompressed(&mut bytes)?;
+    let result = read_storage(&mut data.as_slice(), compression.format)?;
     Ok(result)
 }
 
 pub fn deserialize_version(mut bytes: &[u8])-> DeserResult<(GenericStorage, f64)> {
-    let _compression = read_u8(&mut bytes)?;
-    let loss = read_f64(&mut bytes)?;
-    let storage = read_storage(&mut bytes)?;
+    let (compression, data) = read_decompressed(&mut bytes)?;
+    let mut data = data.as_slice();
+    let loss = read_f64(&mut data).map_err(|e| StorageDeserError::from(e).with_context("loss"))?;
+    let storage = read_storage(&mut data, compression.format)?;
     Ok((storage, loss))
 }
 
 pub fn deserialize_pairs(mut bytes: &[u8]) -> DeserResult<Pairs> {
-    let _compression = read_u8(&mut bytes)?;
+    let (compression, data) = read_decompressed(&mut bytes)?;
+    let mut data = data.as_slice();
 
     Ok(Pairs {
-        inputs: read_array(&mut bytes)?,
-        expected: read_array(&mut bytes)?,
+        inputs: read_array(&mut data, compression.format)
+            .map_err(|e| StorageDeserError::from(e).with_context("inputs"))?,
+        expected: read_array(&mut data, compression.format)
+            .map_err(|e| StorageDeserError::from(e).with_context("expected"))?,
     })
 }
 
@@ -78,7 +107,9 @@ mod tests {
     use super::*;
     use ndarray_rand::rand;
     use rand::prelude::*;
-    use crate::integration::serialization::serialize_storage;
+    use crate::integration::compression::Compression;
+    use crate::integration::model_deltas::{export_deltas, import_deltas};
+    use crate::integration::serialization::*;
 
     #[test]
     fn test_integrity() {
@@ -122,4 +153,118 @@ mod tests {
             assert_eq!(inputs[key], result[key]);
         }
     }
+
+    #[test]
+    fn test_reduced_precision() {
+        let mut storage = GenericStorage::new();
+        storage.insert("dense_0".to_owned(), vec![
+            ArrayDynF::from_shape_vec(vec![2, 3], vec![0.1, -2.5, 3.125, 1000.3, -0.0001, 7.0]).unwrap(),
+            ArrayDynF::from_elem(vec![], 12345.0),
+        ]);
+        let full_len = serialize_storage(&storage).len();
+
+        for precision in [Precision::F16, Precision::Bf16] {
+            let serialized = serialize_storage_with_precision(&storage, precision);
+            // Only the 6 values of the first array are halved
+            assert_eq!(serialized.len(), full_len - 6 * 2);
+
+            let result = deserialize_storage(&serialized).unwrap();
+            let mut expected = storage.clone();
+            precision.round_storage(&mut expected);
+            assert_eq!(result, expected);
+        }
+    }
+
+    #[test]
+    fn test_unknown_compression() {
+        let mut serialized = serialize_storage(&GenericStorage::new());
+        serialized[0] = 200;
+        assert!(deserialize_storage(&serialized).is_err());
+    }
+
+    #[test]
+    fn test_oversized_lengths() {
+        let header = serialize_storage(&GenericStorage::new());
+        let with_body = |body: &[u32]| {
+            let mut result = header.clone();
+            write_string(&mut result, "dense_0");
+            body.iter().for_each(|o| write_u32(&mut result, *o));
+            result
+        };
+
+        // The length of the key
+        let mut serialized = header.clone();
+        write_u32(&mut serialized, u32::MAX);
+        assert!(matches!(deserialize_storage(&serialized).unwrap_err().kind(), ErrorKind::NotEnoughBytes));
+
+        // The number of arrays, the number of axes and the number of values
+        for body in [vec![u32::MAX], vec![1, u32::MAX], vec![1, 3, u32::MAX, u32::MAX, u32::MAX], vec![1, 1, u32::MAX]] {
+            let error = deserialize_storage(&with_body(&body)).unwrap_err();
+            assert!(matches!(error.kind(), ErrorKind::NotEnoughBytes), "{:?}: {}", body, error);
+        }
+    }
+
+    fn get_storage(scale: f32) -> GenericStorage {
+        let mut storage = GenericStorage::new();
+        storage.insert("dense_0".to_owned(), vec![
+            ArrayDynF::from_shape_fn(vec![8, 16], |o| ((o[0] * 16 + o[1]) % 13) as f32 * scale - scale * 6.0),
+            ArrayDynF::from_shape_fn(vec![8], |o| o[0] as f32 * scale),
+            ArrayDynF::from_elem(vec![], 12345.0),
+            ArrayDynF::zeros(vec![0, 3]),
+        ]);
+        storage
+    }
+
+    #[test]
+    fn test_deflate() {
+        let storage = get_storage(0.25);
+        let compressed = serialize_storage_compressed(&storage, Compression::deflate());
+        assert!(compressed.len() < serialize_storage(&storage).len() / 2);
+        assert_eq!(deserialize_storage(&compressed).unwrap(), storage);
+
+        let (result, loss) = deserialize_version(&serialize_version_compressed(&storage, 1.5, Compression::deflate())).unwrap();
+        assert_eq!(result, storage);
+        assert_eq!(loss, 1.5);
+
+        let pairs = Pairs { inputs: storage["dense_0"][0].clone(), expected: storage["dense_0"][1].clone() };
+        let result = deserialize_pairs(&serialize_pairs_compressed(&pairs, Compression::deflate())).unwrap();
+        assert_eq!(result.inputs, pairs.inputs);
+        assert_eq!(result.expected, pairs.expected);
+    }
+
+    #[test]
+    fn test_delta_quantized() {
+        let initial = get_storage(1.0);
+        let mut trained = initial.clone();
+        import_deltas(&mut trained, get_storage(0.001));
+
+        let mut deltas = trained.clone();
+        export_deltas(&initial, &mut deltas);
+        let compressed = serialize_storage_compressed(&deltas, Compression::delta_quantized());
+        assert!(compressed.len() < serialize_storage(&deltas).len() / 4);
+
+        let mut result = initial.clone();
+        import_deltas(&mut result, deserialize_storage(&compressed).unwrap());
+        for (key, arrays) in trained.iter() {
+            for (expected, actual) in arrays.iter().zip(result[key].iter()) {
+                assert_eq!(expected.shape(), actual.shape());
+                assert!(expected.iter().zip(actual.iter()).all(|(a, b)| (a - b).abs() < 0.0001));
+            }
+        }
+    }
+
+    #[test]
+    fn test_truncated() {
+        let serialized = serialize_storage(&get_storage(1.0));
+        let error = deserialize_storage(&serialized[..serialized.len() - 10]).unwrap_err();
+        assert!(matches!(error.kind(), ErrorKind::NotEnoughBytes));
+        assert_eq!(error.to_string(), "Data ended unexpectedly (truncated or corrupted) while reading array 3 of key dense_0");
+    }
+
+    #[test]
+    fn test_invalid_compressed_data() {
+        let mut compressed = serialize_storage_compressed(&get_storage(1.0), Compression::deflate());
+        compressed.truncate(compressed.len() / 2);
+        assert!(deserialize_storage(&compressed).is_err());
+    }
 }
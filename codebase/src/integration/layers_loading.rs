@@ -1,5 +1,6 @@
 use crate::nn::{
-    layers::nn_layers::Layer, loss::loss_func::LossFunc, lr_calculators::lr_calculator::LrCalc,
+    controller::HeadConfig, layers::nn_layers::{Layer, TrainOptions}, loss::loss_func::LossFunc,
+    lr_calculators::lr_calculator::LrCalc, lr_calculators::lr_schedule::LrSchedule,
 };
 use std::{error::Error, fmt::Display};
 use xmltree::Element;
@@ -11,6 +12,13 @@ pub enum XmlError {
     AttributeNotFound(String, &'static str),
     AttributeParseError(String, &'static str, String),
     UnexpectedChildCount(String, u32, u32),
+    /// The document isn't valid XML (or JSON/TOML for the other formats)
+    ParseError(String),
+    /// The config can't be written, like a **Debug** layer that calls functions
+    NotWritable(String),
+    /// Error that happened inside an element. The path is like `AIModel/Layer/Sequential/Dense[1]`,
+    /// where the index is the position of the element among its siblings
+    InElement(String, Box<XmlError>),
 }
 
 impl Display for XmlError {
@@ -31,23 +39,48 @@ impl Display for XmlError {
                 "Tag <{}> is expected to have exactly {} children, but has {}",
                 tag, expected, actual
             )?,
+            Self::ParseError(e) => write!(f, "Could not parse the model definition: {}", e)?,
+            Self::NotWritable(e) => write!(f, "Can't write the model definition: {}", e)?,
+            Self::InElement(path, e) => write!(f, "{} (in {})", e, path)?,
         }
         Ok(())
     }
 }
 
+impl XmlError {
+    /// Path of the element where the error happened, if known
+    pub fn path(&self) -> Option<&str> {
+        match self {
+            Self::InElement(path, _) => Some(path),
+            _ => None,
+        }
+    }
+
+    /// Prepend **segment** to the path of the error
+    pub(crate) fn at(self, segment: impl Display) -> Self {
+        match self {
+            Self::InElement(path, e) => Self::InElement(format!("{}/{}", segment, path), e),
+            e => Self::InElement(segment.to_string(), Box::new(e)),
+        }
+    }
+}
+
 impl Error for XmlError {}
 
 type Result<T> = std::result::Result<T, XmlError>;
 
 #[derive(Clone, Debug)]
 pub struct ModelXmlConfig {
+    /// Loss of the main layer. Only used if the model has no heads
     pub loss_func: LossFunc,
     pub main_layer: Layer,
+    /// Named outputs that receive the output of the main layer
+    pub heads: Vec<HeadConfig>,
 }
 
 pub fn load_model_xml(bytes: &[u8]) -> Result<ModelXmlConfig> {
-    let elements = Element::parse_all(bytes).unwrap();
+    let elements = Element::parse_all(bytes)
+        .map_err(|e| XmlError::ParseError(e.to_string()))?;
 
     let mut root = None;
     for e in iter_elements(&elements) {
@@ -59,30 +92,40 @@ pub fn load_model_xml(bytes: &[u8]) -> Result<ModelXmlConfig> {
     }
 
     match root {
-        Some(root) => {
-            let children = &root.children;
-            let mut loss_func = None;
-            let mut main_layer = None;
-
-            for e in iter_elements(children) {
-                if e.name == "LossFunc" {
-                    loss_func = Some(load_loss_func(e)?);
-                } else if e.name == "Layer" {
-                    main_layer = Some(load_main_layer(e)?);
-                } else {
-                    return Err(XmlError::UnexpectedTag(e.name.clone()));
-                }
-            }
+        Some(root) => load_model(root).map_err(|e| e.at(&root.name)),
+        None => Err(XmlError::ElementNotFound("AIModel")),
+    }
+}
 
-            let loss_func = loss_func.ok_or(XmlError::ElementNotFound("LossFunc"))?;
-            let main_layer = main_layer.ok_or(XmlError::ElementNotFound("Layer"))?;
-            Ok(ModelXmlConfig {
-                loss_func,
-                main_layer,
-            })
+fn load_model(root: &Element) -> Result<ModelXmlConfig> {
+    let mut loss_func = None;
+    let mut main_layer = None;
+    let mut heads = Vec::new();
+
+    for (index, e) in iter_elements(&root.children).enumerate() {
+        if e.name == "LossFunc" {
+            loss_func = Some(load_loss_func(e).map_err(|err| err.at(&e.name))?);
+        } else if e.name == "Layer" {
+            main_layer = Some(load_main_layer(e).map_err(|err| err.at(&e.name))?);
+        } else if e.name == "Head" {
+            heads.push(load_head(e).map_err(|err| err.at(format!("{}[{}]", e.name, index)))?);
+        } else {
+            return Err(XmlError::UnexpectedTag(e.name.clone()));
         }
-        None => Err(XmlError::ElementNotFound("AIModel")),
     }
+
+    // Models with heads have a loss function for each head
+    let loss_func = match loss_func {
+        Some(v) => v,
+        None if !heads.is_empty() => LossFunc::Mse,
+        None => return Err(XmlError::ElementNotFound("LossFunc")),
+    };
+    let main_layer = main_layer.ok_or(XmlError::ElementNotFound("Layer"))?;
+    Ok(ModelXmlConfig {
+        loss_func,
+        main_layer,
+        heads,
+    })
 }
 
 fn load_loss_func(element: &Element) -> Result<LossFunc> {
@@ -96,44 +139,73 @@ fn load_loss_func(element: &Element) -> Result<LossFunc> {
     Err(XmlError::ElementNotFound("Any loss function node"))
 }
 
+fn load_head(element: &Element) -> Result<HeadConfig> {
+    let name = element.attributes.get("name")
+        .cloned()
+        .ok_or_else(|| XmlError::AttributeNotFound(element.name.clone(), "name"))?;
+    let loss_func = iter_elements(&element.children)
+        .find(|o| o.name == "LossFunc")
+        .ok_or(XmlError::ElementNotFound("LossFunc"))?;
+    let layer = iter_elements(&element.children)
+        .find(|o| o.name == "Layer")
+        .ok_or(XmlError::ElementNotFound("Layer"))?;
+
+    Ok(HeadConfig {
+        name,
+        layer: load_main_layer(layer).map_err(|e| e.at(&layer.name))?,
+        loss_func: load_loss_func(loss_func).map_err(|e| e.at(&loss_func.name))?,
+        weight: get_f32_attr(element, "weight").unwrap_or(1.0),
+    })
+}
+
+/// Load the only child of **element**, like the one of `<Layer>` or `<Projection>`
 fn load_main_layer(element: &Element) -> Result<Layer> {
     let child = load_single_child(element)?;
-    load_layer(child)
+    load_layer(child).map_err(|e| e.at(&child.name))
+}
+
+/// Load all children of **element**, like the ones of `<Sequential>`
+fn load_child_layers(element: &Element) -> Result<Vec<Layer>> {
+    iter_elements(&element.children)
+        .enumerate()
+        .map(|(index, e)| load_layer(e).map_err(|err| err.at(format!("{}[{}]", e.name, index))))
+        .collect()
+}
+
+/// Load the learning rate calculator inside the child of **element** called **name**, like `<WeightsLr>`
+fn load_child_lr(element: &Element, name: &'static str) -> Result<LrCalc> {
+    let child = iter_elements(&element.children)
+        .find(|o| o.name == name)
+        .ok_or(XmlError::ElementNotFound(name))?;
+    load_lr(child).map_err(|e| e.at(name))
 }
 
 fn load_layer(element: &Element) -> Result<Layer> {
     use crate::nn::layers::*;
-    use crate::nn::layers::filtering::{convolution, max_pool};
+    use crate::nn::layers::filtering::{convolution, conv_transpose, max_pool, avg_pool};
+    use crate::nn::layers::activation::*;
     match element.name.as_str() {
         "Sequential" => {
-            let mut layers = Vec::new();
-            for e in iter_elements(&element.children) {
-                layers.push(load_layer(e)?)
-            }
             Ok(Layer::Sequential(sequential_layer::SequentialConfig {
-                layers,
+                layers: load_child_layers(element)?,
+                train_options: load_train_options(element)?,
             }))
         }
         "Dense" => {
-            let weights_lr = iter_elements(&element.children)
-                .find(|o| o.name == "WeightsLr")
-                .ok_or(XmlError::ElementNotFound("WeightsLr"))?;
-            let biases_lr = iter_elements(&element.children)
-                .find(|o| o.name == "BiasesLr")
-                .ok_or(XmlError::ElementNotFound("BiasesLr"))?;
-
             Ok(Layer::Dense(dense_layer::DenseConfig {
                 in_values: get_usize_attr(element, "in_values")?,
                 out_values: get_usize_attr(element, "out_values")?,
                 init_mode: dense_layer::DenseLayerInit::Random(),
-                weights_lr_calc: load_lr(weights_lr)?,
-                biases_lr_calc: load_lr(biases_lr)?,
+                weights_lr_calc: load_child_lr(element, "WeightsLr")?,
+                biases_lr_calc: load_child_lr(element, "BiasesLr")?,
+                train_options: load_train_options(element)?,
             }))
         }
         "Convolution" => {
-            let kernels_lr = iter_elements(&element.children)
-                .find(|o| o.name == "KernelsLr")
-                .ok_or(XmlError::ElementNotFound("KernelsLr"))?;
+            let dilation = get_usize_attr(element, "dilation").unwrap_or(1);
+            if dilation == 0 {
+                return Err(XmlError::AttributeParseError(element.name.clone(), "dilation", dilation.to_string()));
+            }
 
             Ok(Layer::Convolution(convolution::ConvolutionConfig {
                 in_channels: get_usize_attr(element, "in_channels")?,
@@ -141,9 +213,22 @@ fn load_layer(element: &Element) -> Result<Layer> {
                 kernel_size: get_usize_attr(element, "kernel_size")?,
                 stride: get_usize_attr(element, "stride")?,
                 padding: get_usize_attr(element, "padding")?,
+                dilation,
                 init_mode: convolution::ConvolutionInitMode::HeNormal(),
-                lr_calc: load_lr(kernels_lr)?,
+                lr_calc: load_child_lr(element, "KernelsLr")?,
                 cache: get_bool_attr(element, "cache"),
+                train_options: load_train_options(element)?,
+            }))
+        }
+        "ConvTranspose" => {
+            Ok(Layer::ConvTranspose(conv_transpose::ConvTransposeConfig {
+                in_channels: get_usize_attr(element, "in_channels")?,
+                out_channels: get_usize_attr(element, "out_channels")?,
+                kernel_size: get_usize_attr(element, "kernel_size")?,
+                stride: get_usize_attr(element, "stride")?,
+                padding: get_usize_attr(element, "padding").unwrap_or(0),
+                init_mode: convolution::ConvolutionInitMode::HeNormal(),
+                lr_calc: load_child_lr(element, "KernelsLr")?,
             }))
         }
         "MaxPool" => {
@@ -153,6 +238,16 @@ fn load_layer(element: &Element) -> Result<Layer> {
                 padding: get_usize_attr(element, "padding")?,
             }))
         }
+        "AvgPool" => {
+            Ok(Layer::AvgPool(avg_pool::AvgPoolConfig {
+                size: get_usize_attr(element, "size")?,
+                stride: get_usize_attr(element, "stride")?,
+                padding: get_usize_attr(element, "padding").unwrap_or(0),
+            }))
+        }
+        "GlobalAvgPool" => {
+            Ok(Layer::GlobalAvgPool)
+        }
         "Debug" => {
             let action = get_string_attr(element, "action")?;
             Ok(Layer::Debug(debug_layer::DebugLayerConfig {
@@ -167,13 +262,26 @@ fn load_layer(element: &Element) -> Result<Layer> {
             }))
         }
         "Concat" => {
+            Ok(Layer::Concat(concat_layer::ConcatConfig {
+                layers: load_child_layers(element)?,
+                dim: get_usize_attr(element, "dim")?,
+                train_options: load_train_options(element)?,
+            }))
+        }
+        "Residual" => {
             let mut layers = Vec::new();
-            for e in iter_elements(&element.children) {
-                layers.push(load_layer(e)?)
+            let mut projection = None;
+            for (index, e) in iter_elements(&element.children).enumerate() {
+                let at = |err: XmlError| err.at(format!("{}[{}]", e.name, index));
+                if e.name == "Projection" {
+                    projection = Some(Box::new(load_main_layer(e).map_err(at)?));
+                } else {
+                    layers.push(load_layer(e).map_err(at)?)
+                }
             }
-            Ok(Layer::Concat(concat_layer::ConcatConfig {
+            Ok(Layer::Residual(residual_layer::ResidualConfig {
                 layers,
-                dim: get_usize_attr(element, "dim")?,
+                projection,
             }))
         }
         "Relu" => {
@@ -182,6 +290,24 @@ fn load_layer(element: &Element) -> Result<Layer> {
         "Tanh" => {
             Ok(Layer::Tanh)
         }
+        "LeakyRelu" => {
+            Ok(Layer::LeakyRelu(leaky_relu_layer::LeakyReluConfig {
+                alpha: get_f32_attr(element, "alpha").unwrap_or(0.01),
+            }))
+        }
+        "Elu" => {
+            Ok(Layer::Elu(elu_layer::EluConfig {
+                alpha: get_f32_attr(element, "alpha").unwrap_or(1.0),
+            }))
+        }
+        "Gelu" => {
+            Ok(Layer::Gelu)
+        }
+        "Softmax" => {
+            Ok(Layer::Softmax(softmax_layer::SoftmaxConfig {
+                axis: get_usize_attr(element, "axis").unwrap_or(0),
+            }))
+        }
         "Sigmoid" => {
             Ok(Layer::Sigmoid)
         }
@@ -201,13 +327,26 @@ fn load_layer(element: &Element) -> Result<Layer> {
         "TwoComplementsTransformer" => {
             Ok(Layer::TwoComplementsTransformer)
         }
+        "BatchNorm" => {
+            Ok(Layer::BatchNorm(batch_norm_layer::BatchNormConfig {
+                channels: get_usize_attr(element, "channels")?,
+                momentum: get_f32_attr(element, "momentum").unwrap_or(0.9),
+                epsilon: get_f32_attr(element, "epsilon").unwrap_or(0.00001),
+                gamma_lr_calc: load_child_lr(element, "GammaLr")?,
+                beta_lr_calc: load_child_lr(element, "BetaLr")?,
+            }))
+        }
         _ => Err(XmlError::UnexpectedTag(element.name.clone())),
     }
 }
 
 fn load_lr(element: &Element) -> Result<LrCalc> {
+    let child = load_single_child(element)?;
+    load_lr_calc(child).map_err(|e| e.at(&child.name))
+}
+
+fn load_lr_calc(element: &Element) -> Result<LrCalc> {
     use crate::nn::lr_calculators::*;
-    let element = load_single_child(element)?;
     match element.name.as_str() {
         "Adam" => {
             let mut config = adam_lr::AdamConfig::default();
@@ -221,10 +360,71 @@ fn load_lr(element: &Element) -> Result<LrCalc> {
             if let Ok(v) = get_f32_attr(element, "lr") { config.lr = v };
             Ok(LrCalc::Constant(config))
         }
+        "Momentum" => {
+            let mut config = momentum_lr::MomentumConfig::default();
+            if let Ok(v) = get_f32_attr(element, "lr") { config.lr = v };
+            if let Ok(v) = get_f32_attr(element, "momentum") { config.momentum = v };
+            Ok(LrCalc::Momentum(config))
+        }
+        "RmsProp" => {
+            let mut config = rms_prop_lr::RmsPropConfig::default();
+            if let Ok(v) = get_f32_attr(element, "alpha") { config.alpha = v };
+            if let Ok(v) = get_f32_attr(element, "decay") { config.decay = v };
+            Ok(LrCalc::RmsProp(config))
+        }
+        "AdamW" => {
+            let mut config = adam_w_lr::AdamWConfig::default();
+            if let Ok(v) = get_f32_attr(element, "alpha") { config.adam.alpha = v };
+            if let Ok(v) = get_f32_attr(element, "decay1") { config.adam.decay1 = v };
+            if let Ok(v) = get_f32_attr(element, "decay2") { config.adam.decay2 = v };
+            if let Ok(v) = get_f32_attr(element, "decay") { config.weight_decay = v };
+            Ok(LrCalc::AdamW(config))
+        }
+        "Scheduled" => {
+            let mut schedules = Vec::new();
+            let mut inner = Vec::new();
+            for (index, e) in iter_elements(&element.children).enumerate() {
+                let at = |err: XmlError| err.at(format!("{}[{}]", e.name, index));
+                match load_lr_schedule(e).map_err(at)? {
+                    Some(v) => schedules.push(v),
+                    None => inner.push(load_lr_calc(e).map_err(at)?),
+                }
+            }
+
+            if inner.len() != 1 {
+                return Err(XmlError::UnexpectedChildCount(element.name.clone(), 1, inner.len() as u32));
+            }
+            Ok(LrCalc::Scheduled(lr_schedule::ScheduledLrConfig {
+                schedules,
+                inner: Box::new(inner.remove(0)),
+            }))
+        }
         _ => Err(XmlError::UnexpectedTag(element.name.clone())),
     }
 }
 
+/// Returns None if the element isn't a schedule
+fn load_lr_schedule(element: &Element) -> Result<Option<LrSchedule>> {
+    Ok(Some(match element.name.as_str() {
+        "LinearWarmup" => LrSchedule::LinearWarmup {
+            steps: get_usize_attr(element, "steps")? as u32,
+        },
+        "StepDecay" => LrSchedule::StepDecay {
+            step_size: get_usize_attr(element, "step_size")? as u32,
+            gamma: get_f32_attr(element, "gamma")?,
+        },
+        "ExponentialDecay" => LrSchedule::ExponentialDecay {
+            gamma: get_f32_attr(element, "gamma")?,
+        },
+        "CosineAnnealing" => LrSchedule::CosineAnnealing {
+            period: get_usize_attr(element, "period")? as u32,
+            min_factor: get_f32_attr(element, "min_factor").unwrap_or(0.0),
+            period_mult: get_f32_attr(element, "period_mult").unwrap_or(1.0),
+        },
+        _ => return Ok(None),
+    }))
+}
+
 fn load_single_child(element: &Element) -> Result<&Element> {
     let mut result = None;
     let mut count = 0;
@@ -245,6 +445,18 @@ fn load_single_child(element: &Element) -> Result<&Element> {
     }
 }
 
+/// The `frozen` and `lr_multiplier` attributes, that are optional
+fn load_train_options(element: &Element) -> Result<TrainOptions> {
+    let lr_multiplier = match element.attributes.get("lr_multiplier") {
+        Some(_) => get_f32_attr(element, "lr_multiplier")?,
+        None => 1.0,
+    };
+    if lr_multiplier < 0.0 {
+        return Err(XmlError::AttributeParseError(element.name.clone(), "lr_multiplier", lr_multiplier.to_string()));
+    }
+    Ok(TrainOptions { frozen: get_bool_attr(element, "frozen"), lr_multiplier })
+}
+
 fn iter_elements(elements: &[xmltree::XMLNode]) -> impl Iterator<Item=&Element> {
     elements.iter().filter_map(|o| o.as_element())
 }
@@ -291,8 +503,10 @@ fn get_f32_attr(element: &Element, name: &'static str) -> Result<f32> {
 #[cfg(test)]
 mod tests {
     use crate::nn::layers::nn_layers::Layer;
+    use crate::nn::loss::loss_func::LossFunc;
+    use crate::nn::lr_calculators::lr_calculator::LrCalc;
 
-    use super::load_model_xml;
+    use super::{load_model_xml, XmlError};
 
     #[test]
     fn test1() {
@@ -335,4 +549,237 @@ mod tests {
 
         assert_eq!(correct, Some(784))
     }
+
+    #[test]
+    fn test_batch_norm() {
+        let str = r###"<AIModel>
+    <LossFunc>
+        <Mse/>
+    </LossFunc>
+    <Layer>
+        <BatchNorm channels="16" momentum="0.8">
+            <GammaLr>
+                <Adam/>
+            </GammaLr>
+            <BetaLr>
+                <Constant/>
+            </BetaLr>
+        </BatchNorm>
+    </Layer>
+</AIModel>
+"###;
+        let result = load_model_xml(str.as_bytes()).unwrap();
+        match result.main_layer {
+            Layer::BatchNorm(c) => {
+                assert_eq!(c.channels, 16);
+                assert_eq!(c.momentum, 0.8);
+                assert_eq!(c.epsilon, 0.00001);
+            }
+            _ => panic!("Expected BatchNorm"),
+        }
+    }
+
+    #[test]
+    fn test_residual() {
+        let str = r###"<AIModel>
+    <LossFunc>
+        <Mse/>
+    </LossFunc>
+    <Layer>
+        <Residual>
+            <Relu/>
+            <Tanh/>
+            <Projection>
+                <Sigmoid/>
+            </Projection>
+        </Residual>
+    </Layer>
+</AIModel>
+"###;
+        let result = load_model_xml(str.as_bytes()).unwrap();
+        match result.main_layer {
+            Layer::Residual(c) => {
+                assert_eq!(c.layers.len(), 2);
+                assert!(matches!(c.projection.as_deref(), Some(Layer::Sigmoid)));
+            }
+            _ => panic!("Expected Residual"),
+        }
+    }
+
+    #[test]
+    fn test_lr_calcs() {
+        let str = r###"<AIModel>
+    <LossFunc>
+        <Mse/>
+    </LossFunc>
+    <Layer>
+        <Convolution in_channels="1" out_channels="2" kernel_size="3" stride="1" padding="0">
+            <KernelsLr>
+                <AdamW decay="0.02"/>
+            </KernelsLr>
+        </Convolution>
+    </Layer>
+</AIModel>
+"###;
+        let result = load_model_xml(str.as_bytes()).unwrap();
+        match result.main_layer {
+            Layer::Convolution(c) => match c.lr_calc {
+                LrCalc::AdamW(c) => {
+                    assert_eq!(c.weight_decay, 0.02);
+                    assert_eq!(c.adam.alpha, 0.001);
+                }
+                _ => panic!("Expected AdamW"),
+            },
+            _ => panic!("Expected Convolution"),
+        }
+    }
+
+    #[test]
+    fn test_lr_schedule() {
+        let str = r###"<AIModel>
+    <LossFunc>
+        <Mse/>
+    </LossFunc>
+    <Layer>
+        <Convolution in_channels="1" out_channels="2" kernel_size="3" stride="1" padding="0">
+            <KernelsLr>
+                <Scheduled>
+                    <LinearWarmup steps="100"/>
+                    <CosineAnnealing period="1000" period_mult="2"/>
+                    <Adam/>
+                </Scheduled>
+            </KernelsLr>
+        </Convolution>
+    </Layer>
+</AIModel>
+"###;
+        let result = load_model_xml(str.as_bytes()).unwrap();
+        match result.main_layer {
+            Layer::Convolution(c) => match c.lr_calc {
+                LrCalc::Scheduled(c) => {
+                    assert_eq!(c.schedules.len(), 2);
+                    assert!(matches!(c.inner.as_ref(), LrCalc::Adam(_)));
+                }
+                _ => panic!("Expected Scheduled"),
+            },
+            _ => panic!("Expected Convolution"),
+        }
+    }
+
+    #[test]
+    fn test_conv_dilation_and_transpose() {
+        let str = r###"<AIModel>
+    <LossFunc>
+        <Mse/>
+    </LossFunc>
+    <Layer>
+        <Sequential>
+            <Convolution in_channels="1" out_channels="2" kernel_size="3" stride="1" padding="2" dilation="2">
+                <KernelsLr>
+                    <Adam/>
+                </KernelsLr>
+            </Convolution>
+            <ConvTranspose in_channels="2" out_channels="1" kernel_size="2" stride="2">
+                <KernelsLr>
+                    <Adam/>
+                </KernelsLr>
+            </ConvTranspose>
+        </Sequential>
+    </Layer>
+</AIModel>
+"###;
+        let result = load_model_xml(str.as_bytes()).unwrap();
+        match result.main_layer {
+            Layer::Sequential(c) => {
+                assert!(matches!(&c.layers[0], Layer::Convolution(c) if c.dilation == 2));
+                assert!(matches!(&c.layers[1], Layer::ConvTranspose(c) if c.stride == 2 && c.padding == 0));
+            }
+            _ => panic!("Expected Sequential"),
+        }
+    }
+
+    #[test]
+    fn test_heads() {
+        let str = r###"<AIModel>
+    <Layer>
+        <Dense in_values="4" out_values="8">
+            <WeightsLr><Adam/></WeightsLr>
+            <BiasesLr><Adam/></BiasesLr>
+        </Dense>
+    </Layer>
+    <Head name="value" weight="0.5">
+        <LossFunc><Mse/></LossFunc>
+        <Layer><Tanh/></Layer>
+    </Head>
+    <Head name="policy">
+        <LossFunc><CrossEntropy/></LossFunc>
+        <Layer><Softmax/></Layer>
+    </Head>
+</AIModel>
+"###;
+        let result = load_model_xml(str.as_bytes()).unwrap();
+        assert_eq!(result.heads.len(), 2);
+        assert_eq!(result.heads[0].name, "value");
+        assert_eq!(result.heads[0].weight, 0.5);
+        assert!(matches!(result.heads[1].loss_func, LossFunc::CrossEntropy));
+        assert_eq!(result.heads[1].weight, 1.0);
+    }
+
+    #[test]
+    fn test_train_options() {
+        let str = r###"<AIModel>
+    <LossFunc><Mse/></LossFunc>
+    <Layer>
+        <Sequential frozen="true">
+            <Concat dim="0" lr_multiplier="0.5"><Relu/></Concat>
+            <Relu/>
+        </Sequential>
+    </Layer>
+</AIModel>
+"###;
+        let result = load_model_xml(str.as_bytes()).unwrap();
+        match result.main_layer {
+            Layer::Sequential(c) => {
+                assert!(c.train_options.frozen);
+                assert_eq!(c.train_options.lr_multiplier, 1.0);
+                assert!(matches!(&c.layers[0], Layer::Concat(c) if c.train_options.lr_multiplier == 0.5 && !c.train_options.frozen));
+            }
+            _ => panic!("Expected Sequential"),
+        }
+
+        let str = str.replace("0.5", "-1");
+        let error = load_model_xml(str.as_bytes()).unwrap_err();
+        assert_eq!(error.path(), Some("AIModel/Layer/Sequential/Concat[0]"));
+    }
+
+    #[test]
+    fn test_malformed() {
+        let result = load_model_xml("<AIModel><Layer></AIModel>".as_bytes());
+        assert!(matches!(result, Err(XmlError::ParseError(_))));
+    }
+
+    #[test]
+    fn test_error_path() {
+        let str = r###"<AIModel>
+    <LossFunc><Mse/></LossFunc>
+    <Layer>
+        <Sequential>
+            <Relu/>
+            <Dense in_values="4" out_values="8">
+                <WeightsLr><Adam alpha="0.1"/></WeightsLr>
+                <BiasesLr>
+                    <Scheduled>
+                        <StepDecay step_size="10"/>
+                        <Adam/>
+                    </Scheduled>
+                </BiasesLr>
+            </Dense>
+        </Sequential>
+    </Layer>
+</AIModel>
+"###;
+        let error = load_model_xml(str.as_bytes()).unwrap_err();
+        assert_eq!(error.path(), Some("AIModel/Layer/Sequential/Dense[1]/BiasesLr/Scheduled/StepDecay[0]"));
+        assert!(error.to_string().starts_with("Attribute 'gamma' not found in <StepDecay>"));
+    }
 }
@@ -0,0 +1,300 @@
+use crate::integration::layers_loading::{ModelXmlConfig, XmlError};
+use crate::nn::{
+    controller::HeadConfig, layers::nn_layers::{Layer, TrainOptions}, loss::loss_func::LossFunc,
+    lr_calculators::lr_calculator::LrCalc, lr_calculators::lr_schedule::LrSchedule,
+};
+use xmltree::{Element, EmitterConfig, XMLNode};
+
+type Result<T> = std::result::Result<T, XmlError>;
+
+/// Write **config** in the format read by **load_model_xml**.
+/// Every attribute is written, even the ones with a default value.
+/// Initialization modes aren't part of the format, so they are lost
+pub fn write_model_xml(config: &ModelXmlConfig) -> Result<String> {
+    let mut root = Element::new("AIModel");
+    root.children.push(XMLNode::Element(loss_func_element(&config.loss_func)));
+    root.children.push(XMLNode::Element(wrap("Layer", layer_element(&config.main_layer)?)));
+    for head in config.heads.iter() {
+        root.children.push(XMLNode::Element(head_element(head)?));
+    }
+    write_element(&root)
+}
+
+/// Write a single layer, without the `<Layer>` wrapper
+pub fn write_layer_xml(layer: &Layer) -> Result<String> {
+    write_element(&layer_element(layer)?)
+}
+
+fn write_element(element: &Element) -> Result<String> {
+    let mut bytes = Vec::new();
+    let config = EmitterConfig::new().perform_indent(true);
+    element
+        .write_with_config(&mut bytes, config)
+        .map_err(|e| XmlError::NotWritable(e.to_string()))?;
+    String::from_utf8(bytes).map_err(|e| XmlError::NotWritable(e.to_string()))
+}
+
+fn head_element(head: &HeadConfig) -> Result<Element> {
+    let mut element = Element::new("Head");
+    set_attr(&mut element, "name", &head.name);
+    set_attr(&mut element, "weight", head.weight);
+    element.children.push(XMLNode::Element(loss_func_element(&head.loss_func)));
+    element.children.push(XMLNode::Element(wrap("Layer", layer_element(&head.layer)?)));
+    Ok(element)
+}
+
+fn loss_func_element(loss_func: &LossFunc) -> Element {
+    let name = match loss_func {
+        LossFunc::Mse => "Mse",
+        LossFunc::CrossEntropy => "CrossEntropy",
+    };
+    wrap("LossFunc", Element::new(name))
+}
+
+fn layer_element(layer: &Layer) -> Result<Element> {
+    use crate::nn::layers::debug_layer::DebugAction;
+
+    let mut element = Element::new(layer.tag_name());
+    match layer {
+        Layer::Sequential(c) => {
+            set_train_options(&mut element, &c.train_options);
+            push_layers(&mut element, &c.layers)?;
+        }
+        Layer::Dense(c) => {
+            set_attr(&mut element, "in_values", c.in_values);
+            set_attr(&mut element, "out_values", c.out_values);
+            set_train_options(&mut element, &c.train_options);
+            element.children.push(XMLNode::Element(wrap("WeightsLr", lr_element(&c.weights_lr_calc))));
+            element.children.push(XMLNode::Element(wrap("BiasesLr", lr_element(&c.biases_lr_calc))));
+        }
+        Layer::Convolution(c) => {
+            set_attr(&mut element, "in_channels", c.in_channels);
+            set_attr(&mut element, "out_channels", c.out_channels);
+            set_attr(&mut element, "kernel_size", c.kernel_size);
+            set_attr(&mut element, "stride", c.stride);
+            set_attr(&mut element, "padding", c.padding);
+            set_attr(&mut element, "dilation", c.dilation);
+            if c.cache {
+                set_attr(&mut element, "cache", true);
+            }
+            set_train_options(&mut element, &c.train_options);
+            element.children.push(XMLNode::Element(wrap("KernelsLr", lr_element(&c.lr_calc))));
+        }
+        Layer::ConvTranspose(c) => {
+            set_attr(&mut element, "in_channels", c.in_channels);
+            set_attr(&mut element, "out_channels", c.out_channels);
+            set_attr(&mut element, "kernel_size", c.kernel_size);
+            set_attr(&mut element, "stride", c.stride);
+            set_attr(&mut element, "padding", c.padding);
+            element.children.push(XMLNode::Element(wrap("KernelsLr", lr_element(&c.lr_calc))));
+        }
+        Layer::MaxPool(c) => {
+            set_attr(&mut element, "size", c.size);
+            set_attr(&mut element, "stride", c.stride);
+            set_attr(&mut element, "padding", c.padding);
+        }
+        Layer::AvgPool(c) => {
+            set_attr(&mut element, "size", c.size);
+            set_attr(&mut element, "stride", c.stride);
+            set_attr(&mut element, "padding", c.padding);
+        }
+        Layer::Debug(c) => {
+            let action = match c.action {
+                DebugAction::PrintShape => "print_shape",
+                DebugAction::PrintTime => "print_time",
+                DebugAction::PrintElapsed => "print_elapsed",
+                DebugAction::PrintArray => "print_array",
+                DebugAction::Call(..) => {
+                    return Err(XmlError::NotWritable(format!("Debug layer '{}' calls functions", c.tag)))
+                }
+            };
+            set_attr(&mut element, "tag", &c.tag);
+            set_attr(&mut element, "action", action);
+        }
+        Layer::Concat(c) => {
+            set_attr(&mut element, "dim", c.dim);
+            set_train_options(&mut element, &c.train_options);
+            push_layers(&mut element, &c.layers)?;
+        }
+        Layer::Residual(c) => {
+            push_layers(&mut element, &c.layers)?;
+            if let Some(projection) = &c.projection {
+                element.children.push(XMLNode::Element(wrap("Projection", layer_element(projection)?)));
+            }
+        }
+        Layer::LeakyRelu(c) => set_attr(&mut element, "alpha", c.alpha),
+        Layer::Elu(c) => set_attr(&mut element, "alpha", c.alpha),
+        Layer::Softmax(c) => set_attr(&mut element, "axis", c.axis),
+        Layer::ExpandDim(c) => set_attr(&mut element, "dim", c.dim),
+        Layer::Dropout(c) => set_attr(&mut element, "drop", c.drop),
+        Layer::BatchNorm(c) => {
+            set_attr(&mut element, "channels", c.channels);
+            set_attr(&mut element, "momentum", c.momentum);
+            set_attr(&mut element, "epsilon", c.epsilon);
+            element.children.push(XMLNode::Element(wrap("GammaLr", lr_element(&c.gamma_lr_calc))));
+            element.children.push(XMLNode::Element(wrap("BetaLr", lr_element(&c.beta_lr_calc))));
+        }
+        Layer::Relu | Layer::Tanh | Layer::Gelu | Layer::Sigmoid | Layer::Flatten
+        | Layer::GlobalAvgPool | Layer::TwoComplementsTransformer => {}
+    }
+    Ok(element)
+}
+
+fn lr_element(lr_calc: &LrCalc) -> Element {
+    match lr_calc {
+        LrCalc::Constant(c) => {
+            let mut element = Element::new("Constant");
+            set_attr(&mut element, "lr", c.lr);
+            element
+        }
+        LrCalc::Adam(c) => {
+            let mut element = Element::new("Adam");
+            set_attr(&mut element, "alpha", c.alpha);
+            set_attr(&mut element, "decay1", c.decay1);
+            set_attr(&mut element, "decay2", c.decay2);
+            element
+        }
+        LrCalc::Momentum(c) => {
+            let mut element = Element::new("Momentum");
+            set_attr(&mut element, "lr", c.lr);
+            set_attr(&mut element, "momentum", c.momentum);
+            element
+        }
+        LrCalc::RmsProp(c) => {
+            let mut element = Element::new("RmsProp");
+            set_attr(&mut element, "alpha", c.alpha);
+            set_attr(&mut element, "decay", c.decay);
+            element
+        }
+        LrCalc::AdamW(c) => {
+            let mut element = Element::new("AdamW");
+            set_attr(&mut element, "alpha", c.adam.alpha);
+            set_attr(&mut element, "decay1", c.adam.decay1);
+            set_attr(&mut element, "decay2", c.adam.decay2);
+            set_attr(&mut element, "decay", c.weight_decay);
+            element
+        }
+        LrCalc::Scheduled(c) => {
+            let mut element = Element::new("Scheduled");
+            for schedule in c.schedules.iter() {
+                element.children.push(XMLNode::Element(schedule_element(schedule)));
+            }
+            element.children.push(XMLNode::Element(lr_element(&c.inner)));
+            element
+        }
+    }
+}
+
+fn schedule_element(schedule: &LrSchedule) -> Element {
+    match schedule {
+        LrSchedule::LinearWarmup { steps } => {
+            let mut element = Element::new("LinearWarmup");
+            set_attr(&mut element, "steps", steps);
+            element
+        }
+        LrSchedule::StepDecay { step_size, gamma } => {
+            let mut element = Element::new("StepDecay");
+            set_attr(&mut element, "step_size", step_size);
+            set_attr(&mut element, "gamma", gamma);
+            element
+        }
+        LrSchedule::ExponentialDecay { gamma } => {
+            let mut element = Element::new("ExponentialDecay");
+            set_attr(&mut element, "gamma", gamma);
+            element
+        }
+        LrSchedule::CosineAnnealing { period, min_factor, period_mult } => {
+            let mut element = Element::new("CosineAnnealing");
+            set_attr(&mut element, "period", period);
+            set_attr(&mut element, "min_factor", min_factor);
+            set_attr(&mut element, "period_mult", period_mult);
+            element
+        }
+    }
+}
+
+/// Like `cache`, the options are only written when they aren't the default
+fn set_train_options(element: &mut Element, options: &TrainOptions) {
+    if options.frozen {
+        set_attr(element, "frozen", true);
+    }
+    if options.lr_multiplier != 1.0 {
+        set_attr(element, "lr_multiplier", options.lr_multiplier);
+    }
+}
+
+fn push_layers(element: &mut Element, layers: &[Layer]) -> Result<()> {
+    for layer in layers.iter() {
+        element.children.push(XMLNode::Element(layer_element(layer)?));
+    }
+    Ok(())
+}
+
+fn wrap(name: &str, child: Element) -> Element {
+    let mut element = Element::new(name);
+    element.children.push(XMLNode::Element(child));
+    element
+}
+
+fn set_attr(element: &mut Element, name: &str, value: impl ToString) {
+    element.attributes.insert(name.to_owned(), value.to_string());
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::integration::layers_loading::load_model_xml;
+
+    use super::write_model_xml;
+
+    #[test]
+    fn test_round_trip() {
+        let str = r###"<AIModel>
+    <Layer>
+        <Sequential frozen="true">
+            <Convolution in_channels="1" out_channels="2" kernel_size="3" stride="1" padding="1" cache="true">
+                <KernelsLr>
+                    <Scheduled>
+                        <LinearWarmup steps="100"/>
+                        <CosineAnnealing period="1000" period_mult="2"/>
+                        <AdamW decay="0.02"/>
+                    </Scheduled>
+                </KernelsLr>
+            </Convolution>
+            <Residual>
+                <BatchNorm channels="2">
+                    <GammaLr><Momentum/></GammaLr>
+                    <BetaLr><Constant lr="0.1"/></BetaLr>
+                </BatchNorm>
+                <Projection><LeakyRelu alpha="0.2"/></Projection>
+            </Residual>
+            <Debug tag="conv" action="print_shape"/>
+            <Flatten/>
+            <Dense in_values="18" out_values="4" lr_multiplier="0.1">
+                <WeightsLr><RmsProp/></WeightsLr>
+                <BiasesLr><Adam alpha="0.01"/></BiasesLr>
+            </Dense>
+        </Sequential>
+    </Layer>
+    <Head name="value" weight="0.5">
+        <LossFunc><Mse/></LossFunc>
+        <Layer><Tanh/></Layer>
+    </Head>
+    <Head name="policy">
+        <LossFunc><CrossEntropy/></LossFunc>
+        <Layer><Softmax/></Layer>
+    </Head>
+</AIModel>
+"###;
+        let config = load_model_xml(str.as_bytes()).unwrap();
+        let written = write_model_xml(&config).unwrap();
+        assert!(written.contains(r#"<AdamW alpha="0.001" decay1="0.9" decay2="0.999" decay="0.02" />"#));
+        assert!(written.contains(r#"<BatchNorm channels="2" momentum="0.9" epsilon="0.00001">"#));
+        assert!(written.contains(r#"<Sequential frozen="true">"#));
+        assert!(written.contains(r#"<Dense in_values="18" out_values="4" lr_multiplier="0.1">"#));
+
+        let reloaded = load_model_xml(written.as_bytes()).unwrap();
+        assert_eq!(write_model_xml(&reloaded).unwrap(), written);
+        assert_eq!(reloaded.heads.len(), 2);
+        assert_eq!(reloaded.heads[0].weight, 0.5);
+    }
+}
@@ -1,6 +1,14 @@
 pub mod layers_loading;
+pub mod layers_writing;
+pub mod model_definition;
 pub mod model_deltas;
 pub mod serialization;
 pub mod deserialization;
 pub mod serde_utils;
-pub mod random_picker;
\ No newline at end of file
+pub mod compression;
+pub mod checkpoint;
+pub mod npz;
+pub mod onnx;
+pub mod random_picker;
+pub mod dataset;
+pub mod augmentation;
\ No newline at end of file
@@ -0,0 +1,692 @@
+use crate::integration::layers_loading::{ModelXmlConfig, XmlError};
+use crate::nn::{
+    controller::HeadConfig, layers::nn_layers::{Layer, TrainOptions}, loss::loss_func::LossFunc,
+    lr_calculators::lr_calculator::LrCalc, lr_calculators::lr_schedule::LrSchedule,
+};
+use serde::{Deserialize, Serialize};
+
+type Result<T> = std::result::Result<T, XmlError>;
+
+/// Same model as the XML format described in `docs/layers_config_types.dtd`.
+/// Elements become objects with a `type` field, attributes keep their names and
+/// children are in `layers`, `projection` or in the `*_lr` fields, like `weights_lr` for `<WeightsLr>`
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ModelDef {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    loss_func: Option<LossFuncDef>,
+    layer: LayerDef,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    heads: Vec<HeadDef>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct HeadDef {
+    name: String,
+    weight: Option<f32>,
+    loss_func: LossFuncDef,
+    layer: LayerDef,
+}
+
+#[derive(Serialize, Deserialize)]
+enum LossFuncDef {
+    Mse,
+    CrossEntropy,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", deny_unknown_fields)]
+enum LayerDef {
+    Sequential {
+        layers: Vec<LayerDef>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        frozen: Option<bool>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        lr_multiplier: Option<f32>,
+    },
+    Dense {
+        in_values: usize,
+        out_values: usize,
+        weights_lr: LrDef,
+        biases_lr: LrDef,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        frozen: Option<bool>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        lr_multiplier: Option<f32>,
+    },
+    Convolution {
+        in_channels: usize,
+        out_channels: usize,
+        kernel_size: usize,
+        stride: usize,
+        padding: usize,
+        dilation: Option<usize>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        cache: Option<bool>,
+        kernels_lr: LrDef,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        frozen: Option<bool>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        lr_multiplier: Option<f32>,
+    },
+    ConvTranspose {
+        in_channels: usize,
+        out_channels: usize,
+        kernel_size: usize,
+        stride: usize,
+        padding: Option<usize>,
+        kernels_lr: LrDef,
+    },
+    MaxPool {
+        size: usize,
+        stride: usize,
+        padding: usize,
+    },
+    AvgPool {
+        size: usize,
+        stride: usize,
+        padding: Option<usize>,
+    },
+    GlobalAvgPool,
+    Debug {
+        tag: String,
+        /// One of print_shape, print_time, print_elapsed or print_array
+        action: String,
+    },
+    Concat {
+        dim: usize,
+        layers: Vec<LayerDef>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        frozen: Option<bool>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        lr_multiplier: Option<f32>,
+    },
+    Residual {
+        layers: Vec<LayerDef>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        projection: Option<Box<LayerDef>>,
+    },
+    Relu,
+    Tanh,
+    LeakyRelu {
+        alpha: Option<f32>,
+    },
+    Elu {
+        alpha: Option<f32>,
+    },
+    Gelu,
+    Softmax {
+        axis: Option<usize>,
+    },
+    Sigmoid,
+    Flatten,
+    ExpandDim {
+        dim: usize,
+    },
+    Dropout {
+        drop: f32,
+    },
+    TwoComplementsTransformer,
+    BatchNorm {
+        channels: usize,
+        momentum: Option<f32>,
+        epsilon: Option<f32>,
+        gamma_lr: LrDef,
+        beta_lr: LrDef,
+    },
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", deny_unknown_fields)]
+enum LrDef {
+    Constant {
+        lr: Option<f32>,
+    },
+    Adam {
+        alpha: Option<f32>,
+        decay1: Option<f32>,
+        decay2: Option<f32>,
+    },
+    Momentum {
+        lr: Option<f32>,
+        momentum: Option<f32>,
+    },
+    RmsProp {
+        alpha: Option<f32>,
+        decay: Option<f32>,
+    },
+    AdamW {
+        alpha: Option<f32>,
+        decay1: Option<f32>,
+        decay2: Option<f32>,
+        decay: Option<f32>,
+    },
+    Scheduled {
+        #[serde(default)]
+        schedules: Vec<ScheduleDef>,
+        inner: Box<LrDef>,
+    },
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", deny_unknown_fields)]
+enum ScheduleDef {
+    LinearWarmup {
+        steps: u32,
+    },
+    StepDecay {
+        step_size: u32,
+        gamma: f32,
+    },
+    ExponentialDecay {
+        gamma: f32,
+    },
+    CosineAnnealing {
+        period: u32,
+        min_factor: Option<f32>,
+        period_mult: Option<f32>,
+    },
+}
+
+pub fn load_model_json(bytes: &[u8]) -> Result<ModelXmlConfig> {
+    let def: ModelDef = serde_json::from_slice(bytes)
+        .map_err(|e| XmlError::ParseError(e.to_string()))?;
+    model_from_def(def)
+}
+
+pub fn load_model_toml(text: &str) -> Result<ModelXmlConfig> {
+    let def: ModelDef = toml::from_str(text)
+        .map_err(|e| XmlError::ParseError(e.to_string()))?;
+    model_from_def(def)
+}
+
+/// Like **write_model_xml**, every field is written, even the ones with a default value
+pub fn write_model_json(config: &ModelXmlConfig) -> Result<String> {
+    serde_json::to_string_pretty(&model_to_def(config)?)
+        .map_err(|e| XmlError::NotWritable(e.to_string()))
+}
+
+pub fn write_model_toml(config: &ModelXmlConfig) -> Result<String> {
+    toml::to_string_pretty(&model_to_def(config)?)
+        .map_err(|e| XmlError::NotWritable(e.to_string()))
+}
+
+/// Write a single layer, like the value of the `layer` field
+pub fn write_layer_json(layer: &Layer) -> Result<String> {
+    serde_json::to_string_pretty(&layer_to_def(layer)?)
+        .map_err(|e| XmlError::NotWritable(e.to_string()))
+}
+
+fn model_from_def(def: ModelDef) -> Result<ModelXmlConfig> {
+    let heads = def.heads
+        .into_iter()
+        .enumerate()
+        .map(|(index, o)| head_from_def(o).map_err(|e| e.at(format!("heads[{}]", index))))
+        .collect::<Result<Vec<_>>>()?;
+
+    // Models with heads have a loss function for each head
+    let loss_func = match def.loss_func {
+        Some(v) => loss_func_from_def(v),
+        None if !heads.is_empty() => LossFunc::Mse,
+        None => return Err(XmlError::ElementNotFound("loss_func")),
+    };
+    Ok(ModelXmlConfig {
+        loss_func,
+        main_layer: layer_from_def(def.layer).map_err(|e| e.at("layer"))?,
+        heads,
+    })
+}
+
+fn head_from_def(def: HeadDef) -> Result<HeadConfig> {
+    Ok(HeadConfig {
+        name: def.name,
+        layer: layer_from_def(def.layer).map_err(|e| e.at("layer"))?,
+        loss_func: loss_func_from_def(def.loss_func),
+        weight: def.weight.unwrap_or(1.0),
+    })
+}
+
+fn loss_func_from_def(def: LossFuncDef) -> LossFunc {
+    match def {
+        LossFuncDef::Mse => LossFunc::Mse,
+        LossFuncDef::CrossEntropy => LossFunc::CrossEntropy,
+    }
+}
+
+fn layers_from_def(defs: Vec<LayerDef>) -> Result<Vec<Layer>> {
+    defs.into_iter()
+        .enumerate()
+        .map(|(index, o)| layer_from_def(o).map_err(|e| e.at(format!("layers[{}]", index))))
+        .collect()
+}
+
+fn layer_from_def(def: LayerDef) -> Result<Layer> {
+    use crate::nn::layers::*;
+    use crate::nn::layers::filtering::{convolution, conv_transpose, max_pool, avg_pool};
+    use crate::nn::layers::activation::*;
+    Ok(match def {
+        LayerDef::Sequential { layers, frozen, lr_multiplier } => Layer::Sequential(sequential_layer::SequentialConfig {
+            layers: layers_from_def(layers)?,
+            train_options: train_options_from_def("Sequential", frozen, lr_multiplier)?,
+        }),
+        LayerDef::Dense { in_values, out_values, weights_lr, biases_lr, frozen, lr_multiplier } => Layer::Dense(dense_layer::DenseConfig {
+            in_values,
+            out_values,
+            init_mode: dense_layer::DenseLayerInit::Random(),
+            weights_lr_calc: lr_from_def(weights_lr),
+            biases_lr_calc: lr_from_def(biases_lr),
+            train_options: train_options_from_def("Dense", frozen, lr_multiplier)?,
+        }),
+        LayerDef::Convolution {
+            in_channels, out_channels, kernel_size, stride, padding, dilation, cache, kernels_lr, frozen, lr_multiplier
+        } => {
+            let dilation = dilation.unwrap_or(1);
+            if dilation == 0 {
+                return Err(XmlError::AttributeParseError("Convolution".to_owned(), "dilation", dilation.to_string()));
+            }
+            Layer::Convolution(convolution::ConvolutionConfig {
+                in_channels,
+                out_channels,
+                kernel_size,
+                stride,
+                padding,
+                dilation,
+                init_mode: convolution::ConvolutionInitMode::HeNormal(),
+                lr_calc: lr_from_def(kernels_lr),
+                cache: cache.unwrap_or(false),
+                train_options: train_options_from_def("Convolution", frozen, lr_multiplier)?,
+            })
+        }
+        LayerDef::ConvTranspose { in_channels, out_channels, kernel_size, stride, padding, kernels_lr } => {
+            Layer::ConvTranspose(conv_transpose::ConvTransposeConfig {
+                in_channels,
+                out_channels,
+                kernel_size,
+                stride,
+                padding: padding.unwrap_or(0),
+                init_mode: convolution::ConvolutionInitMode::HeNormal(),
+                lr_calc: lr_from_def(kernels_lr),
+            })
+        }
+        LayerDef::MaxPool { size, stride, padding } => Layer::MaxPool(max_pool::MaxPoolConfig { size, stride, padding }),
+        LayerDef::AvgPool { size, stride, padding } => Layer::AvgPool(avg_pool::AvgPoolConfig {
+            size,
+            stride,
+            padding: padding.unwrap_or(0),
+        }),
+        LayerDef::GlobalAvgPool => Layer::GlobalAvgPool,
+        LayerDef::Debug { tag, action } => Layer::Debug(debug_layer::DebugLayerConfig {
+            tag,
+            action: match action.to_lowercase().as_str() {
+                "print_shape" => debug_layer::DebugAction::PrintShape,
+                "print_time" => debug_layer::DebugAction::PrintTime,
+                "print_elapsed" => debug_layer::DebugAction::PrintElapsed,
+                "print_array" => debug_layer::DebugAction::PrintArray,
+                _ => return Err(XmlError::AttributeParseError("Debug".to_owned(), "action", action)),
+            },
+        }),
+        LayerDef::Concat { dim, layers, frozen, lr_multiplier } => Layer::Concat(concat_layer::ConcatConfig {
+            layers: layers_from_def(layers)?,
+            dim,
+            train_options: train_options_from_def("Concat", frozen, lr_multiplier)?,
+        }),
+        LayerDef::Residual { layers, projection } => Layer::Residual(residual_layer::ResidualConfig {
+            layers: layers_from_def(layers)?,
+            projection: match projection {
+                Some(v) => Some(Box::new(layer_from_def(*v).map_err(|e| e.at("projection"))?)),
+                None => None,
+            },
+        }),
+        LayerDef::Relu => Layer::Relu,
+        LayerDef::Tanh => Layer::Tanh,
+        LayerDef::LeakyRelu { alpha } => Layer::LeakyRelu(leaky_relu_layer::LeakyReluConfig {
+            alpha: alpha.unwrap_or(0.01),
+        }),
+        LayerDef::Elu { alpha } => Layer::Elu(elu_layer::EluConfig {
+            alpha: alpha.unwrap_or(1.0),
+        }),
+        LayerDef::Gelu => Layer::Gelu,
+        LayerDef::Softmax { axis } => Layer::Softmax(softmax_layer::SoftmaxConfig {
+            axis: axis.unwrap_or(0),
+        }),
+        LayerDef::Sigmoid => Layer::Sigmoid,
+        LayerDef::Flatten => Layer::Flatten,
+        LayerDef::ExpandDim { dim } => Layer::ExpandDim(expand_dim_layer::ExpandDimConfig { dim }),
+        LayerDef::Dropout { drop } => Layer::Dropout(dropout_layer::DropoutConfig { drop }),
+        LayerDef::TwoComplementsTransformer => Layer::TwoComplementsTransformer,
+        LayerDef::BatchNorm { channels, momentum, epsilon, gamma_lr, beta_lr } => {
+            Layer::BatchNorm(batch_norm_layer::BatchNormConfig {
+                channels,
+                momentum: momentum.unwrap_or(0.9),
+                epsilon: epsilon.unwrap_or(0.00001),
+                gamma_lr_calc: lr_from_def(gamma_lr),
+                beta_lr_calc: lr_from_def(beta_lr),
+            })
+        }
+    })
+}
+
+fn train_options_from_def(name: &str, frozen: Option<bool>, lr_multiplier: Option<f32>) -> Result<TrainOptions> {
+    let lr_multiplier = lr_multiplier.unwrap_or(1.0);
+    if lr_multiplier < 0.0 {
+        return Err(XmlError::AttributeParseError(name.to_owned(), "lr_multiplier", lr_multiplier.to_string()));
+    }
+    Ok(TrainOptions { frozen: frozen.unwrap_or(false), lr_multiplier })
+}
+
+fn lr_from_def(def: LrDef) -> LrCalc {
+    use crate::nn::lr_calculators::*;
+    match def {
+        LrDef::Constant { lr } => {
+            let mut config = constant_lr::ConstantLrConfig::default();
+            if let Some(v) = lr { config.lr = v };
+            LrCalc::Constant(config)
+        }
+        LrDef::Adam { alpha, decay1, decay2 } => {
+            let mut config = adam_lr::AdamConfig::default();
+            if let Some(v) = alpha { config.alpha = v };
+            if let Some(v) = decay1 { config.decay1 = v };
+            if let Some(v) = decay2 { config.decay2 = v };
+            LrCalc::Adam(config)
+        }
+        LrDef::Momentum { lr, momentum } => {
+            let mut config = momentum_lr::MomentumConfig::default();
+            if let Some(v) = lr { config.lr = v };
+            if let Some(v) = momentum { config.momentum = v };
+            LrCalc::Momentum(config)
+        }
+        LrDef::RmsProp { alpha, decay } => {
+            let mut config = rms_prop_lr::RmsPropConfig::default();
+            if let Some(v) = alpha { config.alpha = v };
+            if let Some(v) = decay { config.decay = v };
+            LrCalc::RmsProp(config)
+        }
+        LrDef::AdamW { alpha, decay1, decay2, decay } => {
+            let mut config = adam_w_lr::AdamWConfig::default();
+            if let Some(v) = alpha { config.adam.alpha = v };
+            if let Some(v) = decay1 { config.adam.decay1 = v };
+            if let Some(v) = decay2 { config.adam.decay2 = v };
+            if let Some(v) = decay { config.weight_decay = v };
+            LrCalc::AdamW(config)
+        }
+        LrDef::Scheduled { schedules, inner } => LrCalc::Scheduled(lr_schedule::ScheduledLrConfig {
+            schedules: schedules.into_iter().map(schedule_from_def).collect(),
+            inner: Box::new(lr_from_def(*inner)),
+        }),
+    }
+}
+
+fn schedule_from_def(def: ScheduleDef) -> LrSchedule {
+    match def {
+        ScheduleDef::LinearWarmup { steps } => LrSchedule::LinearWarmup { steps },
+        ScheduleDef::StepDecay { step_size, gamma } => LrSchedule::StepDecay { step_size, gamma },
+        ScheduleDef::ExponentialDecay { gamma } => LrSchedule::ExponentialDecay { gamma },
+        ScheduleDef::CosineAnnealing { period, min_factor, period_mult } => LrSchedule::CosineAnnealing {
+            period,
+            min_factor: min_factor.unwrap_or(0.0),
+            period_mult: period_mult.unwrap_or(1.0),
+        },
+    }
+}
+
+fn model_to_def(config: &ModelXmlConfig) -> Result<ModelDef> {
+    Ok(ModelDef {
+        loss_func: Some(loss_func_to_def(&config.loss_func)),
+        layer: layer_to_def(&config.main_layer)?,
+        heads: config.heads.iter()
+            .map(|o| Ok(HeadDef {
+                name: o.name.clone(),
+                weight: Some(o.weight),
+                loss_func: loss_func_to_def(&o.loss_func),
+                layer: layer_to_def(&o.layer)?,
+            }))
+            .collect::<Result<_>>()?,
+    })
+}
+
+fn loss_func_to_def(loss_func: &LossFunc) -> LossFuncDef {
+    match loss_func {
+        LossFunc::Mse => LossFuncDef::Mse,
+        LossFunc::CrossEntropy => LossFuncDef::CrossEntropy,
+    }
+}
+
+fn layers_to_def(layers: &[Layer]) -> Result<Vec<LayerDef>> {
+    layers.iter().map(layer_to_def).collect()
+}
+
+fn layer_to_def(layer: &Layer) -> Result<LayerDef> {
+    use crate::nn::layers::debug_layer::DebugAction;
+    Ok(match layer {
+        Layer::Sequential(c) => {
+            let (frozen, lr_multiplier) = train_options_to_def(&c.train_options);
+            LayerDef::Sequential { layers: layers_to_def(&c.layers)?, frozen, lr_multiplier }
+        }
+        Layer::Dense(c) => {
+            let (frozen, lr_multiplier) = train_options_to_def(&c.train_options);
+            LayerDef::Dense {
+                in_values: c.in_values,
+                out_values: c.out_values,
+                weights_lr: lr_to_def(&c.weights_lr_calc),
+                biases_lr: lr_to_def(&c.biases_lr_calc),
+                frozen,
+                lr_multiplier,
+            }
+        }
+        Layer::Convolution(c) => {
+            let (frozen, lr_multiplier) = train_options_to_def(&c.train_options);
+            LayerDef::Convolution {
+                in_channels: c.in_channels,
+                out_channels: c.out_channels,
+                kernel_size: c.kernel_size,
+                stride: c.stride,
+                padding: c.padding,
+                dilation: Some(c.dilation),
+                cache: if c.cache { Some(true) } else { None },
+                kernels_lr: lr_to_def(&c.lr_calc),
+                frozen,
+                lr_multiplier,
+            }
+        }
+        Layer::ConvTranspose(c) => LayerDef::ConvTranspose {
+            in_channels: c.in_channels,
+            out_channels: c.out_channels,
+            kernel_size: c.kernel_size,
+            stride: c.stride,
+            padding: Some(c.padding),
+            kernels_lr: lr_to_def(&c.lr_calc),
+        },
+        Layer::MaxPool(c) => LayerDef::MaxPool { size: c.size, stride: c.stride, padding: c.padding },
+        Layer::AvgPool(c) => LayerDef::AvgPool { size: c.size, stride: c.stride, padding: Some(c.padding) },
+        Layer::GlobalAvgPool => LayerDef::GlobalAvgPool,
+        Layer::Debug(c) => LayerDef::Debug {
+            tag: c.tag.clone(),
+            action: match c.action {
+                DebugAction::PrintShape => "print_shape",
+                DebugAction::PrintTime => "print_time",
+                DebugAction::PrintElapsed => "print_elapsed",
+                DebugAction::PrintArray => "print_array",
+                DebugAction::Call(..) => {
+                    return Err(XmlError::NotWritable(format!("Debug layer '{}' calls functions", c.tag)))
+                }
+            }.to_owned(),
+        },
+        Layer::Concat(c) => {
+            let (frozen, lr_multiplier) = train_options_to_def(&c.train_options);
+            LayerDef::Concat { dim: c.dim, layers: layers_to_def(&c.layers)?, frozen, lr_multiplier }
+        }
+        Layer::Residual(c) => LayerDef::Residual {
+            layers: layers_to_def(&c.layers)?,
+            projection: match &c.projection {
+                Some(v) => Some(Box::new(layer_to_def(v)?)),
+                None => None,
+            },
+        },
+        Layer::Relu => LayerDef::Relu,
+        Layer::Tanh => LayerDef::Tanh,
+        Layer::LeakyRelu(c) => LayerDef::LeakyRelu { alpha: Some(c.alpha) },
+        Layer::Elu(c) => LayerDef::Elu { alpha: Some(c.alpha) },
+        Layer::Gelu => LayerDef::Gelu,
+        Layer::Softmax(c) => LayerDef::Softmax { axis: Some(c.axis) },
+        Layer::Sigmoid => LayerDef::Sigmoid,
+        Layer::Flatten => LayerDef::Flatten,
+        Layer::ExpandDim(c) => LayerDef::ExpandDim { dim: c.dim },
+        Layer::Dropout(c) => LayerDef::Dropout { drop: c.drop },
+        Layer::TwoComplementsTransformer => LayerDef::TwoComplementsTransformer,
+        Layer::BatchNorm(c) => LayerDef::BatchNorm {
+            channels: c.channels,
+            momentum: Some(c.momentum),
+            epsilon: Some(c.epsilon),
+            gamma_lr: lr_to_def(&c.gamma_lr_calc),
+            beta_lr: lr_to_def(&c.beta_lr_calc),
+        },
+    })
+}
+
+/// Only the options that differ from the default are written
+fn train_options_to_def(options: &TrainOptions) -> (Option<bool>, Option<f32>) {
+    let frozen = if options.frozen { Some(true) } else { None };
+    let lr_multiplier = if options.lr_multiplier != 1.0 { Some(options.lr_multiplier) } else { None };
+    (frozen, lr_multiplier)
+}
+
+fn lr_to_def(lr_calc: &LrCalc) -> LrDef {
+    match lr_calc {
+        LrCalc::Constant(c) => LrDef::Constant { lr: Some(c.lr) },
+        LrCalc::Adam(c) => LrDef::Adam {
+            alpha: Some(c.alpha),
+            decay1: Some(c.decay1),
+            decay2: Some(c.decay2),
+        },
+        LrCalc::Momentum(c) => LrDef::Momentum { lr: Some(c.lr), momentum: Some(c.momentum) },
+        LrCalc::RmsProp(c) => LrDef::RmsProp { alpha: Some(c.alpha), decay: Some(c.decay) },
+        LrCalc::AdamW(c) => LrDef::AdamW {
+            alpha: Some(c.adam.alpha),
+            decay1: Some(c.adam.decay1),
+            decay2: Some(c.adam.decay2),
+            decay: Some(c.weight_decay),
+        },
+        LrCalc::Scheduled(c) => LrDef::Scheduled {
+            schedules: c.schedules.iter().map(schedule_to_def).collect(),
+            inner: Box::new(lr_to_def(&c.inner)),
+        },
+    }
+}
+
+fn schedule_to_def(schedule: &LrSchedule) -> ScheduleDef {
+    match *schedule {
+        LrSchedule::LinearWarmup { steps } => ScheduleDef::LinearWarmup { steps },
+        LrSchedule::StepDecay { step_size, gamma } => ScheduleDef::StepDecay { step_size, gamma },
+        LrSchedule::ExponentialDecay { gamma } => ScheduleDef::ExponentialDecay { gamma },
+        LrSchedule::CosineAnnealing { period, min_factor, period_mult } => ScheduleDef::CosineAnnealing {
+            period,
+            min_factor: Some(min_factor),
+            period_mult: Some(period_mult),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::integration::layers_loading::{load_model_xml, XmlError};
+    use crate::integration::layers_writing::write_model_xml;
+    use crate::nn::layers::nn_layers::Layer;
+    use crate::nn::lr_calculators::lr_calculator::LrCalc;
+
+    use super::{load_model_json, load_model_toml, write_model_json, write_model_toml};
+
+    const MODEL_XML: &str = r###"<AIModel>
+    <Layer>
+        <Sequential>
+            <Convolution in_channels="1" out_channels="2" kernel_size="3" stride="1" padding="1" dilation="2">
+                <KernelsLr>
+                    <Scheduled>
+                        <StepDecay step_size="10" gamma="0.5"/>
+                        <Momentum lr="0.02"/>
+                    </Scheduled>
+                </KernelsLr>
+            </Convolution>
+            <Residual>
+                <Relu/>
+                <Projection><Elu/></Projection>
+            </Residual>
+            <Concat dim="0" frozen="true" lr_multiplier="0.5">
+                <MaxPool size="2" stride="2" padding="0"/>
+                <AvgPool size="2" stride="2"/>
+            </Concat>
+            <Flatten/>
+            <Dense in_values="16" out_values="4">
+                <WeightsLr><AdamW/></WeightsLr>
+                <BiasesLr><Constant/></BiasesLr>
+            </Dense>
+        </Sequential>
+    </Layer>
+    <Head name="value" weight="0.25">
+        <LossFunc><Mse/></LossFunc>
+        <Layer><Dropout drop="0.1"/></Layer>
+    </Head>
+</AIModel>
+"###;
+
+    #[test]
+    fn test_round_trip() {
+        let config = load_model_xml(MODEL_XML.as_bytes()).unwrap();
+        let xml = write_model_xml(&config).unwrap();
+
+        let json = write_model_json(&config).unwrap();
+        assert!(json.contains(r#""frozen": true"#));
+        let from_json = load_model_json(json.as_bytes()).unwrap();
+        assert_eq!(write_model_xml(&from_json).unwrap(), xml);
+
+        let toml = write_model_toml(&config).unwrap();
+        let from_toml = load_model_toml(&toml).unwrap();
+        assert_eq!(write_model_xml(&from_toml).unwrap(), xml);
+    }
+
+    #[test]
+    fn test_defaults() {
+        let json = r#"{
+            "loss_func": "CrossEntropy",
+            "layer": {
+                "type": "Sequential",
+                "layers": [
+                    { "type": "LeakyRelu" },
+                    { "type": "Dense", "in_values": 3, "out_values": 2,
+                      "weights_lr": { "type": "Adam", "alpha": 0.01 }, "biases_lr": { "type": "Adam" } }
+                ]
+            }
+        }"#;
+        let config = load_model_json(json.as_bytes()).unwrap();
+        match config.main_layer {
+            Layer::Sequential(c) => {
+                assert!(matches!(&c.layers[0], Layer::LeakyRelu(c) if c.alpha == 0.01));
+                match &c.layers[1] {
+                    Layer::Dense(c) => {
+                        assert!(matches!(&c.weights_lr_calc, LrCalc::Adam(c) if c.alpha == 0.01 && c.decay1 == 0.9));
+                        assert!(matches!(&c.biases_lr_calc, LrCalc::Adam(c) if c.alpha == 0.001));
+                    }
+                    _ => panic!("Expected Dense"),
+                }
+            }
+            _ => panic!("Expected Sequential"),
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        let json = r#"{ "layer": { "type": "Relu" }, "loss_func": "Mse", "extra": 1 }"#;
+        assert!(matches!(load_model_json(json.as_bytes()), Err(XmlError::ParseError(_))));
+
+        let json = r#"{ "loss_func": "Mse", "layer": { "type": "Sequential", "layers": [
+            { "type": "Relu" },
+            { "type": "Convolution", "in_channels": 1, "out_channels": 1, "kernel_size": 3, "stride": 1,
+              "padding": 0, "dilation": 0, "kernels_lr": { "type": "Adam" } }
+        ] } }"#;
+        let error = load_model_json(json.as_bytes()).unwrap_err();
+        assert_eq!(error.path(), Some("layer/layers[1]"));
+
+        let toml = "loss_func = \"Mse\"\n[layer]\ntype = \"Dense\"\nin_values = 1\n";
+        assert!(matches!(load_model_toml(toml), Err(XmlError::ParseError(_))));
+    }
+}
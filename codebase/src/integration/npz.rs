@@ -0,0 +1,297 @@
+use std::io::{Cursor, Read, Write};
+use ndarray::{IxDyn, ShapeBuilder};
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+use zip::write::FileOptions;
+use crate::integration::layers_loading::ModelXmlConfig;
+use crate::nn::controller::NNController;
+use crate::nn::layers::nn_layers::GenericStorage;
+use crate::utils::{ArrayDynF, GenericResult};
+
+const NPY_MAGIC: &[u8] = b"\x93NUMPY";
+
+/// Name of the .npy file of an array inside the archive. NumPy exposes it without the extension,
+/// so `np.load(path)["dense_3_4_0/1"]` returns the biases of that layer
+fn entry_name(key: &str, index: usize) -> String {
+    format!("{}/{}.npy", key, index)
+}
+
+/// The opposite of **entry_name**
+fn parse_entry_name(name: &str) -> GenericResult<(String, usize)> {
+    name.strip_suffix(".npy")
+        .and_then(|o| o.rsplit_once('/'))
+        .and_then(|(key, index)| Some((key.to_owned(), index.parse().ok()?)))
+        .ok_or_else(|| anyhow::anyhow!("Invalid entry name {}, expected <key>/<index>.npy", name))
+}
+
+/// Write an array in the .npy format (version 1.0), as little-endian f32 in C order
+fn write_npy(result: &mut Vec<u8>, array: &ArrayDynF) {
+    let shape = match array.shape() {
+        [] => "()".to_owned(),
+        [len] => format!("({},)", len),
+        shape => format!("({})", shape.iter().map(|o| o.to_string()).collect::<Vec<_>>().join(", ")),
+    };
+    let mut header = format!("{{'descr': '<f4', 'fortran_order': False, 'shape': {}, }}", shape);
+    // The data must start at a multiple of 64 bytes, and the header must end with a newline
+    let prefix_len = NPY_MAGIC.len() + 4;
+    let padding = (64 - (prefix_len + header.len() + 1) % 64) % 64;
+    header.push_str(&" ".repeat(padding));
+    header.push('\n');
+
+    result.extend(NPY_MAGIC);
+    result.extend([1, 0]);
+    result.extend((header.len() as u16).to_le_bytes());
+    result.extend(header.as_bytes());
+    for value in array.iter() {
+        result.extend(value.to_le_bytes());
+    }
+}
+
+/// Value of **key** in the python dict of a .npy header
+fn header_value<'a>(header: &'a str, key: &str) -> GenericResult<&'a str> {
+    let pattern = format!("'{}':", key);
+    let start = header.find(&pattern)
+        .ok_or_else(|| anyhow::anyhow!("Missing {} in the header", key))? + pattern.len();
+    let value = header[start..].trim_start();
+    let end = match value.chars().next() {
+        Some('(') => value.find(')').map(|o| o + 1),
+        Some('\'') => value[1..].find('\'').map(|o| o + 2),
+        _ => value.find([',', '}']),
+    }.ok_or_else(|| anyhow::anyhow!("Invalid value of {} in the header", key))?;
+    Ok(&value[..end])
+}
+
+/// Read an array in the .npy format, with f4 or f8 values in any byte order and memory layout
+fn read_npy(bytes: &[u8]) -> GenericResult<ArrayDynF> {
+    if bytes.len() < 10 || &bytes[..6] != NPY_MAGIC {
+        return Err(anyhow::anyhow!("Not a .npy file"));
+    }
+    let (header_len, header_start) = match bytes[6] {
+        1 => (u16::from_le_bytes([bytes[8], bytes[9]]) as usize, 10),
+        2 | 3 if bytes.len() >= 12 => (u32::from_le_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]) as usize, 12),
+        version => return Err(anyhow::anyhow!("Unsupported .npy version {}", version)),
+    };
+    let data_start = header_start + header_len;
+    let header = bytes.get(header_start..data_start)
+        .ok_or_else(|| anyhow::anyhow!("The .npy header is truncated"))?;
+    let header = std::str::from_utf8(header)?;
+
+    let shape: Vec<usize> = header_value(header, "shape")?
+        .trim_matches(['(', ')'])
+        .split(',')
+        .map(str::trim)
+        .filter(|o| !o.is_empty())
+        .map(str::parse)
+        .collect::<Result<_, _>>()?;
+    let fortran_order = match header_value(header, "fortran_order")? {
+        "False" => false,
+        "True" => true,
+        value => return Err(anyhow::anyhow!("Invalid fortran_order {}", value)),
+    };
+
+    let data = &bytes[data_start..];
+    let values: Vec<f32> = match header_value(header, "descr")?.trim_matches('\'') {
+        "<f4" => data.chunks_exact(4).map(|o| f32::from_le_bytes(o.try_into().unwrap())).collect(),
+        ">f4" => data.chunks_exact(4).map(|o| f32::from_be_bytes(o.try_into().unwrap())).collect(),
+        "<f8" => data.chunks_exact(8).map(|o| f64::from_le_bytes(o.try_into().unwrap()) as f32).collect(),
+        ">f8" => data.chunks_exact(8).map(|o| f64::from_be_bytes(o.try_into().unwrap()) as f32).collect(),
+        descr => return Err(anyhow::anyhow!("Unsupported dtype {}, expected float32 or float64", descr)),
+    };
+
+    let shape = IxDyn(&shape);
+    let array = if fortran_order {
+        ArrayDynF::from_shape_vec(shape.f(), values)
+    } else {
+        ArrayDynF::from_shape_vec(shape, values)
+    }.map_err(|e| anyhow::anyhow!("The data doesn't match the shape in the header: {}", e))?;
+    Ok(array.as_standard_layout().into_owned())
+}
+
+/// Export the storage as a .npz archive (the format of `np.savez`), with one .npy array for each
+/// index of each key. Includes everything in the storage, like the state of the optimizers
+pub fn export_npz(storage: &GenericStorage) -> GenericResult<Vec<u8>> {
+    let mut keys: Vec<_> = storage.keys().collect();
+    keys.sort();
+
+    let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = FileOptions::default().compression_method(CompressionMethod::Stored);
+    let mut buffer = Vec::new();
+    for key in keys {
+        for (index, array) in storage[key].iter().enumerate() {
+            buffer.clear();
+            write_npy(&mut buffer, array);
+            writer.start_file(entry_name(key, index), options)?;
+            writer.write_all(&buffer)?;
+        }
+    }
+    Ok(writer.finish()?.into_inner())
+}
+
+/// Import a .npz archive written by **export_npz**, or by NumPy with the same entry names.
+/// Compressed archives (`np.savez_compressed`) are supported as well
+pub fn import_npz(bytes: &[u8]) -> GenericResult<GenericStorage> {
+    let mut archive = ZipArchive::new(Cursor::new(bytes))?;
+    let mut entries = Vec::with_capacity(archive.len());
+    let mut buffer = Vec::new();
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)?;
+        let (key, index) = parse_entry_name(file.name())?;
+        buffer.clear();
+        file.read_to_end(&mut buffer)?;
+        let array = read_npy(&buffer)
+            .map_err(|e| anyhow::anyhow!("Invalid array {} of key {}: {}", index, key, e))?;
+        entries.push((key, index, array));
+    }
+
+    entries.sort_by(|a, b| (&a.0, a.1).cmp(&(&b.0, b.1)));
+    let mut result = GenericStorage::new();
+    for (key, index, array) in entries {
+        let arrays = result.entry(key.clone()).or_default();
+        if arrays.len() != index {
+            return Err(anyhow::anyhow!("Array {} of key {} is missing", arrays.len(), key));
+        }
+        arrays.push(array);
+    }
+    Ok(result)
+}
+
+/// Check that **storage** has every parameter of the model defined by **config**, with the same
+/// shapes as the ones created by **NNController**. Keys that the model doesn't create when
+/// initialized, like the state of the optimizers, are not checked
+pub fn validate_storage(storage: &GenericStorage, config: &ModelXmlConfig) -> GenericResult<()> {
+    let controller = NNController::new_with_heads(config.main_layer.clone(), config.loss_func.clone(), config.heads.clone())?;
+    let expected = controller.export();
+
+    let mut keys: Vec<_> = expected.keys().collect();
+    keys.sort();
+    for key in keys {
+        let expected = &expected[key];
+        let arrays = storage.get(key)
+            .ok_or_else(|| anyhow::anyhow!("Key {} is missing", key))?;
+        if arrays.len() != expected.len() {
+            return Err(anyhow::anyhow!("Key {} has {} arrays, expected {}", key, arrays.len(), expected.len()));
+        }
+        for (index, (array, expected)) in arrays.iter().zip(expected.iter()).enumerate() {
+            if array.shape() != expected.shape() {
+                return Err(anyhow::anyhow!("Array {} of key {} has shape {:?}, expected {:?}",
+                    index, key, array.shape(), expected.shape()));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Import a .npz archive with **import_npz** and validate it with **validate_storage**
+pub fn import_npz_for_model(bytes: &[u8], config: &ModelXmlConfig) -> GenericResult<GenericStorage> {
+    let storage = import_npz(bytes)?;
+    validate_storage(&storage, config)?;
+    Ok(storage)
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::{array, Array0};
+    use crate::integration::layers_loading::load_model_xml;
+    use super::*;
+
+    const MODEL_XML: &str = r###"<AIModel>
+    <LossFunc>
+        <Mse/>
+    </LossFunc>
+    <Layer>
+        <Sequential>
+            <Dense in_values="3" out_values="4">
+                <WeightsLr>
+                    <Adam/>
+                </WeightsLr>
+                <BiasesLr>
+                    <Adam/>
+                </BiasesLr>
+            </Dense>
+            <Relu/>
+            <Dense in_values="4" out_values="2">
+                <WeightsLr>
+                    <Adam/>
+                </WeightsLr>
+                <BiasesLr>
+                    <Adam/>
+                </BiasesLr>
+            </Dense>
+        </Sequential>
+    </Layer>
+</AIModel>
+"###;
+
+    #[test]
+    fn test_integrity() {
+        let mut storage = GenericStorage::new();
+        storage.insert("conv".to_owned(), vec![
+            ArrayDynF::from_shape_fn(vec![2, 3, 4, 5], |o| (o[0] * 60 + o[1] * 20 + o[2] * 5 + o[3]) as f32 * 0.25),
+            ArrayDynF::from_shape_fn(vec![2], |o| o[0] as f32 - 1.0),
+        ]);
+        storage.insert("global_step".to_owned(), vec![Array0::from_elem((), 12.0).into_dyn()]);
+
+        let result = import_npz(&export_npz(&storage).unwrap()).unwrap();
+        assert_eq!(result, storage);
+    }
+
+    #[test]
+    fn test_npy_header() {
+        let mut bytes = Vec::new();
+        write_npy(&mut bytes, &array![[1.0, 2.0, 3.0], [4.0, 5.0, 6.0]].into_dyn());
+        let header = "{'descr': '<f4', 'fortran_order': False, 'shape': (2, 3), }";
+        assert_eq!(&bytes[..10], b"\x93NUMPY\x01\x00\x76\x00");
+        assert_eq!(&bytes[10..10 + header.len()], header.as_bytes());
+        assert_eq!(bytes.len(), 128 + 6 * 4);
+        assert_eq!(bytes[127], b'\n');
+    }
+
+    #[test]
+    fn test_read_npy() {
+        // Like np.asfortranarray(np.array([[1, 2, 3], [4, 5, 6]], dtype='>f8'))
+        let header = "{'descr': '>f8', 'fortran_order': True, 'shape': (2, 3), }          \n";
+        let mut bytes = Vec::new();
+        bytes.extend(NPY_MAGIC);
+        bytes.extend([1, 0]);
+        bytes.extend((header.len() as u16).to_le_bytes());
+        bytes.extend(header.as_bytes());
+        for value in [1.0f64, 4.0, 2.0, 5.0, 3.0, 6.0] {
+            bytes.extend(value.to_be_bytes());
+        }
+        assert_eq!(read_npy(&bytes).unwrap(), array![[1.0, 2.0, 3.0], [4.0, 5.0, 6.0]].into_dyn());
+
+        let header = header.replace(">f8", "<i4");
+        let mut bytes = bytes[..10].to_vec();
+        bytes.extend(header.as_bytes());
+        assert!(read_npy(&bytes).unwrap_err().to_string().contains("Unsupported dtype <i4"));
+    }
+
+    #[test]
+    fn test_validate() {
+        let config = load_model_xml(MODEL_XML.as_bytes()).unwrap();
+        let controller = NNController::new_with_heads(config.main_layer.clone(), config.loss_func.clone(), config.heads.clone()).unwrap();
+        let bytes = export_npz(&controller.export()).unwrap();
+        assert_eq!(import_npz_for_model(&bytes, &config).unwrap(), controller.export());
+
+        let mut storage = controller.export();
+        storage.get_mut("dense_4_2_0").unwrap()[1] = ArrayDynF::zeros(vec![3]);
+        let error = import_npz_for_model(&export_npz(&storage).unwrap(), &config).unwrap_err();
+        assert_eq!(error.to_string(), "Array 1 of key dense_4_2_0 has shape [3], expected [2]");
+
+        storage.remove("dense_3_4_0");
+        let error = import_npz_for_model(&export_npz(&storage).unwrap(), &config).unwrap_err();
+        assert_eq!(error.to_string(), "Key dense_3_4_0 is missing");
+    }
+
+    #[test]
+    fn test_missing_index() {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        let mut buffer = Vec::new();
+        write_npy(&mut buffer, &array![1.0].into_dyn());
+        writer.start_file("dense_0/1.npy", FileOptions::default()).unwrap();
+        writer.write_all(&buffer).unwrap();
+        let bytes = writer.finish().unwrap().into_inner();
+
+        let error = import_npz(&bytes).unwrap_err();
+        assert_eq!(error.to_string(), "Array 0 of key dense_0 is missing");
+    }
+}
@@ -0,0 +1,429 @@
+/// Subset of the messages of onnx.proto used by the exporter, with the same field numbers.
+/// https://github.com/onnx/onnx/blob/main/onnx/onnx.proto
+pub mod proto;
+
+use prost::Message;
+use crate::integration::layers_loading::ModelXmlConfig;
+use crate::nn::key_assigner::KeyAssigner;
+use crate::nn::layers::dense_layer;
+use crate::nn::layers::filtering::convolution;
+use crate::nn::layers::nn_layers::{GenericStorage, Layer};
+use crate::utils::{ArrayDynF, GenericResult};
+use self::proto::*;
+
+/// Version of the ONNX file format (1.10)
+const IR_VERSION: i64 = 8;
+/// Version of the default operator set. Unsqueeze receives the axes as an input since 13
+const OPSET_VERSION: i64 = 13;
+
+pub const INPUT_NAME: &str = "input";
+/// Name of the output of models without heads. Otherwise, each output has the name of its head
+pub const OUTPUT_NAME: &str = "output";
+
+/// Export the model defined by **config**, with the parameters in **storage**, as an ONNX model.
+/// **input_shape** is the shape of a single input, the batch dimension is added as a dynamic one.
+/// Returns an error if a layer can't be represented in ONNX, or its parameters aren't in **storage**
+pub fn export_onnx(config: &ModelXmlConfig, storage: &GenericStorage, input_shape: &[usize]) -> GenericResult<Vec<u8>> {
+    let mut builder = GraphBuilder::new(storage);
+    let main_output = builder.add_layer(&config.main_layer, INPUT_NAME.to_owned())?;
+
+    let mut outputs = Vec::new();
+    if config.heads.is_empty() {
+        builder.add_output(main_output, OUTPUT_NAME, &mut outputs);
+    } else {
+        for head in config.heads.iter() {
+            let output = builder.add_layer(&head.layer, main_output.clone())?;
+            builder.add_output(output, &head.name, &mut outputs);
+        }
+    }
+
+    let mut dims = vec![Dimension { dim_value: None, dim_param: Some("batch".to_owned()) }];
+    dims.extend(input_shape.iter().map(|o| Dimension { dim_value: Some(*o as i64), dim_param: None }));
+    let input = value_info(INPUT_NAME, Some(TensorShapeProto { dim: dims }));
+
+    let model = ModelProto {
+        ir_version: Some(IR_VERSION),
+        opset_import: vec![OperatorSetIdProto { domain: Some(String::new()), version: Some(OPSET_VERSION) }],
+        producer_name: Some("codebase".to_owned()),
+        graph: Some(GraphProto {
+            node: builder.nodes,
+            name: Some("model".to_owned()),
+            initializer: builder.initializers,
+            input: vec![input],
+            output: outputs,
+        }),
+    };
+    Ok(model.encode_to_vec())
+}
+
+/// Float tensor, without a fixed shape if **shape** is None
+fn value_info(name: &str, shape: Option<TensorShapeProto>) -> ValueInfoProto {
+    ValueInfoProto {
+        name: Some(name.to_owned()),
+        r#type: Some(TypeProto {
+            tensor_type: Some(TensorTypeProto { elem_type: Some(DATA_TYPE_FLOAT), shape }),
+        }),
+    }
+}
+
+fn int_attr(name: &str, value: usize) -> AttributeProto {
+    AttributeProto { name: Some(name.to_owned()), r#type: Some(ATTRIBUTE_INT), i: Some(value as i64), ..Default::default() }
+}
+
+fn ints_attr(name: &str, values: &[usize]) -> AttributeProto {
+    AttributeProto {
+        name: Some(name.to_owned()),
+        r#type: Some(ATTRIBUTE_INTS),
+        ints: values.iter().map(|o| *o as i64).collect(),
+        ..Default::default()
+    }
+}
+
+/// Creates the nodes of the graph. Parameters are found with the same keys used by **init_layer()**
+struct GraphBuilder<'a> {
+    storage: &'a GenericStorage,
+    assigner: KeyAssigner,
+    nodes: Vec<NodeProto>,
+    initializers: Vec<TensorProto>,
+}
+
+impl<'a> GraphBuilder<'a> {
+    fn new(storage: &'a GenericStorage) -> Self {
+        Self { storage, assigner: KeyAssigner::new(), nodes: Vec::new(), initializers: Vec::new() }
+    }
+
+    /// Add a node with a single output and return the name of the output
+    fn add_node(&mut self, op_type: &str, inputs: Vec<String>, attribute: Vec<AttributeProto>) -> String {
+        let name = format!("{}_{}", op_type.to_lowercase(), self.nodes.len());
+        self.nodes.push(NodeProto {
+            input: inputs,
+            output: vec![name.clone()],
+            name: Some(name.clone()),
+            op_type: Some(op_type.to_owned()),
+            attribute,
+        });
+        name
+    }
+
+    fn add_output(&mut self, value: String, name: &str, outputs: &mut Vec<ValueInfoProto>) {
+        self.nodes.push(NodeProto {
+            input: vec![value],
+            output: vec![name.to_owned()],
+            name: Some(format!("{}_identity", name)),
+            op_type: Some("Identity".to_owned()),
+            attribute: Vec::new(),
+        });
+        outputs.push(value_info(name, None));
+    }
+
+    fn add_initializer(&mut self, name: String, array: &ArrayDynF) -> String {
+        self.initializers.push(TensorProto {
+            dims: array.shape().iter().map(|o| *o as i64).collect(),
+            data_type: Some(DATA_TYPE_FLOAT),
+            name: Some(name.clone()),
+            raw_data: Some(array.iter().flat_map(|o| o.to_le_bytes()).collect()),
+        });
+        name
+    }
+
+    fn add_int_initializer(&mut self, name: String, values: &[i64]) -> String {
+        self.initializers.push(TensorProto {
+            dims: vec![values.len() as i64],
+            data_type: Some(DATA_TYPE_INT64),
+            name: Some(name.clone()),
+            raw_data: Some(values.iter().flat_map(|o| o.to_le_bytes()).collect()),
+        });
+        name
+    }
+
+    fn params(&self, key: &str, count: usize) -> GenericResult<&'a [ArrayDynF]> {
+        let storage: &'a GenericStorage = self.storage;
+        storage.get(key)
+            .filter(|o| o.len() >= count)
+            .map(|o| &o[..count])
+            .ok_or_else(|| anyhow::anyhow!("Parameters {} not found in the storage", key))
+    }
+
+    /// Add the nodes of **layer** and return the name of its output
+    fn add_layer(&mut self, layer: &Layer, input: String) -> GenericResult<String> {
+        let output = match layer {
+            Layer::Sequential(config) => {
+                let mut output = input;
+                for layer in config.layers.iter() {
+                    output = self.add_layer(layer, output)?;
+                }
+                output
+            }
+            Layer::Dense(config) => {
+                let key = self.assigner.get_key(dense_layer::gen_name(config));
+                let params = self.params(&key, 2)?;
+                let weights = self.add_initializer(format!("{}_weights", key), &params[0]);
+                let biases = self.add_initializer(format!("{}_biases", key), &params[1]);
+                // Weights are (out_values, in_values)
+                self.add_node("Gemm", vec![input, weights, biases], vec![int_attr("transB", 1)])
+            }
+            Layer::Convolution(config) => {
+                let key = self.assigner.get_key(convolution::gen_name(config));
+                let params = self.params(&key, 1)?;
+                let kernel = self.add_initializer(format!("{}_kernel", key), &params[0]);
+                self.add_node("Conv", vec![input, kernel], vec![
+                    ints_attr("kernel_shape", &[config.kernel_size; 2]),
+                    ints_attr("strides", &[config.stride; 2]),
+                    ints_attr("pads", &[config.padding; 4]),
+                    ints_attr("dilations", &[config.dilation; 2]),
+                ])
+            }
+            Layer::MaxPool(config) => {
+                // The padding of MaxPool in ONNX never wins the max, but here it's filled with zeros
+                let input = if config.padding > 0 {
+                    let p = config.padding as i64;
+                    let pads = self.add_int_initializer(format!("pads_{}", self.nodes.len()), &[0, 0, p, p, 0, 0, p, p]);
+                    self.add_node("Pad", vec![input, pads], Vec::new())
+                } else {
+                    input
+                };
+                self.add_node("MaxPool", vec![input], vec![
+                    ints_attr("kernel_shape", &[config.size; 2]),
+                    ints_attr("strides", &[config.stride; 2]),
+                ])
+            }
+            Layer::Relu => self.add_node("Relu", vec![input], Vec::new()),
+            Layer::Tanh => self.add_node("Tanh", vec![input], Vec::new()),
+            Layer::Sigmoid => self.add_node("Sigmoid", vec![input], Vec::new()),
+            Layer::Flatten => self.add_node("Flatten", vec![input], vec![int_attr("axis", 1)]),
+            Layer::ExpandDim(config) => {
+                let axes = self.add_int_initializer(format!("axes_{}", self.nodes.len()), &[config.dim as i64 + 1]);
+                self.add_node("Unsqueeze", vec![input, axes], Vec::new())
+            }
+            Layer::Concat(config) => {
+                let mut outputs = Vec::with_capacity(config.layers.len());
+                for layer in config.layers.iter() {
+                    outputs.push(self.add_layer(layer, input.clone())?);
+                }
+                self.add_node("Concat", outputs, vec![int_attr("axis", config.dim + 1)])
+            }
+            Layer::TwoComplementsTransformer => {
+                // (Batch, 2) x (2, 1) = (Batch, 1), with A - B in each item
+                let matrix = ndarray::array![[1.0], [-1.0]].into_dyn();
+                let matrix = self.add_initializer(format!("two_complements_{}", self.nodes.len()), &matrix);
+                self.add_node("MatMul", vec![input, matrix], Vec::new())
+            }
+            _ => return Err(anyhow::anyhow!("Layer {} can't be exported to ONNX", layer.tag_name())),
+        };
+        Ok(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use ndarray::{Axis, concatenate, IxDyn, s};
+    use ndarray_rand::rand_distr::Normal;
+    use ndarray_rand::RandomExt;
+    use crate::integration::layers_loading::load_model_xml;
+    use crate::nn::controller::NNController;
+    use crate::utils::{Array2F, Array4F, arrays_almost_equal};
+    use super::*;
+
+    fn get_ints(node: &NodeProto, name: &str) -> Vec<usize> {
+        let attribute = node.attribute.iter().find(|o| o.name.as_deref() == Some(name)).unwrap();
+        match attribute.r#type {
+            Some(ATTRIBUTE_INT) => vec![attribute.i.unwrap() as usize],
+            _ => attribute.ints.iter().map(|o| *o as usize).collect(),
+        }
+    }
+
+    fn conv(inputs: &Array4F, kernel: &Array4F, stride: usize, padding: usize, dilation: usize) -> Array4F {
+        let [batch, in_channels, height, width] = [0, 1, 2, 3].map(|o| inputs.shape()[o]);
+        let [out_channels, _, size, _] = [0, 1, 2, 3].map(|o| kernel.shape()[o]);
+        let mut padded = Array4F::zeros((batch, in_channels, height + 2 * padding, width + 2 * padding));
+        padded.slice_mut(s![.., .., padding..height + padding, padding..width + padding]).assign(inputs);
+        let dilated = (size - 1) * dilation + 1;
+        let out_height = (height + 2 * padding - dilated) / stride + 1;
+        let out_width = (width + 2 * padding - dilated) / stride + 1;
+        Array4F::from_shape_fn((batch, out_channels, out_height, out_width), |(b, o, h, w)| {
+            let mut sum = 0.0;
+            for c in 0..in_channels {
+                for kh in 0..size {
+                    for kw in 0..size {
+                        sum += padded[(b, c, h * stride + kh * dilation, w * stride + kw * dilation)] * kernel[(o, c, kh, kw)];
+                    }
+                }
+            }
+            sum
+        })
+    }
+
+    /// Straightforward implementation of the operators used by the exporter, to run decoded models
+    fn evaluate(bytes: &[u8], inputs: ArrayDynF) -> HashMap<String, ArrayDynF> {
+        let model = ModelProto::decode(bytes).unwrap();
+        assert_eq!(model.ir_version, Some(IR_VERSION));
+        let graph = model.graph.unwrap();
+
+        let mut values = HashMap::new();
+        let mut ints = HashMap::new();
+        for tensor in graph.initializer {
+            let name = tensor.name.unwrap();
+            let data = tensor.raw_data.unwrap();
+            if tensor.data_type == Some(DATA_TYPE_FLOAT) {
+                let shape: Vec<usize> = tensor.dims.iter().map(|o| *o as usize).collect();
+                let data = data.chunks_exact(4).map(|o| f32::from_le_bytes(o.try_into().unwrap())).collect();
+                values.insert(name, ArrayDynF::from_shape_vec(shape, data).unwrap());
+            } else {
+                let data: Vec<i64> = data.chunks_exact(8).map(|o| i64::from_le_bytes(o.try_into().unwrap())).collect();
+                ints.insert(name, data);
+            }
+        }
+        values.insert(graph.input[0].name.clone().unwrap(), inputs);
+
+        for node in graph.node.iter() {
+            let input = |index: usize| values[&node.input[index]].clone();
+            let result = match node.op_type.as_deref().unwrap() {
+                "Identity" => input(0),
+                "Relu" => input(0).mapv(|o| o.max(0.0)),
+                "Tanh" => input(0).mapv(f32::tanh),
+                "Sigmoid" => input(0).mapv(|o| 1.0 / (1.0 + (-o).exp())),
+                "Gemm" => {
+                    assert_eq!(get_ints(node, "transB"), vec![1]);
+                    let a: Array2F = input(0).into_dimensionality().unwrap();
+                    let b: Array2F = input(1).into_dimensionality().unwrap();
+                    (a.dot(&b.t()) + input(2)).into_dyn()
+                }
+                "MatMul" => {
+                    let a: Array2F = input(0).into_dimensionality().unwrap();
+                    let b: Array2F = input(1).into_dimensionality().unwrap();
+                    a.dot(&b).into_dyn()
+                }
+                "Flatten" => {
+                    assert_eq!(get_ints(node, "axis"), vec![1]);
+                    let inputs = input(0);
+                    let batch = inputs.shape()[0];
+                    let len = inputs.len() / batch;
+                    inputs.into_shape(vec![batch, len]).unwrap()
+                }
+                "Unsqueeze" => input(0).insert_axis(Axis(ints[&node.input[1]][0] as usize)),
+                "Concat" => {
+                    let arrays: Vec<_> = node.input.iter().map(|o| values[o].view()).collect();
+                    concatenate(Axis(get_ints(node, "axis")[0]), &arrays).unwrap()
+                }
+                "Pad" => {
+                    let pads = &ints[&node.input[1]];
+                    let inputs = input(0);
+                    let ndim = inputs.ndim();
+                    let shape: Vec<usize> = (0..ndim).map(|i| inputs.shape()[i] + (pads[i] + pads[i + ndim]) as usize).collect();
+                    let mut result = ArrayDynF::zeros(IxDyn(&shape));
+                    result.slice_each_axis_mut(|o| {
+                        let start = pads[o.axis.index()] as usize;
+                        (start..start + inputs.shape()[o.axis.index()]).into()
+                    }).assign(&inputs);
+                    result
+                }
+                "MaxPool" => {
+                    let size = get_ints(node, "kernel_shape")[0];
+                    let stride = get_ints(node, "strides")[0];
+                    let inputs: Array4F = input(0).into_dimensionality().unwrap();
+                    let [batch, channels, height, width] = [0, 1, 2, 3].map(|o| inputs.shape()[o]);
+                    Array4F::from_shape_fn((batch, channels, (height - size) / stride + 1, (width - size) / stride + 1), |(b, c, h, w)| {
+                        inputs.slice(s![b, c, h * stride..h * stride + size, w * stride..w * stride + size])
+                            .fold(f32::NEG_INFINITY, |a, b| a.max(*b))
+                    }).into_dyn()
+                }
+                "Conv" => {
+                    let kernel: Array4F = input(1).into_dimensionality().unwrap();
+                    assert_eq!(get_ints(node, "kernel_shape"), vec![kernel.shape()[2]; 2]);
+                    conv(&input(0).into_dimensionality().unwrap(), &kernel,
+                         get_ints(node, "strides")[0], get_ints(node, "pads")[0], get_ints(node, "dilations")[0]).into_dyn()
+                }
+                op_type => panic!("Unexpected operator {}", op_type),
+            };
+            values.insert(node.output[0].clone(), result);
+        }
+
+        graph.output.iter()
+            .map(|o| o.name.clone().unwrap())
+            .map(|o| (o.clone(), values.remove(&o).unwrap()))
+            .collect()
+    }
+
+    fn dense(in_values: usize, out_values: usize) -> String {
+        format!(r#"<Dense in_values="{}" out_values="{}"><WeightsLr><Constant/></WeightsLr><BiasesLr><Constant/></BiasesLr></Dense>"#,
+                in_values, out_values)
+    }
+
+    #[test]
+    fn test_export() {
+        let xml = format!(r#"<AIModel>
+    <LossFunc><Mse/></LossFunc>
+    <Layer>
+        <Sequential>
+            <ExpandDim dim="0"/>
+            <Convolution in_channels="1" out_channels="2" kernel_size="3" stride="1" padding="1">
+                <KernelsLr><Constant/></KernelsLr>
+            </Convolution>
+            <MaxPool size="2" stride="2" padding="1"/>
+            <Relu/>
+            <Convolution in_channels="2" out_channels="3" kernel_size="2" stride="1" padding="0" dilation="2">
+                <KernelsLr><Constant/></KernelsLr>
+            </Convolution>
+            <Flatten/>
+            <Concat dim="0">
+                <Sequential>{}<Tanh/></Sequential>
+                <Sequential>{}<Sigmoid/></Sequential>
+            </Concat>
+            {}
+            <TwoComplementsTransformer/>
+        </Sequential>
+    </Layer>
+</AIModel>"#, dense(12, 3), dense(12, 1), dense(4, 2));
+        let config = load_model_xml(xml.as_bytes()).unwrap();
+        let controller = NNController::new(config.main_layer.clone(), config.loss_func.clone()).unwrap();
+        let bytes = export_onnx(&config, &controller.export(), &[6, 6]).unwrap();
+
+        let inputs = ArrayDynF::random(vec![3, 6, 6], Normal::new(0.0, 1.0).unwrap());
+        let expected = controller.eval_batch(inputs.clone()).unwrap();
+        let result = evaluate(&bytes, inputs);
+        assert_eq!(result.len(), 1);
+        assert!(arrays_almost_equal(&result[OUTPUT_NAME], &expected));
+
+        let input = &ModelProto::decode(bytes.as_slice()).unwrap().graph.unwrap().input[0];
+        let dims = &input.r#type.as_ref().unwrap().tensor_type.as_ref().unwrap().shape.as_ref().unwrap().dim;
+        assert_eq!(dims[0].dim_param.as_deref(), Some("batch"));
+        assert_eq!(dims[1..].iter().map(|o| o.dim_value.unwrap()).collect::<Vec<_>>(), vec![6, 6]);
+    }
+
+    #[test]
+    fn test_export_heads() {
+        let xml = format!(r#"<AIModel>
+    <LossFunc><Mse/></LossFunc>
+    <Layer><Sequential>{}<Relu/></Sequential></Layer>
+    <Head name="policy"><LossFunc><Mse/></LossFunc><Layer>{}</Layer></Head>
+    <Head name="value"><LossFunc><Mse/></LossFunc><Layer><Sequential>{}<Tanh/></Sequential></Layer></Head>
+</AIModel>"#, dense(4, 5), dense(5, 3), dense(5, 1));
+        let config = load_model_xml(xml.as_bytes()).unwrap();
+        let controller = NNController::new_with_heads(config.main_layer.clone(), config.loss_func.clone(), config.heads.clone()).unwrap();
+        let bytes = export_onnx(&config, &controller.export(), &[4]).unwrap();
+
+        let inputs = ArrayDynF::random(vec![2, 4], Normal::new(0.0, 1.0).unwrap());
+        let expected = controller.eval_batch_heads(inputs.clone()).unwrap();
+        let result = evaluate(&bytes, inputs);
+        assert_eq!(result.len(), 2);
+        for name in ["policy", "value"] {
+            assert!(arrays_almost_equal(&result[name], &expected[name]));
+        }
+    }
+
+    #[test]
+    fn test_unsupported() {
+        let xml = format!(r#"<AIModel>
+    <LossFunc><Mse/></LossFunc>
+    <Layer><Sequential>{}<Softmax/></Sequential></Layer>
+</AIModel>"#, dense(2, 2));
+        let config = load_model_xml(xml.as_bytes()).unwrap();
+        let controller = NNController::new(config.main_layer.clone(), config.loss_func.clone()).unwrap();
+        let error = export_onnx(&config, &controller.export(), &[2]).unwrap_err();
+        assert_eq!(error.to_string(), "Layer Softmax can't be exported to ONNX");
+
+        let error = export_onnx(&config, &GenericStorage::new(), &[2]).unwrap_err();
+        assert_eq!(error.to_string(), "Parameters dense_2_2_0 not found in the storage");
+    }
+}
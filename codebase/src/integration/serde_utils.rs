@@ -3,6 +3,7 @@ use ndarray::{ArrayViewD, Axis, concatenate, Slice};
 use ndarray_rand::rand;
 use crate::ArrayDynF;
 use crate::integration::random_picker::RandomPicker;
+use crate::integration::compression::{Compression, MAX_DECOMPRESSED_LEN};
 
 pub fn read_u8(source: &mut &[u8]) -> io::Result<u8> {
     let mut buffer = [0];
@@ -20,36 +21,117 @@ pub fn write_u32(result: &mut Vec<u8>, num: u32) {
     result.extend(num.to_be_bytes())
 }
 
+pub fn read_f32(source: &mut &[u8]) -> io::Result<f32> {
+    let mut buffer = [0; 4];
+    source.read_exact(&mut buffer)?;
+    Ok(f32::from_be_bytes(buffer))
+}
+
 pub fn read_f64(source: &mut &[u8]) -> io::Result<f64> {
     let mut buffer = [0; 8];
     source.read_exact(&mut buffer)?;
     Ok(f64::from_be_bytes(buffer))
 }
 
+/// Fail if **source** has less than **len** bytes left, to not allocate buffers for corrupted lengths
+pub fn check_remaining(source: &[u8], len: Option<usize>) -> io::Result<()> {
+    match len {
+        Some(len) if len <= source.len() => Ok(()),
+        _ => Err(io::Error::new(io::ErrorKind::UnexpectedEof, "length larger than the remaining bytes")),
+    }
+}
+
+/// Read a string prefixed by its length
+pub fn read_string(source: &mut &[u8]) -> DeserResult<String> {
+    let len = read_u32(source)? as usize;
+    check_remaining(source, Some(len))?;
+    let mut bytes = vec![0; len];
+    source.read_exact(&mut bytes)?;
+    Ok(String::from_utf8(bytes)?)
+}
+
+/// Write a string prefixed by its length
+pub fn write_string(result: &mut Vec<u8>, value: &str) {
+    write_u32(result, value.len() as u32);
+    result.extend(value.as_bytes());
+}
+
 pub fn write_f64(result: &mut Vec<u8>, num: f64) {
     result.extend(num.to_be_bytes())
 }
 
+/// The first byte of serialized data describes how the rest is stored
+pub fn read_compression(source: &mut &[u8]) -> DeserResult<Compression> {
+    let byte = read_u8(source)?;
+    Compression::from_byte(byte).ok_or_else(|| StorageDeserError::new(ErrorKind::UnknownCompression(byte)))
+}
+
+/// Read the compression byte and decompress the rest of **source**
+pub fn read_decompressed(source: &mut &[u8]) -> DeserResult<(Compression, Vec<u8>)> {
+    let compression = read_compression(source)?;
+    let data = compression.decompress(source, MAX_DECOMPRESSED_LEN)?;
+    Ok((compression, data))
+}
+
 #[derive(Debug)]
-enum ErrorKind {
+pub enum ErrorKind {
+    /// The data ended before everything was read. Usually the file or the upload was truncated
     NotEnoughBytes,
     WrongStringEncoding(FromUtf8Error),
+    UnknownCompression(u8),
+    InvalidCompressedData,
+    /// The decompressed data would be larger than the limit
+    DecompressedTooLarge(usize),
+    /// The data doesn't start with the magic bytes of a checkpoint
+    InvalidMagic,
+    UnsupportedVersion(u32),
+    /// The data was corrupted
+    ChecksumMismatch,
+    /// There's data left after everything was read
+    TrailingBytes(usize),
 }
 
 #[derive(Debug)]
 pub struct StorageDeserError {
-    _kind: ErrorKind,
+    kind: ErrorKind,
+    /// What was being read when the error happened, from the innermost to the outermost
+    context: Vec<String>,
 }
 
 impl StorageDeserError {
-    fn new(kind: ErrorKind) -> Self {
-        Self { _kind: kind }
+    pub fn new(kind: ErrorKind) -> Self {
+        Self { kind, context: Vec::new() }
+    }
+
+    pub fn kind(&self) -> &ErrorKind {
+        &self.kind
+    }
+
+    /// Add a description of what was being read, like "key dense_0"
+    pub fn with_context(mut self, context: impl Display) -> Self {
+        self.context.push(context.to_string());
+        self
     }
 }
 
 impl Display for StorageDeserError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{:?}", self)
+        match &self.kind {
+            ErrorKind::NotEnoughBytes => write!(f, "Data ended unexpectedly (truncated or corrupted)")?,
+            ErrorKind::WrongStringEncoding(e) => write!(f, "Invalid UTF-8 string: {}", e)?,
+            ErrorKind::UnknownCompression(v) => write!(f, "Unknown compression byte {}", v)?,
+            ErrorKind::InvalidCompressedData => write!(f, "Compressed data is invalid (truncated or corrupted)")?,
+            ErrorKind::DecompressedTooLarge(v) => write!(f, "Decompressed data is larger than {} bytes", v)?,
+            ErrorKind::InvalidMagic => write!(f, "Invalid magic bytes, the data isn't a checkpoint")?,
+            ErrorKind::UnsupportedVersion(v) => write!(f, "Unsupported format version {}", v)?,
+            ErrorKind::ChecksumMismatch => write!(f, "Checksum mismatch (corrupted data)")?,
+            ErrorKind::TrailingBytes(v) => write!(f, "{} unexpected bytes after the end of the data", v)?,
+        }
+
+        if !self.context.is_empty() {
+            write!(f, " while reading {}", self.context.join(" in "))?;
+        }
+        Ok(())
     }
 }
 
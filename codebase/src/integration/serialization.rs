@@ -1,60 +1,99 @@
 use crate::integration::serde_utils::*;
 use crate::{nn::layers::nn_layers::GenericStorage, utils::ArrayDynF};
+use crate::integration::compression::{Compression, int8_channels, ValuesFormat};
+use crate::nn::precision::Precision;
+use crate::nn::quantization::QuantizedArray;
 
 pub fn write_num_vec(result: &mut Vec<u8>, array: &ArrayDynF) {
-    array
-        .iter()
-        .flat_map(|o| o.to_be_bytes())
-        .for_each(|o| result.push(o))
+    write_values(result, array, ValuesFormat::Float(Precision::F32))
 }
 
-fn write_array(result: &mut Vec<u8>, array: &ArrayDynF) {
+fn write_values(result: &mut Vec<u8>, array: &ArrayDynF, format: ValuesFormat) {
+    match format.for_shape(array.shape()) {
+        ValuesFormat::Float(precision) => {
+            result.reserve(array.len() * precision.bytes_per_value());
+            array.iter().for_each(|o| precision.to_be_bytes(*o, result));
+        }
+        ValuesFormat::Int8 => {
+            if array.is_empty() {
+                return;
+            }
+            let channels = int8_channels(array.shape());
+            let values = ArrayDynF::from_shape_vec(vec![channels, array.len() / channels], array.iter().copied().collect()).unwrap();
+            let quantized = QuantizedArray::quantize(&values);
+            quantized.scales.iter().for_each(|o| result.extend(o.to_be_bytes()));
+            result.extend(quantized.values.iter().map(|o| *o as u8));
+        }
+    }
+}
+
+pub(crate) fn write_array(result: &mut Vec<u8>, array: &ArrayDynF, format: ValuesFormat) {
     write_u32(result, array.shape().len() as u32);
     for shape_item in array.shape().iter() {
         write_u32(result, *shape_item as u32);
     }
-    write_num_vec(result, array);
+    write_values(result, array, format);
 }
 
-fn write_storage(result: &mut Vec<u8>, storage: &GenericStorage) {
+fn write_storage(result: &mut Vec<u8>, storage: &GenericStorage, format: ValuesFormat) {
     for (key, value) in storage.iter() {
-        write_u32(result, key.len() as u32);
-        result.extend(key.as_bytes());
+        write_string(result, key);
 
         write_u32(result, value.len() as u32);
         for item in value.iter() {
-            write_array(result, item);
+            write_array(result, item, format);
         }
     }
 }
 
 pub fn serialize_array(array: &ArrayDynF) -> Vec<u8> {
     let mut result = Vec::new();
-    write_array(&mut result, array);
+    write_array(&mut result, array, ValuesFormat::Float(Precision::F32));
     result
 }
 
-pub fn serialize_storage(storage: &GenericStorage) -> Vec<u8> {
-    let mut result = Vec::new();
-    result.push(0); // No compression
-    write_storage(&mut result, storage);
+/// Write the compression byte followed by the (compressed) data
+fn finish(compression: Compression, data: Vec<u8>) -> Vec<u8> {
+    let mut result = vec![compression.to_byte()];
+    result.extend(compression.compress(data));
     result
 }
 
+pub fn serialize_storage(storage: &GenericStorage) -> Vec<u8> {
+    serialize_storage_compressed(storage, Compression::none())
+}
+
+/// Serialize the storage with the values rounded to **precision**. Reduced precisions use half
+/// the bytes and are read transparently by **deserialize_storage**
+pub fn serialize_storage_with_precision(storage: &GenericStorage, precision: Precision) -> Vec<u8> {
+    serialize_storage_compressed(storage, Compression::from_precision(precision))
+}
+
+/// All compressions are read transparently by **deserialize_storage**
+pub fn serialize_storage_compressed(storage: &GenericStorage, compression: Compression) -> Vec<u8> {
+    let mut data = Vec::new();
+    write_storage(&mut data, storage, compression.format);
+    finish(compression, data)
+}
+
 pub fn serialize_version(storage: &GenericStorage, loss: f64) -> Vec<u8> {
-    let mut result = Vec::new();
-    result.push(0); // No compression
-    write_f64(&mut result, loss);
-    write_storage(&mut result, storage);
-    result
+    serialize_version_compressed(storage, loss, Compression::none())
 }
 
-pub fn serialize_pairs(pairs: &Pairs) -> Vec<u8> {
-    let mut result = Vec::new();
+pub fn serialize_version_compressed(storage: &GenericStorage, loss: f64, compression: Compression) -> Vec<u8> {
+    let mut data = Vec::new();
+    write_f64(&mut data, loss);
+    write_storage(&mut data, storage, compression.format);
+    finish(compression, data)
+}
 
-    result.push(0); // No compression
-    write_array(&mut result, &pairs.inputs);
-    write_array(&mut result, &pairs.expected);
+pub fn serialize_pairs(pairs: &Pairs) -> Vec<u8> {
+    serialize_pairs_compressed(pairs, Compression::none())
+}
 
-    result
+pub fn serialize_pairs_compressed(pairs: &Pairs, compression: Compression) -> Vec<u8> {
+    let mut data = Vec::new();
+    write_array(&mut data, &pairs.inputs, compression.format);
+    write_array(&mut data, &pairs.expected, compression.format);
+    finish(compression, data)
 }
@@ -1,14 +1,24 @@
+use std::sync::Arc;
+use crate::nn::quantization::QuantizedStorage;
+
 /// Simple struct that contains some information about the current batch
 pub struct BatchConfig {
     pub is_training: bool,
+    /// Quantized parameters. When present, the layers that support it use them with int8 kernels
+    /// on the CPU instead of the parameters in the storage
+    pub quantized: Option<Arc<QuantizedStorage>>,
 }
 
 impl BatchConfig {
     pub fn new_not_train() -> Self {
-        Self { is_training: false }
+        Self { is_training: false, quantized: None }
     }
 
     pub fn new_train() -> Self {
-        Self { is_training: true }
+        Self { is_training: true, quantized: None }
+    }
+
+    pub fn new_quantized(quantized: Arc<QuantizedStorage>) -> Self {
+        Self { is_training: false, quantized: Some(quantized) }
     }
 }
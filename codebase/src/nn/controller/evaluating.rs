@@ -1,9 +1,9 @@
 
+use std::collections::HashMap;
 use ndarray::{Axis, stack};
 use crate::ArrayDynF;
 use crate::gpu::buffers::upload_array_to_gpu;
-use crate::gpu::gpu_data::{get_global_gpu, GlobalGpu};
-use crate::nn::batch_config::BatchConfig;
+use crate::gpu::gpu_data::GlobalGpu;
 use crate::nn::controller::NNController;
 use crate::nn::key_assigner::KeyAssigner;
 use crate::nn::layers::nn_layers::{forward_layer, ForwardData, GenericStorage};
@@ -34,9 +34,13 @@ impl NNController {
     /// Forward the input through the layers and return the result.
     /// Uses GPU if available
     pub fn eval_batch(&self, inputs: ArrayDynF) -> GenericResult<ArrayDynF> {
+        if !self.heads.is_empty() {
+            return Err(anyhow::anyhow!("The model has multiple outputs, use eval_batch_heads()"));
+        }
+
         let mut assigner = KeyAssigner::new();
-        let config = BatchConfig::new_not_train();
-        let gpu = get_global_gpu();
+        let config = self.eval_config();
+        let gpu = self.eval_gpu();
 
         let result = forward_layer(
             &self.main_layer,
@@ -54,11 +58,57 @@ impl NNController {
         Ok(result)
     }
 
+    /// Forward the input through the main layer and then through every head. Returns the outputs
+    /// keyed by the name of the head.
+    /// Uses GPU if available
+    pub fn eval_batch_heads(&self, inputs: ArrayDynF) -> GenericResult<HashMap<String, ArrayDynF>> {
+        let mut assigner = KeyAssigner::new();
+        let config = self.eval_config();
+        let gpu = self.eval_gpu();
+
+        let trunk_output = forward_layer(
+            &self.main_layer,
+            ForwardData {
+                inputs: prepare_inputs(inputs, gpu.clone())?,
+                assigner: &mut assigner,
+                storage: &self.storage,
+                forward_cache: None,
+                batch_config: &config,
+                prev_iteration_cache: None,
+                gpu: gpu.clone(),
+            },
+        )?;
+
+        let mut result = HashMap::with_capacity(self.heads.len());
+        for head in self.heads.iter() {
+            let output = forward_layer(
+                &head.layer,
+                ForwardData {
+                    inputs: trunk_output.clone(),
+                    assigner: &mut assigner,
+                    storage: &self.storage,
+                    forward_cache: None,
+                    batch_config: &config,
+                    prev_iteration_cache: None,
+                    gpu: gpu.clone(),
+                },
+            )?.into_memory()?;
+            result.insert(head.name.clone(), output);
+        }
+
+        self.finish_method()?;
+        Ok(result)
+    }
+
     pub fn eval_with_cache(&self, inputs: ArrayDynF, prev_iteration_cache: Option<GenericStorage>)
                            -> GenericResult<(ArrayDynF, GenericStorage)> {
+        if !self.heads.is_empty() {
+            return Err(anyhow::anyhow!("The model has multiple outputs, which isn't supported with cache"));
+        }
+
         let mut assigner = KeyAssigner::new();
-        let config = BatchConfig::new_not_train();
-        let gpu = get_global_gpu();
+        let config = self.eval_config();
+        let gpu = self.eval_gpu();
         let mut cache = prev_iteration_cache.unwrap_or_default();
 
         let result = forward_layer(
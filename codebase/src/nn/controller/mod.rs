@@ -1,11 +1,21 @@
 mod evaluating;
 mod training;
 mod testing;
+mod quantizing;
+mod summary;
 
+use std::collections::HashMap;
+use std::sync::Arc;
+use crate::gpu::gpu_data::{get_global_gpu, GlobalGpu};
+use crate::nn::batch_config::BatchConfig;
+use crate::nn::gradient_clipping::GradientClipping;
 use crate::nn::key_assigner::KeyAssigner;
 use crate::nn::layers::nn_layers::*;
 use crate::nn::loss::loss_func::LossFunc;
+use crate::nn::precision::Precision;
+use crate::nn::quantization::QuantizedStorage;
 use crate::utils::GenericResult;
+use training::AccumulatedGradients;
 
 /// Main struct to train and use the AI model
 /// ```
@@ -17,8 +27,8 @@ use crate::utils::GenericResult;
 /// println!("Started training");
 /// for epoch in 0..10 {
 ///     // It's better to split the data into random batches
-///     let loss = controller.train_batch(data_inputs.clone(), &data_expected ).unwrap();
-///     println!("Epoch {} finished with avg loss {}", epoch, loss);
+///     let result = controller.train_batch(data_inputs.clone(), &data_expected ).unwrap();
+///     println!("Epoch {} finished with avg loss {}", epoch, result.loss);
 /// }
 ///
 /// println!("Finished training. Started validating");
@@ -30,30 +40,126 @@ pub struct NNController {
     main_layer: Layer,
     storage: GenericStorage,
     loss: LossFunc,
+    heads: Vec<HeadConfig>,
+    clipping: GradientClipping,
+    quantized: Option<Arc<QuantizedStorage>>,
+    /// Shards of each batch when training, see **set_data_parallel()**
+    data_parallel_shards: usize,
+    accumulated: Option<AccumulatedGradients>,
+    /// Multiplies the changes of every layer, see **set_lr_multiplier()**
+    lr_multiplier: f32,
+}
+
+/// A named output of a model with multiple outputs. Every head receives the output of the main
+/// layer (shared by all heads) and has its own loss function.
+#[derive(Clone, Debug)]
+pub struct HeadConfig {
+    /// Key of the outputs and of the expected values of this head
+    pub name: String,
+    pub layer: Layer,
+    pub loss_func: LossFunc,
+    /// Multiplies the loss of this head, and therefore its gradient
+    pub weight: f32,
+}
+
+/// Information about a training step
+#[derive(Clone, Debug)]
+pub struct TrainBatchResult {
+    /// Average loss in the batch
+    pub loss: f64,
+    /// Global L2 norm of all gradients, before clipping
+    pub grad_norm: f64,
+    /// L2 norm of the gradients of each layer key, before clipping
+    pub key_norms: HashMap<String, f64>,
+    /// Average loss of each head, before applying the weights. Empty if the model has no heads
+    pub head_losses: HashMap<String, f64>,
+    /// Number of items the gradients were calculated from
+    pub samples: usize,
+}
+
+/// Information about a micro-batch added with **accumulate_batch()**
+#[derive(Clone, Debug)]
+pub struct AccumulateResult {
+    /// Average loss in the micro-batch
+    pub loss: f64,
+    /// Average loss of each head in the micro-batch. Empty if the model has no heads
+    pub head_losses: HashMap<String, f64>,
+    /// Number of items accumulated since the last **apply_accumulated()**, including this micro-batch
+    pub samples: usize,
+}
+
+/// Results of evaluating a dataset with the f32 and the quantized parameters
+#[derive(Clone, Debug)]
+pub struct QuantizationReport {
+    /// Average loss with the f32 parameters, like in **test_batch()**
+    pub loss: f64,
+    /// Average loss with the quantized parameters
+    pub quantized_loss: f64,
+    /// Largest difference between the f32 and the quantized outputs
+    pub max_output_diff: f32,
+    /// Fraction of items where the index of the largest output matches the expected one, with the f32 parameters
+    pub accuracy: f64,
+    /// Same as **accuracy**, with the quantized parameters
+    pub quantized_accuracy: f64,
+    /// Fraction of items where the index of the largest output is the same with both parameters
+    pub agreement: f64,
+}
+
+/// Information about a layer of the model, from **summary()**
+#[derive(Clone, Debug)]
+pub struct LayerSummary {
+    /// Like `Sequential/Dense[1]`. Layers of heads start with the name of the head
+    pub path: String,
+    /// 0 for the main layer and for the layers of the heads
+    pub depth: usize,
+    /// Without the batch dimension
+    pub output_shape: Vec<usize>,
+    /// Key in the storage and in the caches, if the layer gets one from **KeyAssigner**
+    pub key: Option<String>,
+    /// Number of values updated when training
+    pub trainable_params: usize,
+    /// Bytes of all arrays stored under **key**, including the ones that aren't trainable
+    pub memory: usize,
+    /// Estimated floating point operations to evaluate a single input, not including the children
+    pub flops: usize,
+}
+
+/// Structure and size of a model, from **summary()**
+#[derive(Clone, Debug)]
+pub struct ModelSummary {
+    /// Layers of the main layer and then of each head, with parents before their children
+    pub layers: Vec<LayerSummary>,
+    pub trainable_params: usize,
+    pub memory: usize,
+    pub flops: usize,
 }
 
 impl NNController {
     /// Create a controller with an empty storage and init its layers
     pub fn new(main_layer: Layer, loss: LossFunc) -> GenericResult<Self> {
-        let mut storage = GenericStorage::new();
-        let mut assigner = KeyAssigner::new();
-        init_layer(
-            &main_layer,
-            InitData {
-                assigner: &mut assigner,
-                storage: &mut storage,
-            },
-        )?;
-
-        Ok(Self {
-            main_layer,
-            storage,
-            loss,
-        })
+        Self::load_with_heads(main_layer, loss, Vec::new(), GenericStorage::new())
     }
 
     /// Create a controller with the provided storage and init its layers
-    pub fn load(main_layer: Layer, loss: LossFunc, mut storage: GenericStorage) -> GenericResult<Self> {
+    pub fn load(main_layer: Layer, loss: LossFunc, storage: GenericStorage) -> GenericResult<Self> {
+        Self::load_with_heads(main_layer, loss, Vec::new(), storage)
+    }
+
+    /// Create a controller with multiple outputs and an empty storage, and init its layers.
+    /// **loss** is only used if **heads** is empty
+    pub fn new_with_heads(main_layer: Layer, loss: LossFunc, heads: Vec<HeadConfig>) -> GenericResult<Self> {
+        Self::load_with_heads(main_layer, loss, heads, GenericStorage::new())
+    }
+
+    /// Create a controller with multiple outputs and the provided storage, and init its layers.
+    /// **loss** is only used if **heads** is empty
+    pub fn load_with_heads(main_layer: Layer, loss: LossFunc, heads: Vec<HeadConfig>, mut storage: GenericStorage) -> GenericResult<Self> {
+        for (index, head) in heads.iter().enumerate() {
+            if heads[..index].iter().any(|o| o.name == head.name) {
+                return Err(anyhow::anyhow!("Duplicate head name {}", head.name));
+            }
+        }
+
         let mut assigner = KeyAssigner::new();
         init_layer(
             &main_layer,
@@ -63,18 +169,77 @@ impl NNController {
             },
         )?;
 
+        for head in heads.iter() {
+            init_layer(
+                &head.layer,
+                InitData {
+                    assigner: &mut assigner,
+                    storage: &mut storage,
+                },
+            )?;
+        }
+
         Ok(Self {
             main_layer,
             storage,
             loss,
+            heads,
+            clipping: GradientClipping::default(),
+            quantized: None,
+            data_parallel_shards: 0,
+            accumulated: None,
+            lr_multiplier: 1.0,
         })
     }
 
+    /// Names of the outputs of the model, in order. Empty if the model has a single output
+    pub fn head_names(&self) -> Vec<&str> {
+        self.heads.iter().map(|o| o.name.as_str()).collect()
+    }
+
+    /// Set how the gradients are clipped before updating the parameters. Disabled by default
+    pub fn set_gradient_clipping(&mut self, clipping: GradientClipping) {
+        self.clipping = clipping;
+    }
+
+    /// Multiply the changes calculated by the lr calculators of every layer, on top of the
+    /// **lr_multiplier** of each layer. 1 by default
+    pub fn set_lr_multiplier(&mut self, multiplier: f32) {
+        self.lr_multiplier = multiplier;
+    }
+
+    pub fn lr_multiplier(&self) -> f32 {
+        self.lr_multiplier
+    }
+
+    /// Round the parameters to **precision**, like they would be in a storage serialized with it.
+    /// They are still held and evaluated in f32, so this only shows the effect of the rounding
+    /// on the results, and training afterwards updates them in f32
+    pub fn round_to_precision(&mut self, precision: Precision) {
+        precision.round_storage(&mut self.storage);
+    }
+
     /// Return a copy of the inner storage
     pub fn export(&self) -> GenericStorage {
         self.storage.clone()
     }
 
+    /// Config used to evaluate the model, with the quantized parameters if available
+    fn eval_config(&self) -> BatchConfig {
+        match &self.quantized {
+            Some(quantized) => BatchConfig::new_quantized(quantized.clone()),
+            None => BatchConfig::new_not_train(),
+        }
+    }
+
+    /// The quantized kernels only run on the CPU
+    fn eval_gpu(&self) -> Option<GlobalGpu> {
+        match self.quantized {
+            Some(_) => None,
+            None => get_global_gpu(),
+        }
+    }
+
     fn finish_method(&self) -> GenericResult<()> {
         // if let Some(gpu) = get_global_gpu() {
             // gpu.reset_fast_mem_alloc()?;
@@ -89,7 +254,7 @@ mod tests {
 
     use crate::{
         integration::layers_loading::load_model_xml,
-        utils::{Array2F, Array3F},
+        utils::{arrays_almost_equal, Array2F, Array3F},
     };
 
     use super::*;
@@ -111,6 +276,317 @@ mod tests {
                 &Array2F::ones((64, 10)).into_dyn(),
             )
             .unwrap();
-        println!("{}", result);
+        println!("{}", result.loss);
+    }
+
+    fn get_dense(in_values: usize, out_values: usize) -> Layer {
+        use crate::nn::layers::dense_layer::{DenseConfig, DenseLayerInit};
+        use crate::nn::lr_calculators::{constant_lr::ConstantLrConfig, lr_calculator::LrCalc};
+        Layer::Dense(DenseConfig {
+            in_values,
+            out_values,
+            init_mode: DenseLayerInit::Random(),
+            weights_lr_calc: LrCalc::Constant(ConstantLrConfig::default()),
+            biases_lr_calc: LrCalc::Constant(ConstantLrConfig::default()),
+            train_options: Default::default(),
+        })
+    }
+
+    #[test]
+    fn test_train_heads() {
+        use ndarray_rand::rand_distr::Normal;
+        use ndarray_rand::RandomExt;
+
+        let heads = vec![
+            HeadConfig { name: "value".to_owned(), layer: get_dense(6, 1), loss_func: LossFunc::Mse, weight: 1.0 },
+            HeadConfig { name: "other".to_owned(), layer: get_dense(6, 1), loss_func: LossFunc::Mse, weight: 0.5 },
+        ];
+        let mut controller = NNController::new_with_heads(get_dense(4, 6), LossFunc::Mse, heads).unwrap();
+
+        let dist = Normal::new(0.0, 0.5).unwrap();
+        let inputs = Array2F::random((4, 4), dist).into_dyn();
+        let mut expected = HashMap::new();
+        expected.insert("value".to_owned(), Array2F::random((4, 1), dist).into_dyn());
+        expected.insert("other".to_owned(), Array2F::random((4, 1), dist).into_dyn());
+
+        assert!(controller.train_batch(inputs.clone(), &expected["value"]).is_err());
+
+        let first = controller.train_batch_heads(inputs.clone(), &expected).unwrap();
+        assert!((first.loss - first.head_losses["value"] - first.head_losses["other"] * 0.5).abs() < 0.0001);
+        for _ in 0..50 {
+            controller.train_batch_heads(inputs.clone(), &expected).unwrap();
+        }
+
+        let losses = controller.test_batch_heads(inputs.clone(), &expected).unwrap();
+        assert!(losses["value"] + losses["other"] * 0.5 < first.loss);
+
+        let outputs = controller.eval_batch_heads(inputs).unwrap();
+        assert_eq!(outputs["value"].shape(), &[4, 1]);
+        assert_eq!(outputs["other"].shape(), &[4, 1]);
+    }
+
+    #[test]
+    fn test_train_options() {
+        fn build(lr_multiplier: f32, storage: Option<GenericStorage>) -> NNController {
+            let str = format!(r#"<AIModel>
+    <LossFunc><Mse/></LossFunc>
+    <Layer>
+        <Sequential>
+            <Sequential frozen="true">
+                <Dense in_values="4" out_values="6">
+                    <WeightsLr><Constant/></WeightsLr>
+                    <BiasesLr><Constant/></BiasesLr>
+                </Dense>
+            </Sequential>
+            <Concat dim="0" lr_multiplier="{}">
+                <Dense in_values="6" out_values="3">
+                    <WeightsLr><Constant lr="1"/></WeightsLr>
+                    <BiasesLr><Constant lr="1"/></BiasesLr>
+                </Dense>
+            </Concat>
+        </Sequential>
+    </Layer>
+</AIModel>"#, lr_multiplier);
+            let config = load_model_xml(str.as_bytes()).unwrap();
+            match storage {
+                Some(storage) => NNController::load(config.main_layer, config.loss_func, storage).unwrap(),
+                None => NNController::new(config.main_layer, config.loss_func).unwrap(),
+            }
+        }
+
+        let mut full = build(1.0, None);
+        let initial = full.export();
+        let mut halved = build(0.5, Some(initial.clone()));
+        let mut halved_global = build(1.0, Some(initial.clone()));
+        halved_global.set_lr_multiplier(0.5);
+
+        let inputs = Array2F::ones((2, 4)).into_dyn();
+        let expected = Array2F::zeros((2, 3)).into_dyn();
+        full.train_batch(inputs.clone(), &expected).unwrap();
+        halved.train_batch(inputs.clone(), &expected).unwrap();
+        halved_global.train_batch(inputs, &expected).unwrap();
+
+        let (full, halved) = (full.export(), halved.export());
+        assert_eq!(halved, halved_global.export());
+        assert_eq!(full["dense_4_6_0"], initial["dense_4_6_0"]);
+        assert_eq!(halved["dense_4_6_0"], initial["dense_4_6_0"]);
+        for index in 0..2 {
+            let full_delta = &full["dense_6_3_0"][index] - &initial["dense_6_3_0"][index];
+            let halved_delta = &halved["dense_6_3_0"][index] - &initial["dense_6_3_0"][index];
+            assert!(full_delta.iter().any(|o| o.abs() > 0.01));
+            assert!(arrays_almost_equal(&(full_delta * 0.5), &halved_delta));
+        }
+    }
+
+    #[test]
+    fn test_frozen_optimizer_keys() {
+        fn build(frozen: bool) -> NNController {
+            let str = format!(r#"<AIModel>
+    <LossFunc><Mse/></LossFunc>
+    <Layer>
+        <Sequential>
+            <Dense in_values="4" out_values="6">
+                <WeightsLr><Adam/></WeightsLr>
+                <BiasesLr><Adam/></BiasesLr>
+            </Dense>
+            <Sequential frozen="{}">
+                <Dense in_values="6" out_values="5">
+                    <WeightsLr><Adam/></WeightsLr>
+                    <BiasesLr><Adam/></BiasesLr>
+                </Dense>
+            </Sequential>
+            <Dense in_values="5" out_values="3">
+                <WeightsLr><Adam/></WeightsLr>
+                <BiasesLr><Adam/></BiasesLr>
+            </Dense>
+        </Sequential>
+    </Layer>
+</AIModel>"#, frozen);
+            let config = load_model_xml(str.as_bytes()).unwrap();
+            NNController::new(config.main_layer, config.loss_func).unwrap()
+        }
+
+        let inputs = Array2F::ones((2, 4)).into_dyn();
+        let expected = Array2F::zeros((2, 3)).into_dyn();
+        let mut frozen = build(true);
+        let mut trainable = build(false);
+        // The second step reads the moments stored by the first one
+        for _ in 0..2 {
+            frozen.train_batch(inputs.clone(), &expected).unwrap();
+            trainable.train_batch(inputs.clone(), &expected).unwrap();
+        }
+
+        let (frozen, trainable) = (frozen.export(), trainable.export());
+        assert!(!frozen.contains_key("adam_2") && !frozen.contains_key("adam_3"));
+        for key in ["adam_0", "adam_1", "adam_4", "adam_5"] {
+            assert_eq!(frozen[key][0].shape(), trainable[key][0].shape());
+        }
+        assert_eq!(frozen["adam_4"][0].shape(), &[3, 5]);
+    }
+
+    #[test]
+    fn test_data_parallel() {
+        use ndarray_rand::rand_distr::Normal;
+        use ndarray_rand::RandomExt;
+        use crate::nn::layers::sequential_layer::SequentialConfig;
+
+        let layer = Layer::Sequential(SequentialConfig {
+            layers: vec![get_dense(4, 8), Layer::Tanh, get_dense(8, 2)],
+            train_options: Default::default(),
+        });
+        let mut serial = NNController::new(layer.clone(), LossFunc::Mse).unwrap();
+        let mut parallel = NNController::load(layer, LossFunc::Mse, serial.export()).unwrap();
+        parallel.set_data_parallel(4);
+
+        let dist = Normal::new(0.0, 0.5).unwrap();
+        let inputs = Array2F::random((10, 4), dist).into_dyn();
+        let expected = Array2F::random((10, 2), dist).into_dyn();
+        for _ in 0..3 {
+            let serial_result = serial.train_batch(inputs.clone(), &expected).unwrap();
+            let parallel_result = parallel.train_batch(inputs.clone(), &expected).unwrap();
+            assert!((serial_result.loss - parallel_result.loss).abs() < 1e-5);
+            assert!((serial_result.grad_norm - parallel_result.grad_norm).abs() < 1e-5);
+        }
+
+        let (serial, parallel) = (serial.export(), parallel.export());
+        for (key, arrays) in serial.iter() {
+            for (a, b) in arrays.iter().zip(parallel[key].iter()) {
+                assert!(arrays_almost_equal(a, b), "{} differs", key);
+            }
+        }
+    }
+
+    #[test]
+    fn test_accumulate() {
+        use ndarray::s;
+        use ndarray_rand::rand_distr::Normal;
+        use ndarray_rand::RandomExt;
+        use crate::nn::layers::dense_layer::{DenseConfig, DenseLayerInit};
+        use crate::nn::lr_calculators::{adam_lr::AdamConfig, lr_calculator::LrCalc};
+
+        let layer = Layer::Dense(DenseConfig {
+            in_values: 4,
+            out_values: 2,
+            init_mode: DenseLayerInit::Random(),
+            weights_lr_calc: LrCalc::Adam(AdamConfig::default()),
+            biases_lr_calc: LrCalc::Adam(AdamConfig::default()),
+            train_options: Default::default(),
+        });
+        let mut full = NNController::new(layer.clone(), LossFunc::Mse).unwrap();
+        let mut accumulated = NNController::load(layer, LossFunc::Mse, full.export()).unwrap();
+        assert!(accumulated.apply_accumulated().is_err());
+
+        let dist = Normal::new(0.0, 0.5).unwrap();
+        let inputs = Array2F::random((10, 4), dist).into_dyn();
+        let expected = Array2F::random((10, 2), dist).into_dyn();
+        let full_result = full.train_batch(inputs.clone(), &expected).unwrap();
+
+        for (start, end) in [(0, 4), (4, 8), (8, 10)] {
+            let result = accumulated.accumulate_batch(
+                inputs.slice(s![start..end, ..]).to_owned().into_dyn(),
+                &expected.slice(s![start..end, ..]).to_owned().into_dyn(),
+            ).unwrap();
+            assert_eq!(result.samples, end);
+        }
+        let result = accumulated.apply_accumulated().unwrap();
+        assert_eq!(result.samples, 10);
+        assert_eq!(accumulated.accumulated_samples(), 0);
+        assert!((result.loss - full_result.loss).abs() < 1e-5);
+        assert!((result.grad_norm - full_result.grad_norm).abs() < 1e-5);
+
+        // Adam counts a single step, so the bias correction is the same
+        let (full, accumulated) = (full.export(), accumulated.export());
+        for (key, arrays) in full.iter() {
+            for (a, b) in arrays.iter().zip(accumulated[key].iter()) {
+                assert!(arrays_almost_equal(a, b), "{} differs", key);
+            }
+            if key.starts_with("adam") {
+                assert_eq!(accumulated[key][2].first(), Some(&2.0));
+            }
+        }
+    }
+
+    #[test]
+    fn test_round_to_precision() {
+        use ndarray_rand::rand_distr::Normal;
+        use ndarray_rand::RandomExt;
+        use crate::nn::layers::sequential_layer::SequentialConfig;
+
+        let layer = Layer::Sequential(SequentialConfig { layers: vec![get_dense(8, 16), Layer::Relu, get_dense(16, 2)], train_options: Default::default() });
+        let mut controller = NNController::new(layer, LossFunc::Mse).unwrap();
+        let inputs = Array2F::random((4, 8), Normal::new(0.0, 1.0).unwrap()).into_dyn();
+        let expected = controller.eval_batch(inputs.clone()).unwrap();
+
+        for precision in [Precision::F16, Precision::Bf16] {
+            let mut reduced = NNController::load(controller.main_layer.clone(), LossFunc::Mse, controller.export()).unwrap();
+            reduced.round_to_precision(precision);
+            let result = reduced.eval_batch(inputs.clone()).unwrap();
+            let max_diff = (&result - &expected).mapv(f32::abs).fold(0.0f32, |a, &b| a.max(b));
+            assert!(max_diff < 0.05, "{:?} {}", precision, max_diff);
+        }
+        controller.round_to_precision(Precision::F32);
+        assert_eq!(controller.eval_batch(inputs).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_quantized() {
+        use ndarray::Axis;
+        use ndarray_rand::rand_distr::Normal;
+        use ndarray_rand::RandomExt;
+        use crate::integration::serde_utils::Pairs;
+        use crate::nn::layers::filtering::convolution::{ConvolutionConfig, ConvolutionInitMode};
+        use crate::nn::layers::sequential_layer::SequentialConfig;
+        use crate::nn::lr_calculators::{constant_lr::ConstantLrConfig, lr_calculator::LrCalc};
+        use crate::utils::{Array4F, ArrayDynF};
+
+        let layer = Layer::Sequential(SequentialConfig {
+            layers: vec![
+                Layer::Convolution(ConvolutionConfig {
+                    in_channels: 1,
+                    out_channels: 4,
+                    kernel_size: 3,
+                    stride: 1,
+                    padding: 1,
+                    dilation: 1,
+                    init_mode: ConvolutionInitMode::HeNormal(),
+                    lr_calc: LrCalc::Constant(ConstantLrConfig::default()),
+                    cache: false,
+                    train_options: Default::default(),
+                }),
+                Layer::Relu,
+                Layer::Flatten,
+                get_dense(4 * 6 * 6, 10),
+            ],
+            train_options: Default::default(),
+        });
+        let mut controller = NNController::new(layer, LossFunc::Mse).unwrap();
+        let inputs = Array4F::random((64, 1, 6, 6), Normal::new(0.0, 1.0).unwrap()).into_dyn();
+        let output = controller.eval_batch(inputs.clone()).unwrap();
+
+        // Label each item with the class chosen by the f32 model
+        let mut expected = ArrayDynF::zeros(output.shape());
+        for (mut expected, output) in expected.outer_iter_mut().zip(output.outer_iter()) {
+            let class = output.iter().enumerate().fold((0, f32::NEG_INFINITY), |acc, (i, v)| if *v > acc.1 { (i, *v) } else { acc }).0;
+            expected[class] = 1.0;
+        }
+        let pairs = Pairs { inputs: inputs.clone(), expected: expected.clone() };
+
+        let report = controller.compare_quantized(&pairs, 16).unwrap();
+        assert_eq!(report.accuracy, 1.0);
+        assert!(report.agreement >= 0.9, "{:?}", report);
+        assert!((report.quantized_loss - report.loss).abs() < report.loss * 0.02, "{:?}", report);
+        assert!((report.loss - controller.test_batch(inputs.clone(), &expected).unwrap()).abs() < 0.0001);
+
+        controller.quantize().unwrap();
+        assert!(controller.is_quantized());
+        let quantized_output = controller.eval_batch(inputs.clone()).unwrap();
+        let max_diff = (&quantized_output - &output).mapv(f32::abs).fold(0.0f32, |a, &b| a.max(b));
+        assert!((max_diff - report.max_output_diff).abs() < 0.0001);
+        assert!(controller.train_batch(inputs.clone(), &expected).is_err());
+
+        controller.dequantize();
+        assert_eq!(controller.eval_batch(inputs.clone()).unwrap(), output);
+        assert_eq!(controller.eval_batch(inputs.select(Axis(0), &[0])).unwrap().shape(), &[1, 10]);
+        controller.train_batch(inputs, &expected).unwrap();
     }
 }
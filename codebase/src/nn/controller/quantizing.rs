@@ -0,0 +1,120 @@
+use std::sync::Arc;
+use crate::ArrayDynF;
+use crate::gpu::gpu_data::{get_global_gpu, GlobalGpu};
+use crate::integration::serde_utils::Pairs;
+use crate::nn::batch_config::BatchConfig;
+use crate::nn::controller::{NNController, QuantizationReport};
+use crate::nn::key_assigner::KeyAssigner;
+use crate::nn::layers::nn_layers::{forward_layer, ForwardData};
+use crate::nn::loss::loss_func::calc_loss;
+use crate::nn::quantization::{quantize_storage, QuantizedStorage};
+use crate::utils::GenericResult;
+
+/// Index of the largest value in each item of the batch
+fn argmax_batch(array: &ArrayDynF) -> Vec<usize> {
+    array.outer_iter()
+        .map(|o| o.iter()
+            .enumerate()
+            .fold((0, f32::NEG_INFINITY), |acc, (i, v)| if *v > acc.1 { (i, *v) } else { acc })
+            .0)
+        .collect()
+}
+
+impl NNController {
+    /// Convert the weights of **Dense** layers and the kernels of **Convolution** layers to int8, with
+    /// one scale per output channel. After that, evaluating the model uses int8 kernels on the CPU.
+    /// Training isn't allowed until **dequantize()** is called
+    pub fn quantize(&mut self) -> GenericResult<()> {
+        self.quantized = Some(Arc::new(self.calc_quantized()?));
+        Ok(())
+    }
+
+    /// Go back to evaluating the model with the f32 parameters
+    pub fn dequantize(&mut self) {
+        self.quantized = None;
+    }
+
+    pub fn is_quantized(&self) -> bool {
+        self.quantized.is_some()
+    }
+
+    /// Evaluate **pairs** in batches of **batch_size**, with the f32 and the quantized parameters, to
+    /// measure how much accuracy the quantization loses.
+    /// The accuracy assumes that the outputs are classes (like in the digits model)
+    pub fn compare_quantized(&self, pairs: &Pairs, batch_size: usize) -> GenericResult<QuantizationReport> {
+        if !self.heads.is_empty() {
+            return Err(anyhow::anyhow!("The model has multiple outputs, which isn't supported"));
+        }
+
+        let quantized = match &self.quantized {
+            Some(v) => v.clone(),
+            None => Arc::new(self.calc_quantized()?),
+        };
+
+        let mut loss = 0.0;
+        let mut quantized_loss = 0.0;
+        let mut max_output_diff = 0.0f32;
+        let mut correct = 0;
+        let mut quantized_correct = 0;
+        let mut agreed = 0;
+        let mut count = 0;
+        let mut values_count = 0;
+
+        for (inputs, expected) in pairs.chunks_iter(batch_size) {
+            let expected = expected.to_owned();
+            let output = self.eval_main(inputs.to_owned(), &BatchConfig::new_not_train(), get_global_gpu())?;
+            let quantized_output = self.eval_main(inputs.to_owned(), &BatchConfig::new_quantized(quantized.clone()), None)?;
+
+            loss += calc_loss(&self.loss, &expected, &output).mapv(|o| o as f64).sum();
+            quantized_loss += calc_loss(&self.loss, &expected, &quantized_output).mapv(|o| o as f64).sum();
+            max_output_diff = (&output - &quantized_output).iter().fold(max_output_diff, |acc, o| acc.max(o.abs()));
+
+            let expected_classes = argmax_batch(&expected);
+            let classes = argmax_batch(&output);
+            let quantized_classes = argmax_batch(&quantized_output);
+            for ((e, c), q) in expected_classes.iter().zip(classes.iter()).zip(quantized_classes.iter()) {
+                correct += (e == c) as usize;
+                quantized_correct += (e == q) as usize;
+                agreed += (c == q) as usize;
+            }
+            count += expected_classes.len();
+            values_count += expected.len();
+        }
+
+        if count == 0 {
+            return Err(anyhow::anyhow!("Empty dataset"));
+        }
+        let count_f = count as f64;
+        self.finish_method()?;
+        Ok(QuantizationReport {
+            loss: loss / values_count as f64,
+            quantized_loss: quantized_loss / values_count as f64,
+            max_output_diff,
+            accuracy: correct as f64 / count_f,
+            quantized_accuracy: quantized_correct as f64 / count_f,
+            agreement: agreed as f64 / count_f,
+        })
+    }
+
+    fn calc_quantized(&self) -> GenericResult<QuantizedStorage> {
+        let layers: Vec<_> = std::iter::once(&self.main_layer)
+            .chain(self.heads.iter().map(|o| &o.layer))
+            .collect();
+        quantize_storage(&layers, &self.storage)
+    }
+
+    fn eval_main(&self, inputs: ArrayDynF, config: &BatchConfig, gpu: Option<GlobalGpu>) -> GenericResult<ArrayDynF> {
+        forward_layer(
+            &self.main_layer,
+            ForwardData {
+                inputs: inputs.into(),
+                assigner: &mut KeyAssigner::new(),
+                storage: &self.storage,
+                forward_cache: None,
+                batch_config: config,
+                prev_iteration_cache: None,
+                gpu,
+            },
+        )?.into_memory()
+    }
+}
@@ -0,0 +1,191 @@
+use std::fmt::{Display, Formatter};
+use crate::nn::controller::{LayerSummary, ModelSummary, NNController};
+use crate::nn::key_assigner::KeyAssigner;
+use crate::nn::layers::nn_layers::Layer;
+use crate::nn::shape_inference::{infer_shapes, validate_shapes, ShapeError, ShapeNode};
+use crate::utils::{shape_length, GenericResult};
+
+impl NNController {
+    /// Describe every layer of the model for inputs with **input_shape** (without the batch dimension).
+    /// The shapes are inferred, so no data is needed
+    pub fn summary(&self, input_shape: &[usize]) -> GenericResult<ModelSummary> {
+        let mut assigner = KeyAssigner::new();
+        let mut layers = Vec::new();
+
+        let main = infer_shapes(&self.main_layer, input_shape)?;
+        self.summarize_node(&main, "", &mut assigner, &mut layers);
+
+        for head in self.heads.iter() {
+            let node = infer_shapes(&head.layer, &main.output)
+                .map_err(|e| e.at(&head.name))?;
+            self.summarize_node(&node, &head.name, &mut assigner, &mut layers);
+        }
+
+        Ok(ModelSummary {
+            trainable_params: layers.iter().map(|o| o.trainable_params).sum(),
+            memory: layers.iter().map(|o| o.memory).sum(),
+            flops: layers.iter().map(|o| o.flops).sum(),
+            layers,
+        })
+    }
+
+    /// Check that every layer accepts its inputs when the model receives **input_shape**
+    /// (without the batch dimension), without executing the model
+    pub fn validate_shapes(&self, input_shape: &[usize]) -> Result<(), ShapeError> {
+        validate_shapes(&self.main_layer, &self.heads, input_shape)
+    }
+
+    /// The keys are assigned in the same order as in **forward_layer()**
+    fn summarize_node(&self, root: &ShapeNode, prefix: &str, assigner: &mut KeyAssigner, result: &mut Vec<LayerSummary>) {
+        for (depth, node) in root.iter() {
+            let key = node.layer.key_name().map(|o| assigner.get_key(o));
+            let memory = key.as_ref()
+                .and_then(|o| self.storage.get(o))
+                .map(|o| o.iter().map(|o| o.len() * std::mem::size_of::<f32>()).sum())
+                .unwrap_or(0);
+
+            result.push(LayerSummary {
+                path: if prefix.is_empty() { node.path.clone() } else { format!("{}/{}", prefix, node.path) },
+                depth,
+                output_shape: node.output.clone(),
+                key,
+                trainable_params: trainable_params(node.layer),
+                memory,
+                flops: estimate_flops(node),
+            });
+        }
+    }
+}
+
+fn trainable_params(layer: &Layer) -> usize {
+    match layer {
+        Layer::Dense(c) => c.in_values * c.out_values + c.out_values,
+        Layer::Convolution(c) => c.out_channels * c.in_channels * c.kernel_size * c.kernel_size,
+        Layer::ConvTranspose(c) => c.in_channels * c.out_channels * c.kernel_size * c.kernel_size,
+        Layer::BatchNorm(c) => 2 * c.channels, // Gamma and beta
+        _ => 0,
+    }
+}
+
+/// Multiplications and additions count as 1 operation each. Element-wise functions count as 1 per
+/// element, even when they are more expensive, like **Gelu**
+fn estimate_flops(node: &ShapeNode) -> usize {
+    let inputs = shape_length(&node.input);
+    let outputs = shape_length(&node.output);
+    match node.layer {
+        Layer::Dense(c) => 2 * c.in_values * c.out_values + c.out_values,
+        Layer::Convolution(c) => 2 * outputs * c.in_channels * c.kernel_size * c.kernel_size,
+        Layer::ConvTranspose(c) => 2 * inputs * c.out_channels * c.kernel_size * c.kernel_size,
+        Layer::MaxPool(c) => outputs * c.size * c.size,
+        Layer::AvgPool(c) => outputs * c.size * c.size,
+        Layer::GlobalAvgPool => inputs,
+        Layer::Tanh | Layer::Sigmoid | Layer::Relu | Layer::LeakyRelu(_) | Layer::Elu(_) | Layer::Gelu => outputs,
+        Layer::Softmax(_) => 3 * outputs, // Exponential, sum and division
+        Layer::BatchNorm(_) => 2 * outputs,
+        Layer::Residual(_) => outputs,
+        Layer::TwoComplementsTransformer => 1,
+        _ => 0,
+    }
+}
+
+impl Display for ModelSummary {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{:<40} {:<16} {:<32} {:>12} {:>12} {:>14}", "Layer", "Output shape", "Key", "Params", "Memory", "FLOPs")?;
+        for layer in self.layers.iter() {
+            let name = layer.path.rsplit('/').next().unwrap_or_default();
+            writeln!(f, "{:<40} {:<16} {:<32} {:>12} {:>12} {:>14}",
+                     format!("{}{}", "  ".repeat(layer.depth), name),
+                     format!("{:?}", layer.output_shape),
+                     layer.key.as_deref().unwrap_or("-"),
+                     layer.trainable_params,
+                     layer.memory,
+                     layer.flops)?;
+        }
+        writeln!(f, "{:<40} {:<16} {:<32} {:>12} {:>12} {:>14}", "Total", "", "",
+                 self.trainable_params, self.memory, self.flops)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::integration::layers_loading::load_model_xml;
+    use crate::nn::controller::NNController;
+
+    #[test]
+    fn test_summary() {
+        let str = r###"<AIModel>
+    <LossFunc><Mse/></LossFunc>
+    <Layer>
+        <Sequential>
+            <ExpandDim dim="0"/>
+            <Convolution in_channels="1" out_channels="4" kernel_size="3" stride="1" padding="1">
+                <KernelsLr><Adam/></KernelsLr>
+            </Convolution>
+            <Relu/>
+            <MaxPool size="2" stride="2" padding="0"/>
+            <Flatten/>
+            <Dense in_values="64" out_values="10">
+                <WeightsLr><Adam/></WeightsLr>
+                <BiasesLr><Adam/></BiasesLr>
+            </Dense>
+            <Relu/>
+        </Sequential>
+    </Layer>
+    <Head name="value">
+        <LossFunc><Mse/></LossFunc>
+        <Layer>
+            <Dense in_values="10" out_values="1">
+                <WeightsLr><Adam/></WeightsLr>
+                <BiasesLr><Adam/></BiasesLr>
+            </Dense>
+        </Layer>
+    </Head>
+</AIModel>
+"###;
+        let config = load_model_xml(str.as_bytes()).unwrap();
+        let controller = NNController::new_with_heads(config.main_layer, config.loss_func, config.heads).unwrap();
+        let summary = controller.summary(&[8, 8]).unwrap();
+
+        let paths: Vec<_> = summary.layers.iter().map(|o| o.path.as_str()).collect();
+        assert_eq!(paths, vec![
+            "Sequential", "Sequential/ExpandDim[0]", "Sequential/Convolution[1]", "Sequential/Relu[2]",
+            "Sequential/MaxPool[3]", "Sequential/Flatten[4]", "Sequential/Dense[5]", "Sequential/Relu[6]",
+            "value/Dense",
+        ]);
+        assert_eq!(summary.layers[0].output_shape, vec![10]);
+        assert_eq!(summary.layers[2].output_shape, vec![4, 8, 8]);
+        assert_eq!(summary.layers[4].output_shape, vec![4, 4, 4]);
+        assert_eq!(summary.layers[7].key.as_deref(), Some("relu_1"));
+        assert_eq!(summary.layers[8].key.as_deref(), Some("dense_10_1_0"));
+
+        // Every parameter is in the storage
+        assert_eq!(summary.trainable_params, 4 * 9 + 64 * 10 + 10 + 10 + 1);
+        assert_eq!(summary.memory, summary.trainable_params * 4);
+        assert_eq!(summary.layers[2].flops, 2 * 4 * 8 * 8 * 9);
+        assert_eq!(summary.layers[6].flops, 2 * 64 * 10 + 10);
+
+        let storage = controller.export();
+        for layer in summary.layers.iter().filter(|o| o.trainable_params != 0) {
+            assert!(storage.contains_key(layer.key.as_ref().unwrap()));
+        }
+        assert!(summary.to_string().contains("dense_64_10_0"));
+    }
+
+    #[test]
+    fn test_summary_invalid_shape() {
+        let str = r###"<AIModel>
+    <LossFunc><Mse/></LossFunc>
+    <Layer>
+        <Sequential>
+            <Relu/>
+            <MaxPool size="2" stride="2" padding="0"/>
+        </Sequential>
+    </Layer>
+</AIModel>
+"###;
+        let config = load_model_xml(str.as_bytes()).unwrap();
+        let controller = NNController::new(config.main_layer, config.loss_func).unwrap();
+        let error = controller.summary(&[8, 8]).unwrap_err();
+        assert!(error.to_string().contains("Sequential/MaxPool[1]"));
+    }
+}
@@ -1,7 +1,6 @@
+use std::collections::HashMap;
 use ndarray::{stack, Axis};
 use crate::ArrayDynF;
-use crate::gpu::gpu_data::get_global_gpu;
-use crate::nn::batch_config::BatchConfig;
 use crate::nn::controller::NNController;
 use crate::nn::key_assigner::KeyAssigner;
 use crate::nn::layers::nn_layers::{forward_layer, ForwardData, GenericStorage};
@@ -17,10 +16,14 @@ impl NNController {
     /// Calculate the loss between **expected** and the result of the forward propagation of **inputs**.
     /// Uses GPU if available
     pub fn test_batch(&self, inputs: ArrayDynF, expected: &ArrayDynF) -> GenericResult<f64> {
-        let config = BatchConfig::new_not_train();
+        if !self.heads.is_empty() {
+            return Err(anyhow::anyhow!("The model has multiple outputs, use test_batch_heads()"));
+        }
+
+        let config = self.eval_config();
         let mut assigner = KeyAssigner::new();
         let mut forward_cache = GenericStorage::new();
-        let gpu = get_global_gpu();
+        let gpu = self.eval_gpu();
 
         let output = forward_layer(
             &self.main_layer,
@@ -42,4 +45,23 @@ impl NNController {
         self.finish_method()?;
         Ok(loss_mean)
     }
-}
\ No newline at end of file
+
+    /// Calculate the average loss of each head, keyed by the name of the head, between **expected**
+    /// and the outputs of the model for **inputs**. The weights of the heads aren't applied.
+    /// Uses GPU if available
+    pub fn test_batch_heads(&self, inputs: ArrayDynF, expected: &HashMap<String, ArrayDynF>) -> GenericResult<HashMap<String, f64>> {
+        let outputs = self.eval_batch_heads(inputs)?;
+        let mut result = HashMap::with_capacity(self.heads.len());
+
+        for head in self.heads.iter() {
+            let expected = expected.get(&head.name)
+                .ok_or_else(|| anyhow::anyhow!("Expected values for head {} not found", head.name))?;
+            let loss_mean = calc_loss(&head.loss_func, expected, &outputs[&head.name])
+                .mapv(|o| o as f64)
+                .mean()
+                .unwrap();
+            result.insert(head.name.clone(), loss_mean);
+        }
+        Ok(result)
+    }
+}
@@ -1,16 +1,36 @@
+use std::collections::HashMap;
+use std::ops::Range;
 use ndarray::{stack, Axis};
+use ndarray::parallel::prelude::*;
 use crate::ArrayDynF;
-use crate::gpu::gpu_data::get_global_gpu;
+use crate::gpu::gpu_data::{get_global_gpu, GlobalGpu};
 use crate::nn::batch_config::BatchConfig;
-use crate::nn::controller::NNController;
+use crate::nn::controller::{AccumulateResult, NNController, TrainBatchResult};
+use crate::nn::data_parallel::{average_storages, shard_ranges};
+use crate::nn::gradient_clipping::{calc_global_norm, calc_key_norms, clip_gradients};
 use crate::nn::key_assigner::KeyAssigner;
 use crate::nn::layers::nn_layers::*;
 use crate::nn::loss::loss_func::{calc_loss, calc_loss_grad};
+use crate::nn::lr_calculators::lr_schedule::increment_global_step;
 use crate::utils::GenericResult;
 
+/// Gradients and losses of a batch, before updating the parameters
+pub(super) struct BatchGradients {
+    backward_cache: GenericStorage,
+    loss: f64,
+    head_losses: HashMap<String, f64>,
+}
+
+/// Sum of the gradients and losses of the micro-batches from **accumulate_batch()**, each one
+/// multiplied by its number of items
+pub(super) struct AccumulatedGradients {
+    sums: BatchGradients,
+    samples: usize,
+}
+
 impl NNController {
     /// The same as train_batch except without the "batch" dimension in the input
-    pub fn train_one(&mut self, inputs: ArrayDynF, expected: ArrayDynF) -> GenericResult<f64> {
+    pub fn train_one(&mut self, inputs: ArrayDynF, expected: ArrayDynF) -> GenericResult<TrainBatchResult> {
         self.train_batch(
             stack![Axis(0), inputs],
             &stack![Axis(0), expected],
@@ -22,22 +42,211 @@ impl NNController {
     /// 2) Calculate the loss between the output and **expected**
     /// 3) Calculate the gradient of that loss
     /// 4) Use gradient descent to find the gradients of all parameters in all layers (backwards propagation)
-    /// 5) Update all parameters with those gradients
+    /// 5) Clip the gradients, if configured
+    /// 6) Update all parameters with those gradients
+    /// 7) Increment the global step count, used by learning rate schedules
     /// #####
-    /// Uses GPU if available.
-    /// Returns the average loss in the batch
-    pub fn train_batch(&mut self, inputs: ArrayDynF, expected: &ArrayDynF) -> GenericResult<f64> {
+    /// Uses GPU if available, unless data-parallel training is enabled with **set_data_parallel()**.
+    /// Returns the average loss in the batch and the norms of the gradients
+    pub fn train_batch(&mut self, inputs: ArrayDynF, expected: &ArrayDynF) -> GenericResult<TrainBatchResult> {
+        if !self.heads.is_empty() {
+            return Err(anyhow::anyhow!("The model has multiple outputs, use train_batch_heads()"));
+        }
+        if self.quantized.is_some() {
+            return Err(anyhow::anyhow!("The model is quantized, call dequantize() before training"));
+        }
+
+        let samples = inputs.shape()[0];
+        let gradients = self.calc_gradients_sharded(inputs, |inputs, range, gpu| {
+            let expected = expected.slice_axis(Axis(0), range.into()).to_owned();
+            self.calc_gradients(inputs, &expected, gpu)
+        })?;
+        self.apply_gradients(gradients, samples)
+    }
+
+    /// The same as train_batch, but for models with multiple outputs. **expected** should contain the
+    /// labels of every head, keyed by the name of the head. The gradients of all heads are weighted
+    /// and added before going through the main layer.
+    /// Returns the weighted sum of the average losses of the heads, the loss of each head and the
+    /// norms of the gradients
+    pub fn train_batch_heads(&mut self, inputs: ArrayDynF, expected: &HashMap<String, ArrayDynF>) -> GenericResult<TrainBatchResult> {
+        if self.heads.is_empty() {
+            return Err(anyhow::anyhow!("The model has a single output, use train_batch()"));
+        }
+        if self.quantized.is_some() {
+            return Err(anyhow::anyhow!("The model is quantized, call dequantize() before training"));
+        }
+
+        let samples = inputs.shape()[0];
+        let gradients = self.calc_gradients_sharded(inputs, |inputs, range, gpu| {
+            let expected = expected.iter()
+                .map(|(name, o)| (name.clone(), o.slice_axis(Axis(0), range.clone().into()).to_owned()))
+                .collect();
+            self.calc_gradients_heads(inputs, &expected, gpu)
+        })?;
+        self.apply_gradients(gradients, samples)
+    }
+
+    /// Calculate the gradients of a micro-batch and add them to the ones of the previous micro-batches,
+    /// without updating the parameters. Call **apply_accumulated()** to train with the average of all
+    /// of them, as if they were a single batch. **train_batch()** doesn't use the accumulated gradients
+    pub fn accumulate_batch(&mut self, inputs: ArrayDynF, expected: &ArrayDynF) -> GenericResult<AccumulateResult> {
+        if !self.heads.is_empty() {
+            return Err(anyhow::anyhow!("The model has multiple outputs, use accumulate_batch_heads()"));
+        }
+        if self.quantized.is_some() {
+            return Err(anyhow::anyhow!("The model is quantized, call dequantize() before training"));
+        }
+
+        let samples = inputs.shape()[0];
+        let gradients = self.calc_gradients_sharded(inputs, |inputs, range, gpu| {
+            let expected = expected.slice_axis(Axis(0), range.into()).to_owned();
+            self.calc_gradients(inputs, &expected, gpu)
+        })?;
+        self.accumulate(gradients, samples)
+    }
+
+    /// The same as **accumulate_batch()**, but for models with multiple outputs
+    pub fn accumulate_batch_heads(&mut self, inputs: ArrayDynF, expected: &HashMap<String, ArrayDynF>) -> GenericResult<AccumulateResult> {
+        if self.heads.is_empty() {
+            return Err(anyhow::anyhow!("The model has a single output, use accumulate_batch()"));
+        }
+        if self.quantized.is_some() {
+            return Err(anyhow::anyhow!("The model is quantized, call dequantize() before training"));
+        }
+
+        let samples = inputs.shape()[0];
+        let gradients = self.calc_gradients_sharded(inputs, |inputs, range, gpu| {
+            let expected = expected.iter()
+                .map(|(name, o)| (name.clone(), o.slice_axis(Axis(0), range.clone().into()).to_owned()))
+                .collect();
+            self.calc_gradients_heads(inputs, &expected, gpu)
+        })?;
+        self.accumulate(gradients, samples)
+    }
+
+    /// Update the parameters once with the average of the gradients accumulated since the last call,
+    /// weighted by the size of the micro-batches. The losses in the result are averages of all the items.
+    /// Learning rate calculators and schedules see a single step
+    pub fn apply_accumulated(&mut self) -> GenericResult<TrainBatchResult> {
+        let AccumulatedGradients { sums, samples } = self.accumulated.take()
+            .ok_or_else(|| anyhow::anyhow!("There are no accumulated gradients, call accumulate_batch() first"))?;
+
+        let factor = 1.0 / samples as f32;
+        let mut backward_cache = sums.backward_cache;
+        backward_cache.values_mut()
+            .flat_map(|o| o.iter_mut())
+            .for_each(|o| o.mapv_inplace(|v| v * factor));
+
+        let gradients = BatchGradients {
+            backward_cache,
+            loss: sums.loss / samples as f64,
+            head_losses: sums.head_losses.into_iter().map(|(name, loss)| (name, loss / samples as f64)).collect(),
+        };
+        self.apply_gradients(gradients, samples)
+    }
+
+    /// Number of items whose gradients are waiting for **apply_accumulated()**
+    pub fn accumulated_samples(&self) -> usize {
+        self.accumulated.as_ref().map(|o| o.samples).unwrap_or(0)
+    }
+
+    /// Add **gradients** of a micro-batch with **samples** items to the accumulated ones
+    fn accumulate(&mut self, gradients: BatchGradients, samples: usize) -> GenericResult<AccumulateResult> {
+        let result_loss = gradients.loss;
+        let result_head_losses = gradients.head_losses.clone();
+        let weight = samples as f32;
+
+        let accumulated = match self.accumulated.take() {
+            None => {
+                let mut sums = gradients;
+                sums.backward_cache.values_mut()
+                    .flat_map(|o| o.iter_mut())
+                    .for_each(|o| o.mapv_inplace(|v| v * weight));
+                sums.loss *= samples as f64;
+                sums.head_losses.values_mut().for_each(|o| *o *= samples as f64);
+                AccumulatedGradients { sums, samples }
+            }
+            Some(mut accumulated) => {
+                let sums = &mut accumulated.sums;
+                for (key, arrays) in gradients.backward_cache {
+                    // Every micro-batch goes through the same layers, so the keys and shapes are the same
+                    let target = sums.backward_cache.get_mut(&key)
+                        .ok_or_else(|| anyhow::anyhow!("Gradients of {} weren't accumulated before", key))?;
+                    for (target, array) in target.iter_mut().zip(arrays.iter()) {
+                        target.scaled_add(weight, array);
+                    }
+                }
+                sums.loss += gradients.loss * samples as f64;
+                for (name, loss) in gradients.head_losses {
+                    *sums.head_losses.entry(name).or_insert(0.0) += loss * samples as f64;
+                }
+                accumulated.samples += samples;
+                accumulated
+            }
+        };
+
+        let samples = accumulated.samples;
+        self.accumulated = Some(accumulated);
+        Ok(AccumulateResult {
+            loss: result_loss,
+            head_losses: result_head_losses,
+            samples,
+        })
+    }
+
+    /// Enable data-parallel training: each batch is split into **shards** that go through the forward
+    /// and backward propagation in parallel threads, and their gradients are averaged before updating
+    /// the parameters once. The shards don't use the GPU. Layers that depend on the whole batch,
+    /// like **BatchNorm**, only see their shard. 0 or 1 disables it, which is the default
+    pub fn set_data_parallel(&mut self, shards: usize) {
+        self.data_parallel_shards = shards;
+    }
+
+    /// Calculate the gradients of the whole batch with **calc**, which receives the inputs of a shard
+    /// and its range in the batch. The shards are processed in parallel if data-parallel training is enabled
+    fn calc_gradients_sharded<F>(&self, inputs: ArrayDynF, calc: F) -> GenericResult<BatchGradients>
+        where F: Fn(ArrayDynF, Range<usize>, Option<GlobalGpu>) -> GenericResult<BatchGradients> + Sync {
+        let batch_size = inputs.shape()[0];
+        let ranges = shard_ranges(batch_size, self.data_parallel_shards);
+        if ranges.len() <= 1 {
+            return calc(inputs, 0..batch_size, get_global_gpu());
+        }
+
+        let weights: Vec<_> = ranges.iter().map(|o| o.len() as f32).collect();
+        let results = ranges.into_par_iter()
+            .map(|range| calc(inputs.slice_axis(Axis(0), range.clone().into()).to_owned(), range, None))
+            .collect::<GenericResult<Vec<_>>>()?;
+
+        let total: f32 = weights.iter().sum();
+        let mut head_losses = HashMap::new();
+        for (result, weight) in results.iter().zip(weights.iter()) {
+            for (name, loss) in result.head_losses.iter() {
+                *head_losses.entry(name.clone()).or_insert(0.0) += loss * (weight / total) as f64;
+            }
+        }
+        let loss = results.iter().zip(weights.iter())
+            .map(|(o, weight)| o.loss * (weight / total) as f64)
+            .sum();
+
+        let caches: Vec<_> = results.into_iter().map(|o| o.backward_cache).collect();
+        let backward_cache = average_storages(&caches, &weights)
+            .ok_or_else(|| anyhow::anyhow!("The shards produced different gradients"))?;
+        Ok(BatchGradients { backward_cache, loss, head_losses })
+    }
+
+    /// Forward and backward propagation of a model with a single output
+    fn calc_gradients(&self, inputs: ArrayDynF, expected: &ArrayDynF, gpu: Option<GlobalGpu>) -> GenericResult<BatchGradients> {
         let config = BatchConfig::new_train();
         let mut assigner = KeyAssigner::new();
         let mut forward_cache = GenericStorage::new();
-        let gpu = get_global_gpu();
 
         let output = forward_layer(
             &self.main_layer,
             ForwardData {
                 inputs: inputs.into(),
                 assigner: &mut assigner,
-                storage: &mut self.storage,
+                storage: &self.storage,
                 forward_cache: Some(&mut forward_cache),
                 batch_config: &config,
                 gpu: gpu.clone(),
@@ -61,25 +270,147 @@ impl NNController {
                 batch_config: &config,
                 backward_cache: &mut backward_cache,
                 forward_cache: &mut forward_cache,
-                storage: &mut self.storage,
+                storage: &self.storage,
                 assigner: &mut assigner,
                 gpu,
             },
         )?;
 
+        Ok(BatchGradients {
+            backward_cache,
+            loss: loss_mean,
+            head_losses: HashMap::new(),
+        })
+    }
+
+    /// Forward and backward propagation of a model with multiple outputs. The gradients of all heads
+    /// are weighted and added before going through the main layer
+    fn calc_gradients_heads(&self, inputs: ArrayDynF, expected: &HashMap<String, ArrayDynF>,
+                            gpu: Option<GlobalGpu>) -> GenericResult<BatchGradients> {
+        let config = BatchConfig::new_train();
+        let mut assigner = KeyAssigner::new();
+        let mut forward_cache = GenericStorage::new();
+
+        let trunk_output = forward_layer(
+            &self.main_layer,
+            ForwardData {
+                inputs: inputs.into(),
+                assigner: &mut assigner,
+                storage: &self.storage,
+                forward_cache: Some(&mut forward_cache),
+                batch_config: &config,
+                gpu: gpu.clone(),
+                prev_iteration_cache: None,
+            },
+        )?;
+
+        let mut outputs = Vec::with_capacity(self.heads.len());
+        for head in self.heads.iter() {
+            outputs.push(forward_layer(
+                &head.layer,
+                ForwardData {
+                    inputs: trunk_output.clone(),
+                    assigner: &mut assigner,
+                    storage: &self.storage,
+                    forward_cache: Some(&mut forward_cache),
+                    batch_config: &config,
+                    gpu: gpu.clone(),
+                    prev_iteration_cache: None,
+                },
+            )?.into_memory()?);
+        }
+
         assigner.revert();
 
-        train_layer(
+        let mut backward_cache = GenericStorage::new();
+        let mut loss_total = 0.0;
+        let mut head_losses = HashMap::new();
+        let mut trunk_grad: Option<ArrayDynF> = None;
+
+        // The order is the exact reverse of the forward propagation
+        for (head, output) in self.heads.iter().zip(outputs.iter()).rev() {
+            let expected = expected.get(&head.name)
+                .ok_or_else(|| anyhow::anyhow!("Expected values for head {} not found", head.name))?;
+
+            let grad = calc_loss_grad(&head.loss_func, expected, output) * head.weight;
+            let loss_mean = calc_loss(&head.loss_func, expected, output)
+                .mapv(|o| o as f64)
+                .mean()
+                .unwrap();
+            loss_total += loss_mean * head.weight as f64;
+            head_losses.insert(head.name.clone(), loss_mean);
+
+            let grad = backward_layer(
+                &head.layer,
+                BackwardData {
+                    grad,
+                    batch_config: &config,
+                    backward_cache: &mut backward_cache,
+                    forward_cache: &mut forward_cache,
+                    storage: &self.storage,
+                    assigner: &mut assigner,
+                    gpu: gpu.clone(),
+                },
+            )?.into_memory()?;
+
+            trunk_grad = Some(match trunk_grad {
+                Some(v) => v + grad,
+                None => grad,
+            });
+        }
+
+        backward_layer(
             &self.main_layer,
-            TrainData {
-                storage: &mut self.storage,
+            BackwardData {
+                grad: trunk_grad.unwrap(),
                 batch_config: &config,
-                assigner: &mut assigner,
                 backward_cache: &mut backward_cache,
+                forward_cache: &mut forward_cache,
+                storage: &self.storage,
+                assigner: &mut assigner,
+                gpu,
             },
         )?;
 
+        Ok(BatchGradients {
+            backward_cache,
+            loss: loss_total,
+            head_losses,
+        })
+    }
+
+    /// Clip the gradients, update the parameters of all layers and increment the global step
+    fn apply_gradients(&mut self, gradients: BatchGradients, samples: usize) -> GenericResult<TrainBatchResult> {
+        let BatchGradients { mut backward_cache, loss, head_losses } = gradients;
+        let key_norms = calc_key_norms(&backward_cache);
+        let grad_norm = calc_global_norm(&key_norms);
+        clip_gradients(&mut backward_cache, &self.clipping);
+
+        let config = BatchConfig::new_train();
+        let mut assigner = KeyAssigner::new();
+        let layers = std::iter::once(&self.main_layer)
+            .chain(self.heads.iter().map(|o| &o.layer));
+        for layer in layers {
+            train_layer(
+                layer,
+                TrainData {
+                    storage: &mut self.storage,
+                    batch_config: &config,
+                    assigner: &mut assigner,
+                    backward_cache: &mut backward_cache,
+                    options: TrainOptions { lr_multiplier: self.lr_multiplier, ..TrainOptions::default() },
+                },
+            )?;
+        }
+
+        increment_global_step(&mut self.storage);
         self.finish_method()?;
-        Ok(loss_mean)
+        Ok(TrainBatchResult {
+            loss,
+            grad_norm,
+            key_norms,
+            head_losses,
+            samples,
+        })
     }
-}
\ No newline at end of file
+}
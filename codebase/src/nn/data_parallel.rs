@@ -0,0 +1,76 @@
+use std::ops::Range;
+use ndarray::Axis;
+use crate::nn::generic_storage::combine_storages;
+use crate::nn::layers::nn_layers::GenericStorage;
+
+/// Split **batch_size** items into **shards** contiguous ranges, with sizes that differ by at most 1.
+/// There are never empty ranges, so there may be less than **shards** ranges
+pub fn shard_ranges(batch_size: usize, shards: usize) -> Vec<Range<usize>> {
+    let shards = shards.clamp(1, batch_size.max(1));
+    let (size, remainder) = (batch_size / shards, batch_size % shards);
+
+    let mut start = 0;
+    (0..shards)
+        .map(|index| {
+            let end = start + size + usize::from(index < remainder);
+            let range = start..end;
+            start = end;
+            range
+        })
+        .filter(|o| !o.is_empty())
+        .collect()
+}
+
+/// Weighted average of storages with the same keys and shapes, like the backward caches of the shards
+/// of a batch. Returns None if the keys or shapes differ
+pub fn average_storages(storages: &[GenericStorage], weights: &[f32]) -> Option<GenericStorage> {
+    if storages.is_empty() || storages.len() != weights.len() {
+        return None;
+    }
+
+    let refs: Vec<_> = storages.iter().collect();
+    let mut combined = combine_storages(&refs)?;
+    let total: f32 = weights.iter().sum();
+
+    for arrays in combined.values_mut() {
+        for array in arrays.iter_mut() {
+            let mut result = array.index_axis(Axis(0), 0).to_owned() * (weights[0] / total);
+            for (part, weight) in array.outer_iter().zip(weights.iter()).skip(1) {
+                result.scaled_add(weight / total, &part);
+            }
+            *array = result;
+        }
+    }
+    Some(combined)
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::array;
+    use crate::utils::arrays_almost_equal;
+    use super::*;
+
+    #[test]
+    fn test_shard_ranges() {
+        assert_eq!(shard_ranges(10, 4), vec![0..3, 3..6, 6..8, 8..10]);
+        assert_eq!(shard_ranges(2, 4), vec![0..1, 1..2]);
+        assert_eq!(shard_ranges(5, 1), vec![0..5]);
+        assert_eq!(shard_ranges(5, 0), vec![0..5]);
+        assert!(shard_ranges(0, 4).is_empty());
+    }
+
+    #[test]
+    fn test_average_storages() {
+        let mut first = GenericStorage::new();
+        first.insert("a".to_owned(), vec![array![1.0, 2.0].into_dyn(), array![0.0].into_dyn()]);
+        let mut second = GenericStorage::new();
+        second.insert("a".to_owned(), vec![array![4.0, 8.0].into_dyn(), array![3.0].into_dyn()]);
+
+        let result = average_storages(&[first.clone(), second.clone()], &[2.0, 1.0]).unwrap();
+        assert!(arrays_almost_equal(&result["a"][0], &array![2.0, 4.0].into_dyn()));
+        assert!(arrays_almost_equal(&result["a"][1], &array![1.0].into_dyn()));
+
+        second.insert("b".to_owned(), vec![]);
+        assert!(average_storages(&[first, second], &[1.0, 1.0]).is_none());
+    }
+}
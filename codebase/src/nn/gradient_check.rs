@@ -0,0 +1,396 @@
+use std::collections::HashMap;
+use ndarray_rand::rand_distr::Normal;
+use ndarray_rand::RandomExt;
+use crate::nn::batch_config::BatchConfig;
+use crate::nn::gradient_clipping::is_gradient_key;
+use crate::nn::key_assigner::KeyAssigner;
+use crate::nn::layers::nn_layers::*;
+use crate::nn::shape_inference::infer_shapes;
+use crate::utils::{ArrayDynF, GenericResult, shape_length};
+
+#[derive(Clone, Debug)]
+pub struct GradientCheckConfig {
+    /// Step used to calculate the numeric derivatives with central differences
+    pub delta: f32,
+    /// Maximum error accepted, relative to the size of the gradients
+    pub tolerance: f32,
+    /// Whether the layer runs in training mode. Layers that aren't deterministic in training mode
+    /// (like **Dropout**) can only be checked with this disabled
+    pub training: bool,
+}
+
+impl Default for GradientCheckConfig {
+    fn default() -> Self {
+        Self {
+            delta: 0.001,
+            tolerance: 0.01,
+            training: true,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct GradientCheckResult {
+    /// Largest difference between the analytic and the numeric inputs gradient, relative to the
+    /// largest numeric value
+    pub inputs_error: f32,
+    /// Error of each parameter, keyed by "{storage key}[{index}]". Calculated like **inputs_error**,
+    /// after undoing the factor the layer applies to the gradient
+    pub params_errors: HashMap<String, f32>,
+    /// Error of the direction of each parameter gradient, ignoring its size. Only informative: a
+    /// small direction error with a large error means the factor of the layer is wrong
+    pub params_direction_errors: HashMap<String, f32>,
+}
+
+/// Compare the gradients calculated by `backward()` with numeric ones, calculated by changing each
+/// value slightly and running `forward()` again. The value being derived is sum(output * grad),
+/// where grad is a random array that is also fed to `backward()`.
+///
+/// The parameters gradients in the **backward_cache** are averaged (by the batch size, for example)
+/// with factors that depend on the layer, so they are divided by the factor documented in the
+/// `backward()` of their layer before being compared.
+/// Returns an error describing the first gradient that doesn't match.
+pub fn check_gradients(layer: &Layer, inputs: &ArrayDynF, mut storage: GenericStorage,
+                       config: &GradientCheckConfig) -> GenericResult<GradientCheckResult> {
+    init_layer(layer, InitData {
+        assigner: &mut KeyAssigner::new(),
+        storage: &mut storage,
+    })?;
+
+    let batch_config = if config.training { BatchConfig::new_train() } else { BatchConfig::new_not_train() };
+    let mut assigner = KeyAssigner::new();
+    let mut forward_cache = GenericStorage::new();
+    let output = forward_layer(layer, ForwardData {
+        inputs: inputs.clone().into(),
+        assigner: &mut assigner,
+        storage: &storage,
+        forward_cache: Some(&mut forward_cache),
+        batch_config: &batch_config,
+        prev_iteration_cache: None,
+        gpu: None,
+    })?.into_memory()?;
+
+    let grad = ArrayDynF::random(output.shape(), Normal::new(0.0, 1.0)?);
+
+    assigner.revert();
+    let mut backward_cache = GenericStorage::new();
+    let inputs_grad = backward_layer(layer, BackwardData {
+        grad: grad.clone(),
+        assigner: &mut assigner,
+        storage: &storage,
+        forward_cache: &mut forward_cache,
+        backward_cache: &mut backward_cache,
+        batch_config: &batch_config,
+        gpu: None,
+    })?.into_memory()?;
+
+    if inputs_grad.shape() != inputs.shape() {
+        return Err(anyhow::anyhow!("Inputs gradient has shape {:?}, but the inputs have shape {:?}",
+            inputs_grad.shape(), inputs.shape()));
+    }
+
+    let calc = |inputs: &ArrayDynF, storage: &GenericStorage| -> GenericResult<f64> {
+        let output = forward_layer(layer, ForwardData {
+            inputs: inputs.clone().into(),
+            assigner: &mut KeyAssigner::new(),
+            storage,
+            forward_cache: None,
+            batch_config: &batch_config,
+            prev_iteration_cache: None,
+            gpu: None,
+        })?.into_memory()?;
+        Ok(output.iter().zip(grad.iter()).map(|(o, g)| (o * g) as f64).sum())
+    };
+
+    let mut numeric_inputs_grad = ArrayDynF::zeros(inputs.shape());
+    let mut changed = inputs.clone();
+    for ((index, value), numeric) in inputs.indexed_iter().zip(numeric_inputs_grad.iter_mut()) {
+        changed[&index] = value + config.delta;
+        let plus = calc(&changed, &storage)?;
+        changed[&index] = value - config.delta;
+        let minus = calc(&changed, &storage)?;
+        changed[&index] = *value;
+        *numeric = ((plus - minus) / (2.0 * config.delta as f64)) as f32;
+    }
+
+    let inputs_error = calc_abs_error(&inputs_grad, &numeric_inputs_grad);
+    if inputs_error > config.tolerance {
+        return Err(anyhow::anyhow!("Inputs gradient differs from the numeric one by {}:\n{:?}\n----------\n{:?}",
+            inputs_error, inputs_grad, numeric_inputs_grad));
+    }
+
+    let mut params_errors = HashMap::new();
+    let mut params_direction_errors = HashMap::new();
+    let factors = params_grad_factors(layer, inputs.shape())?;
+    for (key, grads) in backward_cache.iter().filter(|(key, _)| is_gradient_key(key)) {
+        for (param_index, param_grad) in grads.iter().enumerate() {
+            let name = format!("{}[{}]", key, param_index);
+            let params = storage.get(key).and_then(|o| o.get(param_index))
+                .ok_or_else(|| anyhow::anyhow!("Parameter {} not found in the storage", name))?
+                .clone();
+
+            let mut changed = storage.clone();
+            let mut numeric_grad = ArrayDynF::zeros(params.shape());
+            for ((index, value), numeric) in params.indexed_iter().zip(numeric_grad.iter_mut()) {
+                changed.get_mut(key).unwrap()[param_index][&index] = value + config.delta;
+                let plus = calc(inputs, &changed)?;
+                changed.get_mut(key).unwrap()[param_index][&index] = value - config.delta;
+                let minus = calc(inputs, &changed)?;
+                changed.get_mut(key).unwrap()[param_index][&index] = *value;
+                *numeric = ((plus - minus) / (2.0 * config.delta as f64)) as f32;
+            }
+
+            let factor = factors.get(key).and_then(|o| o.get(param_index))
+                .ok_or_else(|| anyhow::anyhow!("The factor of the gradient of {} is unknown", name))?;
+            let error = calc_abs_error(&(param_grad / *factor), &numeric_grad);
+            let direction_error = calc_direction_error(param_grad, &numeric_grad);
+            if error > config.tolerance {
+                return Err(anyhow::anyhow!("Gradient of {} (divided by {}) differs from the numeric one by {} \
+                    (direction error {}):\n{:?}\n----------\n{:?}",
+                    name, factor, error, direction_error, param_grad, numeric_grad));
+            }
+            params_errors.insert(name.clone(), error);
+            params_direction_errors.insert(name, direction_error);
+        }
+    }
+
+    Ok(GradientCheckResult { inputs_error, params_errors, params_direction_errors })
+}
+
+/// Factors that each layer applies to the gradients of its parameters, keyed like the
+/// **backward_cache**. They are the means over the batch (and positions) described in the
+/// `backward()` of each layer
+fn params_grad_factors(layer: &Layer, inputs_shape: &[usize]) -> GenericResult<HashMap<String, Vec<f32>>> {
+    let root = infer_shapes(layer, &inputs_shape[1..])?;
+    let batch = inputs_shape[0];
+    let mut assigner = KeyAssigner::new();
+    let mut result = HashMap::new();
+
+    // The keys are assigned in the same order as in **forward_layer()**
+    for (_, node) in root.iter() {
+        let key = match node.layer.key_name() {
+            Some(name) => assigner.get_key(name),
+            None => continue,
+        };
+        let mean = |count: usize| 1.0 / (batch * count) as f32;
+        let factors = match node.layer {
+            // The weights are also divided by their number, to avoid exploding weights
+            Layer::Dense(c) => vec![mean(c.in_values * c.out_values), mean(1)],
+            Layer::Convolution(c) => vec![mean(shape_length(&node.output) / c.out_channels * c.kernel_size * c.kernel_size)],
+            Layer::ConvTranspose(c) => vec![mean(shape_length(&node.input) / c.in_channels)],
+            Layer::BatchNorm(c) => vec![mean(shape_length(&node.input) / c.channels); 2],
+            _ => continue,
+        };
+        result.insert(key, factors);
+    }
+    Ok(result)
+}
+
+/// Largest difference between the values, relative to the largest numeric value (or 1, if it's smaller)
+fn calc_abs_error(analytic: &ArrayDynF, numeric: &ArrayDynF) -> f32 {
+    let scale = numeric.iter().fold(1.0f32, |acc, o| acc.max(o.abs()));
+    analytic.iter().zip(numeric.iter())
+        .map(|(a, n)| (a - n).abs())
+        .fold(0.0, f32::max) / scale
+}
+
+/// Scale **analytic** to have the same norm as **numeric** and calculate the norm of the difference,
+/// relative to the norm of **numeric**
+fn calc_direction_error(analytic: &ArrayDynF, numeric: &ArrayDynF) -> f32 {
+    let analytic_norm = analytic.iter().map(|o| o * o).sum::<f32>().sqrt();
+    let numeric_norm = numeric.iter().map(|o| o * o).sum::<f32>().sqrt();
+    if numeric_norm < 0.0001 || analytic_norm < 0.0001 {
+        return (analytic_norm - numeric_norm).abs();
+    }
+
+    let factor = numeric_norm / analytic_norm;
+    let diff_norm = analytic.iter().zip(numeric.iter())
+        .map(|(a, n)| (a * factor - n).powi(2))
+        .sum::<f32>().sqrt();
+    diff_norm / numeric_norm
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use crate::nn::layers::*;
+    use crate::nn::layers::activation::*;
+    use crate::nn::layers::filtering::{avg_pool, conv_transpose, convolution, max_pool};
+    use crate::nn::lr_calculators::constant_lr::ConstantLrConfig;
+    use crate::nn::lr_calculators::lr_calculator::LrCalc;
+    use super::*;
+
+    /// Distinct values spaced far apart from each other and from 0, so small changes don't cross
+    /// the points where layers like **Relu** and **MaxPool** aren't differentiable
+    fn get_inputs(shape: &[usize]) -> ArrayDynF {
+        let len: usize = shape.iter().product();
+        let step = 3.0 / len as f32;
+        // 7919 is prime, so this is a permutation of 0..len
+        let values = (0..len).map(|o| ((o * 7919) % len) as f32 * step - 1.5 + step / 2.0).collect();
+        ArrayDynF::from_shape_vec(shape, values).unwrap()
+    }
+
+    fn lr() -> LrCalc {
+        LrCalc::Constant(ConstantLrConfig::default())
+    }
+
+    fn dense(in_values: usize, out_values: usize) -> Layer {
+        Layer::Dense(dense_layer::DenseConfig {
+            in_values,
+            out_values,
+            init_mode: dense_layer::DenseLayerInit::Random(),
+            weights_lr_calc: lr(),
+            biases_lr_calc: lr(),
+            train_options: Default::default(),
+        })
+    }
+
+    fn convolution(padding: usize, dilation: usize) -> Layer {
+        Layer::Convolution(convolution::ConvolutionConfig {
+            in_channels: 2,
+            out_channels: 3,
+            kernel_size: 3,
+            stride: 1,
+            padding,
+            dilation,
+            init_mode: convolution::ConvolutionInitMode::HeNormal(),
+            lr_calc: lr(),
+            cache: false,
+            train_options: Default::default(),
+        })
+    }
+
+    /// Every variant is listed here, so this stops compiling when a layer is added
+    fn variant_name(layer: &Layer) -> &'static str {
+        match layer {
+            Layer::Dense(_) => "Dense",
+            Layer::Sequential(_) => "Sequential",
+            Layer::Tanh => "Tanh",
+            Layer::Sigmoid => "Sigmoid",
+            Layer::Relu => "Relu",
+            Layer::LeakyRelu(_) => "LeakyRelu",
+            Layer::Elu(_) => "Elu",
+            Layer::Gelu => "Gelu",
+            Layer::Softmax(_) => "Softmax",
+            Layer::Debug(_) => "Debug",
+            Layer::Convolution(_) => "Convolution",
+            Layer::ConvTranspose(_) => "ConvTranspose",
+            Layer::MaxPool(_) => "MaxPool",
+            Layer::AvgPool(_) => "AvgPool",
+            Layer::GlobalAvgPool => "GlobalAvgPool",
+            Layer::Flatten => "Flatten",
+            Layer::ExpandDim(_) => "ExpandDim",
+            Layer::Dropout(_) => "Dropout",
+            Layer::Concat(_) => "Concat",
+            Layer::Residual(_) => "Residual",
+            Layer::TwoComplementsTransformer => "TwoComplementsTransformer",
+            Layer::BatchNorm(_) => "BatchNorm",
+        }
+    }
+    const VARIANTS_COUNT: usize = 22;
+
+    fn get_cases() -> Vec<(Layer, Vec<usize>, bool)> {
+        vec![
+            (dense(3, 4), vec![2, 3], true),
+            (Layer::Sequential(sequential_layer::SequentialConfig {
+                layers: vec![dense(3, 4), Layer::Tanh, dense(4, 2)],
+                train_options: Default::default(),
+            }), vec![2, 3], true),
+            (Layer::Tanh, vec![2, 5], true),
+            (Layer::Sigmoid, vec![2, 5], true),
+            (Layer::Relu, vec![2, 5], true),
+            (Layer::LeakyRelu(leaky_relu_layer::LeakyReluConfig { alpha: 0.1 }), vec![2, 5], true),
+            (Layer::Elu(elu_layer::EluConfig { alpha: 1.0 }), vec![2, 5], true),
+            (Layer::Gelu, vec![2, 5], true),
+            (Layer::Softmax(softmax_layer::SoftmaxConfig { axis: 0 }), vec![2, 5], true),
+            (Layer::Debug(debug_layer::DebugLayerConfig {
+                tag: "gradient_check".to_owned(),
+                action: debug_layer::DebugAction::Call(|_, _, _| {}, |_, _, _| {}, |_, _, _| {}),
+            }), vec![2, 3], true),
+            (convolution(1, 1), vec![2, 2, 5, 5], true),
+            (convolution(2, 2), vec![2, 2, 5, 5], true),
+            (Layer::ConvTranspose(conv_transpose::ConvTransposeConfig {
+                in_channels: 2,
+                out_channels: 2,
+                kernel_size: 3,
+                stride: 2,
+                padding: 1,
+                init_mode: convolution::ConvolutionInitMode::HeNormal(),
+                lr_calc: lr(),
+            }), vec![2, 2, 3, 3], true),
+            (Layer::MaxPool(max_pool::MaxPoolConfig { size: 2, stride: 2, padding: 0 }), vec![2, 2, 4, 4], true),
+            (Layer::AvgPool(avg_pool::AvgPoolConfig { size: 3, stride: 2, padding: 1 }), vec![2, 2, 5, 5], true),
+            (Layer::GlobalAvgPool, vec![2, 3, 4, 4], true),
+            (Layer::Flatten, vec![2, 2, 3, 3], true),
+            (Layer::ExpandDim(expand_dim_layer::ExpandDimConfig { dim: 1 }), vec![2, 3], true),
+            // Dropout is random while training
+            (Layer::Dropout(dropout_layer::DropoutConfig { drop: 0.5 }), vec![2, 3], false),
+            (Layer::Concat(concat_layer::ConcatConfig {
+                dim: 0,
+                layers: vec![dense(3, 2), Layer::Relu],
+                train_options: Default::default(),
+            }), vec![2, 3], true),
+            (Layer::Residual(residual_layer::ResidualConfig {
+                layers: vec![dense(3, 3), Layer::Tanh],
+                projection: None,
+            }), vec![2, 3], true),
+            (Layer::Residual(residual_layer::ResidualConfig {
+                layers: vec![dense(3, 2)],
+                projection: Some(Box::new(dense(3, 2))),
+            }), vec![2, 3], true),
+            (Layer::TwoComplementsTransformer, vec![3, 2], true),
+            (Layer::BatchNorm(batch_norm_layer::BatchNormConfig {
+                channels: 3,
+                momentum: 0.9,
+                epsilon: 0.00001,
+                gamma_lr_calc: lr(),
+                beta_lr_calc: lr(),
+            }), vec![4, 3], true),
+        ]
+    }
+
+    #[test]
+    fn test_all_layers() {
+        let mut checked = HashSet::new();
+        for (layer, shape, training) in get_cases() {
+            let config = GradientCheckConfig { training, ..GradientCheckConfig::default() };
+            let result = check_gradients(&layer, &get_inputs(&shape), GenericStorage::new(), &config);
+            if let Err(e) = result {
+                panic!("{} failed the gradient check: {}", variant_name(&layer), e);
+            }
+            checked.insert(variant_name(&layer));
+        }
+        assert_eq!(checked.len(), VARIANTS_COUNT);
+    }
+
+    #[test]
+    fn test_detects_scaled_gradient() {
+        let numeric = get_inputs(&[3, 4]);
+        let factor = 1.0 / 24.0;
+        // Right direction, but twice the size
+        let analytic = &numeric * factor * 2.0;
+        assert!(calc_direction_error(&analytic, &numeric) < 0.0001);
+        assert!(calc_abs_error(&(analytic / factor), &numeric) > 0.5);
+    }
+
+    #[test]
+    fn test_params_grad_factors() {
+        let layer = Layer::Sequential(sequential_layer::SequentialConfig {
+            layers: vec![convolution(0, 1), Layer::Flatten, dense(27, 4), dense(4, 4), dense(4, 4)],
+            train_options: Default::default(),
+        });
+        let factors = params_grad_factors(&layer, &[2, 2, 5, 5]).unwrap();
+        assert_eq!(factors.len(), 4);
+        assert_eq!(factors["convolution_2_3_3_1_0_0"], vec![1.0 / (2 * 9 * 9) as f32]);
+        assert_eq!(factors["dense_27_4_0"], vec![1.0 / (2 * 27 * 4) as f32, 0.5]);
+        assert_eq!(factors["dense_4_4_1"], vec![1.0 / (2 * 16) as f32, 0.5]);
+    }
+
+    #[test]
+    fn test_detects_wrong_gradient() {
+        // Relu isn't differentiable at 0, so the numeric derivative (0.5) differs from the analytic one
+        let inputs = ArrayDynF::zeros(vec![2, 5]);
+        let result = check_gradients(&Layer::Relu, &inputs, GenericStorage::new(), &GradientCheckConfig::default());
+        assert!(result.is_err());
+    }
+}
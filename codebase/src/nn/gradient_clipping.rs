@@ -0,0 +1,98 @@
+use std::collections::HashMap;
+use crate::nn::layers::nn_layers::GenericStorage;
+
+/// Entries of the backward cache whose key ends with this suffix aren't gradients (like batch statistics),
+/// so they're ignored by the norms and clipping
+pub const NOT_GRADIENT_SUFFIX: &str = "_stats";
+
+#[derive(Clone, Debug, Default)]
+pub struct GradientClipping {
+    /// Clamp every value of the gradients to [-max_value, max_value]
+    pub max_value: Option<f32>,
+    /// Scale all gradients down if their global L2 norm is greater than this
+    pub max_norm: Option<f32>,
+}
+
+pub fn is_gradient_key(key: &str) -> bool {
+    !key.ends_with(NOT_GRADIENT_SUFFIX)
+}
+
+/// Calculate the L2 norm of the gradients of each key in the backward cache
+pub fn calc_key_norms(backward_cache: &GenericStorage) -> HashMap<String, f64> {
+    backward_cache.iter()
+        .filter(|(key, _)| is_gradient_key(key))
+        .map(|(key, arrays)| {
+            let squared: f64 = arrays.iter()
+                .flat_map(|o| o.iter())
+                .map(|o| (*o as f64) * (*o as f64))
+                .sum();
+            (key.clone(), squared.sqrt())
+        })
+        .collect()
+}
+
+/// Combine the norms of each key into the norm of all gradients
+pub fn calc_global_norm(key_norms: &HashMap<String, f64>) -> f64 {
+    key_norms.values().map(|o| o * o).sum::<f64>().sqrt()
+}
+
+/// Apply the clipping to all gradients in the backward cache. Clipping by value happens first.
+pub fn clip_gradients(backward_cache: &mut GenericStorage, config: &GradientClipping) {
+    if let Some(max_value) = config.max_value {
+        for (_, arrays) in backward_cache.iter_mut().filter(|(key, _)| is_gradient_key(key)) {
+            arrays.iter_mut().for_each(|o| o.mapv_inplace(|v| v.clamp(-max_value, max_value)));
+        }
+    }
+
+    if let Some(max_norm) = config.max_norm {
+        let norm = calc_global_norm(&calc_key_norms(backward_cache));
+        if norm > max_norm as f64 {
+            let factor = (max_norm as f64 / norm) as f32;
+            for (_, arrays) in backward_cache.iter_mut().filter(|(key, _)| is_gradient_key(key)) {
+                arrays.iter_mut().for_each(|o| o.mapv_inplace(|v| v * factor));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::array;
+    use crate::utils::arrays_almost_equal;
+    use super::*;
+
+    fn get_cache() -> GenericStorage {
+        let mut cache = GenericStorage::new();
+        cache.insert("dense_1_2_0".to_owned(), vec![array![[3.0, 0.0]].into_dyn(), array![0.0].into_dyn()]);
+        cache.insert("dense_2_1_0".to_owned(), vec![array![[0.0], [4.0]].into_dyn()]);
+        cache.insert("batch_norm_2_0_stats".to_owned(), vec![array![100.0, 100.0].into_dyn()]);
+        cache
+    }
+
+    #[test]
+    fn test_norms() {
+        let norms = calc_key_norms(&get_cache());
+        assert_eq!(norms.len(), 2);
+        assert!((norms["dense_1_2_0"] - 3.0).abs() < 0.0001);
+        assert!((calc_global_norm(&norms) - 5.0).abs() < 0.0001);
+    }
+
+    #[test]
+    fn test_clip_by_norm() {
+        let mut cache = get_cache();
+        clip_gradients(&mut cache, &GradientClipping { max_value: None, max_norm: Some(1.0) });
+
+        assert!(arrays_almost_equal(&cache["dense_1_2_0"][0], &array![[0.6, 0.0]].into_dyn()));
+        assert!(arrays_almost_equal(&cache["dense_2_1_0"][0], &array![[0.0], [0.8]].into_dyn()));
+        assert!(arrays_almost_equal(&cache["batch_norm_2_0_stats"][0], &array![100.0, 100.0].into_dyn()));
+    }
+
+    #[test]
+    fn test_clip_by_value() {
+        let mut cache = get_cache();
+        clip_gradients(&mut cache, &GradientClipping { max_value: Some(2.0), max_norm: None });
+
+        assert!(arrays_almost_equal(&cache["dense_1_2_0"][0], &array![[2.0, 0.0]].into_dyn()));
+        assert!(arrays_almost_equal(&cache["dense_2_1_0"][0], &array![[0.0], [2.0]].into_dyn()));
+    }
+}
@@ -0,0 +1,87 @@
+use crate::nn::generic_storage::remove_from_storage1;
+use crate::nn::layers::nn_layers::*;
+use crate::nn::layers::stored_array::StoredArray;
+
+#[derive(Clone, Debug)]
+pub struct EluConfig {
+    /// Value that negative inputs approach
+    pub alpha: f32,
+}
+
+/// Apply the Exponential Linear Unit (ELU) activation function.
+/// https://paperswithcode.com/method/elu
+/// That means:
+/// * For x > 0: x
+/// * For x <= 0: alpha * (exp(x) - 1)
+pub struct EluLayer;
+
+pub(crate) fn gen_name() -> String {
+    "elu".to_owned()
+}
+
+impl LayerOps<EluConfig> for EluLayer {
+    fn init(_: InitData, _: &EluConfig) -> EmptyLayerResult { Ok(()) }
+
+    fn forward(data: ForwardData, layer_config: &EluConfig) -> LayerResult {
+        let ForwardData { inputs, assigner, forward_cache, .. } = data;
+        let inputs = inputs.into_memory()?;
+        let key = assigner.get_key(gen_name());
+        let alpha = layer_config.alpha;
+
+        let result = inputs.mapv(|o| if o > 0.0 { o } else { alpha * (o.exp() - 1.0) });
+
+        if let Some(forward_cache) = forward_cache {
+            forward_cache.insert(key, vec![inputs]);
+        }
+        Ok(StoredArray::Memory { data: result })
+    }
+
+    /// The gradient of ELU is:
+    /// * For x > 0: 1
+    /// * For x <= 0: alpha * exp(x)
+    fn backward(data: BackwardData, layer_config: &EluConfig) -> LayerResult {
+        let BackwardData { assigner, forward_cache, grad, .. } = data;
+        let key = assigner.get_key(gen_name());
+        let [cache] = remove_from_storage1(forward_cache, &key);
+        let alpha = layer_config.alpha;
+        Ok(StoredArray::Memory {
+            data: grad * cache.mapv_into(|o| if o > 0.0 { 1.0 } else { alpha * o.exp() })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::array;
+    use crate::nn::batch_config::BatchConfig;
+    use crate::nn::key_assigner::KeyAssigner;
+    use crate::utils::arrays_almost_equal;
+    use super::*;
+
+    #[test]
+    fn test_forward_backward() {
+        let config = EluConfig { alpha: 2.0 };
+        let mut forward_cache = GenericStorage::new();
+        let output = EluLayer::forward(ForwardData {
+            inputs: array![[1.5, 0.0, -1.0]].into_dyn().into(),
+            batch_config: &BatchConfig::new_train(),
+            assigner: &mut KeyAssigner::new(),
+            storage: &GenericStorage::new(),
+            forward_cache: Some(&mut forward_cache),
+            prev_iteration_cache: None,
+            gpu: None,
+        }, &config).unwrap().into_memory().unwrap();
+        assert!(arrays_almost_equal(&output, &array![[1.5, 0.0, -1.264241]].into_dyn()));
+
+        let grad = EluLayer::backward(BackwardData {
+            grad: array![[2.0, 1.0, 1.0]].into_dyn(),
+            batch_config: &BatchConfig::new_train(),
+            assigner: &mut KeyAssigner::new(),
+            storage: &GenericStorage::new(),
+            forward_cache: &mut forward_cache,
+            backward_cache: &mut GenericStorage::new(),
+            gpu: None,
+        }, &config).unwrap().into_memory().unwrap();
+        assert!(arrays_almost_equal(&grad, &array![[2.0, 2.0, 0.735759]].into_dyn()));
+    }
+}
@@ -0,0 +1,89 @@
+use std::f32::consts::{FRAC_2_SQRT_PI, FRAC_1_SQRT_2};
+use crate::nn::generic_storage::remove_from_storage1;
+use crate::nn::layers::nn_layers::*;
+use crate::nn::layers::stored_array::StoredArray;
+
+/// sqrt(2 / PI)
+const SQRT_2_PI: f32 = FRAC_2_SQRT_PI * FRAC_1_SQRT_2;
+const CUBIC_COEF: f32 = 0.044715;
+
+/// Apply the Gaussian Error Linear Unit (GELU) activation function, using the tanh approximation:
+/// 0.5 * x * (1 + tanh(sqrt(2 / PI) * (x + 0.044715 * x³)))
+/// https://paperswithcode.com/method/gelu
+pub struct GeluLayer;
+
+pub(crate) fn gen_name() -> String {
+    "gelu".to_owned()
+}
+
+impl LayerOps<()> for GeluLayer {
+    fn init(_: InitData, _: &()) -> EmptyLayerResult { Ok(()) }
+
+    fn forward(data: ForwardData, _: &()) -> LayerResult {
+        let ForwardData { inputs, assigner, forward_cache, .. } = data;
+        let inputs = inputs.into_memory()?;
+        let key = assigner.get_key(gen_name());
+
+        let result = inputs.mapv(|x| 0.5 * x * (1.0 + inner_tanh(x)));
+
+        if let Some(forward_cache) = forward_cache {
+            forward_cache.insert(key, vec![inputs]);
+        }
+        Ok(StoredArray::Memory { data: result })
+    }
+
+    /// The gradient of the approximation is:
+    /// 0.5 * (1 + t) + 0.5 * x * (1 - t²) * sqrt(2 / PI) * (1 + 3 * 0.044715 * x²)
+    /// where t is the tanh term of the forward function
+    fn backward(data: BackwardData, _: &()) -> LayerResult {
+        let BackwardData { assigner, forward_cache, grad, .. } = data;
+        let key = assigner.get_key(gen_name());
+        let [cache] = remove_from_storage1(forward_cache, &key);
+
+        let derivative = cache.mapv_into(|x| {
+            let t = inner_tanh(x);
+            0.5 * (1.0 + t) + 0.5 * x * (1.0 - t * t) * SQRT_2_PI * (1.0 + 3.0 * CUBIC_COEF * x * x)
+        });
+        Ok(StoredArray::Memory { data: grad * derivative })
+    }
+}
+
+#[inline]
+fn inner_tanh(x: f32) -> f32 {
+    (SQRT_2_PI * (x + CUBIC_COEF * x * x * x)).tanh()
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::array;
+    use crate::nn::batch_config::BatchConfig;
+    use crate::nn::key_assigner::KeyAssigner;
+    use crate::utils::arrays_almost_equal;
+    use super::*;
+
+    #[test]
+    fn test_forward_backward() {
+        let mut forward_cache = GenericStorage::new();
+        let output = GeluLayer::forward(ForwardData {
+            inputs: array![[1.0, 0.0, -2.0]].into_dyn().into(),
+            batch_config: &BatchConfig::new_train(),
+            assigner: &mut KeyAssigner::new(),
+            storage: &GenericStorage::new(),
+            forward_cache: Some(&mut forward_cache),
+            prev_iteration_cache: None,
+            gpu: None,
+        }, &()).unwrap().into_memory().unwrap();
+        assert!(arrays_almost_equal(&output, &array![[0.841192, 0.0, -0.045402]].into_dyn()));
+
+        let grad = GeluLayer::backward(BackwardData {
+            grad: array![[1.0, 1.0, 1.0]].into_dyn(),
+            batch_config: &BatchConfig::new_train(),
+            assigner: &mut KeyAssigner::new(),
+            storage: &GenericStorage::new(),
+            forward_cache: &mut forward_cache,
+            backward_cache: &mut GenericStorage::new(),
+            gpu: None,
+        }, &()).unwrap().into_memory().unwrap();
+        assert!(arrays_almost_equal(&grad, &array![[1.082964, 0.5, -0.086099]].into_dyn()));
+    }
+}
@@ -0,0 +1,147 @@
+use crate::ArrayDynF;
+use crate::gpu::gpu_data::GlobalGpu;
+use crate::gpu::shader_context::{BufferConfig, ContextBinding, ShaderBinding, ShaderContext};
+use crate::gpu::shader_runner_2::ShaderRunner2;
+use crate::gpu::{BufferChecksumMethod, shaders};
+use crate::nn::generic_storage::remove_from_storage1;
+use crate::nn::layers::nn_layers::*;
+use crate::nn::layers::stored_array::StoredArray;
+use crate::utils::shape_length;
+use crate::utils::GenericResult;
+
+#[derive(Clone, Debug)]
+pub struct LeakyReluConfig {
+    /// Slope for negative inputs
+    pub alpha: f32,
+}
+
+/// Apply the Leaky ReLu activation function. Unlike ReLu, the gradient is never 0, so neurons
+/// can't "die". That means:
+/// * For x >= 0: x
+/// * For x < 0: alpha * x
+pub struct LeakyReluLayer;
+
+pub(crate) fn gen_name() -> String {
+    "leaky_relu".to_owned()
+}
+
+impl LayerOps<LeakyReluConfig> for LeakyReluLayer {
+    fn init(_: InitData, _: &LeakyReluConfig) -> EmptyLayerResult { Ok(()) }
+
+    /// Multiply numbers < 0 by alpha.
+    /// **GPU compatible**
+    fn forward(data: ForwardData, layer_config: &LeakyReluConfig) -> LayerResult {
+        let ForwardData { inputs, assigner, gpu, forward_cache, .. } = data;
+        let key = assigner.get_key(gen_name());
+
+        if let Some(forward_cache) = forward_cache {
+            forward_cache.insert(key.clone(), vec![inputs.to_memory()?]);
+        }
+
+        // Only use the GPU if the data is already there
+        if matches!(inputs, StoredArray::GpuLocal {..}) {
+            match forward_gpu(key, &inputs, gpu.unwrap(), layer_config) {
+                Ok(v) => Ok(v),
+                Err(_e) => {
+                    #[cfg(debug_assertions)]
+                    eprintln!("{}", _e);
+                    Ok(forward_cpu(inputs.into_memory()?, layer_config))
+                }
+            }
+        } else {
+            Ok(forward_cpu(inputs.into_memory()?, layer_config))
+        }
+    }
+
+    /// Multiply the gradient by alpha for inputs < 0
+    fn backward(data: BackwardData, layer_config: &LeakyReluConfig) -> LayerResult {
+        let BackwardData { assigner, forward_cache, grad, .. } = data;
+        let key = assigner.get_key(gen_name());
+
+        let [cache] = remove_from_storage1(forward_cache, &key);
+        let alpha = layer_config.alpha;
+        Ok(StoredArray::Memory {
+            data: grad * cache.mapv_into(|o| if o > 0.0 { 1.0 } else { alpha })
+        })
+    }
+}
+
+fn forward_cpu(inputs: ArrayDynF, layer_config: &LeakyReluConfig) -> StoredArray {
+    let alpha = layer_config.alpha;
+    inputs.mapv_into(|o| if o > 0.0 { o } else { o * alpha }).into()
+}
+
+fn forward_gpu(id: String, inputs: &StoredArray, gpu: GlobalGpu, layer_config: &LeakyReluConfig) -> GenericResult<StoredArray> {
+    let shape = inputs.shape().to_vec();
+    let key = (id, "forward".to_owned());
+
+    ShaderContext::register(&key, gpu.clone(), &[BufferConfig::floats(shape_length(&shape))], |mut b| {
+        b.register_shader("forward", shaders::leaky_relu_forward::load, vec![
+            (ContextBinding(0), ShaderBinding(0)),
+        ], &shaders::leaky_relu_forward::SpecializationConstants {
+            alpha: layer_config.alpha,
+        })?;
+        Ok(b)
+    })?;
+
+    let mut runner = ShaderRunner2::new(key, gpu.clone())?;
+
+    runner
+        .update_buffer_with_stored_array(ContextBinding(0), inputs, BufferChecksumMethod::None)?
+        .dispatch("forward", [shape_length(&shape) as u32, 1, 1], shaders::leaky_relu_forward::BLOCK_SIZE)?;
+    Ok(StoredArray::GpuLocal { data: runner.finish()?, gpu, shape })
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::array;
+    use crate::gpu::buffers::upload_array_to_gpu;
+    use crate::gpu::gpu_data::get_global_gpu;
+    use crate::nn::batch_config::BatchConfig;
+    use crate::nn::key_assigner::KeyAssigner;
+    use crate::utils::{Array3F, arrays_almost_equal};
+    use super::*;
+
+    #[test]
+    fn test_forward_backward_cpu() {
+        let config = LeakyReluConfig { alpha: 0.1 };
+        let mut forward_cache = GenericStorage::new();
+        let output = LeakyReluLayer::forward(ForwardData {
+            inputs: array![[2.0, -3.0, 0.5, -1.0]].into_dyn().into(),
+            batch_config: &BatchConfig::new_train(),
+            assigner: &mut KeyAssigner::new(),
+            storage: &GenericStorage::new(),
+            forward_cache: Some(&mut forward_cache),
+            prev_iteration_cache: None,
+            gpu: None,
+        }, &config).unwrap().into_memory().unwrap();
+        assert!(arrays_almost_equal(&output, &array![[2.0, -0.3, 0.5, -0.1]].into_dyn()));
+
+        let grad = LeakyReluLayer::backward(BackwardData {
+            grad: array![[1.0, 1.0, 2.0, 2.0]].into_dyn(),
+            batch_config: &BatchConfig::new_train(),
+            assigner: &mut KeyAssigner::new(),
+            storage: &GenericStorage::new(),
+            forward_cache: &mut forward_cache,
+            backward_cache: &mut GenericStorage::new(),
+            gpu: None,
+        }, &config).unwrap().into_memory().unwrap();
+        assert!(arrays_almost_equal(&grad, &array![[1.0, 0.1, 2.0, 0.2]].into_dyn()));
+    }
+
+    #[test]
+    fn test_forward_gpu() {
+        let inputs = Array3F::from_shape_vec((2, 2, 2),
+                                             vec![1.0, 2.0, 3.0, -1.0, -9.0, 0.0, -1.5, 1.0]).unwrap().into_dyn();
+        let expected_array = Array3F::from_shape_vec((2, 2, 2),
+                                                     vec![1.0, 2.0, 3.0, -0.5, -4.5, 0.0, -0.75, 1.0]).unwrap().into_dyn();
+
+        let gpu = get_global_gpu().unwrap();
+        let inputs = StoredArray::GpuLocal { data: upload_array_to_gpu(&inputs, &gpu).unwrap(), shape: inputs.shape().to_vec(), gpu: gpu.clone() };
+
+        let output = forward_gpu("test_forward_gpu".to_owned(), &inputs, gpu, &LeakyReluConfig { alpha: 0.5 })
+            .unwrap().into_memory().unwrap();
+
+        assert!(arrays_almost_equal(&output, &expected_array));
+    }
+}
@@ -1,3 +1,7 @@
 pub mod relu_layer;
 pub mod sigmoid_layer;
-pub mod tanh_layer;
\ No newline at end of file
+pub mod tanh_layer;
+pub mod leaky_relu_layer;
+pub mod elu_layer;
+pub mod gelu_layer;
+pub mod softmax_layer;
@@ -14,7 +14,7 @@ use crate::utils::GenericResult;
 /// * For x < 0: 0
 pub(crate) struct ReluLayer;
 
-fn gen_name() -> String {
+pub(crate) fn gen_name() -> String {
     "relu".to_owned()
 }
 
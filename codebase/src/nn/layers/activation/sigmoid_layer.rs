@@ -6,7 +6,7 @@ use crate::nn::layers::stored_array::*;
 /// https://en.wikipedia.org/wiki/Sigmoid_function
 pub struct SigmoidLayer;
 
-fn gen_name() -> String {
+pub(crate) fn gen_name() -> String {
     "sigmoid".to_owned()
 }
 
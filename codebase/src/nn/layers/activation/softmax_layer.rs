@@ -0,0 +1,123 @@
+use ndarray::Axis;
+use crate::nn::generic_storage::remove_from_storage1;
+use crate::nn::layers::nn_layers::*;
+use crate::nn::layers::stored_array::StoredArray;
+use crate::utils::ArrayDynF;
+
+#[derive(Clone, Debug)]
+pub struct SoftmaxConfig {
+    /// Axis along which the values will sum 1. Ignores the batch dimension, so 0 is the first axis
+    /// after the batch
+    pub axis: usize,
+}
+
+/// Convert the values along an axis into probabilities that sum 1. Note that **CrossEntropy** loss
+/// already applies softmax internally.
+/// https://en.wikipedia.org/wiki/Softmax_function
+pub struct SoftmaxLayer;
+
+pub(crate) fn gen_name(config: &SoftmaxConfig) -> String {
+    format!("softmax_{}", config.axis)
+}
+
+impl LayerOps<SoftmaxConfig> for SoftmaxLayer {
+    fn init(_: InitData, _: &SoftmaxConfig) -> EmptyLayerResult { Ok(()) }
+
+    /// Calculate exp(x - max) / sum(exp(x - max)) along the axis. Subtracting the max doesn't change
+    /// the result but avoids overflows
+    fn forward(data: ForwardData, layer_config: &SoftmaxConfig) -> LayerResult {
+        let ForwardData { inputs, assigner, forward_cache, .. } = data;
+        let inputs = inputs.into_memory()?;
+        let key = assigner.get_key(gen_name(layer_config));
+        let axis = Axis(layer_config.axis + 1);
+
+        if axis.index() >= inputs.ndim() {
+            return Err(anyhow::anyhow!("Softmax axis {} is out of bounds for shape {:?}", layer_config.axis, inputs.shape()));
+        }
+
+        let max = inputs.map_axis(axis, |o| o.fold(f32::MIN, |acc, v| acc.max(*v)))
+            .insert_axis(axis);
+        let exp = (inputs - max).mapv_into(f32::exp);
+        let sum = exp.sum_axis(axis).insert_axis(axis);
+        let result: ArrayDynF = exp / sum;
+
+        if let Some(forward_cache) = forward_cache {
+            forward_cache.insert(key, vec![result.clone()]);
+        }
+        Ok(StoredArray::Memory { data: result })
+    }
+
+    /// Multiply the gradient by the Jacobian of softmax, which simplifies to:
+    /// y * (grad - sum(grad * y))
+    fn backward(data: BackwardData, layer_config: &SoftmaxConfig) -> LayerResult {
+        let BackwardData { assigner, forward_cache, grad, .. } = data;
+        let key = assigner.get_key(gen_name(layer_config));
+        let axis = Axis(layer_config.axis + 1);
+        let [cache] = remove_from_storage1(forward_cache, &key);
+
+        let dot = (&grad * &cache).sum_axis(axis).insert_axis(axis);
+        Ok(StoredArray::Memory { data: cache * (grad - dot) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::array;
+    use crate::nn::batch_config::BatchConfig;
+    use crate::nn::key_assigner::KeyAssigner;
+    use crate::utils::{Array3F, arrays_almost_equal};
+    use super::*;
+
+    fn forward(inputs: &ArrayDynF, config: &SoftmaxConfig, cache: Option<&mut GenericStorage>) -> ArrayDynF {
+        SoftmaxLayer::forward(ForwardData {
+            inputs: inputs.clone().into(),
+            batch_config: &BatchConfig::new_train(),
+            assigner: &mut KeyAssigner::new(),
+            storage: &GenericStorage::new(),
+            forward_cache: cache,
+            prev_iteration_cache: None,
+            gpu: None,
+        }, config).unwrap().into_memory().unwrap()
+    }
+
+    #[test]
+    fn test_forward() {
+        let inputs = array![[1.0, 2.0, 3.0], [1000.0, 1000.0, 1000.0]].into_dyn();
+        let expected = array![[0.090031, 0.244728, 0.665241], [0.333333, 0.333333, 0.333333]].into_dyn();
+        let output = forward(&inputs, &SoftmaxConfig { axis: 0 }, None);
+        assert!(arrays_almost_equal(&output, &expected));
+    }
+
+    #[test]
+    fn test_backward() {
+        let config = SoftmaxConfig { axis: 0 };
+        let inputs = Array3F::from_shape_fn((2, 3, 2), |(a, b, c)| (a * 6 + b * 2 + c) as f32 * 0.3 - 1.5).into_dyn();
+        let grad = Array3F::from_shape_fn((2, 3, 2), |(a, b, c)| ((a + b * 3 + c * 5) % 4) as f32 - 1.5).into_dyn();
+
+        let mut forward_cache = GenericStorage::new();
+        forward(&inputs, &config, Some(&mut forward_cache));
+        let result = SoftmaxLayer::backward(BackwardData {
+            grad: grad.clone(),
+            batch_config: &BatchConfig::new_train(),
+            assigner: &mut KeyAssigner::new(),
+            storage: &GenericStorage::new(),
+            forward_cache: &mut forward_cache,
+            backward_cache: &mut GenericStorage::new(),
+            gpu: None,
+        }, &config).unwrap().into_memory().unwrap();
+
+        // Compare with the derivative of sum(grad * output) calculated with finite differences
+        let delta = 0.01;
+        let mut expected = ArrayDynF::zeros(inputs.shape());
+        for (index, value) in expected.indexed_iter_mut() {
+            let mut plus = inputs.clone();
+            plus[&index] += delta;
+            let mut minus = inputs.clone();
+            minus[&index] -= delta;
+            let diff = (forward(&plus, &config, None) - forward(&minus, &config, None)) * &grad;
+            *value = diff.sum() / (2.0 * delta);
+        }
+
+        assert!(arrays_almost_equal(&result, &expected));
+    }
+}
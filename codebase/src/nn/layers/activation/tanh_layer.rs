@@ -6,7 +6,7 @@ use crate::nn::layers::stored_array::StoredArray;
 /// https://pt.wikipedia.org/wiki/Tangente_hiperb%C3%B3lica
 pub struct TanhLayer;
 
-fn gen_name() -> String {
+pub(crate) fn gen_name() -> String {
     "tanh".to_owned()
 }
 
@@ -0,0 +1,350 @@
+use std::ops::AddAssign;
+use ndarray::{Axis, IxDyn};
+use crate::nn::generic_storage::*;
+use crate::nn::gradient_clipping::NOT_GRADIENT_SUFFIX;
+use crate::nn::layers::nn_layers::*;
+use crate::nn::layers::stored_array::StoredArray;
+use crate::nn::lr_calculators::lr_calculator::{apply_lr_calc, reserve_lr_calc_keys, LrCalc, LrCalcData};
+use crate::utils::{Array1F, ArrayDynF};
+
+#[derive(Clone, Debug)]
+pub struct BatchNormConfig {
+    pub channels: usize,
+    /// Weight of the previous running statistics when they're updated after each training batch
+    pub momentum: f32,
+    /// Small value added to the variance to avoid dividing by zero
+    pub epsilon: f32,
+    pub gamma_lr_calc: LrCalc,
+    pub beta_lr_calc: LrCalc,
+}
+
+/// Normalizes each channel (the axis right after the batch) to have mean 0 and variance 1, and then
+/// scales and shifts the result by gamma and beta.
+/// While training, uses the mean and the variance of the current batch, and keeps running averages
+/// of them in the storage. When not training, uses the running averages instead.
+/// Accepts inputs with shape (Batch, Channels, ...).
+/// https://en.wikipedia.org/wiki/Batch_normalization
+/// ### Trainable
+/// * Gamma
+/// * Beta
+pub struct BatchNormLayer;
+
+pub(crate) fn gen_name(config: &BatchNormConfig) -> String {
+    format!("batch_norm_{}", config.channels)
+}
+
+/// Sum all values in the same channel, resulting in an array with shape (Channels)
+fn sum_per_channel(array: &ArrayDynF) -> Array1F {
+    let mut result = array.clone();
+    for axis in (2..array.ndim()).rev() {
+        result = result.sum_axis(Axis(axis));
+    }
+    result.sum_axis(Axis(0)).into_dimensionality().unwrap()
+}
+
+/// Reshape an array with shape (Channels) to be broadcast to an array with **ndim** dimensions
+fn per_channel(values: &Array1F, ndim: usize) -> ArrayDynF {
+    let mut shape = vec![1; ndim];
+    shape[1] = values.len();
+    values.clone().into_shape(IxDyn(&shape)).unwrap()
+}
+
+impl LayerOps<BatchNormConfig> for BatchNormLayer {
+    fn init(data: InitData, layer_config: &BatchNormConfig) -> EmptyLayerResult {
+        let InitData { assigner, storage } = data;
+        let key = assigner.get_key(gen_name(layer_config));
+
+        if let std::collections::hash_map::Entry::Vacant(e) = storage.entry(key) {
+            let channels = layer_config.channels;
+            e.insert(vec![
+                Array1F::ones(channels).into_dyn(), // Gamma
+                Array1F::zeros(channels).into_dyn(), // Beta
+                Array1F::zeros(channels).into_dyn(), // Running mean
+                Array1F::ones(channels).into_dyn(), // Running variance
+            ]);
+        }
+
+        Ok(())
+    }
+
+    /// Calculate (inputs - mean) / sqrt(variance + epsilon) * gamma + beta
+    fn forward(data: ForwardData, layer_config: &BatchNormConfig) -> LayerResult {
+        let ForwardData { inputs, assigner, storage, forward_cache, batch_config, .. } = data;
+        let key = assigner.get_key(gen_name(layer_config));
+        let inputs = inputs.into_memory()?;
+        let ndim = inputs.ndim();
+
+        if ndim < 2 || inputs.shape()[1] != layer_config.channels {
+            return Err(anyhow::anyhow!("Expected inputs with {} channels in axis 1, but got shape {:?}",
+                layer_config.channels, inputs.shape()));
+        }
+
+        let params = &storage[&key];
+        let gamma: Array1F = params[0].clone().into_dimensionality()?;
+        let beta: Array1F = params[1].clone().into_dimensionality()?;
+
+        let (mean, var) = if batch_config.is_training {
+            let count = (inputs.len() / layer_config.channels) as f32;
+            let mean = sum_per_channel(&inputs) / count;
+            let centered = &inputs - &per_channel(&mean, ndim);
+            let var = sum_per_channel(&(&centered * &centered)) / count;
+            (mean, var)
+        } else {
+            (params[2].clone().into_dimensionality()?, params[3].clone().into_dimensionality()?)
+        };
+
+        let inv_std = var.mapv(|o| 1.0 / (o + layer_config.epsilon).sqrt());
+        let normalized = (inputs - per_channel(&mean, ndim)) * per_channel(&inv_std, ndim);
+        let result = &normalized * &per_channel(&gamma, ndim) + per_channel(&beta, ndim);
+
+        if let Some(forward_cache) = forward_cache {
+            forward_cache.insert(key, vec![normalized, inv_std.into_dyn(), mean.into_dyn(), var.into_dyn()]);
+        }
+
+        Ok(StoredArray::Memory { data: result })
+    }
+
+    /// Calculates gamma's error as the mean of the gradient multiplied by the normalized inputs,
+    /// and beta's error as the mean of the gradient.
+    /// The batch statistics are also sent to `train()` in a separate key, so the running averages
+    /// can be updated.
+    fn backward(data: BackwardData, layer_config: &BatchNormConfig) -> LayerResult {
+        let BackwardData { assigner, storage, forward_cache, backward_cache, grad, .. } = data;
+        let key = assigner.get_key(gen_name(layer_config));
+        let ndim = grad.ndim();
+        let count = (grad.len() / layer_config.channels) as f32;
+
+        let [normalized, inv_std, mean, var] = remove_from_storage4(forward_cache, &key);
+        let inv_std: Array1F = inv_std.into_dimensionality()?;
+        let gamma: Array1F = storage[&key][0].clone().into_dimensionality()?;
+
+        let grad_sum = sum_per_channel(&grad);
+        let grad_normalized_sum = sum_per_channel(&(&grad * &normalized));
+
+        // Standard formula of the inputs' gradient with the batch mean and variance as functions of the inputs
+        let factor = per_channel(&(&gamma * &inv_std / count), ndim);
+        let inputs_grad = factor * (&grad * count
+            - per_channel(&grad_sum, ndim)
+            - &normalized * &per_channel(&grad_normalized_sum, ndim));
+
+        backward_cache.insert(format!("{}{}", key, NOT_GRADIENT_SUFFIX), vec![mean, var]);
+        backward_cache.insert(key, vec![
+            (grad_normalized_sum / count).into_dyn(),
+            (grad_sum / count).into_dyn(),
+        ]);
+
+        Ok(StoredArray::Memory { data: inputs_grad })
+    }
+}
+
+impl TrainableLayerOps<BatchNormConfig> for BatchNormLayer {
+    fn train(data: TrainData, layer_config: &BatchNormConfig) -> EmptyLayerResult {
+        let TrainData { backward_cache, assigner, storage, batch_config, options } = data;
+        let key = assigner.get_key(gen_name(layer_config));
+
+        let [gamma_grad, beta_grad] = remove_from_storage2(backward_cache, &key);
+        let [batch_mean, batch_var] = remove_from_storage2(backward_cache, &format!("{}{}", key, NOT_GRADIENT_SUFFIX));
+        // The running statistics are also kept
+        if options.frozen {
+            reserve_lr_calc_keys(&layer_config.gamma_lr_calc, assigner);
+            reserve_lr_calc_keys(&layer_config.beta_lr_calc, assigner);
+            return Ok(());
+        }
+
+        let gamma_grad = apply_lr_calc(
+            &layer_config.gamma_lr_calc,
+            gamma_grad,
+            LrCalcData {
+                batch_config,
+                storage,
+                assigner,
+                param_key: &key,
+                param_index: 0,
+                lr_multiplier: options.lr_multiplier,
+            },
+        )?.into_memory()?;
+
+        let beta_grad = apply_lr_calc(
+            &layer_config.beta_lr_calc,
+            beta_grad,
+            LrCalcData {
+                batch_config,
+                storage,
+                assigner,
+                param_key: &key,
+                param_index: 1,
+                lr_multiplier: options.lr_multiplier,
+            },
+        )?.into_memory()?;
+
+        get_mut_from_storage(storage, &key, 0).add_assign(&gamma_grad);
+        get_mut_from_storage(storage, &key, 1).add_assign(&beta_grad);
+
+        let momentum = layer_config.momentum;
+        let running_mean = get_mut_from_storage(storage, &key, 2);
+        *running_mean = &*running_mean * momentum + batch_mean * (1.0 - momentum);
+        let running_var = get_mut_from_storage(storage, &key, 3);
+        *running_var = &*running_var * momentum + batch_var * (1.0 - momentum);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::array;
+    use ndarray_rand::rand_distr::Normal;
+    use ndarray_rand::RandomExt;
+    use crate::nn::batch_config::BatchConfig;
+    use crate::nn::key_assigner::KeyAssigner;
+    use crate::nn::lr_calculators::constant_lr::ConstantLrConfig;
+    use crate::utils::{Array2F, Array4F, arrays_almost_equal};
+    use super::*;
+
+    fn get_config(channels: usize) -> BatchNormConfig {
+        BatchNormConfig {
+            channels,
+            momentum: 0.9,
+            epsilon: 0.00001,
+            gamma_lr_calc: LrCalc::Constant(ConstantLrConfig::default()),
+            beta_lr_calc: LrCalc::Constant(ConstantLrConfig::default()),
+        }
+    }
+
+    fn get_storage(config: &BatchNormConfig) -> GenericStorage {
+        let mut storage = GenericStorage::new();
+        BatchNormLayer::init(InitData {
+            assigner: &mut KeyAssigner::new(),
+            storage: &mut storage,
+        }, config).unwrap();
+        storage
+    }
+
+    #[test]
+    fn test_forward_train() {
+        let config = get_config(3);
+        let storage = get_storage(&config);
+        let inputs = Array4F::random((8, 3, 4, 4), Normal::new(5.0, 3.0).unwrap()).into_dyn();
+
+        let result = BatchNormLayer::forward(ForwardData {
+            inputs: inputs.into(),
+            batch_config: &BatchConfig::new_train(),
+            assigner: &mut KeyAssigner::new(),
+            storage: &storage,
+            forward_cache: None,
+            prev_iteration_cache: None,
+            gpu: None,
+        }, &config).unwrap().into_memory().unwrap();
+
+        let count = (result.len() / 3) as f32;
+        let mean = sum_per_channel(&result) / count;
+        let var = sum_per_channel(&(&result * &result)) / count;
+        assert!(arrays_almost_equal(&mean, &Array1F::zeros(3)));
+        assert!(arrays_almost_equal(&var, &Array1F::ones(3)));
+    }
+
+    #[test]
+    fn test_forward_not_train() {
+        let config = get_config(2);
+        let mut storage = get_storage(&config);
+        storage.get_mut("batch_norm_2_0").unwrap()[2] = array![1.0, -1.0].into_dyn();
+        storage.get_mut("batch_norm_2_0").unwrap()[3] = array![4.0, 0.25].into_dyn();
+        let inputs = array![[3.0, 0.0], [-1.0, -1.5]].into_dyn();
+        let expected = array![[1.0, 2.0], [-1.0, -1.0]].into_dyn();
+
+        let result = BatchNormLayer::forward(ForwardData {
+            inputs: inputs.into(),
+            batch_config: &BatchConfig::new_not_train(),
+            assigner: &mut KeyAssigner::new(),
+            storage: &storage,
+            forward_cache: None,
+            prev_iteration_cache: None,
+            gpu: None,
+        }, &config).unwrap().into_memory().unwrap();
+
+        assert!(arrays_almost_equal(&result, &expected));
+    }
+
+    #[test]
+    fn test_backward() {
+        let config = get_config(2);
+        let storage = get_storage(&config);
+        let inputs = array![[1.0, 2.0], [3.0, 6.0], [5.0, 1.0]].into_dyn();
+        let grad = array![[0.5, -1.0], [0.2, 0.3], [-0.4, 0.1]].into_dyn();
+        let mut forward_cache = GenericStorage::new();
+        let mut backward_cache = GenericStorage::new();
+
+        BatchNormLayer::forward(ForwardData {
+            inputs: inputs.clone().into(),
+            batch_config: &BatchConfig::new_train(),
+            assigner: &mut KeyAssigner::new(),
+            storage: &storage,
+            forward_cache: Some(&mut forward_cache),
+            prev_iteration_cache: None,
+            gpu: None,
+        }, &config).unwrap();
+
+        let result = BatchNormLayer::backward(BackwardData {
+            grad: grad.clone(),
+            batch_config: &BatchConfig::new_train(),
+            assigner: &mut KeyAssigner::new(),
+            storage: &storage,
+            forward_cache: &mut forward_cache,
+            backward_cache: &mut backward_cache,
+            gpu: None,
+        }, &config).unwrap().into_memory().unwrap();
+
+        // Compare with the derivative of sum(grad * output) calculated with finite differences
+        let calc = |inputs: &ArrayDynF| -> f32 {
+            let output = BatchNormLayer::forward(ForwardData {
+                inputs: inputs.clone().into(),
+                batch_config: &BatchConfig::new_train(),
+                assigner: &mut KeyAssigner::new(),
+                storage: &storage,
+                forward_cache: None,
+                prev_iteration_cache: None,
+                gpu: None,
+            }, &config).unwrap().into_memory().unwrap();
+            (output * &grad).sum()
+        };
+        let delta = 0.01;
+        let expected = Array2F::from_shape_fn((3, 2), |(b, c)| {
+            let mut plus = inputs.clone();
+            plus[[b, c]] += delta;
+            let mut minus = inputs.clone();
+            minus[[b, c]] -= delta;
+            (calc(&plus) - calc(&minus)) / (2.0 * delta)
+        }).into_dyn();
+
+        assert!(arrays_almost_equal(&result, &expected));
+        assert_eq!(backward_cache["batch_norm_2_0"].len(), 2);
+        assert_eq!(backward_cache["batch_norm_2_0_stats"].len(), 2);
+    }
+
+    #[test]
+    fn test_train_updates_running_stats() {
+        let config = get_config(2);
+        let mut storage = get_storage(&config);
+        let mut backward_cache = GenericStorage::new();
+        backward_cache.insert("batch_norm_2_0".to_owned(), vec![
+            Array1F::zeros(2).into_dyn(),
+            Array1F::zeros(2).into_dyn(),
+        ]);
+        backward_cache.insert("batch_norm_2_0_stats".to_owned(), vec![
+            array![10.0, -10.0].into_dyn(),
+            array![3.0, 5.0].into_dyn(),
+        ]);
+
+        BatchNormLayer::train(TrainData {
+            batch_config: &BatchConfig::new_train(),
+            assigner: &mut KeyAssigner::new(),
+            storage: &mut storage,
+            backward_cache: &mut backward_cache,
+            options: TrainOptions::default(),
+        }, &config).unwrap();
+
+        let params = &storage["batch_norm_2_0"];
+        assert!(arrays_almost_equal(&params[2], &array![1.0, -1.0].into_dyn()));
+        assert!(arrays_almost_equal(&params[3], &array![1.2, 1.4].into_dyn()));
+    }
+}
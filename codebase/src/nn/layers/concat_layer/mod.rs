@@ -11,9 +11,11 @@ pub struct ConcatLayer;
 pub struct ConcatConfig {
     pub dim: usize,
     pub layers: Vec<Layer>,
+    /// Applied to all the layers
+    pub train_options: TrainOptions,
 }
 
-fn gen_name(config: &ConcatConfig) -> String {
+pub(crate) fn gen_name(config: &ConcatConfig) -> String {
     format!("concat_{}", config.dim)
 }
 
@@ -39,12 +41,14 @@ impl LayerOps<ConcatConfig> for ConcatLayer {
 
 impl TrainableLayerOps<ConcatConfig> for ConcatLayer {
     fn train(data: TrainData, layer_config: &ConcatConfig) -> EmptyLayerResult {
+        let options = data.options.combine(&layer_config.train_options);
         for layer in &layer_config.layers {
             train_layer(layer, TrainData {
                 storage: data.storage,
                 batch_config: data.batch_config,
                 backward_cache: data.backward_cache,
                 assigner: data.assigner,
+                options,
             })?;
         }
         Ok(())
@@ -72,10 +76,11 @@ mod tests {
         let config = ConcatConfig {
             dim: 0,
             layers: vec![
-                Layer::Sequential(SequentialConfig { layers: vec![] }),
-                Layer::Sequential(SequentialConfig { layers: vec![] }),
-                Layer::Sequential(SequentialConfig { layers: vec![] }),
+                Layer::Sequential(SequentialConfig { layers: vec![], train_options: Default::default() }),
+                Layer::Sequential(SequentialConfig { layers: vec![], train_options: Default::default() }),
+                Layer::Sequential(SequentialConfig { layers: vec![], train_options: Default::default() }),
             ],
+            train_options: Default::default(),
         };
 
         let cache = &mut GenericStorage::new();
@@ -109,10 +114,11 @@ mod tests {
         let config = ConcatConfig {
             dim: 0,
             layers: vec![
-                Layer::Sequential(SequentialConfig { layers: vec![] }),
-                Layer::Sequential(SequentialConfig { layers: vec![] }),
-                Layer::Sequential(SequentialConfig { layers: vec![] }),
+                Layer::Sequential(SequentialConfig { layers: vec![], train_options: Default::default() }),
+                Layer::Sequential(SequentialConfig { layers: vec![], train_options: Default::default() }),
+                Layer::Sequential(SequentialConfig { layers: vec![], train_options: Default::default() }),
             ],
+            train_options: Default::default(),
         };
         let result = ConcatLayer::backward(BackwardData {
             forward_cache: &mut forward_cache,
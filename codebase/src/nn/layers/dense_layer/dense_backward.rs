@@ -96,6 +96,7 @@ mod tests {
             weights_lr_calc: LrCalc::Constant(ConstantLrConfig { lr: 0.05 }),
             biases_lr_calc: LrCalc::Constant(ConstantLrConfig { lr: 0.05 }),
             init_mode: DenseLayerInit::WeightsAndBiases(weights, biases),
+            train_options: Default::default(),
         };
 
         let mut storage = GenericStorage::new();
@@ -3,6 +3,7 @@ use crate::gpu::gpu_data::GlobalGpu;
 use crate::gpu::shader_context::{BufferConfig, ContextBinding, ShaderBinding, ShaderContext};
 use crate::gpu::shader_runner_2::ShaderRunner2;
 use crate::nn::layers::dense_layer::*;
+use crate::nn::layers::dense_layer::dense_quantized::forward_quantized;
 use crate::nn::layers::nn_layers::*;
 use crate::nn::layers::stored_array::StoredArray;
 use crate::utils::{Array1F, Array2F, GenericResult, shape_length};
@@ -16,6 +17,7 @@ pub fn forward(data: ForwardData, layer_config: &DenseConfig) -> LayerResult {
         inputs,
         forward_cache,
         gpu,
+        batch_config,
         ..
     } = data;
     let ish = inputs.shape();
@@ -32,6 +34,9 @@ pub fn forward(data: ForwardData, layer_config: &DenseConfig) -> LayerResult {
     }
 
     let [weights, biases] = get_from_storage2(storage, &key);
+    if let Some(quantized) = batch_config.quantized.as_ref().and_then(|o| o.get(&key)) {
+        return Ok(forward_quantized(inputs.into_memory()?, quantized, biases)?.into());
+    }
 
     let result = if matches!(inputs, StoredArray::GpuLocal {..}) {
         match forward_gpu(key, &inputs, weights, biases, gpu.unwrap(), layer_config) {
@@ -159,6 +164,7 @@ mod tests {
             weights_lr_calc: LrCalc::Constant(ConstantLrConfig::default()),
             biases_lr_calc: LrCalc::Constant(ConstantLrConfig::default()),
             init_mode: DenseLayerInit::Random(),
+            train_options: Default::default(),
         };
         let gpu = get_global_gpu().unwrap();
 
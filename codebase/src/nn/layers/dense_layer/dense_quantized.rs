@@ -0,0 +1,49 @@
+use ndarray::parallel::prelude::*;
+use ndarray::{ArrayView1, ArrayView2, Axis, Zip};
+use crate::nn::quantization::QuantizedArray;
+use crate::utils::{Array2F, ArrayDynF, GenericResult};
+
+/// Same as the regular forward, but with the weights and the inputs converted to int8. The products
+/// are accumulated in i32 and only converted back to f32 (with the scales) at the end.
+/// The inputs are quantized with one scale for each batch item
+pub fn forward_quantized(inputs: ArrayDynF, weights: &QuantizedArray, biases: &ArrayDynF) -> GenericResult<ArrayDynF> {
+    let weights_values: ArrayView2<i8> = weights.values.view().into_dimensionality()?;
+    let biases: ArrayView1<f32> = biases.view().into_dimensionality()?;
+    let inputs = QuantizedArray::quantize(&inputs);
+    let inputs_values: ArrayView2<i8> = inputs.values.view().into_dimensionality()?;
+
+    let mut result = Array2F::zeros((inputs_values.len_of(Axis(0)), weights_values.len_of(Axis(0))));
+    Zip::from(result.outer_iter_mut())
+        .and(inputs_values.outer_iter())
+        .and(&inputs.scales)
+        .into_par_iter()
+        .for_each(|(mut result, inputs, inputs_scale)| {
+            for (index, (weights, weights_scale)) in weights_values.outer_iter().zip(weights.scales.iter()).enumerate() {
+                let sum: i32 = weights.iter()
+                    .zip(inputs.iter())
+                    .map(|(a, b)| *a as i32 * *b as i32)
+                    .sum();
+                result[index] = sum as f32 * inputs_scale * weights_scale + biases[index];
+            }
+        });
+
+    Ok(result.into_dyn())
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::array;
+    use super::*;
+
+    #[test]
+    fn test_forward_quantized() {
+        let weights = array![[0.5, -1.0, 0.25], [2.0, 0.0, -0.1]];
+        let biases = array![0.1, -0.2];
+        let inputs = array![[1.0, 2.0, -3.0], [0.3, -0.6, 0.05]];
+        let expected = inputs.dot(&weights.t()) + &biases;
+
+        let result = forward_quantized(inputs.into_dyn(), &QuantizedArray::quantize(&weights.into_dyn()), &biases.into_dyn()).unwrap();
+        let max_diff = (&result - &expected.into_dyn()).mapv(f32::abs).fold(0.0f32, |a, &b| a.max(b));
+        assert!(max_diff < 0.05, "{}", max_diff);
+    }
+}
@@ -1,9 +1,10 @@
 mod dense_forward;
 mod dense_backward;
+mod dense_quantized;
 
 use crate::nn::generic_storage::*;
 use crate::nn::layers::nn_layers::*;
-use crate::nn::lr_calculators::lr_calculator::{apply_lr_calc, LrCalc, LrCalcData};
+use crate::nn::lr_calculators::lr_calculator::{apply_lr_calc, reserve_lr_calc_keys, LrCalc, LrCalcData};
 use crate::utils::{Array1F, Array2F, GetBatchSize};
 use ndarray::{Axis, ShapeBuilder, stack};
 use ndarray_rand::rand_distr::Normal;
@@ -18,6 +19,7 @@ pub struct DenseConfig {
     pub init_mode: DenseLayerInit,
     pub weights_lr_calc: LrCalc,
     pub biases_lr_calc: LrCalc,
+    pub train_options: TrainOptions,
 }
 
 #[derive(Clone, Debug)]
@@ -34,7 +36,7 @@ pub enum DenseLayerInit {
 /// **GPU compatible**
 pub struct DenseLayer;
 
-fn gen_name(config: &DenseConfig) -> String {
+pub(crate) fn gen_name(config: &DenseConfig) -> String {
     format!("dense_{}_{}", config.in_values, config.out_values)
 }
 
@@ -86,11 +88,17 @@ impl TrainableLayerOps<DenseConfig> for DenseLayer {
             assigner,
             storage,
             batch_config,
-            ..
+            options,
         } = data;
+        let options = options.combine(&layer_config.train_options);
         let key = assigner.get_key(gen_name(layer_config));
 
         let [weights_grad, biases_grad] = remove_from_storage2(backward_cache, &key);
+        if options.frozen {
+            reserve_lr_calc_keys(&layer_config.weights_lr_calc, assigner);
+            reserve_lr_calc_keys(&layer_config.biases_lr_calc, assigner);
+            return Ok(());
+        }
 
         let weights_grad = apply_lr_calc(
             &layer_config.weights_lr_calc,
@@ -99,6 +107,9 @@ impl TrainableLayerOps<DenseConfig> for DenseLayer {
                 batch_config,
                 storage,
                 assigner,
+                param_key: &key,
+                param_index: 0,
+                lr_multiplier: options.lr_multiplier,
             },
         )?.into_memory().unwrap();
 
@@ -109,6 +120,9 @@ impl TrainableLayerOps<DenseConfig> for DenseLayer {
                 batch_config,
                 storage,
                 assigner,
+                param_key: &key,
+                param_index: 1,
+                lr_multiplier: options.lr_multiplier,
             },
         )?.into_memory().unwrap();
 
@@ -140,6 +154,7 @@ mod tests {
                 init_mode: DenseLayerInit::Random(),
                 weights_lr_calc: LrCalc::Constant(ConstantLrConfig::default()),
                 biases_lr_calc: LrCalc::Constant(ConstantLrConfig::default()),
+                train_options: Default::default(),
             }),
             LossFunc::Mse,
         )
@@ -151,7 +166,7 @@ mod tests {
 
         for _ in 0..100 {
             let inputs = inputs.clone();
-            last_loss = controller.train_batch(inputs, &expected).unwrap();
+            last_loss = controller.train_batch(inputs, &expected).unwrap().loss;
             if first_loss.is_none() {
                 first_loss = Some(last_loss);
             }
@@ -168,6 +183,7 @@ mod tests {
             out_values: 3,
             weights_lr_calc: LrCalc::Constant(ConstantLrConfig::default()),
             biases_lr_calc: LrCalc::Constant(ConstantLrConfig::default()),
+            train_options: Default::default(),
         }
     }
 }
@@ -11,7 +11,7 @@ pub struct DropoutConfig {
     pub drop: f32,
 }
 
-fn gen_name(config: &DropoutConfig) -> String {
+pub(crate) fn gen_name(config: &DropoutConfig) -> String {
     format!("dropout_{}", config.drop)
 }
 
@@ -0,0 +1,69 @@
+use ndarray::s;
+use crate::Array4F;
+use crate::nn::generic_storage::remove_from_storage1;
+use crate::nn::layers::filtering::avg_pool::{gen_name, AvgPoolConfig};
+use crate::nn::layers::filtering::remove_padding_4d;
+use crate::nn::layers::nn_layers::*;
+
+/// Distribute the gradient of each position equally to all inputs in its area
+pub fn backward(data: BackwardData, layer_config: &AvgPoolConfig) -> LayerResult {
+    let BackwardData { forward_cache, assigner, grad, .. } = data;
+    let grad: Array4F = grad.into_dimensionality()?;
+
+    let key = assigner.get_key(gen_name());
+    let [shape] = remove_from_storage1(forward_cache, &key);
+    let shape: Vec<_> = shape.iter().map(|o| o.round() as usize).collect();
+
+    let size = layer_config.size;
+    let stride = layer_config.stride;
+    let padding = layer_config.padding;
+    let area_size = (size * size) as f32;
+    let [_, _, grad_height, grad_width]: [usize; 4] = grad.shape().try_into()?;
+
+    let mut result = Array4F::zeros((shape[0], shape[1], shape[2] + 2 * padding, shape[3] + 2 * padding));
+
+    result.outer_iter_mut().enumerate().for_each(|(b, mut batch)| {
+        batch.outer_iter_mut().enumerate().for_each(|(c, mut channel)| {
+            for h in 0..grad_height {
+                for w in 0..grad_width {
+                    let h_offset = h * stride;
+                    let w_offset = w * stride;
+                    let mut area = channel.slice_mut(s![h_offset..(h_offset + size), w_offset..(w_offset + size)]);
+                    area += grad[(b, c, h, w)] / area_size;
+                }
+            }
+        })
+    });
+
+    let result = remove_padding_4d(result, padding);
+    Ok(result.into_dyn().into())
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::array;
+    use crate::nn::batch_config::BatchConfig;
+    use crate::nn::key_assigner::KeyAssigner;
+    use crate::utils::arrays_almost_equal;
+    use super::*;
+
+    #[test]
+    fn test_backprop() {
+        let mut forward_cache = GenericStorage::new();
+        forward_cache.insert("avg_pool_0".to_owned(), vec![array![1.0, 1.0, 3.0, 3.0].into_dyn()]);
+        let grad = Array4F::from_shape_vec((1, 1, 2, 2), vec![4.0, 8.0, -4.0, 0.0]).unwrap().into_dyn();
+        let expected = Array4F::from_shape_vec((1, 1, 3, 3), vec![1.0, 3.0, 2.0, 0.0, 2.0, 2.0, -1.0, -1.0, 0.0]).unwrap().into_dyn();
+
+        let result = backward(BackwardData {
+            grad,
+            assigner: &mut KeyAssigner::new(),
+            storage: &GenericStorage::new(),
+            forward_cache: &mut forward_cache,
+            backward_cache: &mut GenericStorage::new(),
+            batch_config: &BatchConfig::new_train(),
+            gpu: None,
+        }, &AvgPoolConfig { size: 2, stride: 1, padding: 0 }).unwrap().into_memory().unwrap();
+
+        assert!(arrays_almost_equal(&result, &expected));
+    }
+}
@@ -0,0 +1,57 @@
+use ndarray::s;
+use crate::Array4F;
+use crate::nn::layers::filtering::avg_pool::{gen_name, AvgPoolConfig};
+use crate::nn::layers::filtering::pad4d;
+use crate::nn::layers::nn_layers::{ForwardData, LayerResult};
+use crate::nn::layers::stored_array::StoredArray;
+use crate::utils::{get_dims_after_filter_4, Array1F};
+
+pub fn forward(data: ForwardData, layer_config: &AvgPoolConfig) -> LayerResult {
+    let ForwardData { inputs, forward_cache, assigner, .. } = data;
+    let inputs: Array4F = inputs.into_memory()?.into_dimensionality()?;
+
+    let key = assigner.get_key(gen_name());
+    if let Some(forward_cache) = forward_cache {
+        // Only the shape is needed in backward()
+        let shape: Array1F = inputs.shape().iter().map(|o| *o as f32).collect();
+        forward_cache.insert(key, vec![shape.into_dyn()]);
+    }
+
+    Ok(forward_cpu(inputs, layer_config.size, layer_config.stride, layer_config.padding))
+}
+
+fn forward_cpu(inputs: Array4F, size: usize, stride: usize, padding: usize) -> StoredArray {
+    let inputs = pad4d(inputs, padding);
+    let [batch_size, channels, new_height, new_width] = get_dims_after_filter_4(inputs.shape(), size, stride);
+    let area_size = (size * size) as f32;
+
+    Array4F::from_shape_fn((batch_size, channels, new_height, new_width), |(b, c, h, w)| {
+        let h_offset = h * stride;
+        let w_offset = w * stride;
+        // Slice is the area that the filter encompasses
+        let area = inputs.slice(s![b, c, h_offset..(h_offset + size), w_offset..(w_offset + size)]);
+        area.sum() / area_size
+    }).into_dyn().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::nn::layers::filtering::avg_pool::tests::{create_forward_outputs, create_inputs};
+    use crate::utils::arrays_almost_equal;
+    use super::*;
+
+    #[test]
+    fn test_forward_2x2() {
+        let inputs = create_inputs().into_dimensionality().unwrap();
+        let result = forward_cpu(inputs, 2, 2, 0).into_memory().unwrap();
+        assert!(arrays_almost_equal(&result, &create_forward_outputs()));
+    }
+
+    #[test]
+    fn test_forward_padding() {
+        let inputs = Array4F::ones((1, 1, 2, 2));
+        let result = forward_cpu(inputs, 2, 1, 1).into_memory().unwrap();
+        let expected = Array4F::from_shape_vec((1, 1, 3, 3), vec![0.25, 0.5, 0.25, 0.5, 1.0, 0.5, 0.25, 0.5, 0.25]).unwrap().into_dyn();
+        assert!(arrays_almost_equal(&result, &expected));
+    }
+}
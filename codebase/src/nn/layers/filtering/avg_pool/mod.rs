@@ -0,0 +1,72 @@
+mod avg_pool_forward;
+mod avg_pool_backward;
+
+use crate::nn::layers::nn_layers::*;
+
+#[derive(Clone, Debug)]
+pub struct AvgPoolConfig {
+    pub size: usize,
+    pub stride: usize,
+    pub padding: usize,
+}
+
+/// Apply AVERAGE operation with 2D filters, That means passing a filter through the last
+/// 2 dimension of the input (usually height and width). In position of the filter, the mean
+/// of those input values is computed. Requires a 4 dimensional input (one being the batch).
+/// Padded values count as 0 in the mean.
+/// https://paperswithcode.com/method/average-pooling
+pub struct AvgPoolLayer;
+
+pub(crate) fn gen_name() -> String {
+    "avg_pool".to_owned()
+}
+
+impl LayerOps<AvgPoolConfig> for AvgPoolLayer {
+    fn init(_: InitData, _: &AvgPoolConfig) -> EmptyLayerResult { Ok(()) }
+
+    fn forward(data: ForwardData, layer_config: &AvgPoolConfig) -> LayerResult {
+        avg_pool_forward::forward(data, layer_config)
+    }
+
+    fn backward(data: BackwardData, layer_config: &AvgPoolConfig) -> LayerResult {
+        avg_pool_backward::backward(data, layer_config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::{array, Axis, stack};
+    use crate::utils::{Array3F, ArrayDynF};
+
+    pub fn create_inputs() -> ArrayDynF {
+        let arr: Array3F = array![
+            [
+                [1.0, 2.0, 3.0, 4.0],
+                [5.0, 6.0, 7.0, 8.0],
+                [9.0, 10.0, 11.0, 12.0],
+                [-8.0, 0.0, 0.0, 4.0]
+            ],
+            [
+                [-1.0, 3.0, -5.0, 1.0],
+                [2.0, 4.0, -99.0, 32.0],
+                [16.0, 69.0, -69.0, 1.0],
+                [-8.0, 0.0, 0.0, 4.0]
+            ],
+        ];
+        stack![Axis(0), arr, arr].into_dyn()
+    }
+
+    pub fn create_forward_outputs() -> ArrayDynF {
+        let result: Array3F = array![
+            [
+                [3.5, 5.5],
+                [2.75, 6.75]
+            ],
+            [
+                [2.0, -17.75],
+                [19.25, -16.0]
+            ]
+        ];
+        stack![Axis(0), result, result].into_dyn()
+    }
+}
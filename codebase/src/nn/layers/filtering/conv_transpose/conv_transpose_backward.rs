@@ -0,0 +1,160 @@
+use ndarray::parallel::prelude::*;
+use ndarray::{Axis, s, Zip};
+use crate::Array4F;
+use crate::nn::generic_storage::{clone_from_storage1, remove_from_storage1};
+use crate::nn::layers::filtering::conv_transpose::{ConvTransposeConfig, gen_name};
+use crate::nn::layers::filtering::pad4d;
+use crate::nn::layers::nn_layers::{BackwardData, LayerResult};
+use crate::utils::{Array1F, Array2F, GenericResult};
+
+pub fn backward(data: BackwardData, layer_config: &ConvTransposeConfig) -> LayerResult {
+    let BackwardData {
+        assigner, forward_cache, storage,
+        grad, backward_cache, ..
+    } = data;
+
+    let key = assigner.get_key(gen_name(layer_config));
+
+    let [kernel] = clone_from_storage1(storage, &key);
+    let kernel: Array4F = kernel.into_dimensionality()?;
+
+    let [inputs] = remove_from_storage1(forward_cache, &key);
+    let inputs: Array4F = inputs.into_dimensionality()?;
+
+    // The padding was removed from the result, so the gradient of those positions is 0
+    let grad = pad4d(grad.into_dimensionality()?, layer_config.padding);
+
+    let kernel_grad = calc_kernel_grad(&inputs, &grad, layer_config)?;
+    backward_cache.insert(key, vec![kernel_grad.into_dyn()]);
+
+    Ok(cpu_inputs_grad(&inputs, &grad, &kernel, layer_config)?.into_dyn().into())
+}
+
+/// Like in **Convolution**, the gradient is the mean over the batch and the positions of the inputs
+pub fn calc_kernel_grad(inputs: &Array4F, grad: &Array4F, layer_config: &ConvTransposeConfig) -> GenericResult<Array4F> {
+    let ConvTransposeConfig { in_channels, out_channels, kernel_size, stride, .. } = layer_config;
+    let [batch, _, height, width]: [usize; 4] = inputs.shape().try_into()?;
+    let area_len = out_channels * kernel_size * kernel_size;
+
+    let sum = (0..height * width)
+        .into_par_iter()
+        .map(|o| (o / width, o % width))
+        .map(|(h, w)| {
+            let h_offset = h * stride;
+            let w_offset = w * stride;
+            let values = inputs.slice(s![.., .., h, w]);
+            let area = grad.slice(s![
+                ..,
+                ..,
+                h_offset..(h_offset + kernel_size),
+                w_offset..(w_offset + kernel_size)
+            ]);
+            let area: Array2F = area.to_owned().into_shape((batch, area_len)).unwrap();
+            values.t().dot(&area)
+        })
+        .reduce(|| Array2F::zeros((*in_channels, area_len)), |a, b| a + b);
+
+    let sum = sum.into_shape((*in_channels, *out_channels, *kernel_size, *kernel_size))?;
+    Ok(sum / (batch * height * width) as f32)
+}
+
+/// Each input value receives the sum of the gradient of the area it affected, multiplied by the kernel
+pub fn cpu_inputs_grad(inputs: &Array4F, grad: &Array4F, kernel: &Array4F, layer_config: &ConvTransposeConfig) -> GenericResult<Array4F> {
+    let ConvTransposeConfig { in_channels, out_channels, kernel_size, stride, .. } = layer_config;
+    let area_len = out_channels * kernel_size * kernel_size;
+    let kernel: Array2F = kernel.clone().into_shape((*in_channels, area_len))?;
+
+    let mut result = Array4F::zeros(inputs.raw_dim());
+    let height = inputs.len_of(Axis(2));
+    let width = inputs.len_of(Axis(3));
+
+    Zip::from(grad.outer_iter())
+        .and(result.outer_iter_mut())
+        .into_par_iter()
+        .for_each(|(grad, mut result)| {
+            for h in 0..height {
+                for w in 0..width {
+                    let h_offset = h * stride;
+                    let w_offset = w * stride;
+                    let area = grad.slice(s![
+                        ..,
+                        h_offset..(h_offset + kernel_size),
+                        w_offset..(w_offset + kernel_size)
+                    ]);
+                    let area: Array1F = area.iter().copied().collect();
+                    result.slice_mut(s![.., h, w]).assign(&kernel.dot(&area));
+                }
+            }
+        });
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::nn::batch_config::BatchConfig;
+    use crate::nn::key_assigner::KeyAssigner;
+    use crate::nn::layers::filtering::conv_transpose::conv_transpose_forward::cpu_forward;
+    use crate::nn::layers::filtering::convolution::ConvolutionInitMode;
+    use crate::nn::layers::nn_layers::GenericStorage;
+    use crate::nn::lr_calculators::constant_lr::ConstantLrConfig;
+    use crate::nn::lr_calculators::lr_calculator::LrCalc;
+    use crate::utils::arrays_almost_equal;
+    use super::*;
+
+    #[test]
+    fn test_backward() {
+        let config = ConvTransposeConfig {
+            in_channels: 2,
+            out_channels: 3,
+            kernel_size: 3,
+            stride: 2,
+            padding: 1,
+            init_mode: ConvolutionInitMode::HeNormal(),
+            lr_calc: LrCalc::Constant(ConstantLrConfig::default()),
+        };
+        let inputs = Array4F::from_shape_fn((2, 2, 3, 3), |(b, c, h, w)| ((b * 7 + c * 5 + h * 3 + w) % 11) as f32 / 10.0 - 0.5);
+        let kernel = Array4F::from_shape_fn((2, 3, 3, 3), |(i, o, h, w)| ((i * 5 + o * 3 + h * 2 + w) % 7) as f32 / 7.0 - 0.4);
+        // Output has size (3 - 1) * 2 + 3 - 2 * 1 = 5
+        let grad = Array4F::from_shape_fn((2, 3, 5, 5), |(b, c, h, w)| ((b + c * 2 + h * 3 + w * 5) % 9) as f32 / 9.0 - 0.5);
+
+        let mut storage = GenericStorage::new();
+        storage.insert("conv_transpose_2_3_3_2_1_0".to_owned(), vec![kernel.clone().into_dyn()]);
+        let mut forward_cache = GenericStorage::new();
+        forward_cache.insert("conv_transpose_2_3_3_2_1_0".to_owned(), vec![inputs.clone().into_dyn()]);
+        let mut backward_cache = GenericStorage::new();
+
+        let result = backward(BackwardData {
+            grad: grad.clone().into_dyn(),
+            batch_config: &BatchConfig::new_train(),
+            assigner: &mut KeyAssigner::new(),
+            storage: &storage,
+            forward_cache: &mut forward_cache,
+            backward_cache: &mut backward_cache,
+            gpu: None,
+        }, &config).unwrap().into_memory().unwrap();
+
+        // Compare with the derivatives of sum(grad * output) calculated with finite differences
+        let calc = |inputs: &Array4F, kernel: &Array4F| -> f32 {
+            (cpu_forward(inputs, kernel, &config).unwrap() * &grad).sum()
+        };
+        let delta = 0.01;
+        let expected_inputs_grad = Array4F::from_shape_fn(inputs.raw_dim(), |index| {
+            let mut plus = inputs.clone();
+            plus[index] += delta;
+            let mut minus = inputs.clone();
+            minus[index] -= delta;
+            (calc(&plus, &kernel) - calc(&minus, &kernel)) / (2.0 * delta)
+        });
+        let expected_kernel_grad = Array4F::from_shape_fn(kernel.raw_dim(), |index| {
+            let mut plus = kernel.clone();
+            plus[index] += delta;
+            let mut minus = kernel.clone();
+            minus[index] -= delta;
+            (calc(&inputs, &plus) - calc(&inputs, &minus)) / (2.0 * delta) / (2 * 3 * 3) as f32
+        });
+
+        assert!(arrays_almost_equal(&result, &expected_inputs_grad.into_dyn()));
+        assert!(arrays_almost_equal(&backward_cache["conv_transpose_2_3_3_2_1_0"][0], &expected_kernel_grad.into_dyn()));
+    }
+}
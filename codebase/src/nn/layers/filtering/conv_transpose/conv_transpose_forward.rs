@@ -0,0 +1,149 @@
+use std::ops::AddAssign;
+use ndarray::parallel::prelude::*;
+use ndarray::{s, Zip};
+use crate::Array4F;
+use crate::nn::generic_storage::clone_from_storage1;
+use crate::nn::layers::filtering::conv_transpose::{ConvTransposeConfig, gen_name};
+use crate::nn::layers::filtering::remove_padding_4d;
+use crate::nn::layers::nn_layers::{ForwardData, LayerResult};
+use crate::utils::{Array2F, Array3F, GenericResult};
+
+pub fn forward(data: ForwardData, layer_config: &ConvTransposeConfig) -> LayerResult {
+    let ForwardData { inputs, storage, assigner, forward_cache, .. } = data;
+    let key = assigner.get_key(gen_name(layer_config));
+
+    let inputs = inputs.into_memory()?;
+    let [kernel] = clone_from_storage1(storage, &key);
+    let result = cpu_forward(&inputs.clone().into_dimensionality()?, &kernel.into_dimensionality()?, layer_config)?;
+
+    if let Some(forward_cache) = forward_cache {
+        forward_cache.insert(key, vec![inputs]);
+    }
+
+    Ok(result.into_dyn().into())
+}
+
+pub fn cpu_forward(inputs: &Array4F, kernel: &Array4F, layer_config: &ConvTransposeConfig) -> GenericResult<Array4F> {
+    let ConvTransposeConfig { in_channels, out_channels, kernel_size, stride, padding, .. } = layer_config;
+    let [batch, channels, height, width]: [usize; 4] = inputs.shape().try_into()?;
+    if channels != *in_channels {
+        return Err(anyhow::anyhow!("Expected inputs with {} channels in axis 1, but got shape {:?}",
+            in_channels, inputs.shape()));
+    }
+    if height == 0 || width == 0 {
+        return Err(anyhow::anyhow!("Inputs need a height and width of at least 1, but got shape {:?}", inputs.shape()));
+    }
+    let out_height = (height - 1) * stride + kernel_size;
+    let out_width = (width - 1) * stride + kernel_size;
+    if 2 * padding >= out_height.min(out_width) {
+        return Err(anyhow::anyhow!("Padding {} removes the whole result of size {}x{}", padding, out_height, out_width));
+    }
+
+    // Each input pixel is multiplied by the kernels of all out_channels at once
+    let kernel: Array2F = kernel.clone().into_shape((*in_channels, out_channels * kernel_size * kernel_size))?;
+
+    let mut result = Array4F::zeros((batch, *out_channels, out_height, out_width));
+    Zip::from(inputs.outer_iter())
+        .and(result.outer_iter_mut())
+        .into_par_iter()
+        .for_each(|(inputs, mut result)| {
+            for h in 0..height {
+                for w in 0..width {
+                    let h_offset = h * stride;
+                    let w_offset = w * stride;
+                    let values = inputs.slice(s![.., h, w]);
+                    let area: Array3F = values.dot(&kernel)
+                        .into_shape((*out_channels, *kernel_size, *kernel_size)).unwrap();
+
+                    result.slice_mut(s![
+                        ..,
+                        h_offset..(h_offset + kernel_size),
+                        w_offset..(w_offset + kernel_size)
+                    ]).add_assign(&area);
+                }
+            }
+        });
+
+    Ok(remove_padding_4d(result, *padding))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::nn::batch_config::BatchConfig;
+    use crate::nn::key_assigner::KeyAssigner;
+    use crate::nn::layers::filtering::convolution::ConvolutionInitMode;
+    use crate::nn::layers::nn_layers::{GenericStorage, InitData, LayerOps};
+    use crate::nn::layers::filtering::conv_transpose::ConvTransposeLayer;
+    use crate::nn::lr_calculators::constant_lr::ConstantLrConfig;
+    use crate::nn::lr_calculators::lr_calculator::LrCalc;
+    use crate::utils::arrays_almost_equal;
+    use super::*;
+
+    fn get_config(stride: usize, padding: usize) -> ConvTransposeConfig {
+        ConvTransposeConfig {
+            in_channels: 1,
+            out_channels: 1,
+            kernel_size: 2,
+            stride,
+            padding,
+            init_mode: ConvolutionInitMode::Kernel(Array4F::ones((1, 1, 2, 2))),
+            lr_calc: LrCalc::Constant(ConstantLrConfig::default()),
+        }
+    }
+
+    fn run_forward(config: &ConvTransposeConfig) -> Array4F {
+        let mut storage = GenericStorage::new();
+        ConvTransposeLayer::init(InitData {
+            assigner: &mut KeyAssigner::new(),
+            storage: &mut storage,
+        }, config).unwrap();
+
+        let inputs = Array4F::from_shape_vec((1, 1, 2, 2), vec![1.0, 2.0, 3.0, 4.0]).unwrap();
+        let result = forward(ForwardData {
+            inputs: inputs.into_dyn().into(),
+            forward_cache: None,
+            storage: &storage,
+            assigner: &mut KeyAssigner::new(),
+            batch_config: &BatchConfig::new_train(),
+            prev_iteration_cache: None,
+            gpu: None,
+        }, config).unwrap();
+        result.into_memory().unwrap().into_dimensionality().unwrap()
+    }
+
+    #[test]
+    fn test_forward_no_overlap() {
+        let result = run_forward(&get_config(2, 0));
+        let expected = Array4F::from_shape_vec((1, 1, 4, 4), vec![
+            1.0, 1.0, 2.0, 2.0,
+            1.0, 1.0, 2.0, 2.0,
+            3.0, 3.0, 4.0, 4.0,
+            3.0, 3.0, 4.0, 4.0,
+        ]).unwrap();
+        assert!(arrays_almost_equal(&result, &expected));
+    }
+
+    #[test]
+    fn test_forward_overlap() {
+        let result = run_forward(&get_config(1, 0));
+        let expected = Array4F::from_shape_vec((1, 1, 3, 3), vec![
+            1.0, 3.0, 2.0,
+            4.0, 10.0, 6.0,
+            3.0, 7.0, 4.0,
+        ]).unwrap();
+        assert!(arrays_almost_equal(&result, &expected));
+
+        let result = run_forward(&get_config(1, 1));
+        assert!(arrays_almost_equal(&result, &Array4F::from_elem((1, 1, 1, 1), 10.0)));
+    }
+
+    #[test]
+    fn test_invalid_inputs() {
+        let kernel = Array4F::ones((1, 1, 2, 2));
+        let config = get_config(1, 0);
+        assert!(cpu_forward(&Array4F::ones((1, 2, 2, 2)), &kernel, &config).is_err());
+        assert!(cpu_forward(&Array4F::ones((1, 1, 0, 2)), &kernel, &config).is_err());
+        // The result is 3x3
+        assert!(cpu_forward(&Array4F::ones((1, 1, 2, 2)), &kernel, &get_config(1, 2)).is_err());
+    }
+}
@@ -0,0 +1,40 @@
+use std::ops::AddAssign;
+use crate::nn::generic_storage::{get_mut_from_storage, remove_from_storage1};
+use crate::nn::layers::filtering::conv_transpose::{ConvTransposeConfig, ConvTransposeLayer, gen_name};
+use crate::nn::layers::nn_layers::{EmptyLayerResult, TrainableLayerOps, TrainData};
+use crate::nn::lr_calculators::lr_calculator::{apply_lr_calc, reserve_lr_calc_keys, LrCalcData};
+
+impl TrainableLayerOps<ConvTransposeConfig> for ConvTransposeLayer {
+    fn train(data: TrainData, layer_config: &ConvTransposeConfig) -> EmptyLayerResult {
+        let TrainData {
+            storage,
+            backward_cache,
+            assigner,
+            batch_config,
+            options,
+        } = data;
+        let key = assigner.get_key(gen_name(layer_config));
+
+        let [kernel_grad] = remove_from_storage1(backward_cache, &key);
+        if options.frozen {
+            reserve_lr_calc_keys(&layer_config.lr_calc, assigner);
+            return Ok(());
+        }
+        let kernel_grad = apply_lr_calc(
+            &layer_config.lr_calc,
+            kernel_grad,
+            LrCalcData {
+                batch_config,
+                storage,
+                assigner,
+                param_key: &key,
+                param_index: 0,
+                lr_multiplier: options.lr_multiplier,
+            },
+        )?.into_memory()?;
+
+        let kernel = get_mut_from_storage(storage, &key, 0);
+        kernel.add_assign(&kernel_grad);
+        Ok(())
+    }
+}
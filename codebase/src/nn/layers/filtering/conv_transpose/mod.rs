@@ -0,0 +1,51 @@
+use crate::nn::layers::filtering::convolution::ConvolutionInitMode;
+use crate::nn::layers::nn_layers::{BackwardData, EmptyLayerResult, ForwardData, InitData, LayerOps, LayerResult};
+use crate::nn::lr_calculators::lr_calculator::LrCalc;
+
+mod conv_transpose_forward;
+mod conv_transpose_init;
+mod conv_transpose_backward;
+mod conv_transpose_train;
+
+#[derive(Clone, Debug)]
+pub struct ConvTransposeConfig {
+    pub in_channels: usize,
+    pub out_channels: usize,
+    pub kernel_size: usize,
+    pub stride: usize,
+    /// Removed from the borders of the result, to match a **Convolution** with the same padding
+    pub padding: usize,
+    /// The kernel has shape (in_channels, out_channels, kernel_size, kernel_size)
+    pub init_mode: ConvolutionInitMode,
+    pub lr_calc: LrCalc,
+}
+
+/// Apply the transposed convolution operation with 2D filters. Each input value is multiplied by the
+/// kernel and the result is added to an area of the output, so the height and width become
+/// (size - 1) * stride + kernel_size - 2 * padding. It's the operation used to calculate the inputs
+/// gradient of **Convolution**. Requires a 4 dimensional input (one being the batch).
+/// https://arxiv.org/abs/1603.07285
+/// ### Trainable
+/// * Kernel
+pub struct ConvTransposeLayer;
+
+pub(crate) fn gen_name(config: &ConvTransposeConfig) -> String {
+    format!("conv_transpose_{}_{}_{}_{}_{}", config.in_channels, config.out_channels, config.kernel_size,
+            config.stride, config.padding)
+}
+
+impl LayerOps<ConvTransposeConfig> for ConvTransposeLayer {
+    fn init(data: InitData, layer_config: &ConvTransposeConfig) -> EmptyLayerResult {
+        conv_transpose_init::init(data, layer_config)
+    }
+
+    #[inline(never)]
+    fn forward(data: ForwardData, layer_config: &ConvTransposeConfig) -> LayerResult {
+        conv_transpose_forward::forward(data, layer_config)
+    }
+
+    #[inline(never)]
+    fn backward(data: BackwardData, layer_config: &ConvTransposeConfig) -> LayerResult {
+        conv_transpose_backward::backward(data, layer_config)
+    }
+}
@@ -9,7 +9,7 @@ use crate::gpu::shader_runner_2::ShaderRunner2;
 use crate::gpu::{BufferChecksumMethod, shaders};
 use crate::gpu::buffers::download_array_from_gpu;
 use crate::nn::generic_storage::{clone_from_storage1, remove_from_storage1};
-use crate::nn::layers::filtering::convolution::{ConvolutionConfig, gen_name};
+use crate::nn::layers::filtering::convolution::{ConvolutionConfig, dilated_kernel_size, gen_name};
 use crate::nn::layers::filtering::{pad4d, remove_padding_4d};
 use crate::nn::layers::nn_layers::{BackwardData, LayerResult};
 use crate::utils::{Array2F, Array5F, GenericResult, get_dims_after_filter_4, shape_length};
@@ -34,8 +34,9 @@ pub fn backward(data: BackwardData, layer_config: &ConvolutionConfig) -> LayerRe
     let kernels_grad = calc_kernel_grad(&inputs, &grad, layer_config);
     backward_cache.insert(key.clone(), vec![kernels_grad.into_dyn()]);
 
+    // The inputs gradient shader doesn't support dilation
     let inputs_grad = match data.gpu {
-        Some(gpu) => match gpu_inputs_grad(key, &inputs, &grad, &kernel, gpu, layer_config) {
+        Some(gpu) if layer_config.dilation == 1 => match gpu_inputs_grad(key, &inputs, &grad, &kernel, gpu, layer_config) {
             Ok(v) => v,
             Err(_e) => {
                 #[cfg(debug_assertions)]
@@ -43,25 +44,26 @@ pub fn backward(data: BackwardData, layer_config: &ConvolutionConfig) -> LayerRe
                 cpu_inputs_grad(inputs, grad, kernel, layer_config)
             }
         }
-        None => cpu_inputs_grad(inputs, grad, kernel, layer_config)
+        _ => cpu_inputs_grad(inputs, grad, kernel, layer_config)
     };
 
     Ok(inputs_grad.into_dyn().into())
 }
 
 pub fn calc_kernel_grad(inputs: &Array4F, grad: &Array4F, layer_config: &ConvolutionConfig) -> Array4F {
-    let ConvolutionConfig { in_channels, out_channels, kernel_size, stride, .. } = layer_config;
+    let ConvolutionConfig { in_channels, out_channels, kernel_size, stride, dilation, .. } = layer_config;
     let kernel_size = *kernel_size;
     let stride = *stride;
+    let dilation = *dilation;
+    let dilated_size = dilated_kernel_size(layer_config);
 
     let shape = inputs.shape();
     let batch = shape[0];
     let height = shape[2];
     let width = shape[3];
 
-    let mean_inputs = inputs.mean_axis(Axis(0)).unwrap();
-    let mean_grad = grad.mean_axis(Axis(0)).unwrap();
-    let mean_grad = mean_grad.insert_axis(Axis(1));
+    // (batch, out_channels, 1, height, width), to be multiplied by the inputs of all in_channels
+    let grad = grad.view().insert_axis(Axis(2));
 
     let mut parts = Vec::with_capacity(kernel_size * kernel_size);
 
@@ -69,15 +71,19 @@ pub fn calc_kernel_grad(inputs: &Array4F, grad: &Array4F, layer_config: &Convolu
         .into_par_iter()
         .with_min_len(1)
         .map(|o| (o / kernel_size, o % kernel_size))
+        .map(|(h, w)| (h * dilation, w * dilation))
         .map(|(h, w)| {
-            let affected = mean_inputs.slice(s![
+            let affected = inputs.slice(s![
                     ..,
-                    h..height - (kernel_size - h - 1); stride,
-                    w..width - (kernel_size - w - 1); stride]);
-            let mul: Array4F = &mean_grad * &affected;
+                    ..,
+                    h..height - (dilated_size - h - 1); stride,
+                    w..width - (dilated_size - w - 1); stride]);
+            let affected = affected.insert_axis(Axis(1));
+            // Mean over the batch and the positions
+            let mul: Array5F = &grad * &affected;
             Array2F::from_shape_fn((*out_channels, *in_channels), |(out_c, in_c)| {
-                mul.index_axis(Axis(0), out_c)
-                    .index_axis(Axis(0), in_c)
+                mul.index_axis(Axis(1), out_c)
+                    .index_axis(Axis(1), in_c)
                     .mean().unwrap()
             })
         })
@@ -95,7 +101,8 @@ pub fn calc_kernel_grad(inputs: &Array4F, grad: &Array4F, layer_config: &Convolu
 
 pub fn cpu_inputs_grad(inputs: Array4F, grad: Array4F, kernel: Array4F, layer_config: &ConvolutionConfig) -> Array4F {
     let inputs_shape = inputs.shape();
-    let ConvolutionConfig { kernel_size, stride, padding, .. } = layer_config;
+    let ConvolutionConfig { stride, padding, dilation, .. } = layer_config;
+    let dilated_size = dilated_kernel_size(layer_config);
 
     // Put height and width in front
     let grad = grad.permuted_axes((2, 3, 0, 1));
@@ -103,7 +110,7 @@ pub fn cpu_inputs_grad(inputs: Array4F, grad: Array4F, kernel: Array4F, layer_co
     let kernel = kernel.insert_axis(Axis(3));
 
     let [batch_size, in_channels, new_height, new_width] =
-        get_dims_after_filter_4(inputs.shape(), *kernel_size, *stride);
+        get_dims_after_filter_4(inputs.shape(), dilated_size, *stride);
 
     let mut parts = Vec::with_capacity(new_height * new_width);
     (0..(new_height * new_width))
@@ -129,8 +136,8 @@ pub fn cpu_inputs_grad(inputs: Array4F, grad: Array4F, kernel: Array4F, layer_co
             padded_result.slice_mut(s![
                 ..,
                 ..,
-                h_offset..(h_offset + kernel_size),
-                w_offset..(w_offset + kernel_size)
+                h_offset..(h_offset + dilated_size); *dilation,
+                w_offset..(w_offset + dilated_size); *dilation
             ]).add_assign(&arr);
         });
 
@@ -244,6 +251,38 @@ mod tests {
         assert!(arrays_almost_equal(&expected, &result.into_dyn()));
     }
 
+    #[test]
+    fn test_backward_dilation() {
+        let dist = Normal::new(0.0, 1.0).unwrap();
+        let config = ConvolutionConfig {
+            in_channels: 2,
+            out_channels: 3,
+            kernel_size: 2,
+            padding: 1,
+            dilation: 3,
+            init_mode: HeNormal(),
+            lr_calc: LrCalc::Constant(ConstantLrConfig::default()),
+            stride: 2,
+            cache: false,
+            train_options: Default::default(),
+        };
+        let dilated_config = ConvolutionConfig { kernel_size: 4, dilation: 1, ..config.clone() };
+
+        let inputs = pad4d(Array4F::random((2, config.in_channels, 8, 8), &dist), config.padding);
+        let grad = Array4F::random((2, config.out_channels, 4, 4), &dist);
+        let kernel = Array4F::random((config.out_channels, config.in_channels, 2, 2), &dist);
+        let dilated_kernel = dilate_kernel(&kernel, 3);
+
+        let expected = cpu_inputs_grad(inputs.clone(), grad.clone(), dilated_kernel, &dilated_config);
+        let actual = cpu_inputs_grad(inputs.clone(), grad.clone(), kernel, &config);
+        assert!(arrays_almost_equal(&expected, &actual));
+
+        // The kernel gradient is divided by the number of elements in the kernel
+        let expected = calc_kernel_grad(&inputs, &grad, &dilated_config) * 16.0;
+        let actual = calc_kernel_grad(&inputs, &grad, &config) * 4.0;
+        assert!(arrays_almost_equal(&expected.slice(s![.., .., ..;3, ..;3]).to_owned(), &actual));
+    }
+
     #[test]
     fn test_gpu_cpu_equal_inputs_grad() {
         let dist = Normal::new(0.0, 1.0).unwrap();
@@ -252,10 +291,12 @@ mod tests {
             out_channels: 4,
             kernel_size: 2,
             padding: 1,
+            dilation: 1,
             init_mode: HeNormal(),
             lr_calc: LrCalc::Constant(ConstantLrConfig::default()),
             stride: 2,
             cache: false,
+            train_options: Default::default(),
         };
         let inputs = Array4F::random((8, config.in_channels, 10, 10), &dist);
         let grad_shape = (inputs.shape()[0], config.out_channels,
@@ -6,7 +6,8 @@ use crate::gpu::shader_runner_2::{ShaderRunner2};
 use crate::gpu::{BufferChecksumMethod, shaders};
 use crate::gpu::shader_context::{BufferConfig, ContextBinding, ShaderBinding, ShaderContext};
 use crate::nn::generic_storage::clone_from_storage1;
-use crate::nn::layers::filtering::convolution::{ConvolutionConfig, gen_name};
+use crate::nn::layers::filtering::convolution::{ConvolutionConfig, dilated_kernel_size, gen_name};
+use crate::nn::layers::filtering::convolution::conv_quantized::forward_quantized;
 use crate::nn::layers::filtering::{find_useful_from_prev, pad4d};
 use crate::nn::layers::nn_layers::{ForwardData, LayerResult};
 use crate::nn::layers::stored_array::StoredArray;
@@ -14,13 +15,17 @@ use crate::utils::{shape_length};
 use crate::utils::{Array3F, GenericResult, get_dims_after_filter_4};
 
 pub fn forward(data: ForwardData, layer_config: &ConvolutionConfig) -> LayerResult {
-    let ForwardData { inputs, storage, assigner, forward_cache, mut prev_iteration_cache, .. } = data;
+    let ForwardData { inputs, storage, assigner, forward_cache, mut prev_iteration_cache, batch_config, .. } = data;
     let key = assigner.get_key(gen_name(layer_config));
 
     if let Some(forward_cache) = forward_cache {
         forward_cache.insert(key.clone(), vec![inputs.to_memory()?]);
     }
 
+    if let Some(quantized) = batch_config.quantized.as_ref().and_then(|o| o.get(&key)) {
+        return Ok(forward_quantized(inputs.into_memory()?, quantized, layer_config)?.into());
+    }
+
     let [kernel] = clone_from_storage1(storage, &key);
 
     let result = match data.gpu {
@@ -72,11 +77,11 @@ pub fn forward(data: ForwardData, layer_config: &ConvolutionConfig) -> LayerResu
 
 pub fn cpu_forward(inputs: StoredArray, kernel: ArrayDynF, layer_config: &ConvolutionConfig) -> GenericResult<StoredArray> {
     let kernel = kernel.into_dimensionality()?;
-    let ConvolutionConfig { stride, kernel_size, .. } = layer_config;
+    let ConvolutionConfig { stride, kernel_size, dilation, .. } = layer_config;
     let inputs = inputs.into_memory()?.into_dimensionality()?;
     let inputs = pad4d(inputs, layer_config.padding);
 
-    let [batch, _, new_height, new_width] = get_dims_after_filter_4(inputs.shape(), *kernel_size, *stride);
+    let [batch, _, new_height, new_width] = get_dims_after_filter_4(inputs.shape(), dilated_kernel_size(layer_config), *stride);
     let mut batches = Vec::with_capacity(batch);
     inputs.outer_iter()
         .into_par_iter()
@@ -84,7 +89,7 @@ pub fn cpu_forward(inputs: StoredArray, kernel: ArrayDynF, layer_config: &Convol
             let mut result = Array3F::zeros((layer_config.out_channels, new_height, new_width));
             for h in 0..new_height {
                 for w in 0..new_width {
-                    apply_conv_filter(&kernel, stride, kernel_size, &inputs, &mut result.view_mut(), h, w);
+                    apply_conv_filter(&kernel, stride, kernel_size, dilation, &inputs, &mut result.view_mut(), h, w);
                 }
             }
             result
@@ -99,18 +104,19 @@ pub fn cpu_forward(inputs: StoredArray, kernel: ArrayDynF, layer_config: &Convol
 pub fn cpu_forward_with_cache(inputs: StoredArray, prev_inputs: ArrayDynF, prev_results: ArrayDynF, kernel: ArrayDynF,
                               layer_config: &ConvolutionConfig) -> GenericResult<StoredArray> {
     let kernel = kernel.into_dimensionality()?;
-    let ConvolutionConfig { stride, kernel_size, out_channels, .. } = layer_config;
+    let ConvolutionConfig { stride, kernel_size, out_channels, dilation, .. } = layer_config;
     let inputs = inputs.into_memory()?.into_dimensionality()?;
     let inputs = pad4d(inputs, layer_config.padding);
     let prev_inputs = pad4d(prev_inputs.into_dimensionality()?, layer_config.padding);
     let prev_results = prev_results.into_dimensionality()?;
 
-    let [batch, _, new_height, new_width] = get_dims_after_filter_4(inputs.shape(), *kernel_size, *stride);
+    let dilated_size = dilated_kernel_size(layer_config);
+    let [batch, _, new_height, new_width] = get_dims_after_filter_4(inputs.shape(), dilated_size, *stride);
 
     let useful_cache = find_useful_from_prev(
         &prev_inputs,
         &prev_results,
-        &inputs, *kernel_size, *stride,
+        &inputs, dilated_size, *stride,
     );
     let mut result = Array4F::zeros((batch, layer_config.out_channels, new_height, new_width));
 
@@ -127,7 +133,7 @@ pub fn cpu_forward_with_cache(inputs: StoredArray, prev_inputs: ArrayDynF, prev_
                             result[(och, h, w)] = cache[(och, h, w)].unwrap();
                         }
                     } else {
-                        apply_conv_filter(&kernel, stride, kernel_size, &inputs, &mut result, h, w);
+                        apply_conv_filter(&kernel, stride, kernel_size, dilation, &inputs, &mut result, h, w);
                     }
                 }
             }
@@ -138,12 +144,12 @@ pub fn cpu_forward_with_cache(inputs: StoredArray, prev_inputs: ArrayDynF, prev_
 
 pub fn gpu_forward_with_cache(id: String, inputs: StoredArray, kernel: &ArrayDynF, gpu: GlobalGpu,
                               layer_config: &ConvolutionConfig) -> GenericResult<StoredArray> {
-    let ConvolutionConfig { stride, kernel_size, .. } = layer_config;
+    let stride = layer_config.stride;
     let key = (id, "forward".to_owned());
 
     let ish = inputs.shape();
     let padded_ish = [ish[0], ish[1], ish[2] + 2 * layer_config.padding, ish[3] + 2 * layer_config.padding];
-    let [batch_size, _, new_height, new_width] = get_dims_after_filter_4(&padded_ish, *kernel_size, *stride);
+    let [batch_size, _, new_height, new_width] = get_dims_after_filter_4(&padded_ish, dilated_kernel_size(layer_config), stride);
     let osh = [batch_size, layer_config.out_channels, new_height, new_width];
 
     let buffer_lengths = [
@@ -167,6 +173,7 @@ pub fn gpu_forward_with_cache(id: String, inputs: StoredArray, kernel: &ArrayDyn
             input_width: ish[3] as u32,
             padding: layer_config.padding as u32,
             cache_enabled: layer_config.cache as u32,
+            dilation: layer_config.dilation as u32,
         };
 
         b.register_shader("forward", shaders::convolution_forward::forward::load, vec![
@@ -215,13 +222,16 @@ pub fn gpu_forward_with_cache(id: String, inputs: StoredArray, kernel: &ArrayDyn
     Ok(StoredArray::GpuLocal { gpu, shape: osh.to_vec(), data: result })
 }
 
-fn apply_conv_filter(kernel: &Array4F, stride: &usize, kernel_size: &usize, inputs: &ArrayView3<f32>, result: &mut ArrayViewMut3<f32>, h: usize, w: usize) {
+#[allow(clippy::too_many_arguments)]
+fn apply_conv_filter(kernel: &Array4F, stride: &usize, kernel_size: &usize, dilation: &usize, inputs: &ArrayView3<f32>,
+                     result: &mut ArrayViewMut3<f32>, h: usize, w: usize) {
     let h_offset = h * stride;
     let w_offset = w * stride;
+    let dilated_size = (kernel_size - 1) * dilation + 1;
     let area = inputs.slice(s![
         ..,
-        h_offset..(h_offset + kernel_size),
-        w_offset..(w_offset + kernel_size)
+        h_offset..(h_offset + dilated_size); *dilation,
+        w_offset..(w_offset + dilated_size); *dilation
     ]);
     let area = area.insert_axis(Axis(0));
     let out: Array4F = &area * kernel;
@@ -298,6 +308,35 @@ mod tests {
         assert!(arrays_almost_equal(&expected, &result));
     }
 
+    #[test]
+    fn test_forward_dilation() {
+        let dist = Normal::new(0.0, 1.0).unwrap();
+        let config = ConvolutionConfig {
+            in_channels: 2,
+            out_channels: 3,
+            kernel_size: 3,
+            stride: 1,
+            padding: 2,
+            dilation: 2,
+            init_mode: HeNormal(),
+            lr_calc: LrCalc::Constant(ConstantLrConfig::default()),
+            cache: false,
+            train_options: Default::default(),
+        };
+        let dilated_config = ConvolutionConfig { kernel_size: 5, dilation: 1, ..config.clone() };
+
+        let inputs = Array4F::random((2, config.in_channels, 7, 7), &dist);
+        let kernel = Array4F::random((config.out_channels, config.in_channels, 3, 3), &dist);
+
+        let expected = cpu_forward(inputs.clone().into_dyn().into(), dilate_kernel(&kernel, 2).into_dyn(), &dilated_config)
+            .unwrap().into_memory().unwrap();
+        let actual = cpu_forward(inputs.into_dyn().into(), kernel.into_dyn(), &config)
+            .unwrap().into_memory().unwrap();
+
+        assert_eq!(expected.shape(), &[2, 3, 7, 7]);
+        assert!(arrays_almost_equal(&expected, &actual));
+    }
+
     #[test]
     fn test_gpu_cpu_equal_forward() {
         let config = ConvolutionConfig {
@@ -305,10 +344,12 @@ mod tests {
             out_channels: 3,
             stride: 2,
             padding: 2,
+            dilation: 1,
             init_mode: HeNormal(),
             lr_calc: LrCalc::Constant(ConstantLrConfig::default()),
             kernel_size: 2,
             cache: false,
+            train_options: Default::default(),
         };
 
         let dist = Normal::new(0.0, 1.0).unwrap();
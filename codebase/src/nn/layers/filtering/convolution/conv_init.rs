@@ -54,9 +54,11 @@ mod tests {
             kernel_size: 3,
             stride: 1,
             padding: 0,
+            dilation: 1,
             init_mode: HeNormal(),
             lr_calc: LrCalc::Constant(ConstantLrConfig::default()),
-            cache: false
+            cache: false,
+            train_options: Default::default(),
         };
 
         let mut assigner = KeyAssigner::new();
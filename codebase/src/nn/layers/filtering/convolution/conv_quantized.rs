@@ -0,0 +1,91 @@
+use ndarray::parallel::prelude::*;
+use ndarray::{ArrayView2, ArrayView4, s, Zip};
+use crate::Array4F;
+use crate::nn::layers::filtering::convolution::{ConvolutionConfig, dilated_kernel_size};
+use crate::nn::layers::filtering::pad4d;
+use crate::nn::quantization::QuantizedArray;
+use crate::utils::{ArrayDynF, GenericResult, get_dims_after_filter_4};
+
+/// Same as the regular forward, but with the kernel and the inputs converted to int8. The products
+/// are accumulated in i32 and only converted back to f32 (with the scales) at the end.
+/// The inputs are quantized with one scale for each batch item
+pub fn forward_quantized(inputs: ArrayDynF, kernel: &QuantizedArray, layer_config: &ConvolutionConfig) -> GenericResult<ArrayDynF> {
+    let ConvolutionConfig { out_channels, stride, dilation, .. } = layer_config;
+    let dilated_size = dilated_kernel_size(layer_config);
+    let inputs = pad4d(inputs.into_dimensionality()?, layer_config.padding);
+    let [batch, in_channels, new_height, new_width] = get_dims_after_filter_4(inputs.shape(), dilated_size, *stride);
+
+    let inputs = QuantizedArray::quantize(&inputs.into_dyn());
+    let inputs_values: ArrayView4<i8> = inputs.values.view().into_dimensionality()?;
+    // Each row has all the values of the kernel of an output channel
+    let area_len = in_channels * layer_config.kernel_size * layer_config.kernel_size;
+    let kernel_values: ArrayView2<i8> = kernel.values.view().into_shape((*out_channels, area_len))?;
+
+    let mut result = Array4F::zeros((batch, *out_channels, new_height, new_width));
+    Zip::from(result.outer_iter_mut())
+        .and(inputs_values.outer_iter())
+        .and(&inputs.scales)
+        .into_par_iter()
+        .for_each(|(mut result, inputs, inputs_scale)| {
+            let mut area: Vec<i8> = Vec::with_capacity(area_len);
+            for h in 0..new_height {
+                for w in 0..new_width {
+                    let h_offset = h * stride;
+                    let w_offset = w * stride;
+                    area.clear();
+                    area.extend(inputs.slice(s![
+                        ..,
+                        h_offset..(h_offset + dilated_size); *dilation,
+                        w_offset..(w_offset + dilated_size); *dilation
+                    ]).iter());
+
+                    for (c, (kernel, kernel_scale)) in kernel_values.outer_iter().zip(kernel.scales.iter()).enumerate() {
+                        let sum: i32 = kernel.iter()
+                            .zip(area.iter())
+                            .map(|(a, b)| *a as i32 * *b as i32)
+                            .sum();
+                        result[(c, h, w)] = sum as f32 * inputs_scale * kernel_scale;
+                    }
+                }
+            }
+        });
+
+    Ok(result.into_dyn())
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray_rand::rand_distr::Normal;
+    use ndarray_rand::RandomExt;
+    use crate::nn::layers::filtering::convolution::conv_forward::cpu_forward;
+    use crate::nn::layers::filtering::convolution::ConvolutionInitMode;
+    use crate::nn::lr_calculators::constant_lr::ConstantLrConfig;
+    use crate::nn::lr_calculators::lr_calculator::LrCalc;
+    use super::*;
+
+    #[test]
+    fn test_forward_quantized() {
+        for (stride, padding, dilation) in [(1, 1, 1), (2, 0, 1), (1, 2, 2)] {
+            let config = ConvolutionConfig {
+                in_channels: 2,
+                out_channels: 3,
+                kernel_size: 3,
+                stride,
+                padding,
+                dilation,
+                init_mode: ConvolutionInitMode::HeNormal(),
+                lr_calc: LrCalc::Constant(ConstantLrConfig::default()),
+                cache: false,
+                train_options: Default::default(),
+            };
+            let inputs = Array4F::random((2, 2, 7, 7), Normal::new(0.0, 1.0).unwrap()).into_dyn();
+            let kernel = Array4F::random((3, 2, 3, 3), Normal::new(0.0, 0.5).unwrap()).into_dyn();
+
+            let expected = cpu_forward(inputs.clone().into(), kernel.clone(), &config).unwrap().into_memory().unwrap();
+            let result = forward_quantized(inputs, &QuantizedArray::quantize(&kernel), &config).unwrap();
+            assert_eq!(result.shape(), expected.shape());
+            let max_diff = (&result - &expected).mapv(f32::abs).fold(0.0f32, |a, &b| a.max(b));
+            assert!(max_diff < 0.1, "{}", max_diff);
+        }
+    }
+}
@@ -2,7 +2,7 @@ use std::ops::AddAssign;
 use crate::nn::generic_storage::{get_mut_from_storage, remove_from_storage1};
 use crate::nn::layers::filtering::convolution::{ConvolutionConfig, ConvolutionLayer, gen_name};
 use crate::nn::layers::nn_layers::{EmptyLayerResult, TrainableLayerOps, TrainData};
-use crate::nn::lr_calculators::lr_calculator::{apply_lr_calc, LrCalcData};
+use crate::nn::lr_calculators::lr_calculator::{apply_lr_calc, reserve_lr_calc_keys, LrCalcData};
 
 impl TrainableLayerOps<ConvolutionConfig> for ConvolutionLayer {
     fn train(data: TrainData, layer_config: &ConvolutionConfig) -> EmptyLayerResult {
@@ -11,10 +11,16 @@ impl TrainableLayerOps<ConvolutionConfig> for ConvolutionLayer {
             backward_cache,
             assigner,
             batch_config,
+            options,
         } = data;
+        let options = options.combine(&layer_config.train_options);
         let key = assigner.get_key(gen_name(layer_config));
 
         let [kernel_grad] = remove_from_storage1(backward_cache, &key);
+        if options.frozen {
+            reserve_lr_calc_keys(&layer_config.lr_calc, assigner);
+            return Ok(());
+        }
         let kernel_grad = apply_lr_calc(
             &layer_config.lr_calc,
             kernel_grad,
@@ -22,6 +28,9 @@ impl TrainableLayerOps<ConvolutionConfig> for ConvolutionLayer {
                 batch_config,
                 storage,
                 assigner,
+                param_key: &key,
+                param_index: 0,
+                lr_multiplier: options.lr_multiplier,
             },
         )?.into_memory()?;
 
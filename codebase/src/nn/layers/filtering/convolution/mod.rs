@@ -1,11 +1,12 @@
 use crate::Array4F;
-use crate::nn::layers::nn_layers::{BackwardData, EmptyLayerResult, ForwardData, InitData, LayerOps, LayerResult};
+use crate::nn::layers::nn_layers::{BackwardData, EmptyLayerResult, ForwardData, InitData, LayerOps, LayerResult, TrainOptions};
 use crate::nn::lr_calculators::lr_calculator::LrCalc;
 
 mod conv_forward;
 mod conv_init;
 mod conv_backward;
 mod conv_train;
+mod conv_quantized;
 
 #[cfg(test)]
 mod test_values;
@@ -17,9 +18,12 @@ pub struct ConvolutionConfig {
     pub kernel_size: usize,
     pub stride: usize,
     pub padding: usize,
+    /// Spacing between the elements of the kernel. 1 is a regular convolution
+    pub dilation: usize,
     pub init_mode: ConvolutionInitMode,
     pub lr_calc: LrCalc,
     pub cache: bool,
+    pub train_options: TrainOptions,
 }
 
 #[derive(Clone, Debug)]
@@ -31,15 +35,26 @@ pub enum ConvolutionInitMode {
 /// Apply the convolution operation with 2D filters. That means passing a filter through the last
 /// 2 dimension of the input (usually height and width). In position of the filter, the sum of the
 /// product of those input values and the kernel is computed. Requires a 4 dimensional input (one being
-/// the batch).
+/// the batch). With a **dilation** greater than 1, the kernel is applied to inputs that are
+/// **dilation** positions apart, which increases the receptive field without adding parameters.
 /// ### Trainable
 /// * Kernel
 /// https://en.wikipedia.org/wiki/Convolutional_neural_network
 pub struct ConvolutionLayer;
 
-fn gen_name(config: &ConvolutionConfig) -> String {
-    format!("convolution_{}_{}_{}_{}_{}", config.in_channels, config.out_channels, config.kernel_size,
-            config.stride, config.padding)
+/// The dilation is only added when it isn't 1, so the keys of regular convolutions don't change
+pub(crate) fn gen_name(config: &ConvolutionConfig) -> String {
+    let name = format!("convolution_{}_{}_{}_{}_{}", config.in_channels, config.out_channels, config.kernel_size,
+                       config.stride, config.padding);
+    match config.dilation {
+        1 => name,
+        dilation => format!("{}_d{}", name, dilation),
+    }
+}
+
+/// Size of the area of the inputs covered by the kernel, considering the dilation
+fn dilated_kernel_size(config: &ConvolutionConfig) -> usize {
+    (config.kernel_size - 1) * config.dilation + 1
 }
 
 impl LayerOps<ConvolutionConfig> for ConvolutionLayer {
@@ -56,4 +71,28 @@ impl LayerOps<ConvolutionConfig> for ConvolutionLayer {
     fn backward(data: BackwardData, layer_config: &ConvolutionConfig) -> LayerResult {
         conv_backward::backward(data, layer_config)
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use crate::nn::lr_calculators::constant_lr::ConstantLrConfig;
+    use super::*;
+
+    #[test]
+    fn test_gen_name() {
+        let mut config = ConvolutionConfig {
+            in_channels: 2,
+            out_channels: 3,
+            kernel_size: 3,
+            stride: 1,
+            padding: 1,
+            dilation: 1,
+            init_mode: ConvolutionInitMode::HeNormal(),
+            lr_calc: LrCalc::Constant(ConstantLrConfig::default()),
+            cache: false,
+            train_options: Default::default(),
+        };
+        assert_eq!(gen_name(&config), "convolution_2_3_3_1_1");
+        config.dilation = 2;
+        assert_eq!(gen_name(&config), "convolution_2_3_3_1_1_d2");
+    }
+}
@@ -1,4 +1,4 @@
-use ndarray::{array, stack, Axis};
+use ndarray::{array, s, stack, Axis};
 use crate::{Array4F, ArrayDynF};
 use crate::nn::layers::filtering::convolution::ConvolutionConfig;
 use crate::nn::layers::filtering::convolution::ConvolutionInitMode::Kernel;
@@ -119,11 +119,13 @@ pub fn get_config() -> ConvolutionConfig {
         kernel_size: 2,
         stride: 2,
         padding: 1,
+        dilation: 1,
         init_mode: Kernel(get_kernels()),
         lr_calc: LrCalc::Constant(ConstantLrConfig::default()),
         in_channels: 2,
         out_channels: 3,
         cache: false,
+        train_options: Default::default(),
     }
 }
 
@@ -131,4 +133,14 @@ pub fn get_storage() -> GenericStorage {
     let mut result = GenericStorage::new();
     result.insert("convolution_2_3_2_2_1_0".to_owned(), vec![get_kernels().into_dyn()]);
     result
-}
\ No newline at end of file
+}
+
+/// Insert zeros between the elements of the kernel, so a regular convolution with it is equivalent
+/// to a dilated convolution with the original kernel
+pub fn dilate_kernel(kernel: &Array4F, dilation: usize) -> Array4F {
+    let shape = kernel.shape();
+    let size = (shape[2] - 1) * dilation + 1;
+    let mut result = Array4F::zeros((shape[0], shape[1], size, size));
+    result.slice_mut(s![.., .., ..;dilation, ..;dilation]).assign(kernel);
+    result
+}
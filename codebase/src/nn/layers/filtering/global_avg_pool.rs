@@ -0,0 +1,91 @@
+use ndarray::{Axis, Ix2};
+use crate::Array4F;
+use crate::nn::generic_storage::remove_from_storage1;
+use crate::nn::layers::nn_layers::*;
+use crate::nn::layers::stored_array::StoredArray;
+use crate::utils::Array1F;
+
+/// Calculate the mean of each channel, transforming an input (Batch, Channels, Height, Width) into
+/// (Batch, Channels). Useful for replacing **Flatten** followed by big **Dense** layers, because
+/// it has no parameters.
+/// https://paperswithcode.com/method/global-average-pooling
+pub struct GlobalAvgPoolLayer;
+
+pub(crate) fn gen_name() -> String {
+    "global_avg_pool".to_owned()
+}
+
+impl LayerOps<()> for GlobalAvgPoolLayer {
+    fn init(_: InitData, _: &()) -> EmptyLayerResult { Ok(()) }
+
+    fn forward(data: ForwardData, _: &()) -> LayerResult {
+        let ForwardData { inputs, forward_cache, assigner, .. } = data;
+        let inputs: Array4F = inputs.into_memory()?.into_dimensionality()?;
+
+        let key = assigner.get_key(gen_name());
+        if let Some(forward_cache) = forward_cache {
+            // Only the shape is needed in backward()
+            let shape: Array1F = inputs.shape().iter().map(|o| *o as f32).collect();
+            forward_cache.insert(key, vec![shape.into_dyn()]);
+        }
+
+        let result = inputs.mean_axis(Axis(3)).unwrap()
+            .mean_axis(Axis(2)).unwrap();
+        Ok(StoredArray::Memory { data: result.into_dyn() })
+    }
+
+    /// Distribute the gradient of each channel equally to all its inputs
+    fn backward(data: BackwardData, _: &()) -> LayerResult {
+        let BackwardData { forward_cache, assigner, grad, .. } = data;
+        let grad = grad.into_dimensionality::<Ix2>()?;
+
+        let key = assigner.get_key(gen_name());
+        let [shape] = remove_from_storage1(forward_cache, &key);
+        let shape: Vec<_> = shape.iter().map(|o| o.round() as usize).collect();
+        let area_size = (shape[2] * shape[3]) as f32;
+
+        let grad = (grad / area_size).insert_axis(Axis(2)).insert_axis(Axis(3));
+        let result = grad.broadcast((shape[0], shape[1], shape[2], shape[3]))
+            .ok_or_else(|| anyhow::anyhow!("Gradient with shape {:?} doesn't match inputs {:?}", grad.shape(), shape))?
+            .to_owned();
+        Ok(StoredArray::Memory { data: result.into_dyn() })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::array;
+    use crate::nn::batch_config::BatchConfig;
+    use crate::nn::key_assigner::KeyAssigner;
+    use crate::utils::arrays_almost_equal;
+    use super::*;
+
+    #[test]
+    fn test_forward_backward() {
+        let inputs = Array4F::from_shape_vec((1, 2, 2, 2), vec![1.0, 2.0, 3.0, 4.0, -1.0, -1.0, 5.0, 1.0]).unwrap().into_dyn();
+        let mut forward_cache = GenericStorage::new();
+
+        let result = GlobalAvgPoolLayer::forward(ForwardData {
+            inputs: inputs.into(),
+            batch_config: &BatchConfig::new_train(),
+            assigner: &mut KeyAssigner::new(),
+            storage: &GenericStorage::new(),
+            forward_cache: Some(&mut forward_cache),
+            prev_iteration_cache: None,
+            gpu: None,
+        }, &()).unwrap().into_memory().unwrap();
+        assert!(arrays_almost_equal(&result, &array![[2.5, 1.0]].into_dyn()));
+
+        let grad = GlobalAvgPoolLayer::backward(BackwardData {
+            grad: array![[4.0, -2.0]].into_dyn(),
+            batch_config: &BatchConfig::new_train(),
+            assigner: &mut KeyAssigner::new(),
+            storage: &GenericStorage::new(),
+            forward_cache: &mut forward_cache,
+            backward_cache: &mut GenericStorage::new(),
+            gpu: None,
+        }, &()).unwrap().into_memory().unwrap();
+        let expected = Array4F::from_shape_vec((1, 2, 2, 2), vec![1.0, 1.0, 1.0, 1.0, -0.5, -0.5, -0.5, -0.5]).unwrap().into_dyn();
+        assert!(arrays_almost_equal(&grad, &expected));
+    }
+}
@@ -17,7 +17,7 @@ pub struct MaxPoolConfig {
 /// https://deepai.org/machine-learning-glossary-and-terms/max-pooling
 pub struct MaxPoolLayer;
 
-fn gen_name() -> String {
+pub(crate) fn gen_name() -> String {
     "max_pool".to_owned()
 }
 
@@ -3,7 +3,10 @@ use ndarray::parallel::prelude::{IntoParallelIterator, ParallelIterator};
 use crate::Array4F;
 
 pub mod convolution;
+pub mod conv_transpose;
 pub mod max_pool;
+pub mod avg_pool;
+pub mod global_avg_pool;
 
 fn pad4d(array: Array4F, padding: usize) -> Array4F {
     let shape = array.shape();
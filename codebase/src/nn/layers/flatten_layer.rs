@@ -7,7 +7,7 @@ use super::nn_layers::{LayerOps, LayerResult, EmptyLayerResult, ForwardData, Bac
 /// passing **Convolution** results into **Dense** layers.
 pub struct FlattenLayer;
 
-fn gen_name() -> String {
+pub(crate) fn gen_name() -> String {
     "flatten".to_owned()
 }
 
@@ -7,6 +7,8 @@ pub mod flatten_layer;
 pub mod expand_dim_layer;
 pub mod dropout_layer;
 pub mod concat_layer;
+pub mod batch_norm_layer;
+pub mod residual_layer;
 pub mod filtering;
 mod two_complements_transformer_layer;
 pub mod stored_array;
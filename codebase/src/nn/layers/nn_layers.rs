@@ -6,8 +6,10 @@ use crate::nn::layers::*;
 use crate::nn::layers::activation::*;
 use crate::utils::{ArrayDynF, GenericResult};
 use crate::nn::layers::filtering::convolution::ConvolutionConfig;
+use crate::nn::layers::filtering::conv_transpose::ConvTransposeConfig;
 use super::expand_dim_layer::ExpandDimConfig;
 use crate::nn::layers::filtering::max_pool::MaxPoolConfig;
+use crate::nn::layers::filtering::avg_pool::AvgPoolConfig;
 use crate::nn::layers::stored_array::StoredArray;
 
 /// Enum to represent the layers that create the model and its parameters
@@ -37,6 +39,24 @@ pub enum Layer {
     /// * For x < 0: 0
     Relu,
 
+    /// Apply the Leaky ReLu activation function. That means:
+    /// * For x >= 0: x
+    /// * For x < 0: alpha * x
+    LeakyRelu(leaky_relu_layer::LeakyReluConfig),
+
+    /// Apply the Exponential Linear Unit (ELU) activation function. That means:
+    /// * For x > 0: x
+    /// * For x <= 0: alpha * (exp(x) - 1)
+    Elu(elu_layer::EluConfig),
+
+    /// Apply the Gaussian Error Linear Unit (GELU) activation function. Smoother version of ReLu.
+    /// https://paperswithcode.com/method/gelu
+    Gelu,
+
+    /// Convert the values along an axis into probabilities that sum 1.
+    /// https://en.wikipedia.org/wiki/Softmax_function
+    Softmax(softmax_layer::SoftmaxConfig),
+
     /// Can be used to print information about the values passing through it
     Debug(debug_layer::DebugLayerConfig),
 
@@ -49,6 +69,14 @@ pub enum Layer {
     /// https://en.wikipedia.org/wiki/Convolutional_neural_network
     Convolution(ConvolutionConfig),
 
+    /// Apply the transposed convolution operation with 2D filters. Each input value is multiplied
+    /// by the kernel and added to an area of the output, increasing the height and width (usually to
+    /// undo a **Convolution**). Requires a 4 dimensional input (one being the batch).
+    /// https://arxiv.org/abs/1603.07285
+    /// ### Trainable
+    /// * Kernel
+    ConvTranspose(ConvTransposeConfig),
+
     /// Apply MAX operation with 2D filters, That means passing a filter through the last
     /// 2 dimension of the input (usually height and width). In position of the filter, the maximum
     /// value of those input values is computed. Requires a 4 dimensional input (one being the batch).
@@ -56,6 +84,16 @@ pub enum Layer {
     /// https://deepai.org/machine-learning-glossary-and-terms/max-pooling
     MaxPool(MaxPoolConfig),
 
+    /// Apply AVERAGE operation with 2D filters, That means passing a filter through the last
+    /// 2 dimension of the input (usually height and width). In position of the filter, the mean
+    /// of those input values is computed. Requires a 4 dimensional input (one being the batch).
+    AvgPool(AvgPoolConfig),
+
+    /// Calculate the mean of each channel, resulting in a 2D array (Batch, Channels). Requires a
+    /// 4 dimensional input (one being the batch). Can replace **Flatten** before **Dense** layers
+    /// with much fewer parameters.
+    GlobalAvgPool,
+
     /// Flattens all dimensions except the batch. The result will always be a 2D array. Useful for
     /// passing **Convolution** results into **Dense** layers.
     Flatten,
@@ -72,9 +110,79 @@ pub enum Layer {
     /// No extra axis is created
     Concat(concat_layer::ConcatConfig),
 
+    /// Executes its children in sequential order and adds the input of the block to the result. If
+    /// the shapes differ, the input can pass through a projection layer first.
+    /// https://en.wikipedia.org/wiki/Residual_neural_network
+    Residual(residual_layer::ResidualConfig),
+
     /// Receives 2 inputs A and B, and outputs A - B
     /// It's useful because it makes it easier for the model to produce negative values with ReLu layers
     TwoComplementsTransformer,
+
+    /// Normalizes each channel using the mean and variance of the batch (or their running averages
+    /// when not training), then scales and shifts the result. Helps deeper models train faster.
+    /// https://en.wikipedia.org/wiki/Batch_normalization
+    /// ### Trainable
+    /// * Gamma
+    /// * Beta
+    BatchNorm(batch_norm_layer::BatchNormConfig),
+}
+
+impl Layer {
+    /// Name of the tag that defines this layer in the XML config
+    pub fn tag_name(&self) -> &'static str {
+        match self {
+            Layer::Dense(_) => "Dense",
+            Layer::Sequential(_) => "Sequential",
+            Layer::Tanh => "Tanh",
+            Layer::Sigmoid => "Sigmoid",
+            Layer::Relu => "Relu",
+            Layer::LeakyRelu(_) => "LeakyRelu",
+            Layer::Elu(_) => "Elu",
+            Layer::Gelu => "Gelu",
+            Layer::Softmax(_) => "Softmax",
+            Layer::Debug(_) => "Debug",
+            Layer::Convolution(_) => "Convolution",
+            Layer::ConvTranspose(_) => "ConvTranspose",
+            Layer::MaxPool(_) => "MaxPool",
+            Layer::AvgPool(_) => "AvgPool",
+            Layer::GlobalAvgPool => "GlobalAvgPool",
+            Layer::Flatten => "Flatten",
+            Layer::ExpandDim(_) => "ExpandDim",
+            Layer::Dropout(_) => "Dropout",
+            Layer::Concat(_) => "Concat",
+            Layer::Residual(_) => "Residual",
+            Layer::TwoComplementsTransformer => "TwoComplementsTransformer",
+            Layer::BatchNorm(_) => "BatchNorm",
+        }
+    }
+
+    /// Name passed to **KeyAssigner** by this layer, which gets a suffix to become its key in the storage
+    /// and in the caches. None for layers that don't get a key, like **Sequential**
+    pub(crate) fn key_name(&self) -> Option<String> {
+        use crate::nn::layers::filtering::{convolution, conv_transpose, max_pool, avg_pool, global_avg_pool};
+        match self {
+            Layer::Dense(c) => Some(dense_layer::gen_name(c)),
+            Layer::Tanh => Some(tanh_layer::gen_name()),
+            Layer::Sigmoid => Some(sigmoid_layer::gen_name()),
+            Layer::Relu => Some(relu_layer::gen_name()),
+            Layer::LeakyRelu(_) => Some(leaky_relu_layer::gen_name()),
+            Layer::Elu(_) => Some(elu_layer::gen_name()),
+            Layer::Gelu => Some(gelu_layer::gen_name()),
+            Layer::Softmax(c) => Some(softmax_layer::gen_name(c)),
+            Layer::Debug(_) => Some("debug".to_owned()),
+            Layer::Convolution(c) => Some(convolution::gen_name(c)),
+            Layer::ConvTranspose(c) => Some(conv_transpose::gen_name(c)),
+            Layer::MaxPool(_) => Some(max_pool::gen_name()),
+            Layer::AvgPool(_) => Some(avg_pool::gen_name()),
+            Layer::GlobalAvgPool => Some(global_avg_pool::gen_name()),
+            Layer::Flatten => Some(flatten_layer::gen_name()),
+            Layer::Dropout(c) => Some(dropout_layer::gen_name(c)),
+            Layer::Concat(c) => Some(concat_layer::gen_name(c)),
+            Layer::BatchNorm(c) => Some(batch_norm_layer::gen_name(c)),
+            Layer::Sequential(_) | Layer::ExpandDim(_) | Layer::Residual(_) | Layer::TwoComplementsTransformer => None,
+        }
+    }
 }
 
 pub struct InitData<'a> {
@@ -125,6 +233,35 @@ pub struct TrainData<'a> {
     
     /// Temporary storage that comes from `backward()`.
     pub backward_cache: &'a mut GenericStorage,
+
+    /// Options of all the layers that contain this one, combined
+    pub options: TrainOptions,
+}
+
+/// How the parameters of a layer are updated. In layers that contain others, like **Sequential**,
+/// they also apply to all the children
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TrainOptions {
+    /// Frozen layers keep their parameters, but still propagate the gradients to the previous layers
+    pub frozen: bool,
+    /// Multiplies the changes calculated by the lr calculators
+    pub lr_multiplier: f32,
+}
+
+impl Default for TrainOptions {
+    fn default() -> Self {
+        Self { frozen: false, lr_multiplier: 1.0 }
+    }
+}
+
+impl TrainOptions {
+    /// Options of a layer with **options** inside a layer with **self**
+    pub fn combine(&self, options: &TrainOptions) -> TrainOptions {
+        TrainOptions {
+            frozen: self.frozen || options.frozen,
+            lr_multiplier: self.lr_multiplier * options.lr_multiplier,
+        }
+    }
 }
 
 /// Type alias for a map on which layers store all the needed data.
@@ -154,63 +291,90 @@ pub trait TrainableLayerOps<T> {
 /// Call `ìnit()` in the appropriate layer. Not intended to be called directly.
 pub fn init_layer(layer: &Layer, data: InitData) -> EmptyLayerResult {
     use Layer::*;
-    use crate::nn::layers::filtering::{convolution, max_pool};
+    use crate::nn::layers::filtering::{convolution, conv_transpose, max_pool, avg_pool, global_avg_pool};
     match layer {
         Dense(c) => dense_layer::DenseLayer::init(data, c),
         Relu => relu_layer::ReluLayer::init(data, &()),
+        LeakyRelu(c) => leaky_relu_layer::LeakyReluLayer::init(data, c),
+        Elu(c) => elu_layer::EluLayer::init(data, c),
+        Gelu => gelu_layer::GeluLayer::init(data, &()),
+        Softmax(c) => softmax_layer::SoftmaxLayer::init(data, c),
         Tanh => tanh_layer::TanhLayer::init(data, &()),
         Sigmoid => sigmoid_layer::SigmoidLayer::init(data, &()),
         Sequential(c) => sequential_layer::SequentialLayer::init(data, c),
         Debug(c) => debug_layer::DebugLayer::init(data, c),
         Convolution(c) => convolution::ConvolutionLayer::init(data, c),
+        ConvTranspose(c) => conv_transpose::ConvTransposeLayer::init(data, c),
         MaxPool(c) => max_pool::MaxPoolLayer::init(data, c),
+        AvgPool(c) => avg_pool::AvgPoolLayer::init(data, c),
+        GlobalAvgPool => global_avg_pool::GlobalAvgPoolLayer::init(data, &()),
         Flatten => flatten_layer::FlattenLayer::init(data, &()),
         ExpandDim(c) => expand_dim_layer::ExpandDimLayer::init(data, c),
         Dropout(c) => dropout_layer::DropoutLayer::init(data, c),
         Concat(c) => concat_layer::ConcatLayer::init(data, c),
+        Residual(c) => residual_layer::ResidualLayer::init(data, c),
         TwoComplementsTransformer => two_complements_transformer_layer::TwoComplementsTransformerLayer::init(data, &()),
+        BatchNorm(c) => batch_norm_layer::BatchNormLayer::init(data, c),
     }
 }
 
 /// Call `forward()` in the appropriate layer. Not intended to be called directly.
 pub fn forward_layer(layer: &Layer, data: ForwardData) -> LayerResult {
     use Layer::*;
-    use crate::nn::layers::filtering::{convolution, max_pool};
+    use crate::nn::layers::filtering::{convolution, conv_transpose, max_pool, avg_pool, global_avg_pool};
     match layer {
         Dense(c) => dense_layer::DenseLayer::forward(data, c),
         Sequential(c) => sequential_layer::SequentialLayer::forward(data, c),
         Tanh => tanh_layer::TanhLayer::forward(data, &()),
         Sigmoid => sigmoid_layer::SigmoidLayer::forward(data, &()),
         Relu => relu_layer::ReluLayer::forward(data, &()),
+        LeakyRelu(c) => leaky_relu_layer::LeakyReluLayer::forward(data, c),
+        Elu(c) => elu_layer::EluLayer::forward(data, c),
+        Gelu => gelu_layer::GeluLayer::forward(data, &()),
+        Softmax(c) => softmax_layer::SoftmaxLayer::forward(data, c),
         Debug(c) => debug_layer::DebugLayer::forward(data, c),
         Convolution(c) => convolution::ConvolutionLayer::forward(data, c),
+        ConvTranspose(c) => conv_transpose::ConvTransposeLayer::forward(data, c),
         MaxPool(c) => max_pool::MaxPoolLayer::forward(data, c),
+        AvgPool(c) => avg_pool::AvgPoolLayer::forward(data, c),
+        GlobalAvgPool => global_avg_pool::GlobalAvgPoolLayer::forward(data, &()),
         Flatten => flatten_layer::FlattenLayer::forward(data, &()),
         ExpandDim(c) => expand_dim_layer::ExpandDimLayer::forward(data, c),
         Dropout(c) => dropout_layer::DropoutLayer::forward(data, c),
         Concat(c) => concat_layer::ConcatLayer::forward(data, c),
+        Residual(c) => residual_layer::ResidualLayer::forward(data, c),
         TwoComplementsTransformer => two_complements_transformer_layer::TwoComplementsTransformerLayer::forward(data, &()),
+        BatchNorm(c) => batch_norm_layer::BatchNormLayer::forward(data, c),
     }
 }
 
 /// Call `backward()` in the appropriate layer. Not intended to be called directly.
 pub fn backward_layer(layer: &Layer, data: BackwardData) -> LayerResult {
     use Layer::*;
-    use crate::nn::layers::filtering::{convolution, max_pool};
+    use crate::nn::layers::filtering::{convolution, conv_transpose, max_pool, avg_pool, global_avg_pool};
     match layer {
         Dense(c) => dense_layer::DenseLayer::backward(data, c),
         Sequential(c) => sequential_layer::SequentialLayer::backward(data, c),
         Tanh => tanh_layer::TanhLayer::backward(data, &()),
         Sigmoid => sigmoid_layer::SigmoidLayer::backward(data, &()),
         Relu => relu_layer::ReluLayer::backward(data, &()),
+        LeakyRelu(c) => leaky_relu_layer::LeakyReluLayer::backward(data, c),
+        Elu(c) => elu_layer::EluLayer::backward(data, c),
+        Gelu => gelu_layer::GeluLayer::backward(data, &()),
+        Softmax(c) => softmax_layer::SoftmaxLayer::backward(data, c),
         Debug(c) => debug_layer::DebugLayer::backward(data, c),
         Convolution(c) => convolution::ConvolutionLayer::backward(data, c),
+        ConvTranspose(c) => conv_transpose::ConvTransposeLayer::backward(data, c),
         MaxPool(c) => max_pool::MaxPoolLayer::backward(data, c),
+        AvgPool(c) => avg_pool::AvgPoolLayer::backward(data, c),
+        GlobalAvgPool => global_avg_pool::GlobalAvgPoolLayer::backward(data, &()),
         Flatten => flatten_layer::FlattenLayer::backward(data, &()),
         ExpandDim(c) => expand_dim_layer::ExpandDimLayer::backward(data, c),
         Dropout(c) => dropout_layer::DropoutLayer::backward(data, c),
         Concat(c) => concat_layer::ConcatLayer::backward(data, c),
+        Residual(c) => residual_layer::ResidualLayer::backward(data, c),
         TwoComplementsTransformer => two_complements_transformer_layer::TwoComplementsTransformerLayer::backward(data, &()),
+        BatchNorm(c) => batch_norm_layer::BatchNormLayer::backward(data, c),
     }
 }
 
@@ -218,12 +382,15 @@ pub fn backward_layer(layer: &Layer, data: BackwardData) -> LayerResult {
 /// will happen. Not intended to be called directly.
 pub fn train_layer(layer: &Layer, data: TrainData) -> EmptyLayerResult {
     use Layer::*;
-    use crate::nn::layers::filtering::convolution;
+    use crate::nn::layers::filtering::{convolution, conv_transpose};
     match layer {
         Dense(c) => dense_layer::DenseLayer::train(data, c),
         Sequential(c) => sequential_layer::SequentialLayer::train(data, c),
         Convolution(c) => convolution::ConvolutionLayer::train(data, c),
+        ConvTranspose(c) => conv_transpose::ConvTransposeLayer::train(data, c),
         Concat(c) => concat_layer::ConcatLayer::train(data, c),
+        Residual(c) => residual_layer::ResidualLayer::train(data, c),
+        BatchNorm(c) => batch_norm_layer::BatchNormLayer::train(data, c),
         _ => Ok(()),
     }
 }
@@ -0,0 +1,230 @@
+use crate::nn::layers::nn_layers::*;
+use crate::nn::layers::stored_array::StoredArray;
+
+#[derive(Clone, Debug)]
+pub struct ResidualConfig {
+    /// Layers executed in sequential order, like in **Sequential**
+    pub layers: Vec<Layer>,
+    /// Optional layer applied to the inputs before they're added to the result. Required when the
+    /// layers change the shape of the inputs
+    pub projection: Option<Box<Layer>>,
+}
+
+/// Executes its layers in sequential order and adds the inputs of the block (or the projection of them)
+/// to the result. Allows building deeper models because the gradient can flow directly through
+/// the shortcut.
+/// https://en.wikipedia.org/wiki/Residual_neural_network
+pub struct ResidualLayer;
+
+impl LayerOps<ResidualConfig> for ResidualLayer {
+    fn init(data: InitData, layer_config: &ResidualConfig) -> EmptyLayerResult {
+        for layer in layer_config.layers.iter() {
+            init_layer(layer, InitData {
+                assigner: data.assigner,
+                storage: data.storage,
+            })?;
+        }
+
+        if let Some(projection) = &layer_config.projection {
+            init_layer(projection, InitData {
+                assigner: data.assigner,
+                storage: data.storage,
+            })?;
+        }
+        Ok(())
+    }
+
+    fn forward(data: ForwardData, layer_config: &ResidualConfig) -> LayerResult {
+        let ForwardData {
+            inputs, mut forward_cache, storage, gpu, assigner,
+            batch_config, mut prev_iteration_cache
+        } = data;
+
+        let mut result = inputs.clone();
+        for layer in layer_config.layers.iter() {
+            result = forward_layer(layer, ForwardData {
+                inputs: result,
+                forward_cache: forward_cache.as_deref_mut(),
+                storage,
+                gpu: gpu.clone(),
+                assigner,
+                batch_config,
+                prev_iteration_cache: prev_iteration_cache.as_deref_mut(),
+            })?;
+        }
+
+        let shortcut = match &layer_config.projection {
+            Some(projection) => forward_layer(projection, ForwardData {
+                inputs,
+                forward_cache,
+                storage,
+                gpu,
+                assigner,
+                batch_config,
+                prev_iteration_cache,
+            })?,
+            None => inputs,
+        };
+
+        if result.shape() != shortcut.shape() {
+            return Err(anyhow::anyhow!("Residual shapes differ: {:?} and {:?}. Consider adding a projection",
+                result.shape(), shortcut.shape()));
+        }
+
+        Ok(StoredArray::Memory { data: result.into_memory()? + shortcut.into_memory()? })
+    }
+
+    /// Feed the same gradient to the layers and to the shortcut, and add the results
+    fn backward(data: BackwardData, layer_config: &ResidualConfig) -> LayerResult {
+        // The order is the exact reverse of forward()
+        let shortcut_grad = match &layer_config.projection {
+            Some(projection) => backward_layer(projection, BackwardData {
+                grad: data.grad.clone(),
+                assigner: data.assigner,
+                forward_cache: data.forward_cache,
+                backward_cache: data.backward_cache,
+                batch_config: data.batch_config,
+                storage: data.storage,
+                gpu: data.gpu.clone(),
+            })?.into_memory()?,
+            None => data.grad.clone(),
+        };
+
+        let mut grad = data.grad;
+        for layer in layer_config.layers.iter().rev() {
+            grad = backward_layer(layer, BackwardData {
+                grad,
+                assigner: data.assigner,
+                forward_cache: data.forward_cache,
+                backward_cache: data.backward_cache,
+                batch_config: data.batch_config,
+                storage: data.storage,
+                gpu: data.gpu.clone(),
+            })?.into_memory()?;
+        }
+
+        Ok(StoredArray::Memory { data: grad + shortcut_grad })
+    }
+}
+
+impl TrainableLayerOps<ResidualConfig> for ResidualLayer {
+    fn train(data: TrainData, layer_config: &ResidualConfig) -> EmptyLayerResult {
+        for layer in layer_config.layers.iter() {
+            train_layer(layer, TrainData {
+                storage: data.storage,
+                batch_config: data.batch_config,
+                assigner: data.assigner,
+                backward_cache: data.backward_cache,
+                options: data.options,
+            })?;
+        }
+
+        if let Some(projection) = &layer_config.projection {
+            train_layer(projection, TrainData {
+                storage: data.storage,
+                batch_config: data.batch_config,
+                assigner: data.assigner,
+                backward_cache: data.backward_cache,
+                options: data.options,
+            })?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::array;
+    use crate::nn::batch_config::BatchConfig;
+    use crate::nn::key_assigner::KeyAssigner;
+    use crate::nn::layers::dense_layer::{DenseConfig, DenseLayerInit};
+    use crate::nn::lr_calculators::constant_lr::ConstantLrConfig;
+    use crate::nn::lr_calculators::lr_calculator::LrCalc;
+    use crate::utils::arrays_almost_equal;
+    use super::*;
+
+    #[test]
+    fn test_forward_backward() {
+        let config = ResidualConfig {
+            layers: vec![Layer::Relu],
+            projection: None,
+        };
+        let mut forward_cache = GenericStorage::new();
+        let mut assigner = KeyAssigner::new();
+
+        let result = ResidualLayer::forward(ForwardData {
+            inputs: array![[-1.0, 2.0]].into_dyn().into(),
+            batch_config: &BatchConfig::new_train(),
+            assigner: &mut assigner,
+            storage: &GenericStorage::new(),
+            forward_cache: Some(&mut forward_cache),
+            prev_iteration_cache: None,
+            gpu: None,
+        }, &config).unwrap().into_memory().unwrap();
+        assert!(arrays_almost_equal(&result, &array![[-1.0, 4.0]].into_dyn()));
+
+        assigner.revert();
+        let grad = ResidualLayer::backward(BackwardData {
+            grad: array![[1.0, 1.0]].into_dyn(),
+            batch_config: &BatchConfig::new_train(),
+            assigner: &mut assigner,
+            storage: &GenericStorage::new(),
+            forward_cache: &mut forward_cache,
+            backward_cache: &mut GenericStorage::new(),
+            gpu: None,
+        }, &config).unwrap().into_memory().unwrap();
+        assert!(arrays_almost_equal(&grad, &array![[1.0, 2.0]].into_dyn()));
+        assert!(forward_cache.is_empty());
+    }
+
+    #[test]
+    fn test_projection() {
+        let config = ResidualConfig {
+            layers: vec![],
+            projection: Some(Box::new(Layer::Dense(DenseConfig {
+                in_values: 2,
+                out_values: 2,
+                init_mode: DenseLayerInit::WeightsAndBiases(
+                    array![[2.0, 0.0], [0.0, 3.0]],
+                    array![1.0, 1.0],
+                ),
+                weights_lr_calc: LrCalc::Constant(ConstantLrConfig::default()),
+                biases_lr_calc: LrCalc::Constant(ConstantLrConfig::default()),
+                train_options: Default::default(),
+            }))),
+        };
+        let mut storage = GenericStorage::new();
+        ResidualLayer::init(InitData {
+            assigner: &mut KeyAssigner::new(),
+            storage: &mut storage,
+        }, &config).unwrap();
+
+        let mut forward_cache = GenericStorage::new();
+        let mut backward_cache = GenericStorage::new();
+        let mut assigner = KeyAssigner::new();
+
+        let result = ResidualLayer::forward(ForwardData {
+            inputs: array![[1.0, 2.0]].into_dyn().into(),
+            batch_config: &BatchConfig::new_train(),
+            assigner: &mut assigner,
+            storage: &storage,
+            forward_cache: Some(&mut forward_cache),
+            prev_iteration_cache: None,
+            gpu: None,
+        }, &config).unwrap().into_memory().unwrap();
+        assert!(arrays_almost_equal(&result, &array![[4.0, 9.0]].into_dyn()));
+
+        assigner.revert();
+        let grad = ResidualLayer::backward(BackwardData {
+            grad: array![[1.0, 1.0]].into_dyn(),
+            batch_config: &BatchConfig::new_train(),
+            assigner: &mut assigner,
+            storage: &storage,
+            forward_cache: &mut forward_cache,
+            backward_cache: &mut backward_cache,
+            gpu: None,
+        }, &config).unwrap().into_memory().unwrap();
+        assert!(arrays_almost_equal(&grad, &array![[3.0, 4.0]].into_dyn()));
+        assert!(backward_cache.contains_key("dense_2_2_0"));
+    }
+}
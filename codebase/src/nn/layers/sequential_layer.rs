@@ -3,6 +3,8 @@ use crate::nn::layers::nn_layers::*;
 #[derive(Clone, Debug)]
 pub struct SequentialConfig {
     pub layers: Vec<Layer>,
+    /// Applied to all the layers
+    pub train_options: TrainOptions,
 }
 
 /// Simply executes the layers in sequential order, passing the output of a layer as the input
@@ -58,8 +60,9 @@ impl LayerOps<SequentialConfig> for SequentialLayer {
 
 impl TrainableLayerOps<SequentialConfig> for SequentialLayer {
     fn train(data: TrainData, layer_config: &SequentialConfig) -> EmptyLayerResult {
+        let options = data.options.combine(&layer_config.train_options);
         for layer in layer_config.layers.iter() {
-            let train_data = TrainData { storage: data.storage, batch_config: data.batch_config, assigner: data.assigner, backward_cache: data.backward_cache };
+            let train_data = TrainData { storage: data.storage, batch_config: data.batch_config, assigner: data.assigner, backward_cache: data.backward_cache, options };
             train_layer(layer, train_data)?;
         }
         Ok(())
@@ -111,7 +114,8 @@ mod tests {
         INIT_COUNTER.lock().unwrap().clear();
 
         let config = SequentialConfig {
-            layers: debug_vec(Some(|name, _, _| INIT_COUNTER.lock().unwrap().push(name.to_owned())), None, None)
+            layers: debug_vec(Some(|name, _, _| INIT_COUNTER.lock().unwrap().push(name.to_owned())), None, None),
+            train_options: Default::default(),
         };
 
         SequentialLayer::init(data, &config).unwrap();
@@ -133,7 +137,8 @@ mod tests {
         FORWARD_COUNTER.lock().unwrap().clear();
 
         let config = SequentialConfig {
-            layers: debug_vec(None, Some(|name, _, _| FORWARD_COUNTER.lock().unwrap().push(name.to_owned())), None)
+            layers: debug_vec(None, Some(|name, _, _| FORWARD_COUNTER.lock().unwrap().push(name.to_owned())), None),
+            train_options: Default::default(),
         };
 
         SequentialLayer::forward(data, &config).unwrap();
@@ -156,7 +161,8 @@ mod tests {
         BACKWARD_COUNTER.lock().unwrap().clear();
 
         let config = SequentialConfig {
-            layers: debug_vec(None, None, Some(|name, _, _| BACKWARD_COUNTER.lock().unwrap().push(name.to_owned())))
+            layers: debug_vec(None, None, Some(|name, _, _| BACKWARD_COUNTER.lock().unwrap().push(name.to_owned()))),
+            train_options: Default::default(),
         };
 
         SequentialLayer::backward(data, &config).unwrap();
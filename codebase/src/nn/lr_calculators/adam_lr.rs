@@ -1,4 +1,4 @@
-use crate::nn::layers::nn_layers::LayerResult;
+use crate::nn::layers::nn_layers::{GenericStorage, LayerResult};
 use crate::nn::lr_calculators::lr_calculator::{LrCalcData, LrCalcOps};
 use crate::utils::{lerp_arrays, Array0F, ArrayDynF, EPSILON};
 
@@ -19,6 +19,9 @@ impl Default for AdamConfig {
     }
 }
 
+/// Name of the keys where the state of each parameter is stored
+pub(crate) const STATE_KEY: &str = "adam";
+
 /// Adam is an algorithm for first-order gradient-based optimization of stochastic
 /// functions, based on adaptive estimates of lower-order moments.
 /// It's one of the best for training with gradient descent, because it's hyperparameter-resistant
@@ -27,39 +30,43 @@ pub struct AdamLrCalc;
 impl LrCalcOps<AdamConfig> for AdamLrCalc {
     fn apply(target: ArrayDynF, data: LrCalcData, config: &AdamConfig) -> LayerResult {
         let LrCalcData { storage, assigner, .. } = data;
+        let key = assigner.get_key(STATE_KEY.to_owned());
+        Ok(calc_adam_step(target, key, storage, config).into())
+    }
+}
 
-        let key = assigner.get_key("adam".to_owned());
-        let mut moment1: ArrayDynF;
-        let mut moment2: ArrayDynF;
-        let epoch: f32;
-        match storage.remove(&key) {
-            Some(mut v) => {
-                moment1 = v.remove(0);
-                moment2 = v.remove(0);
-                epoch = *v.remove(0).first().unwrap();
-            }
-            None => {
-                moment1 = ArrayDynF::zeros(target.shape());
-                moment2 = ArrayDynF::zeros(target.shape());
-                epoch = 1.0;
-            }
+/// Update the moments stored in **key** and calculate the change in the parameters
+pub(crate) fn calc_adam_step(target: ArrayDynF, key: String, storage: &mut GenericStorage, config: &AdamConfig) -> ArrayDynF {
+    let mut moment1: ArrayDynF;
+    let mut moment2: ArrayDynF;
+    let epoch: f32;
+    match storage.remove(&key) {
+        Some(mut v) => {
+            moment1 = v.remove(0);
+            moment2 = v.remove(0);
+            epoch = *v.remove(0).first().unwrap();
         }
+        None => {
+            moment1 = ArrayDynF::zeros(target.shape());
+            moment2 = ArrayDynF::zeros(target.shape());
+            epoch = 1.0;
+        }
+    }
 
-        moment1 = lerp_arrays(&target, &moment1, config.decay1);
-        moment2 = lerp_arrays(&(&target * &target), &moment2, config.decay2);
+    moment1 = lerp_arrays(&target, &moment1, config.decay1);
+    moment2 = lerp_arrays(&(&target * &target), &moment2, config.decay2);
 
-        let moment1b = &moment1 / (1.0 - (config.decay1.powf(epoch)));
-        let moment2b = &moment2 / (1.0 - (config.decay2.powf(epoch)));
+    let moment1b = &moment1 / (1.0 - (config.decay1.powf(epoch)));
+    let moment2b = &moment2 / (1.0 - (config.decay2.powf(epoch)));
 
-        storage.insert(
-            key,
-            vec![
-                moment1,
-                moment2,
-                Array0F::from_elem((), epoch + 1.0).into_dyn(),
-            ],
-        );
+    storage.insert(
+        key,
+        vec![
+            moment1,
+            moment2,
+            Array0F::from_elem((), epoch + 1.0).into_dyn(),
+        ],
+    );
 
-        Ok((config.alpha * moment1b / (moment2b.mapv(f32::sqrt) + EPSILON)).into())
-    }
+    config.alpha * moment1b / (moment2b.mapv(f32::sqrt) + EPSILON)
 }
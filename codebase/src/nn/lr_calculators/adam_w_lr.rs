@@ -0,0 +1,71 @@
+use crate::nn::layers::nn_layers::LayerResult;
+use crate::nn::lr_calculators::adam_lr::{calc_adam_step, AdamConfig};
+use crate::nn::lr_calculators::lr_calculator::{LrCalcData, LrCalcOps};
+use crate::utils::ArrayDynF;
+
+#[derive(Clone, Debug)]
+pub struct AdamWConfig {
+    pub adam: AdamConfig,
+    pub weight_decay: f32,
+}
+
+impl Default for AdamWConfig {
+    fn default() -> Self {
+        Self {
+            adam: AdamConfig::default(),
+            weight_decay: 0.01,
+        }
+    }
+}
+
+/// Name of the keys where the state of each parameter is stored
+pub(crate) const STATE_KEY: &str = "adam_w";
+
+/// Adam with decoupled weight decay. Instead of adding the decay to the gradient (which would be
+/// scaled by the moments), the parameters are shrunk directly in each step.
+/// https://arxiv.org/abs/1711.05101
+pub struct AdamWLrCalc;
+
+impl LrCalcOps<AdamWConfig> for AdamWLrCalc {
+    fn apply(target: ArrayDynF, data: LrCalcData, config: &AdamWConfig) -> LayerResult {
+        let LrCalcData { storage, assigner, param_key, param_index, .. } = data;
+
+        let key = assigner.get_key(STATE_KEY.to_owned());
+        let decay = &storage[param_key][param_index] * (config.adam.alpha * config.weight_decay);
+        let step = calc_adam_step(target, key, storage, &config.adam);
+        Ok((step - decay).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::array;
+    use crate::nn::batch_config::BatchConfig;
+    use crate::nn::key_assigner::KeyAssigner;
+    use crate::nn::layers::nn_layers::GenericStorage;
+    use crate::utils::arrays_almost_equal;
+    use super::*;
+
+    #[test]
+    fn test_decoupled_decay() {
+        let mut storage = GenericStorage::new();
+        storage.insert("dense_1_2_0".to_owned(), vec![array![[10.0, -10.0]].into_dyn()]);
+        let config = AdamWConfig {
+            adam: AdamConfig { alpha: 0.1, ..AdamConfig::default() },
+            weight_decay: 0.5,
+        };
+
+        let result = AdamWLrCalc::apply(array![[0.0, 0.0]].into_dyn(), LrCalcData {
+            batch_config: &BatchConfig::new_train(),
+            assigner: &mut KeyAssigner::new(),
+            storage: &mut storage,
+            param_key: "dense_1_2_0",
+            param_index: 0,
+            lr_multiplier: 1.0,
+        }, &config).unwrap().into_memory().unwrap();
+
+        // With a null gradient, only the decay is applied
+        assert!(arrays_almost_equal(&result, &array![[-0.5, 0.5]].into_dyn()));
+        assert!(storage.contains_key("adam_w_0"));
+    }
+}
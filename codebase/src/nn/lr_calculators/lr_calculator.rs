@@ -1,22 +1,38 @@
 use crate::nn::batch_config::BatchConfig;
 use crate::nn::key_assigner::KeyAssigner;
 use crate::nn::layers::nn_layers::{GenericStorage, LayerResult, TrainData};
+use crate::nn::lr_calculators::{adam_lr, adam_w_lr, momentum_lr, rms_prop_lr};
 use crate::nn::lr_calculators::adam_lr::{AdamConfig, AdamLrCalc};
+use crate::nn::lr_calculators::adam_w_lr::{AdamWConfig, AdamWLrCalc};
 use crate::nn::lr_calculators::constant_lr::{ConstantLr, ConstantLrConfig};
+use crate::nn::lr_calculators::momentum_lr::{MomentumConfig, MomentumLrCalc};
+use crate::nn::lr_calculators::lr_schedule::{ScheduledLrCalc, ScheduledLrConfig};
+use crate::nn::lr_calculators::rms_prop_lr::{RmsPropConfig, RmsPropLrCalc};
 use crate::utils::ArrayDynF;
 
 pub struct LrCalcData<'a> {
     pub batch_config: &'a BatchConfig,
     pub assigner: &'a mut KeyAssigner,
-    pub storage: &'a mut GenericStorage
+    pub storage: &'a mut GenericStorage,
+
+    /// Location of the parameter being trained in the storage. Used by calculators that also
+    /// depend on the current value of the parameter
+    pub param_key: &'a str,
+    pub param_index: usize,
+
+    /// Multiplies the result of the calculator, from the **TrainOptions** of the layer
+    pub lr_multiplier: f32,
 }
 
 impl<'a> LrCalcData<'a> {
-    pub fn from_train_data(data: &'a mut TrainData) -> Self {
+    pub fn from_train_data(data: &'a mut TrainData, param_key: &'a str, param_index: usize) -> Self {
         Self {
             storage: data.storage,
             batch_config: data.batch_config,
-            assigner: data.assigner
+            assigner: data.assigner,
+            param_key,
+            param_index,
+            lr_multiplier: data.options.lr_multiplier,
         }
     }
 }
@@ -24,7 +40,11 @@ impl<'a> LrCalcData<'a> {
 #[derive(Clone, Debug)]
 pub enum LrCalc {
     Constant(ConstantLrConfig),
-    Adam(AdamConfig)
+    Adam(AdamConfig),
+    Momentum(MomentumConfig),
+    RmsProp(RmsPropConfig),
+    AdamW(AdamWConfig),
+    Scheduled(ScheduledLrConfig),
 }
 
 pub trait LrCalcOps<T> {
@@ -32,8 +52,33 @@ pub trait LrCalcOps<T> {
 }
 
 pub fn apply_lr_calc(calc: &LrCalc, target: ArrayDynF, data: LrCalcData) -> LayerResult {
-    match calc {
+    let lr_multiplier = data.lr_multiplier;
+    let result = match calc {
         LrCalc::Constant(c) => ConstantLr::apply(target, data, c),
-        LrCalc::Adam(c) => AdamLrCalc::apply(target, data, c)
+        LrCalc::Adam(c) => AdamLrCalc::apply(target, data, c),
+        LrCalc::Momentum(c) => MomentumLrCalc::apply(target, data, c),
+        LrCalc::RmsProp(c) => RmsPropLrCalc::apply(target, data, c),
+        LrCalc::AdamW(c) => AdamWLrCalc::apply(target, data, c),
+        LrCalc::Scheduled(c) => ScheduledLrCalc::apply(target, data, c),
+    }?;
+
+    if lr_multiplier == 1.0 {
+        Ok(result)
+    } else {
+        Ok((result.into_memory()? * lr_multiplier).into())
     }
-}
\ No newline at end of file
+}
+
+/// Take the keys that **calc** uses for its state without changing the storage, so the calculators
+/// of the following layers get the same keys as when this one is trained. Used by frozen layers
+pub fn reserve_lr_calc_keys(calc: &LrCalc, assigner: &mut KeyAssigner) {
+    let name = match calc {
+        LrCalc::Constant(_) => return,
+        LrCalc::Adam(_) => adam_lr::STATE_KEY,
+        LrCalc::Momentum(_) => momentum_lr::STATE_KEY,
+        LrCalc::RmsProp(_) => rms_prop_lr::STATE_KEY,
+        LrCalc::AdamW(_) => adam_w_lr::STATE_KEY,
+        LrCalc::Scheduled(c) => return reserve_lr_calc_keys(&c.inner, assigner),
+    };
+    assigner.get_key(name.to_owned());
+}
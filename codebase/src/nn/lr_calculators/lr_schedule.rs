@@ -0,0 +1,188 @@
+use std::f32::consts::PI;
+use crate::nn::layers::nn_layers::{GenericStorage, LayerResult};
+use crate::nn::lr_calculators::lr_calculator::{apply_lr_calc, LrCalc, LrCalcData, LrCalcOps};
+use crate::utils::{Array0F, ArrayDynF};
+
+/// Key in the storage that holds the number of training steps done by the model. It holds 2 scalars,
+/// the lowest and the highest 16 bits of the step, because f32 can't represent every integer above 2^24.
+/// Storages with a single scalar (before the step was split) are read as the lowest bits
+pub const GLOBAL_STEP_KEY: &str = "global_step";
+const STEP_HALF_BITS: u32 = 16;
+
+#[derive(Clone, Debug)]
+pub enum LrSchedule {
+    /// Linearly increase the factor from 0 to 1 during the first **steps**
+    LinearWarmup { steps: u32 },
+
+    /// Multiply the factor by **gamma** every **step_size** steps
+    StepDecay { step_size: u32, gamma: f32 },
+
+    /// Multiply the factor by **gamma** every step
+    ExponentialDecay { gamma: f32 },
+
+    /// Decrease the factor from 1 to **min_factor** following a cosine curve over **period** steps,
+    /// and then restart. The length of the period is multiplied by **period_mult** after each restart,
+    /// and values below 1 are treated as 1.
+    /// https://arxiv.org/abs/1608.03983
+    CosineAnnealing { period: u32, min_factor: f32, period_mult: f32 },
+}
+
+#[derive(Clone, Debug)]
+pub struct ScheduledLrConfig {
+    /// The factors of all schedules are multiplied together
+    pub schedules: Vec<LrSchedule>,
+    pub inner: Box<LrCalc>,
+}
+
+/// Scales the result of another **LrCalc** by a factor that depends on the global step count of
+/// the model. The step count is kept in the storage, so it continues after loading the model.
+pub struct ScheduledLrCalc;
+
+impl LrSchedule {
+    /// Calculate the factor that multiplies the learning rate at **step** (starting at 0)
+    pub fn factor(&self, step: u32) -> f32 {
+        match self {
+            LrSchedule::LinearWarmup { steps } => {
+                ((step + 1) as f32 / (*steps).max(1) as f32).min(1.0)
+            }
+            LrSchedule::StepDecay { step_size, gamma } => {
+                gamma.powi((step / (*step_size).max(1)) as i32)
+            }
+            LrSchedule::ExponentialDecay { gamma } => {
+                gamma.powi(step as i32)
+            }
+            LrSchedule::CosineAnnealing { period, min_factor, period_mult } if *period_mult <= 1.0 => {
+                let period = (*period).max(1);
+                let position = (step % period) as f32;
+                min_factor + (1.0 - min_factor) * (1.0 + (PI * position / period as f32).cos()) / 2.0
+            }
+            LrSchedule::CosineAnnealing { period, min_factor, period_mult } => {
+                // The periods grow exponentially, so there are few restarts
+                let mut period = (*period).max(1) as f32;
+                let mut position = step as f32;
+                while position >= period {
+                    position -= period;
+                    period *= period_mult;
+                }
+                min_factor + (1.0 - min_factor) * (1.0 + (PI * position / period).cos()) / 2.0
+            }
+        }
+    }
+}
+
+impl LrCalcOps<ScheduledLrConfig> for ScheduledLrCalc {
+    fn apply(target: ArrayDynF, data: LrCalcData, config: &ScheduledLrConfig) -> LayerResult {
+        let step = get_global_step(data.storage);
+        let factor: f32 = config.schedules.iter()
+            .map(|o| o.factor(step))
+            .product();
+
+        // The multiplier is applied once, by the outer call
+        let result = apply_lr_calc(&config.inner, target, LrCalcData { lr_multiplier: 1.0, ..data })?.into_memory()?;
+        Ok((result * factor).into())
+    }
+}
+
+pub fn get_global_step(storage: &GenericStorage) -> u32 {
+    let half = |index: usize| storage.get(GLOBAL_STEP_KEY)
+        .and_then(|o| o.get(index))
+        .and_then(|o| o.first())
+        .map(|o| o.round() as u32)
+        .unwrap_or(0);
+    (half(1) << STEP_HALF_BITS) + half(0)
+}
+
+/// Should be called once after each training step
+pub fn increment_global_step(storage: &mut GenericStorage) {
+    set_global_step(storage, get_global_step(storage).saturating_add(1));
+}
+
+pub fn set_global_step(storage: &mut GenericStorage, step: u32) {
+    let low = step & ((1 << STEP_HALF_BITS) - 1);
+    let high = step >> STEP_HALF_BITS;
+    storage.insert(GLOBAL_STEP_KEY.to_owned(), vec![
+        Array0F::from_elem((), low as f32).into_dyn(),
+        Array0F::from_elem((), high as f32).into_dyn(),
+    ]);
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::array;
+    use crate::nn::batch_config::BatchConfig;
+    use crate::nn::key_assigner::KeyAssigner;
+    use crate::nn::lr_calculators::constant_lr::ConstantLrConfig;
+    use crate::utils::arrays_almost_equal;
+    use super::*;
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 0.0001, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn test_factors() {
+        let warmup = LrSchedule::LinearWarmup { steps: 4 };
+        assert_close(warmup.factor(0), 0.25);
+        assert_close(warmup.factor(10), 1.0);
+
+        let step = LrSchedule::StepDecay { step_size: 10, gamma: 0.5 };
+        assert_close(step.factor(9), 1.0);
+        assert_close(step.factor(25), 0.25);
+
+        let exponential = LrSchedule::ExponentialDecay { gamma: 0.9 };
+        assert_close(exponential.factor(2), 0.81);
+
+        let cosine = LrSchedule::CosineAnnealing { period: 10, min_factor: 0.0, period_mult: 2.0 };
+        assert_close(cosine.factor(0), 1.0);
+        assert_close(cosine.factor(5), 0.5);
+        // Restarts with a period of 20
+        assert_close(cosine.factor(10), 1.0);
+        assert_close(cosine.factor(20), 0.5);
+        assert_close(cosine.factor(30), 1.0);
+
+        let cosine = LrSchedule::CosineAnnealing { period: 10, min_factor: 0.0, period_mult: 1.0 };
+        assert_close(cosine.factor(15), 0.5);
+        // Doesn't iterate over every restart
+        assert_close(cosine.factor(4_000_000_005), 0.5);
+    }
+
+    #[test]
+    fn test_large_global_step() {
+        let mut storage = GenericStorage::new();
+        set_global_step(&mut storage, (1 << 24) + 1);
+        increment_global_step(&mut storage);
+        assert_eq!(get_global_step(&storage), (1 << 24) + 2);
+
+        set_global_step(&mut storage, u32::MAX);
+        increment_global_step(&mut storage);
+        assert_eq!(get_global_step(&storage), u32::MAX);
+
+        // Before the step was split in 2 scalars
+        storage.insert(GLOBAL_STEP_KEY.to_owned(), vec![Array0F::from_elem((), 12.0).into_dyn()]);
+        increment_global_step(&mut storage);
+        assert_eq!(get_global_step(&storage), 13);
+    }
+
+    #[test]
+    fn test_uses_global_step() {
+        let config = ScheduledLrConfig {
+            schedules: vec![LrSchedule::StepDecay { step_size: 1, gamma: 0.5 }],
+            inner: Box::new(LrCalc::Constant(ConstantLrConfig { lr: 1.0 })),
+        };
+        let mut storage = GenericStorage::new();
+        increment_global_step(&mut storage);
+        increment_global_step(&mut storage);
+        assert_eq!(get_global_step(&storage), 2);
+
+        let result = ScheduledLrCalc::apply(array![1.0, 2.0].into_dyn(), LrCalcData {
+            batch_config: &BatchConfig::new_train(),
+            assigner: &mut KeyAssigner::new(),
+            storage: &mut storage,
+            param_key: "dense_1_2_0",
+            param_index: 0,
+            lr_multiplier: 1.0,
+        }, &config).unwrap().into_memory().unwrap();
+
+        assert!(arrays_almost_equal(&result, &array![0.25, 0.5].into_dyn()));
+    }
+}
@@ -1,3 +1,7 @@
 pub mod constant_lr;
 pub mod lr_calculator;
-pub mod adam_lr;
\ No newline at end of file
+pub mod adam_lr;
+pub mod momentum_lr;
+pub mod rms_prop_lr;
+pub mod adam_w_lr;
+pub mod lr_schedule;
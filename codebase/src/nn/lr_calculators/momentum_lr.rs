@@ -0,0 +1,74 @@
+use crate::nn::layers::nn_layers::LayerResult;
+use crate::nn::lr_calculators::lr_calculator::{LrCalcData, LrCalcOps};
+use crate::utils::ArrayDynF;
+
+#[derive(Clone, Debug)]
+pub struct MomentumConfig {
+    pub lr: f32,
+    pub momentum: f32,
+}
+
+impl Default for MomentumConfig {
+    fn default() -> Self {
+        Self {
+            lr: 0.01,
+            momentum: 0.9,
+        }
+    }
+}
+
+/// Name of the keys where the state of each parameter is stored
+pub(crate) const STATE_KEY: &str = "momentum";
+
+/// Stochastic gradient descent with momentum. Keeps a velocity that accumulates the previous gradients,
+/// so the parameters keep moving in directions that are consistent between batches.
+pub struct MomentumLrCalc;
+
+impl LrCalcOps<MomentumConfig> for MomentumLrCalc {
+    fn apply(target: ArrayDynF, data: LrCalcData, config: &MomentumConfig) -> LayerResult {
+        let LrCalcData { storage, assigner, .. } = data;
+
+        let key = assigner.get_key(STATE_KEY.to_owned());
+        let velocity = match storage.remove(&key) {
+            Some(mut v) => v.remove(0) * config.momentum + target,
+            None => target,
+        };
+
+        let result = &velocity * config.lr;
+        storage.insert(key, vec![velocity]);
+        Ok(result.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::array;
+    use crate::nn::batch_config::BatchConfig;
+    use crate::nn::key_assigner::KeyAssigner;
+    use crate::nn::layers::nn_layers::GenericStorage;
+    use crate::utils::arrays_almost_equal;
+    use super::*;
+
+    #[test]
+    fn test_accumulates_velocity() {
+        let config = MomentumConfig { lr: 0.5, momentum: 0.5 };
+        let mut storage = GenericStorage::new();
+        let mut results = Vec::new();
+
+        for _ in 0..2 {
+            let mut assigner = KeyAssigner::new();
+            let result = MomentumLrCalc::apply(array![1.0, -2.0].into_dyn(), LrCalcData {
+                batch_config: &BatchConfig::new_train(),
+                assigner: &mut assigner,
+                storage: &mut storage,
+                param_key: "dense_1_1_0",
+                param_index: 0,
+                lr_multiplier: 1.0,
+            }, &config).unwrap().into_memory().unwrap();
+            results.push(result);
+        }
+
+        assert!(arrays_almost_equal(&results[0], &array![0.5, -1.0].into_dyn()));
+        assert!(arrays_almost_equal(&results[1], &array![0.75, -1.5].into_dyn()));
+    }
+}
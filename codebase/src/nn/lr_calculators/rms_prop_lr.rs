@@ -0,0 +1,80 @@
+use crate::nn::layers::nn_layers::LayerResult;
+use crate::nn::lr_calculators::lr_calculator::{LrCalcData, LrCalcOps};
+use crate::utils::{lerp_arrays, ArrayDynF, EPSILON};
+
+#[derive(Clone, Debug)]
+pub struct RmsPropConfig {
+    pub alpha: f32,
+    pub decay: f32,
+}
+
+impl Default for RmsPropConfig {
+    fn default() -> Self {
+        Self {
+            alpha: 0.001,
+            decay: 0.9,
+        }
+    }
+}
+
+/// Name of the keys where the state of each parameter is stored
+pub(crate) const STATE_KEY: &str = "rms_prop";
+
+/// RMSProp divides the gradient by a running average of its recent magnitude, so each parameter
+/// gets its own effective learning rate. The average starts at 0, like the moments of **Adam**.
+/// https://optimization.cbe.cornell.edu/index.php?title=RMSProp
+pub struct RmsPropLrCalc;
+
+impl LrCalcOps<RmsPropConfig> for RmsPropLrCalc {
+    fn apply(target: ArrayDynF, data: LrCalcData, config: &RmsPropConfig) -> LayerResult {
+        let LrCalcData { storage, assigner, .. } = data;
+
+        let key = assigner.get_key(STATE_KEY.to_owned());
+        let squared = &target * &target;
+        let previous = match storage.remove(&key) {
+            Some(mut v) => v.remove(0),
+            None => ArrayDynF::zeros(target.shape()),
+        };
+        let mean_square = lerp_arrays(&squared, &previous, config.decay);
+
+        let result = config.alpha * target / (mean_square.mapv(f32::sqrt) + EPSILON);
+        storage.insert(key, vec![mean_square]);
+        Ok(result.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::array;
+    use crate::nn::batch_config::BatchConfig;
+    use crate::nn::key_assigner::KeyAssigner;
+    use crate::nn::layers::nn_layers::GenericStorage;
+    use crate::utils::arrays_almost_equal;
+    use super::*;
+
+    #[test]
+    fn test_divides_by_mean_square() {
+        let config = RmsPropConfig { alpha: 0.1, decay: 0.5 };
+        let mut storage = GenericStorage::new();
+        let mut results = Vec::new();
+
+        for grad in [array![2.0, -1.0], array![2.0, 3.0]] {
+            let mut assigner = KeyAssigner::new();
+            let result = RmsPropLrCalc::apply(grad.into_dyn(), LrCalcData {
+                batch_config: &BatchConfig::new_train(),
+                assigner: &mut assigner,
+                storage: &mut storage,
+                param_key: "dense_1_1_0",
+                param_index: 0,
+                lr_multiplier: 1.0,
+            }, &config).unwrap().into_memory().unwrap();
+            results.push(result);
+        }
+
+        // Mean squares are 0.5 * [4, 1] = [2, 0.5] and then 0.5 * [2, 0.5] + 0.5 * [4, 9] = [3, 4.75]
+        let sqrt_2 = 2.0f32.sqrt();
+        assert!(arrays_almost_equal(&results[0], &array![0.1 * sqrt_2, -0.1 * sqrt_2].into_dyn()));
+        assert!(arrays_almost_equal(&results[1], &array![0.2 / 3.0f32.sqrt(), 0.3 / 4.75f32.sqrt()].into_dyn()));
+        assert!(arrays_almost_equal(&storage["rms_prop_0"][0], &array![3.0, 4.75].into_dyn()));
+    }
+}
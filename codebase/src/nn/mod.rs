@@ -4,4 +4,11 @@ pub mod loss;
 pub mod controller;
 pub mod lr_calculators;
 pub mod key_assigner;
-pub mod generic_storage;
\ No newline at end of file
+pub mod generic_storage;
+pub mod gradient_clipping;
+pub mod gradient_check;
+pub mod precision;
+pub mod quantization;
+pub mod shape_inference;
+pub mod data_parallel;
+pub mod training_loop;
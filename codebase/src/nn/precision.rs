@@ -0,0 +1,123 @@
+use std::str::FromStr;
+use half::{bf16, f16};
+use crate::nn::layers::nn_layers::GenericStorage;
+use crate::utils::ArrayDynF;
+
+/// Precision in which the parameters are serialized. Reduced precisions halve the size of the
+/// serialized storage. Once deserialized, the parameters are held and evaluated in f32, on the CPU
+/// and on the GPU, so they only lose the rounding and don't make the evaluation faster.
+/// There is no storage that holds the parameters in f16 or bf16, nor kernels that evaluate them.
+///
+/// **F16** has a much smaller range than f32, so very small values (like the second moment in **Adam**)
+/// may become 0. **Bf16** has the same range as f32 and is safer for storages that will be trained.
+///
+/// Scalars (arrays with 0 dimensions) are always kept in f32, because they're used as counters
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Precision {
+    F32,
+    F16,
+    Bf16,
+}
+
+impl Precision {
+    pub fn bytes_per_value(&self) -> usize {
+        match self {
+            Precision::F32 => 4,
+            Precision::F16 | Precision::Bf16 => 2,
+        }
+    }
+
+    /// Round **value** to the nearest value representable in this precision
+    pub fn round(&self, value: f32) -> f32 {
+        match self {
+            Precision::F32 => value,
+            Precision::F16 => f16::from_f32(value).to_f32(),
+            Precision::Bf16 => bf16::from_f32(value).to_f32(),
+        }
+    }
+
+    /// Precision used for an array of this shape
+    pub fn for_shape(&self, shape: &[usize]) -> Precision {
+        if shape.is_empty() { Precision::F32 } else { *self }
+    }
+
+    pub fn round_array(&self, array: &mut ArrayDynF) {
+        let precision = self.for_shape(array.shape());
+        if precision != Precision::F32 {
+            array.mapv_inplace(|o| precision.round(o));
+        }
+    }
+
+    /// Round all arrays in the storage, like they would be after serializing and deserializing them
+    pub fn round_storage(&self, storage: &mut GenericStorage) {
+        storage.values_mut()
+            .flat_map(|o| o.iter_mut())
+            .for_each(|o| self.round_array(o));
+    }
+
+    /// Big-endian bytes of **value** in this precision
+    pub fn to_be_bytes(&self, value: f32, result: &mut Vec<u8>) {
+        match self {
+            Precision::F32 => result.extend(value.to_be_bytes()),
+            Precision::F16 => result.extend(f16::from_f32(value).to_be_bytes()),
+            Precision::Bf16 => result.extend(bf16::from_f32(value).to_be_bytes()),
+        }
+    }
+
+    /// Read a value from big-endian bytes. **bytes** should have length **bytes_per_value()**
+    pub fn from_be_bytes(&self, bytes: &[u8]) -> f32 {
+        match self {
+            Precision::F32 => f32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            Precision::F16 => f16::from_be_bytes([bytes[0], bytes[1]]).to_f32(),
+            Precision::Bf16 => bf16::from_be_bytes([bytes[0], bytes[1]]).to_f32(),
+        }
+    }
+}
+
+impl FromStr for Precision {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "f32" => Ok(Precision::F32),
+            "f16" => Ok(Precision::F16),
+            "bf16" => Ok(Precision::Bf16),
+            _ => Err(anyhow::anyhow!("Invalid precision {}", s)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::array;
+    use super::*;
+
+    #[test]
+    fn test_round() {
+        assert_eq!(Precision::F32.round(0.1), 0.1);
+        assert_eq!(Precision::F16.round(0.1), 0.099975586);
+        assert_eq!(Precision::Bf16.round(0.1), 0.100097656);
+        assert_eq!(Precision::F16.round(100_000.0), f32::INFINITY);
+        assert_eq!(Precision::Bf16.round(100_000.0), 99840.0);
+    }
+
+    #[test]
+    fn test_round_storage() {
+        let mut storage = GenericStorage::new();
+        storage.insert("a".to_owned(), vec![array![0.1, 3.0].into_dyn(), ArrayDynF::from_elem(vec![], 3001.0)]);
+        Precision::F16.round_storage(&mut storage);
+        assert_eq!(storage["a"][0], array![0.099975586, 3.0].into_dyn());
+        // Scalars are kept in f32
+        assert_eq!(storage["a"][1], ArrayDynF::from_elem(vec![], 3001.0));
+    }
+
+    #[test]
+    fn test_bytes() {
+        for precision in [Precision::F32, Precision::F16, Precision::Bf16] {
+            let mut bytes = Vec::new();
+            precision.to_be_bytes(-1.75, &mut bytes);
+            assert_eq!(bytes.len(), precision.bytes_per_value());
+            assert_eq!(precision.from_be_bytes(&bytes), -1.75);
+        }
+    }
+}
@@ -0,0 +1,149 @@
+use std::collections::HashMap;
+use ndarray::ArrayD;
+use crate::nn::key_assigner::KeyAssigner;
+use crate::nn::layers::dense_layer;
+use crate::nn::layers::filtering::convolution;
+use crate::nn::layers::nn_layers::{GenericStorage, Layer};
+use crate::utils::{Array1F, ArrayDynF, GenericResult};
+
+/// Values of an array converted to int8, with one scale for each index of the first axis (the
+/// output channel of weights and kernels, or the batch item of inputs).
+/// Each original value is approximately `values[i, ...] * scales[i]`
+#[derive(Clone, Debug)]
+pub struct QuantizedArray {
+    pub values: ArrayD<i8>,
+    pub scales: Array1F,
+}
+
+/// Quantized parameters, with the same keys as the **GenericStorage** they came from
+pub type QuantizedStorage = HashMap<String, QuantizedArray>;
+
+impl QuantizedArray {
+    /// Symmetric quantization, where the largest absolute value of each channel becomes 127.
+    /// **array** must have at least 1 dimension
+    pub fn quantize(array: &ArrayDynF) -> QuantizedArray {
+        let scales: Array1F = array.outer_iter()
+            .map(|o| o.fold(0.0f32, |acc, v| acc.max(v.abs())) / 127.0)
+            .map(|o| if o == 0.0 { 1.0 } else { o })
+            .collect();
+
+        let values = array.outer_iter()
+            .zip(scales.iter())
+            .flat_map(|(channel, scale)| channel.iter()
+                .map(move |o| (o / scale).round().clamp(-127.0, 127.0) as i8)
+                .collect::<Vec<_>>())
+            .collect();
+
+        QuantizedArray {
+            values: ArrayD::from_shape_vec(array.shape(), values).unwrap(),
+            scales,
+        }
+    }
+
+    /// Convert the values back to f32
+    pub fn dequantize(&self) -> ArrayDynF {
+        let mut result = self.values.mapv(|o| o as f32);
+        result.outer_iter_mut()
+            .zip(self.scales.iter())
+            .for_each(|(mut channel, scale)| channel *= *scale);
+        result
+    }
+}
+
+/// Quantize the weights of every **Dense** layer and the kernel of every **Convolution** layer
+/// in **layers**, which should be the same ones (in the same order) used to init **storage**.
+/// Other parameters, like biases, are kept in f32
+pub fn quantize_storage(layers: &[&Layer], storage: &GenericStorage) -> GenericResult<QuantizedStorage> {
+    let mut assigner = KeyAssigner::new();
+    let mut result = QuantizedStorage::new();
+    for layer in layers {
+        quantize_layer(layer, &mut assigner, storage, &mut result)?;
+    }
+    Ok(result)
+}
+
+/// Visit the layers in the same order as **init_layer()** to get the same keys
+fn quantize_layer(layer: &Layer, assigner: &mut KeyAssigner, storage: &GenericStorage,
+                  result: &mut QuantizedStorage) -> GenericResult<()> {
+    let key = match layer {
+        Layer::Dense(config) => assigner.get_key(dense_layer::gen_name(config)),
+        Layer::Convolution(config) => assigner.get_key(convolution::gen_name(config)),
+        Layer::Sequential(config) => {
+            for layer in config.layers.iter() {
+                quantize_layer(layer, assigner, storage, result)?;
+            }
+            return Ok(());
+        }
+        Layer::Concat(config) => {
+            for layer in config.layers.iter() {
+                quantize_layer(layer, assigner, storage, result)?;
+            }
+            return Ok(());
+        }
+        Layer::Residual(config) => {
+            for layer in config.layers.iter() {
+                quantize_layer(layer, assigner, storage, result)?;
+            }
+            if let Some(projection) = &config.projection {
+                quantize_layer(projection, assigner, storage, result)?;
+            }
+            return Ok(());
+        }
+        _ => return Ok(()),
+    };
+
+    let params = storage.get(&key)
+        .and_then(|o| o.first())
+        .ok_or_else(|| anyhow::anyhow!("Parameters {} not found in the storage", key))?;
+    result.insert(key, QuantizedArray::quantize(params));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::array;
+    use crate::nn::layers::nn_layers::{init_layer, InitData};
+    use crate::nn::layers::sequential_layer::SequentialConfig;
+    use crate::nn::lr_calculators::constant_lr::ConstantLrConfig;
+    use crate::nn::lr_calculators::lr_calculator::LrCalc;
+    use crate::utils::arrays_almost_equal;
+    use super::*;
+
+    #[test]
+    fn test_quantize() {
+        let array = array![[1.27, -0.5, 0.0], [0.0, 0.0, 0.0], [-2.54, 1.0, 0.02]].into_dyn();
+        let quantized = QuantizedArray::quantize(&array);
+        assert_eq!(quantized.values, array![[127, -50, 0], [0, 0, 0], [-127, 50, 1]].into_dyn());
+        assert!(arrays_almost_equal(&quantized.scales, &array![0.01, 1.0, 0.02]));
+        assert!(arrays_almost_equal(&quantized.dequantize(), &array));
+    }
+
+    #[test]
+    fn test_quantize_storage() {
+        let dense = |in_values, out_values| Layer::Dense(dense_layer::DenseConfig {
+            in_values,
+            out_values,
+            init_mode: dense_layer::DenseLayerInit::Random(),
+            weights_lr_calc: LrCalc::Constant(ConstantLrConfig::default()),
+            biases_lr_calc: LrCalc::Constant(ConstantLrConfig::default()),
+            train_options: Default::default(),
+        });
+        let main = Layer::Sequential(SequentialConfig { layers: vec![dense(3, 4), Layer::Relu, dense(4, 4)], train_options: Default::default() });
+        let head = dense(4, 4);
+
+        let mut storage = GenericStorage::new();
+        let mut assigner = KeyAssigner::new();
+        for layer in [&main, &head] {
+            init_layer(layer, InitData { assigner: &mut assigner, storage: &mut storage }).unwrap();
+        }
+
+        let quantized = quantize_storage(&[&main, &head], &storage).unwrap();
+        let mut keys: Vec<_> = quantized.keys().cloned().collect();
+        keys.sort();
+        assert_eq!(keys, vec!["dense_3_4_0", "dense_4_4_0", "dense_4_4_1"]);
+        for (key, value) in quantized.iter() {
+            let max_diff = (&value.dequantize() - &storage[key][0]).mapv(f32::abs).fold(0.0f32, |a, &b| a.max(b));
+            assert!(max_diff <= value.scales.fold(0.0f32, |a, &b| a.max(b)) / 2.0 + 0.00001);
+        }
+    }
+}
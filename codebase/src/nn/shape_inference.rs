@@ -0,0 +1,427 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use crate::nn::controller::HeadConfig;
+use crate::nn::layers::nn_layers::Layer;
+
+/// Shapes of the inputs and outputs of a layer and of its children, without the batch dimension
+#[derive(Clone, Debug)]
+pub struct ShapeNode<'a> {
+    pub layer: &'a Layer,
+    /// Like `Sequential/Dense[1]`, where the index is the position of the layer among its siblings
+    pub path: String,
+    pub input: Vec<usize>,
+    pub output: Vec<usize>,
+    /// In the order they are executed
+    pub children: Vec<ShapeNode<'a>>,
+}
+
+impl<'a> ShapeNode<'a> {
+    /// Visit this node and all its descendants, parents before children
+    pub fn iter(&self) -> impl Iterator<Item=(usize, &ShapeNode<'a>)> {
+        let mut stack = vec![(0, self)];
+        std::iter::from_fn(move || {
+            let (depth, node) = stack.pop()?;
+            stack.extend(node.children.iter().rev().map(|o| (depth + 1, o)));
+            Some((depth, node))
+        })
+    }
+}
+
+/// Reason why a layer can't receive its inputs. All shapes are without the batch dimension
+#[derive(Clone, Debug, PartialEq)]
+pub enum ShapeErrorKind {
+    /// The layer expects inputs with **expected** dimensions
+    DimensionCount { expected: usize, shape: Vec<usize> },
+    /// The layer expects exactly this shape, like **TwoComplementsTransformer** that needs 2 values
+    UnexpectedShape { expected: Vec<usize>, shape: Vec<usize> },
+    /// An attribute of the layer doesn't match the inputs, like **in_values** of **Dense**
+    AttributeMismatch { attribute: &'static str, value: usize, actual: usize },
+    /// An attribute refers to a dimension that the inputs don't have, like **axis** of **Softmax**
+    DimensionOutOfBounds { attribute: &'static str, value: usize, shape: Vec<usize> },
+    /// An attribute can't have this value regardless of the inputs, like a stride of 0
+    InvalidAttribute { attribute: &'static str, value: usize },
+    /// The filter (after dilation) is bigger than the padded inputs
+    FilterTooBig { size: usize, padding: usize, shape: Vec<usize> },
+    /// The padding removes the whole output of **ConvTranspose**
+    PaddingTooBig { padding: usize, shape: Vec<usize> },
+    /// The outputs of the layers of **Concat** differ outside the concatenated dimension
+    ConcatMismatch { dim: usize, first: Vec<usize>, other: Vec<usize> },
+    /// The output of the layers of **Residual** differs from the shortcut
+    ResidualMismatch { output: Vec<usize>, shortcut: Vec<usize> },
+    /// **Concat** needs at least one layer
+    NoLayers,
+}
+
+impl Display for ShapeErrorKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::DimensionCount { expected, shape } =>
+                write!(f, "Expected inputs with {} dimensions, but got shape {:?}", expected, shape),
+            Self::UnexpectedShape { expected, shape } =>
+                write!(f, "Expected inputs with shape {:?}, but got shape {:?}", expected, shape),
+            Self::AttributeMismatch { attribute, value, actual } =>
+                write!(f, "Attribute '{}' is {}, but the inputs have {}", attribute, value, actual),
+            Self::DimensionOutOfBounds { attribute, value, shape } =>
+                write!(f, "Attribute '{}' is {}, which is out of bounds for shape {:?}", attribute, value, shape),
+            Self::InvalidAttribute { attribute, value } =>
+                write!(f, "Attribute '{}' can't be {}", attribute, value),
+            Self::FilterTooBig { size, padding, shape } =>
+                write!(f, "Filter of size {} is bigger than shape {:?} with padding {}", size, shape, padding),
+            Self::PaddingTooBig { padding, shape } =>
+                write!(f, "Padding {} removes the whole output for shape {:?}", padding, shape),
+            Self::ConcatMismatch { dim, first, other } =>
+                write!(f, "Can't concatenate shapes {:?} and {:?} in dimension {}", first, other, dim),
+            Self::ResidualMismatch { output, shortcut } =>
+                write!(f, "Residual shapes differ: {:?} and {:?}. Consider adding a projection", output, shortcut),
+            Self::NoLayers => write!(f, "Expected at least one layer"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ShapeError {
+    /// Path of the offending layer, like in **ShapeNode**
+    pub path: String,
+    pub kind: ShapeErrorKind,
+}
+
+impl ShapeError {
+    fn new(path: &str, kind: ShapeErrorKind) -> Self {
+        Self { path: path.to_owned(), kind }
+    }
+
+    /// Prepend **segment** to the path of the error
+    pub(crate) fn at(self, segment: impl Display) -> Self {
+        Self { path: format!("{}/{}", segment, self.path), kind: self.kind }
+    }
+}
+
+impl Display for ShapeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} (in {})", self.kind, self.path)
+    }
+}
+
+impl Error for ShapeError {}
+
+type Result<T> = std::result::Result<T, ShapeError>;
+
+/// Shape of the output of **layer** for a single input with **shape**, without the batch dimension.
+/// The layers aren't executed, so it's cheap even for big models
+pub fn infer_shape(layer: &Layer, shape: &[usize]) -> Result<Vec<usize>> {
+    infer_shapes(layer, shape).map(|o| o.output)
+}
+
+/// Like **infer_shape**, but keeps the shapes of every layer in the tree
+pub fn infer_shapes<'a>(layer: &'a Layer, shape: &[usize]) -> Result<ShapeNode<'a>> {
+    infer_node(layer, shape, layer.tag_name().to_owned())
+}
+
+/// Check that every layer of the model accepts the outputs of the previous one, when the model
+/// receives inputs with **shape** (without the batch dimension). The paths of the errors in heads
+/// start with the name of the head
+pub fn validate_shapes(main_layer: &Layer, heads: &[HeadConfig], shape: &[usize]) -> Result<()> {
+    let output = infer_shape(main_layer, shape)?;
+    for head in heads.iter() {
+        infer_shape(&head.layer, &output).map_err(|e| e.at(&head.name))?;
+    }
+    Ok(())
+}
+
+fn infer_node<'a>(layer: &'a Layer, shape: &[usize], path: String) -> Result<ShapeNode<'a>> {
+    use ShapeErrorKind::*;
+    let mut children = Vec::new();
+    let output = match layer {
+        Layer::Sequential(c) => {
+            let mut shape = shape.to_vec();
+            for (index, layer) in c.layers.iter().enumerate() {
+                let child = infer_node(layer, &shape, child_path(&path, layer, index))?;
+                shape = child.output.clone();
+                children.push(child);
+            }
+            shape
+        }
+        Layer::Concat(c) => {
+            for (index, layer) in c.layers.iter().enumerate() {
+                children.push(infer_node(layer, shape, child_path(&path, layer, index))?);
+            }
+            concat_shape(&children, c.dim, &path)?
+        }
+        Layer::Residual(c) => {
+            let mut output = shape.to_vec();
+            for (index, layer) in c.layers.iter().enumerate() {
+                let child = infer_node(layer, &output, child_path(&path, layer, index))?;
+                output = child.output.clone();
+                children.push(child);
+            }
+
+            let shortcut = match &c.projection {
+                Some(projection) => {
+                    let projection_path = format!("{}/Projection/{}", path, projection.tag_name());
+                    let child = infer_node(projection, shape, projection_path)?;
+                    let shortcut = child.output.clone();
+                    children.push(child);
+                    shortcut
+                }
+                None => shape.to_vec(),
+            };
+            if output != shortcut {
+                return Err(ShapeError::new(&path, ResidualMismatch { output, shortcut }));
+            }
+            output
+        }
+        Layer::Dense(c) => {
+            let [values] = expect_dims(shape, &path)?;
+            expect_attr("in_values", c.in_values, values, &path)?;
+            vec![c.out_values]
+        }
+        Layer::Convolution(c) => {
+            let [channels, height, width] = expect_dims(shape, &path)?;
+            expect_attr("in_channels", c.in_channels, channels, &path)?;
+            expect_positive("kernel_size", c.kernel_size, &path)?;
+            expect_positive("stride", c.stride, &path)?;
+            expect_positive("dilation", c.dilation, &path)?;
+
+            let size = (c.kernel_size - 1) * c.dilation + 1;
+            vec![
+                c.out_channels,
+                filtered_dim(height, size, c.stride, c.padding, shape, &path)?,
+                filtered_dim(width, size, c.stride, c.padding, shape, &path)?,
+            ]
+        }
+        Layer::ConvTranspose(c) => {
+            let [channels, height, width] = expect_dims(shape, &path)?;
+            expect_attr("in_channels", c.in_channels, channels, &path)?;
+            expect_positive("kernel_size", c.kernel_size, &path)?;
+            expect_positive("stride", c.stride, &path)?;
+            vec![
+                c.out_channels,
+                transposed_dim(height, c.kernel_size, c.stride, c.padding, shape, &path)?,
+                transposed_dim(width, c.kernel_size, c.stride, c.padding, shape, &path)?,
+            ]
+        }
+        Layer::MaxPool(c) => pool_shape(shape, c.size, c.stride, c.padding, &path)?,
+        Layer::AvgPool(c) => pool_shape(shape, c.size, c.stride, c.padding, &path)?,
+        Layer::GlobalAvgPool => {
+            let [channels, _, _] = expect_dims(shape, &path)?;
+            vec![channels]
+        }
+        Layer::BatchNorm(c) => {
+            match shape.first() {
+                Some(channels) => expect_attr("channels", c.channels, *channels, &path)?,
+                None => return Err(ShapeError::new(&path, DimensionCount { expected: 1, shape: Vec::new() })),
+            }
+            shape.to_vec()
+        }
+        Layer::Flatten => vec![shape.iter().product()],
+        Layer::ExpandDim(c) => {
+            if c.dim > shape.len() {
+                return Err(ShapeError::new(&path, DimensionOutOfBounds { attribute: "dim", value: c.dim, shape: shape.to_vec() }));
+            }
+            let mut shape = shape.to_vec();
+            shape.insert(c.dim, 1);
+            shape
+        }
+        Layer::TwoComplementsTransformer => {
+            if shape != [2] {
+                return Err(ShapeError::new(&path, UnexpectedShape { expected: vec![2], shape: shape.to_vec() }));
+            }
+            vec![1]
+        }
+        Layer::Softmax(c) => {
+            if c.axis >= shape.len() {
+                return Err(ShapeError::new(&path, DimensionOutOfBounds { attribute: "axis", value: c.axis, shape: shape.to_vec() }));
+            }
+            shape.to_vec()
+        }
+        Layer::Tanh | Layer::Sigmoid | Layer::Relu | Layer::LeakyRelu(_) | Layer::Elu(_) | Layer::Gelu
+        | Layer::Debug(_) | Layer::Dropout(_) => shape.to_vec(),
+    };
+
+    Ok(ShapeNode {
+        layer,
+        path,
+        input: shape.to_vec(),
+        output,
+        children,
+    })
+}
+
+fn child_path(parent: &str, layer: &Layer, index: usize) -> String {
+    format!("{}/{}[{}]", parent, layer.tag_name(), index)
+}
+
+fn concat_shape(children: &[ShapeNode], dim: usize, path: &str) -> Result<Vec<usize>> {
+    let first = match children.first() {
+        Some(v) => &v.output,
+        None => return Err(ShapeError::new(path, ShapeErrorKind::NoLayers)),
+    };
+    if dim >= first.len() {
+        let kind = ShapeErrorKind::DimensionOutOfBounds { attribute: "dim", value: dim, shape: first.clone() };
+        return Err(ShapeError::new(path, kind));
+    }
+
+    let mut result = first.clone();
+    for other in children[1..].iter().map(|o| &o.output) {
+        let matches = other.len() == first.len()
+            && first.iter().zip(other.iter()).enumerate().all(|(i, (a, b))| i == dim || a == b);
+        if !matches {
+            let kind = ShapeErrorKind::ConcatMismatch { dim, first: first.clone(), other: other.clone() };
+            return Err(ShapeError::new(path, kind));
+        }
+        result[dim] += other[dim];
+    }
+    Ok(result)
+}
+
+fn expect_dims<const N: usize>(shape: &[usize], path: &str) -> Result<[usize; N]> {
+    shape.try_into()
+        .map_err(|_| ShapeError::new(path, ShapeErrorKind::DimensionCount { expected: N, shape: shape.to_vec() }))
+}
+
+fn expect_attr(attribute: &'static str, value: usize, actual: usize, path: &str) -> Result<()> {
+    if value == actual {
+        Ok(())
+    } else {
+        Err(ShapeError::new(path, ShapeErrorKind::AttributeMismatch { attribute, value, actual }))
+    }
+}
+
+fn expect_positive(attribute: &'static str, value: usize, path: &str) -> Result<()> {
+    if value == 0 {
+        Err(ShapeError::new(path, ShapeErrorKind::InvalidAttribute { attribute, value }))
+    } else {
+        Ok(())
+    }
+}
+
+/// Size of a dimension after passing a filter with **size** through it, like in **get_dims_after_filter_4**
+fn filtered_dim(dim: usize, size: usize, stride: usize, padding: usize, shape: &[usize], path: &str) -> Result<usize> {
+    match (dim + 2 * padding).checked_sub(size) {
+        Some(v) => Ok(v / stride + 1),
+        None => Err(ShapeError::new(path, ShapeErrorKind::FilterTooBig { size, padding, shape: shape.to_vec() })),
+    }
+}
+
+/// Size of a dimension after a transposed convolution, like in **ConvTransposeLayer**
+fn transposed_dim(dim: usize, size: usize, stride: usize, padding: usize, shape: &[usize], path: &str) -> Result<usize> {
+    match (dim.saturating_sub(1) * stride + size).checked_sub(2 * padding) {
+        Some(v) if v > 0 => Ok(v),
+        _ => Err(ShapeError::new(path, ShapeErrorKind::PaddingTooBig { padding, shape: shape.to_vec() })),
+    }
+}
+
+fn pool_shape(shape: &[usize], size: usize, stride: usize, padding: usize, path: &str) -> Result<Vec<usize>> {
+    let [channels, height, width] = expect_dims(shape, path)?;
+    expect_positive("size", size, path)?;
+    expect_positive("stride", stride, path)?;
+    Ok(vec![
+        channels,
+        filtered_dim(height, size, stride, padding, shape, path)?,
+        filtered_dim(width, size, stride, padding, shape, path)?,
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::integration::layers_loading::load_model_xml;
+    use super::*;
+
+    fn validate_xml(layer: &str, shape: &[usize]) -> Result<()> {
+        let str = format!("<AIModel><LossFunc><Mse/></LossFunc><Layer>{}</Layer></AIModel>", layer);
+        let config = load_model_xml(str.as_bytes()).unwrap();
+        validate_shapes(&config.main_layer, &config.heads, shape)
+    }
+
+    #[test]
+    fn test_valid() {
+        for path in ["../docs/digits/config.xml", "../docs/chess/config.xml"] {
+            let config = load_model_xml(&std::fs::read(path).unwrap()).unwrap();
+            let shape: &[usize] = if path.contains("digits") { &[28, 28] } else { &[6, 8, 8] };
+            validate_shapes(&config.main_layer, &config.heads, shape).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_dense_after_flatten() {
+        let error = validate_xml(r#"<Sequential>
+            <Convolution in_channels="1" out_channels="2" kernel_size="3" stride="1" padding="0">
+                <KernelsLr><Adam/></KernelsLr>
+            </Convolution>
+            <Flatten/>
+            <Dense in_values="50" out_values="1">
+                <WeightsLr><Adam/></WeightsLr>
+                <BiasesLr><Adam/></BiasesLr>
+            </Dense>
+        </Sequential>"#, &[1, 6, 6]).unwrap_err();
+        assert_eq!(error.path, "Sequential/Dense[2]");
+        assert_eq!(error.kind, ShapeErrorKind::AttributeMismatch { attribute: "in_values", value: 50, actual: 32 });
+    }
+
+    #[test]
+    fn test_conv_channels() {
+        let error = validate_xml(r#"<Sequential>
+            <Relu/>
+            <Convolution in_channels="3" out_channels="2" kernel_size="3" stride="1" padding="0">
+                <KernelsLr><Adam/></KernelsLr>
+            </Convolution>
+        </Sequential>"#, &[1, 6, 6]).unwrap_err();
+        assert_eq!(error.path, "Sequential/Convolution[1]");
+        assert!(matches!(error.kind, ShapeErrorKind::AttributeMismatch { attribute: "in_channels", .. }));
+    }
+
+    #[test]
+    fn test_concat() {
+        let error = validate_xml(r#"<Concat dim="0">
+            <Relu/>
+            <MaxPool size="2" stride="2" padding="0"/>
+        </Concat>"#, &[1, 6, 6]).unwrap_err();
+        assert_eq!(error.path, "Concat");
+        assert!(matches!(error.kind, ShapeErrorKind::ConcatMismatch { dim: 0, .. }));
+
+        assert!(validate_xml(r#"<Concat dim="0"><Relu/><Tanh/></Concat>"#, &[1, 6, 6]).is_ok());
+        let error = validate_xml(r#"<Concat dim="3"><Relu/><Tanh/></Concat>"#, &[1, 6, 6]).unwrap_err();
+        assert!(matches!(error.kind, ShapeErrorKind::DimensionOutOfBounds { attribute: "dim", value: 3, .. }));
+    }
+
+    #[test]
+    fn test_two_complements() {
+        let error = validate_xml(r#"<Sequential>
+            <Dense in_values="4" out_values="3">
+                <WeightsLr><Adam/></WeightsLr>
+                <BiasesLr><Adam/></BiasesLr>
+            </Dense>
+            <TwoComplementsTransformer/>
+        </Sequential>"#, &[4]).unwrap_err();
+        assert_eq!(error.path, "Sequential/TwoComplementsTransformer[1]");
+        assert_eq!(error.kind, ShapeErrorKind::UnexpectedShape { expected: vec![2], shape: vec![3] });
+    }
+
+    #[test]
+    fn test_residual() {
+        let error = validate_xml(r#"<Residual>
+            <MaxPool size="2" stride="2" padding="0"/>
+        </Residual>"#, &[1, 6, 6]).unwrap_err();
+        assert_eq!(error.path, "Residual");
+        assert!(matches!(error.kind, ShapeErrorKind::ResidualMismatch { .. }));
+
+        assert!(validate_xml(r#"<Residual>
+            <MaxPool size="2" stride="2" padding="0"/>
+            <Projection><AvgPool size="2" stride="2"/></Projection>
+        </Residual>"#, &[1, 6, 6]).is_ok());
+    }
+
+    #[test]
+    fn test_heads() {
+        let str = r#"<AIModel>
+    <Layer><Flatten/></Layer>
+    <Head name="value">
+        <LossFunc><Mse/></LossFunc>
+        <Layer><Softmax axis="1"/></Layer>
+    </Head>
+</AIModel>"#;
+        let config = load_model_xml(str.as_bytes()).unwrap();
+        let error = validate_shapes(&config.main_layer, &config.heads, &[2, 3]).unwrap_err();
+        assert_eq!(error.to_string(), "Attribute 'axis' is 1, which is out of bounds for shape [6] (in value/Softmax)");
+    }
+}
@@ -0,0 +1,350 @@
+use anyhow::bail;
+use crate::integration::serde_utils::Pairs;
+use crate::nn::controller::{NNController, TrainBatchResult};
+use crate::nn::layers::nn_layers::GenericStorage;
+use crate::utils::GenericResult;
+
+pub type BatchIter<'a> = Box<dyn Iterator<Item = GenericResult<Pairs>> + 'a>;
+
+/// Data trained by **TrainingLoop**
+pub trait EpochData {
+    /// Batches trained in **epoch**, which starts at 0. Called once at the start of each epoch
+    fn train_batches(&mut self, controller: &NNController, epoch: usize) -> GenericResult<BatchIter<'_>>;
+
+    /// Batches used to calculate the validation loss after each epoch. Without them, the training
+    /// loss is monitored instead
+    fn validation_batches(&mut self) -> Option<BatchIter<'_>> {
+        None
+    }
+}
+
+/// Reduce the learning rate of the whole model when the monitored loss stops improving
+#[derive(Clone, Debug)]
+pub struct ReduceLrOnPlateau {
+    /// Multiplies the learning rate each time it's reduced
+    pub factor: f32,
+    /// Epochs without improvement before reducing it, counted again after each reduction
+    pub patience: usize,
+    /// Lowest value of the **lr_multiplier** of the controller
+    pub min_multiplier: f32,
+}
+
+#[derive(Clone, Debug)]
+pub struct TrainingLoopConfig {
+    pub max_epochs: usize,
+    /// Stop after this many epochs without improving the monitored loss. None disables early stopping
+    pub patience: Option<usize>,
+    /// How much the monitored loss must decrease to count as an improvement
+    pub min_delta: f64,
+    /// Keep a copy of the storage of the epoch with the best monitored loss
+    pub keep_best: bool,
+    pub reduce_lr: Option<ReduceLrOnPlateau>,
+}
+
+impl Default for TrainingLoopConfig {
+    fn default() -> Self {
+        Self {
+            max_epochs: 10,
+            patience: None,
+            min_delta: 0.0,
+            keep_best: false,
+            reduce_lr: None,
+        }
+    }
+}
+
+/// Information about a trained batch, for **TrainCallback::on_batch_end()**
+pub struct BatchEnd<'a> {
+    pub epoch: usize,
+    /// Index of the batch in the epoch
+    pub batch: usize,
+    pub result: &'a TrainBatchResult,
+}
+
+/// Information about a finished epoch, for **TrainCallback::on_epoch_end()**
+#[derive(Clone, Debug)]
+pub struct EpochEnd {
+    pub epoch: usize,
+    /// Average loss of the training batches, weighted by their size
+    pub train_loss: f64,
+    pub validation_loss: Option<f64>,
+    /// The monitored loss is the best so far
+    pub improved: bool,
+    /// **lr_multiplier** of the controller for the next epoch
+    pub lr_multiplier: f32,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum LoopAction {
+    Continue,
+    Stop,
+}
+
+/// Called by **TrainingLoop** while training. Both methods do nothing by default
+pub trait TrainCallback {
+    fn on_batch_end(&mut self, _controller: &NNController, _info: &BatchEnd) -> GenericResult<()> {
+        Ok(())
+    }
+
+    /// Return **LoopAction::Stop** to finish the training after this epoch
+    fn on_epoch_end(&mut self, _controller: &NNController, _info: &EpochEnd) -> GenericResult<LoopAction> {
+        Ok(LoopAction::Continue)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct TrainingSummary {
+    /// Number of epochs trained
+    pub epochs: usize,
+    /// Stopped before **max_epochs**, by early stopping or by a callback
+    pub stopped_early: bool,
+    pub best_epoch: Option<usize>,
+    /// Monitored loss of **best_epoch**
+    pub best_loss: Option<f64>,
+    /// Storage of the controller after **best_epoch**, if **keep_best** is enabled
+    pub best_storage: Option<GenericStorage>,
+}
+
+/// Average loss of the batches with **test_batch()**, weighted by their size
+pub fn validation_loss(controller: &NNController, batches: BatchIter) -> GenericResult<f64> {
+    let mut total = 0.0;
+    let mut count = 0;
+    for batch in batches {
+        let batch = batch?;
+        let size = batch.inputs.shape()[0];
+        total += controller.test_batch(batch.inputs, &batch.expected)? * size as f64;
+        count += size;
+    }
+
+    if count == 0 {
+        bail!("There are no validation pairs");
+    }
+    Ok(total / count as f64)
+}
+
+/// Trains a controller for multiple epochs with **train_batch()**, with early stopping, keeping
+/// the best storage and reducing the learning rate on plateaus
+pub struct TrainingLoop<'a> {
+    config: TrainingLoopConfig,
+    callbacks: Vec<&'a mut dyn TrainCallback>,
+}
+
+impl<'a> TrainingLoop<'a> {
+    pub fn new(config: TrainingLoopConfig) -> Self {
+        Self { config, callbacks: Vec::new() }
+    }
+
+    /// Callbacks are called in the order they were added
+    pub fn add_callback(&mut self, callback: &'a mut dyn TrainCallback) {
+        self.callbacks.push(callback);
+    }
+
+    pub fn run(&mut self, controller: &mut NNController, data: &mut dyn EpochData) -> GenericResult<TrainingSummary> {
+        let mut summary = TrainingSummary {
+            epochs: 0,
+            stopped_early: false,
+            best_epoch: None,
+            best_loss: None,
+            best_storage: None,
+        };
+        let mut epochs_without_improvement = 0;
+        let mut epochs_since_reduction = 0;
+
+        for epoch in 0..self.config.max_epochs {
+            let mut total_loss = 0.0;
+            let mut samples = 0;
+            for (index, batch) in data.train_batches(controller, epoch)?.enumerate() {
+                let batch = batch?;
+                let result = controller.train_batch(batch.inputs, &batch.expected)?;
+                total_loss += result.loss * result.samples as f64;
+                samples += result.samples;
+
+                let info = BatchEnd { epoch, batch: index, result: &result };
+                for callback in self.callbacks.iter_mut() {
+                    callback.on_batch_end(controller, &info)?;
+                }
+            }
+            if samples == 0 {
+                bail!("Epoch {} has no training pairs", epoch);
+            }
+
+            let train_loss = total_loss / samples as f64;
+            let validation_loss = match data.validation_batches() {
+                Some(batches) => Some(validation_loss(controller, batches)?),
+                None => None,
+            };
+            let monitored = validation_loss.unwrap_or(train_loss);
+            let improved = match summary.best_loss {
+                Some(best) => monitored < best - self.config.min_delta,
+                None => true,
+            };
+
+            if improved {
+                summary.best_epoch = Some(epoch);
+                summary.best_loss = Some(monitored);
+                if self.config.keep_best {
+                    summary.best_storage = Some(controller.export());
+                }
+                epochs_without_improvement = 0;
+                epochs_since_reduction = 0;
+            } else {
+                epochs_without_improvement += 1;
+                epochs_since_reduction += 1;
+            }
+
+            if let Some(reduce) = &self.config.reduce_lr {
+                if epochs_since_reduction >= reduce.patience && !improved {
+                    let multiplier = (controller.lr_multiplier() * reduce.factor).max(reduce.min_multiplier);
+                    controller.set_lr_multiplier(multiplier);
+                    epochs_since_reduction = 0;
+                }
+            }
+
+            summary.epochs = epoch + 1;
+            let info = EpochEnd {
+                epoch,
+                train_loss,
+                validation_loss,
+                improved,
+                lr_multiplier: controller.lr_multiplier(),
+            };
+            let mut action = LoopAction::Continue;
+            for callback in self.callbacks.iter_mut() {
+                if callback.on_epoch_end(controller, &info)? == LoopAction::Stop {
+                    action = LoopAction::Stop;
+                }
+            }
+
+            let patience_exceeded = matches!(self.config.patience, Some(o) if epochs_without_improvement >= o);
+            if action == LoopAction::Stop || patience_exceeded {
+                summary.stopped_early = summary.epochs < self.config.max_epochs;
+                break;
+            }
+        }
+
+        Ok(summary)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::nn::layers::dense_layer::{DenseConfig, DenseLayerInit};
+    use crate::nn::layers::nn_layers::{Layer, TrainOptions};
+    use crate::nn::loss::loss_func::LossFunc;
+    use crate::nn::lr_calculators::constant_lr::ConstantLrConfig;
+    use crate::nn::lr_calculators::lr_calculator::LrCalc;
+    use crate::nn::lr_calculators::lr_schedule::get_global_step;
+    use crate::utils::Array2F;
+    use super::*;
+
+    struct FixedData {
+        batches: usize,
+        validation: bool,
+    }
+
+    fn get_pairs() -> Pairs {
+        Pairs {
+            inputs: Array2F::from_shape_fn((4, 3), |(a, b)| (a + b) as f32 / 4.0).into_dyn(),
+            expected: Array2F::from_shape_fn((4, 2), |(a, b)| (a * b) as f32 / 4.0).into_dyn(),
+        }
+    }
+
+    impl EpochData for FixedData {
+        fn train_batches(&mut self, _controller: &NNController, _epoch: usize) -> GenericResult<BatchIter<'_>> {
+            Ok(Box::new((0..self.batches).map(|_| Ok(get_pairs()))))
+        }
+
+        fn validation_batches(&mut self) -> Option<BatchIter<'_>> {
+            if self.validation {
+                Some(Box::new(std::iter::once(Ok(get_pairs()))))
+            } else {
+                None
+            }
+        }
+    }
+
+    #[derive(Default)]
+    struct Recorder {
+        batches: usize,
+        epochs: Vec<EpochEnd>,
+        stop_at: Option<usize>,
+    }
+
+    impl TrainCallback for Recorder {
+        fn on_batch_end(&mut self, _controller: &NNController, _info: &BatchEnd) -> GenericResult<()> {
+            self.batches += 1;
+            Ok(())
+        }
+
+        fn on_epoch_end(&mut self, _controller: &NNController, info: &EpochEnd) -> GenericResult<LoopAction> {
+            self.epochs.push(info.clone());
+            match self.stop_at {
+                Some(epoch) if epoch == info.epoch => Ok(LoopAction::Stop),
+                _ => Ok(LoopAction::Continue),
+            }
+        }
+    }
+
+    fn get_controller(frozen: bool) -> NNController {
+        NNController::new(Layer::Dense(DenseConfig {
+            in_values: 3,
+            out_values: 2,
+            init_mode: DenseLayerInit::Random(),
+            weights_lr_calc: LrCalc::Constant(ConstantLrConfig::default()),
+            biases_lr_calc: LrCalc::Constant(ConstantLrConfig::default()),
+            train_options: TrainOptions { frozen, ..TrainOptions::default() },
+        }), LossFunc::Mse).unwrap()
+    }
+
+    #[test]
+    fn test_improving() {
+        let mut controller = get_controller(false);
+        let mut recorder = Recorder { stop_at: Some(3), ..Recorder::default() };
+        let mut training = TrainingLoop::new(TrainingLoopConfig {
+            max_epochs: 5,
+            patience: Some(1),
+            ..TrainingLoopConfig::default()
+        });
+        training.add_callback(&mut recorder);
+
+        let summary = training.run(&mut controller, &mut FixedData { batches: 3, validation: true }).unwrap();
+        assert_eq!(summary.epochs, 4);
+        assert!(summary.stopped_early);
+        assert_eq!(summary.best_epoch, Some(3));
+        assert!(summary.best_storage.is_none());
+        assert_eq!(recorder.batches, 12);
+        assert!(recorder.epochs.iter().all(|o| o.improved && o.validation_loss.is_some()));
+    }
+
+    #[test]
+    fn test_plateau() {
+        let mut controller = get_controller(true);
+        let mut recorder = Recorder::default();
+        let mut training = TrainingLoop::new(TrainingLoopConfig {
+            max_epochs: 10,
+            patience: Some(4),
+            keep_best: true,
+            reduce_lr: Some(ReduceLrOnPlateau { factor: 0.5, patience: 1, min_multiplier: 0.2 }),
+            ..TrainingLoopConfig::default()
+        });
+        training.add_callback(&mut recorder);
+
+        let summary = training.run(&mut controller, &mut FixedData { batches: 2, validation: false }).unwrap();
+        assert_eq!(summary.epochs, 5);
+        assert!(summary.stopped_early);
+        assert_eq!(summary.best_epoch, Some(0));
+        assert_eq!(get_global_step(&summary.best_storage.unwrap()), 2);
+
+        let multipliers: Vec<_> = recorder.epochs.iter().map(|o| o.lr_multiplier).collect();
+        assert_eq!(multipliers, vec![1.0, 0.5, 0.25, 0.2, 0.2]);
+        assert!(recorder.epochs.iter().all(|o| o.validation_loss.is_none()));
+    }
+
+    #[test]
+    fn test_empty_epoch() {
+        let mut controller = get_controller(false);
+        let result = TrainingLoop::new(TrainingLoopConfig::default())
+            .run(&mut controller, &mut FixedData { batches: 0, validation: false });
+        assert!(result.is_err());
+    }
+}
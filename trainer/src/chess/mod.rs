@@ -21,5 +21,5 @@ const BATCH_SIZE: usize = 64;
 
 pub fn train(initial: GenericStorage, model_config: ModelXmlConfig, config: &EnvConfig, client: Arc<ServerClient>) {
     let mut trainer = TrainerScheduler::new(initial, model_config, config);
-    trainer.train_versions(config.versions, config, client);
+    trainer.train_versions(config.versions, client);
 }
@@ -7,6 +7,7 @@ use codebase::chess::decision_tree::cursor::TreeCursor;
 use codebase::chess::decision_tree::DecisionTree;
 use codebase::chess::game_result::{DrawReason, GameResult};
 use codebase::chess::openings::openings_tree::OpeningsTree;
+use codebase::integration::serde_utils::Pairs;
 use codebase::nn::controller::NNController;
 use codebase::utils::{Array2F};
 use codebase::utils::ndarray::{Axis, stack};
@@ -31,19 +32,22 @@ impl SubtreesTrainer {
         }
     }
 
-    pub fn train_version(&self, controller: &mut NNController, options: BuilderOptions, sim_games: usize) -> GameMetrics {
+    /// Play games and return the batches of positions to train, with the metrics of the games
+    pub fn version_batches(&self, controller: &NNController, options: BuilderOptions, sim_games: usize) -> (Vec<Pairs>, GameMetrics) {
         let (games, metrics) = self.analyze_games(controller, sim_games, options);
 
-        for chunk in games.chunks(BATCH_SIZE) {
-            let inputs: Vec<_> = chunk.iter().map(|(b, _)| b.to_array()).collect();
-            let views: Vec<_> = inputs.iter().map(|o| o.view()).collect();
-            let inputs = stack(Axis(0), &views).unwrap();
+        let batches = games.chunks(BATCH_SIZE)
+            .map(|chunk| {
+                let inputs: Vec<_> = chunk.iter().map(|(b, _)| b.to_array()).collect();
+                let views: Vec<_> = inputs.iter().map(|o| o.view()).collect();
+                let inputs = stack(Axis(0), &views).unwrap();
 
-            let expected: Vec<_> = chunk.iter().map(|(_, v)| *v).collect();
-            let expected = Array2F::from_shape_vec((expected.len(), 1), expected).unwrap();
-            controller.train_batch(inputs.into_dyn(), &expected.into_dyn()).unwrap();
-        }
-        metrics
+                let expected: Vec<_> = chunk.iter().map(|(_, v)| *v).collect();
+                let expected = Array2F::from_shape_vec((expected.len(), 1), expected).unwrap();
+                Pairs { inputs: inputs.into_dyn(), expected: expected.into_dyn() }
+            })
+            .collect();
+        (batches, metrics)
     }
 
     fn get_subtrees(&self, controller: &NNController, limits: LimiterFactors, random_node_chance: f64)
@@ -1,14 +1,16 @@
 use std::fmt::Debug;
 use std::sync::Arc;
 use std::thread;
+use std::thread::JoinHandle;
 use std::time::{Instant, SystemTime, UNIX_EPOCH};
 use codebase::chess::decision_tree::building::{BuilderOptions, LimiterFactors, NextNodeStrategy};
 use codebase::integration::layers_loading::ModelXmlConfig;
 use codebase::nn::controller::NNController;
 use codebase::nn::layers::nn_layers::GenericStorage;
+use codebase::nn::training_loop::*;
+use codebase::utils::GenericResult;
 use crate::chess::NAME;
 use crate::{EnvConfig, ServerClient};
-use crate::chess::game_metrics::GameMetrics;
 use crate::chess::subtrees_trainer::SubtreesTrainer;
 
 pub struct TrainerScheduler {
@@ -16,45 +18,17 @@ pub struct TrainerScheduler {
     controller: NNController,
 }
 
-impl TrainerScheduler {
-    pub fn new(initial: GenericStorage, model_config: ModelXmlConfig, config: &EnvConfig) -> Self {
-        Self {
-            subtrees_trainer: SubtreesTrainer::new(config),
-            controller: NNController::load(model_config.main_layer, model_config.loss_func, initial).unwrap(),
-        }
-    }
-
-    pub fn train_versions(&mut self, count: u32, config: &EnvConfig, client: Arc<ServerClient>) {
-        let mut all_metrics = Vec::new();
-        let mut completed: Option<(_, _)> = None;
-        let client = Arc::new(client);
-
-        for version in 0..count {
-            println!("Start {}", version + 1);
-
-            let start = Instant::now();
-            let prev_completed = completed;
-            completed = None;
-
-            let client = client.clone();
-            let upload_thread = thread::spawn(move || {
-                if let Some(prev_completed) = prev_completed {
-                    client.submit(&prev_completed.0, prev_completed.1, NAME);
-                }
-            });
-
-            all_metrics.push(self.train_cycle(config, &mut completed, version));
-
-            let elapsed = start.elapsed().as_millis();
-            println!("   in {}ms ({} cycles/s)", elapsed, 1000.0 / elapsed as f64);
-            upload_thread.join().unwrap();
-        }
-    }
+/// Each epoch of the training loop is a version, trained on the positions of new games
+struct SubtreesData<'a> {
+    trainer: &'a SubtreesTrainer,
+}
 
-    fn train_cycle(&mut self, config: &EnvConfig, completed: &mut Option<(GenericStorage, f64)>, version: u32) -> GameMetrics {
-        println!("Training cycle {} using Subtrees strategy", version);
+impl EpochData for SubtreesData<'_> {
+    fn train_batches(&mut self, controller: &NNController, epoch: usize) -> GenericResult<BatchIter<'_>> {
+        println!("Start {}", epoch + 1);
+        println!("Training cycle {} using Subtrees strategy", epoch);
 
-        let result = self.subtrees_trainer.train_version(&mut self.controller, BuilderOptions {
+        let (batches, metrics) = self.trainer.version_batches(controller, BuilderOptions {
             limits: LimiterFactors {
                 max_full_paths_explored: Some(40),
                 ..LimiterFactors::default()
@@ -66,23 +40,75 @@ impl TrainerScheduler {
             },
             ..BuilderOptions::default()
         }, 1);
-        Self::print_metrics(&result);
+        print_metrics(&metrics);
+        Ok(Box::new(batches.into_iter().map(Ok)))
+    }
+}
+
+/// Submits every 5th version, uploading it while the next versions train
+struct UploadCallback {
+    client: Arc<ServerClient>,
+    upload_thread: Option<JoinHandle<()>>,
+    start: Instant,
+}
+
+impl UploadCallback {
+    fn wait_upload(&mut self) {
+        if let Some(thread) = self.upload_thread.take() {
+            thread.join().unwrap();
+        }
+    }
+}
 
-        if version != 0 && version % 5 == 0 {
+impl TrainCallback for UploadCallback {
+    fn on_epoch_end(&mut self, controller: &NNController, info: &EpochEnd) -> GenericResult<LoopAction> {
+        let elapsed = self.start.elapsed().as_millis();
+        println!("   in {}ms ({} cycles/s)", elapsed, 1000.0 / elapsed as f64);
+        self.start = Instant::now();
+
+        if info.epoch != 0 && info.epoch % 5 == 0 {
             // For now, the only criteria to evaluate the model's performance is the time spent training
-            let start = SystemTime::now();
-            let since_the_epoch = start
+            let since_the_epoch = SystemTime::now()
                 .duration_since(UNIX_EPOCH)
                 .expect("Time went backwards");
 
-            let export = self.controller.export();
-            *completed = Some((export, -(since_the_epoch.as_millis() as f64)));
+            self.wait_upload();
+            let export = controller.export();
+            let client = self.client.clone();
+            self.upload_thread = Some(thread::spawn(move || {
+                client.submit(&export, -(since_the_epoch.as_millis() as f64), NAME);
+            }));
         }
+        Ok(LoopAction::Continue)
+    }
+}
 
-        result
+impl TrainerScheduler {
+    pub fn new(initial: GenericStorage, model_config: ModelXmlConfig, config: &EnvConfig) -> Self {
+        let mut controller = NNController::load(model_config.main_layer, model_config.loss_func, initial).unwrap();
+        controller.set_data_parallel(config.data_parallel_shards);
+        Self {
+            subtrees_trainer: SubtreesTrainer::new(config),
+            controller,
+        }
     }
 
-    fn print_metrics(metrics: &impl Debug) {
-        println!("   Finished with {:?}", metrics)
+    pub fn train_versions(&mut self, count: u32, client: Arc<ServerClient>) {
+        let mut data = SubtreesData { trainer: &self.subtrees_trainer };
+        let mut callback = UploadCallback { client, upload_thread: None, start: Instant::now() };
+
+        // The games change every version, so their losses can't be compared for early stopping
+        let mut training = TrainingLoop::new(TrainingLoopConfig {
+            max_epochs: count as usize,
+            ..TrainingLoopConfig::default()
+        });
+        training.add_callback(&mut callback);
+        training.run(&mut self.controller, &mut data).unwrap();
+
+        callback.wait_upload();
     }
-}
\ No newline at end of file
+}
+
+fn print_metrics(metrics: &impl Debug) {
+    println!("   Finished with {:?}", metrics)
+}
@@ -1,7 +1,8 @@
 use std::error::Error;
 use std::time::Duration;
 use codebase::integration::layers_loading::{load_model_xml, ModelXmlConfig};
-use codebase::integration::serialization::serialize_version;
+use codebase::integration::compression::Compression;
+use codebase::integration::serialization::serialize_version_compressed;
 use codebase::nn::layers::nn_layers::GenericStorage;
 use http::StatusCode;
 use reqwest::blocking;
@@ -34,7 +35,7 @@ impl ServerClient {
 
     pub fn submit(&self, storage: &GenericStorage, loss: f64, name: &str) {
         println!("Uploading");
-        let bytes = serialize_version(storage, loss);
+        let bytes = serialize_version_compressed(storage, loss, Compression::deflate());
 
         let response = self.blocking_client.post(self.create_url_with_name(name, "trainable"))
             .body(bytes)
@@ -1,50 +1,133 @@
+use std::sync::Arc;
 use codebase::integration::layers_loading::ModelXmlConfig;
+use codebase::integration::augmentation::{augment, AugmentConfig, recenter};
+use codebase::integration::dataset::{Dataset, EpochBatches, sequential_batches, shuffled_batches};
 use codebase::integration::serde_utils::Pairs;
 use codebase::nn::controller::NNController;
 use codebase::nn::layers::nn_layers::GenericStorage;
+use codebase::nn::training_loop::*;
+use codebase::utils::GenericResult;
+use rand::rngs::ThreadRng;
 use rand::thread_rng;
 use crate::{EnvConfig, ServerClient};
-use crate::files::load_file_data;
+use crate::files::open_file_dataset;
 
 const NAME: &str = "digits";
 const BATCH_SIZE: usize = 128;
+const INPUT_SHAPE: [usize; 2] = [28, 28];
+/// The images are only recentered with `RECENTER_DIGITS`, which must match the versions server,
+/// because it recenters the drawn digits before evaluating them with the same variable
+const AUGMENT: AugmentConfig = AugmentConfig {
+    max_shift: 2.0,
+    max_rotation_degrees: 12.0,
+    max_scale: 0.1,
+    elastic_alpha: 30.0,
+    elastic_sigma: 4.0,
+    noise_std_dev: 0.02,
+    recenter: false,
+};
 
-pub fn train(initial: GenericStorage, model_config: ModelXmlConfig, config: &EnvConfig, client: &ServerClient) {
-    let mut controller = NNController::load(model_config.main_layer, model_config.loss_func, initial).unwrap();
-    let train_data = load_file_data("train", NAME, config).unwrap();
-    let validate_data = load_file_data("validate", NAME, config).unwrap();
-    let mut rng = thread_rng();
+/// Each epoch of the training loop is a version with **epochs_per_version** batches, which go
+/// through the shuffled dataset and start over when it's finished
+struct DigitsData {
+    train: Arc<dyn Dataset>,
+    validate: Arc<dyn Dataset>,
+    batches: EpochBatches,
+    batches_per_version: usize,
+    augment: AugmentConfig,
+    rng: ThreadRng,
+}
 
-    for version in 0..config.versions {
-        let mut total_loss = 0.0;
+impl DigitsData {
+    fn next_batch(&mut self) -> GenericResult<Pairs> {
+        let data = match self.batches.next() {
+            Some(data) => data,
+            None => {
+                // The dataset was checked to not be empty
+                self.batches = shuffled_batches(self.train.clone(), BATCH_SIZE, &mut self.rng);
+                self.batches.next().unwrap()
+            }
+        }?;
+        Ok(Pairs {
+            inputs: augment(&data.inputs, &self.augment, &mut self.rng)?,
+            expected: data.expected,
+        })
+    }
+}
 
-        println!("Start {}", version + 1);
-        for epoch in 0..config.epochs_per_version {
-            let data = train_data.pick_rand(BATCH_SIZE, &mut rng);
-            let loss = controller.train_batch(data.inputs, &data.expected).unwrap();
-            total_loss += loss;
+impl EpochData for DigitsData {
+    fn train_batches(&mut self, _controller: &NNController, epoch: usize) -> GenericResult<BatchIter<'_>> {
+        println!("Start {}", epoch + 1);
+        Ok(Box::new((0..self.batches_per_version).map(|_| self.next_batch())))
+    }
 
-            if epoch % 16 == 0 {
-                println!("    {} -> loss={}", epoch, loss);
-            }
-        }
+    fn validation_batches(&mut self) -> Option<BatchIter<'_>> {
+        println!("Started testing");
+        let recenter_digits = self.augment.recenter;
+        let batches = sequential_batches(self.validate.clone(), 256)
+            .map(move |batch| {
+                let batch = batch?;
+                let inputs = if recenter_digits { recenter(&batch.inputs)? } else { batch.inputs };
+                Ok(Pairs { inputs, expected: batch.expected })
+            });
+        Some(Box::new(batches))
+    }
+}
+
+/// Prints the progress and submits the versions that improve the validation loss
+struct DigitsCallback<'a> {
+    client: &'a ServerClient,
+}
 
-        let avg_loss = total_loss / config.epochs_per_version as f64;
-        let tested_loss = validate(&validate_data, &controller);
+impl TrainCallback for DigitsCallback<'_> {
+    fn on_batch_end(&mut self, _controller: &NNController, info: &BatchEnd) -> GenericResult<()> {
+        if info.batch % 16 == 0 {
+            println!("    {} -> loss={}", info.batch, info.result.loss);
+        }
+        Ok(())
+    }
 
-        println!("    Finished with avg_loss={} and tested_loss={}", avg_loss, tested_loss);
-        client.submit(&controller.export(), avg_loss, NAME);
+    fn on_epoch_end(&mut self, controller: &NNController, info: &EpochEnd) -> GenericResult<LoopAction> {
+        println!("    Finished with avg_loss={} and tested_loss={}", info.train_loss, info.validation_loss.unwrap());
+        if info.improved {
+            self.client.submit(&controller.export(), info.train_loss, NAME);
+        } else {
+            println!("    Not submitted, the best tested_loss wasn't improved");
+        }
+        Ok(LoopAction::Continue)
     }
 }
 
-fn validate(data: &Pairs, controller: &NNController) -> f64 {
-    println!("Started testing");
+pub fn train(initial: GenericStorage, model_config: ModelXmlConfig, config: &EnvConfig, client: &ServerClient) {
+    let mut controller = NNController::load(model_config.main_layer, model_config.loss_func, initial).unwrap();
+    // Fail before loading the data if the config can't receive the images
+    controller.validate_shapes(&INPUT_SHAPE).unwrap();
+    controller.set_data_parallel(config.data_parallel_shards);
+    let train = open_file_dataset("train", NAME, config).unwrap();
+    let validate = open_file_dataset("validate", NAME, config).unwrap();
+    assert!(!train.is_empty(), "The train dataset is empty");
+    let mut rng = thread_rng();
+
+    let mut data = DigitsData {
+        batches: shuffled_batches(train.clone(), BATCH_SIZE, &mut rng),
+        train,
+        validate,
+        batches_per_version: config.epochs_per_version as usize,
+        augment: AugmentConfig { recenter: config.recenter_digits, ..AUGMENT },
+        rng,
+    };
+    let mut callback = DigitsCallback { client };
+    // Early stopping and LR reduction use the validation loss, measured after each version
+    let mut training = TrainingLoop::new(TrainingLoopConfig {
+        max_epochs: config.versions as usize,
+        patience: Some(4),
+        reduce_lr: Some(ReduceLrOnPlateau { factor: 0.5, patience: 2, min_multiplier: 0.05 }),
+        ..TrainingLoopConfig::default()
+    });
+    training.add_callback(&mut callback);
 
-    let mut total = 0.0;
-    let mut count = 0;
-    for batch in data.chunks_iter(256) {
-        total += controller.test_batch(batch.0.into_owned(), &batch.1.into_owned()).unwrap();
-        count += 1;
+    let summary = training.run(&mut controller, &mut data).unwrap();
+    if summary.stopped_early {
+        println!("Stopped early after {} versions, the best was {}", summary.epochs, summary.best_epoch.unwrap() + 1);
     }
-    total / (count as f64)
 }
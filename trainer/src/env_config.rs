@@ -8,6 +8,11 @@ pub struct EnvConfig {
     pub name: String,
     pub profile: bool,
     pub max_cache_size_kb: u64,
+    /// Shards of each batch trained in parallel threads. 0 or 1 disables data-parallel training
+    pub data_parallel_shards: usize,
+    /// Whether the digits are recentered by their center of mass while training and validating.
+    /// Must match the `RECENTER_DIGITS` of the versions server that evaluates the models
+    pub recenter_digits: bool,
 }
 
 fn get_path(name: &str) -> Result<String, VarError> {
@@ -36,6 +41,8 @@ impl EnvConfig {
             name: var("NAME").unwrap_or_else(|_| "digits".to_owned()).to_ascii_lowercase(),
             profile: var("PROFILE").is_ok(),
             max_cache_size_kb: var("MAX_CACHE_SIZE_KB").unwrap_or_else(|_|"1000".to_owned()).parse().unwrap(),
+            data_parallel_shards: var("DATA_PARALLEL_SHARDS").unwrap_or_else(|_| "0".to_owned()).parse().unwrap(),
+            recenter_digits: var("RECENTER_DIGITS").is_ok(),
         })
     }
 }
\ No newline at end of file
@@ -1,18 +1,13 @@
 use std::fs::OpenOptions;
 use std::io;
-use std::io::ErrorKind::InvalidData;
 use std::io::Read;
-use codebase::integration::deserialization::deserialize_pairs;
-use codebase::integration::serde_utils::Pairs;
+use std::sync::Arc;
+use codebase::integration::dataset::{Dataset, open_dataset};
+use codebase::utils::GenericResult;
 use crate::EnvConfig;
 
-pub fn load_file_data(filename: &str,name: &str, config: &EnvConfig) -> io::Result<Pairs> {
-    let mut file = OpenOptions::new().read(true)
-        .open(format!("{}/{}/{}.dat", config.mounted_path, name, filename))?;
-
-    let mut buffer = Vec::new();
-    file.read_to_end(&mut buffer)?;
-    deserialize_pairs(&buffer).map_err(|e| io::Error::new(InvalidData, e))
+pub fn open_file_dataset(filename: &str, name: &str, config: &EnvConfig) -> GenericResult<Arc<dyn Dataset>> {
+    open_dataset(format!("{}/{}/{}.dat", config.mounted_path, name, filename))
 }
 
 pub fn load_file_lines<'a>(filename: &str,name: &str, config: &EnvConfig, buffer: &'a mut String) -> io::Result<Vec<&'a str>> {
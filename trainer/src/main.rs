@@ -55,6 +55,10 @@ fn main() {
 
         let name = &config.name;
         let model_config = client.load_model_config(name).unwrap();
+        // The trainers train the main layer against a single label, so the heads would never be trained
+        if !model_config.heads.is_empty() {
+            panic!("The {} trainer doesn't support models with heads, but the config has {}", name, model_config.heads.len());
+        }
 
         let storage_response = client.get_trainable(name).unwrap();
         let storage = match storage_response.status() {
@@ -72,7 +76,7 @@ fn main() {
 }
 
 fn init(model_config: ModelXmlConfig, client: &ServerClient, name: &str) -> GenericStorage {
-    let controller = NNController::new(model_config.main_layer, model_config.loss_func).unwrap();
+    let controller = NNController::new_with_heads(model_config.main_layer, model_config.loss_func, model_config.heads).unwrap();
     let storage = controller.export();
     client.submit(&storage, 100_000.0, name);
     storage
@@ -1,11 +1,13 @@
+use codebase::integration::augmentation::recenter;
 use codebase::utils::Array2F;
 use warp::{reply, Reply};
-use crate::{FileManagerDep, LoadedModelDep, StatusCode};
+use crate::{EnvConfigDep, FileManagerDep, LoadedModelDep, StatusCode};
 use crate::loaded_model::assert_model_loaded;
 use crate::utils::{data_err_proc, EndpointResult};
 
 /// Return the predicted possibilities for each digit based on the user-supplied pixeks
-pub async fn post_eval(body: Vec<u8>, file_manager: FileManagerDep, loaded: LoadedModelDep) -> EndpointResult<impl Reply> {
+pub async fn post_eval(body: Vec<u8>, file_manager: FileManagerDep, loaded: LoadedModelDep,
+                       config: EnvConfigDep) -> EndpointResult<impl Reply> {
     {
         // Code block to free write lock asap
         let file_manager = file_manager.read().await;
@@ -25,6 +27,15 @@ pub async fn post_eval(body: Vec<u8>, file_manager: FileManagerDep, loaded: Load
         Ok(v) => v.into_dyn(),
         Err(e) => return data_err_proc(e, reply::json(&"Input error"))
     };
+    // Hand-drawn digits are often off-center. Only models trained with recentered images expect it
+    let inputs = if config.recenter_digits {
+        match recenter(&inputs) {
+            Ok(v) => v,
+            Err(e) => return data_err_proc(e, reply::json(&"Input error"))
+        }
+    } else {
+        inputs
+    };
 
     let result = match controller.eval_one(inputs) {
         Ok(v) => v,
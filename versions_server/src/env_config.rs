@@ -1,4 +1,5 @@
 use std::env::var;
+use codebase::nn::precision::Precision;
 
 #[derive(Debug)]
 pub struct EnvConfig {
@@ -9,6 +10,13 @@ pub struct EnvConfig {
     pub eval_delta_exp: f64,
     pub depth_delta_exp: f64,
     pub max_cache_size_kb: u64,
+    /// Precision of the parameters sent to the trainers
+    pub trainable_precision: Precision,
+    /// Whether the models used to evaluate requests are quantized to int8
+    pub quantize_models: bool,
+    /// Whether the digits sent to be evaluated are recentered by their center of mass. Only
+    /// enable it for models trained with recentered images
+    pub recenter_digits: bool,
 }
 
 impl Default for EnvConfig {
@@ -27,6 +35,9 @@ impl EnvConfig {
         let eval_delta_exp = var("NEXT_NODE_EVAL_DELTA_EXP").unwrap_or_else(|_| "5".to_owned()).parse().unwrap();
         let depth_delta_exp = var("NEXT_NODE_DEPTH_DELTA_EXP").unwrap_or_else(|_| "0.1".to_owned()).parse().unwrap();
         let max_cache_size_kb = var("MAX_CACHE_SIZE_KB").unwrap_or_else(|_| "5000".to_owned()).parse().unwrap();
+        let trainable_precision = var("TRAINABLE_PRECISION").unwrap_or_else(|_| "f32".to_owned()).parse().unwrap();
+        let quantize_models = var("QUANTIZE_MODELS").is_ok();
+        let recenter_digits = var("RECENTER_DIGITS").is_ok();
 
         let result = Self {
             base_path,
@@ -35,7 +46,10 @@ impl EnvConfig {
             keep_versions,
             eval_delta_exp,
             depth_delta_exp,
-            max_cache_size_kb
+            max_cache_size_kb,
+            trainable_precision,
+            quantize_models,
+            recenter_digits,
         };
         println!("{:?}", result);
         result
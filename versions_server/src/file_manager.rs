@@ -1,7 +1,9 @@
 use std::{fs, io};
+use std::collections::BTreeMap;
 use std::fs::{File, OpenOptions};
 use std::io::{Read, Write};
 use std::io::ErrorKind::InvalidData;
+use codebase::integration::checkpoint::Checkpoint;
 use codebase::integration::deserialization::deserialize_storage;
 use codebase::integration::layers_loading::{load_model_xml, ModelXmlConfig};
 use serde::{Serialize, Deserialize};
@@ -90,6 +92,23 @@ impl FileManager {
             .map_err(|e| io::Error::new(InvalidData, e))
     }
 
+    /// Bundle the config and a version, with its loss as metadata
+    pub fn get_checkpoint(&self, id: u32) -> io::Result<Checkpoint> {
+        let version = self.versions.iter().find(|o| o.id == id)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("Version {} not found", id)))?;
+        let config_xml = String::from_utf8(self.get_config_bytes()?)
+            .map_err(|e| io::Error::new(InvalidData, e))?;
+
+        let mut metadata = BTreeMap::new();
+        metadata.insert("version".to_owned(), id.to_string());
+        metadata.insert("loss".to_owned(), version.meta.loss.to_string());
+        Ok(Checkpoint { config_xml, storage: self.get_storage(id)?, metadata })
+    }
+
+    pub fn env_config(&self) -> &EnvConfigDep {
+        &self.config
+    }
+
     pub fn get_config(&self) -> io::Result<ModelXmlConfig> {
         self.get_config_bytes().and_then(|o| load_model_xml(&o)
             .map_err(|e| io::Error::new(InvalidData, e)))
@@ -22,7 +22,8 @@ impl LoadedModel {
     }
 }
 
-/// Load the model with target version from the disk if it's not already cached
+/// Load the model with target version from the disk if it's not already cached. The model is
+/// quantized if enabled in the config
 pub async fn assert_model_loaded(loaded_model_dep: &LoadedModelDep, target: u32, file_manager: &FileManager) -> GenericResult<()> {
     let curr_version = {
         loaded_model_dep.read().await.version
@@ -32,8 +33,13 @@ pub async fn assert_model_loaded(loaded_model_dep: &LoadedModelDep, target: u32,
         let storage = file_manager.get_storage(target)?;
         let config = file_manager.get_config()?;
 
+        let mut controller = NNController::load_with_heads(config.main_layer, config.loss_func, config.heads, storage)?;
+        if file_manager.env_config().quantize_models {
+            controller.quantize()?;
+        }
+
         let mut loaded = loaded_model_dep.write().await;
-        loaded.controller = Some(NNController::load(config.main_layer, config.loss_func, storage)?);
+        loaded.controller = Some(controller);
         loaded.version = target;
     }
 
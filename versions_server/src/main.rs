@@ -66,8 +66,15 @@ async fn main() {
     let get_trainable_route = path!(String / "trainable")
         .and(warp::get())
         .and(with_file_managers(&file_managers))
+        .and(with_env_config(&config))
         .and_then(rest_handlers::get_trainable);
 
+    let get_checkpoint_route = path!(String / "checkpoint")
+        .and(warp::get())
+        .and(with_file_managers(&file_managers))
+        .and(with_env_config(&config))
+        .and_then(rest_handlers::get_checkpoint);
+
     let get_config_route = path!(String / "config")
         .and(warp::get())
         .and(with_file_managers(&file_managers))
@@ -85,6 +92,7 @@ async fn main() {
         .and(warp::body::json())
         .and(with_file_manager(&file_managers.digits))
         .and(with_loaded_model(&loaded_models.digits))
+        .and(with_env_config(&config))
         .and_then(digits_handler::post_eval);
 
     // Chess routes
@@ -109,6 +117,7 @@ async fn main() {
     let routes = wake_up_route
         .or(post_trainable_route)
         .or(get_trainable_route)
+        .or(get_checkpoint_route)
         .or(get_config_route)
         .or(set_config_route)
 
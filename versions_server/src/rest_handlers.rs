@@ -1,13 +1,20 @@
+use codebase::integration::checkpoint::serialize_checkpoint;
 use codebase::integration::deserialization::{deserialize_version};
 use codebase::integration::layers_loading::load_model_xml;
-use codebase::integration::serialization::{serialize_storage};
+use codebase::integration::compression::{Compression, ValuesFormat};
+use codebase::integration::serialization::{serialize_storage_compressed};
+use codebase::nn::shape_inference::validate_shapes;
 use warp::{Reply, reply};
 use warp::http::{StatusCode};
-use crate::{FileManagersDep};
+use crate::{EnvConfigDep, FileManagersDep};
+use crate::endpoint_dict::EndpointDict;
 use crate::utils::{EndpointResult, stderr_proc};
 
-/// Returns the trainable parameters of a specific model
-pub async fn get_trainable(name: String, file_managers: FileManagersDep) -> EndpointResult<impl Reply> {
+/// Shape of a single input of each model, without the batch dimension
+const INPUT_SHAPES: EndpointDict<&[usize]> = EndpointDict { digits: &[28, 28], chess: &[6, 8, 8] };
+
+/// Returns the trainable parameters of a specific model, compressed and in the precision set in the config
+pub async fn get_trainable(name: String, file_managers: FileManagersDep, config: EnvConfigDep) -> EndpointResult<impl Reply> {
     let file_manager = match file_managers.get_from_name(&name) {
         Some(v) => v,
         None => return Ok(reply::with_status(vec![], StatusCode::NOT_FOUND)),
@@ -17,7 +24,32 @@ pub async fn get_trainable(name: String, file_managers: FileManagersDep) -> Endp
     let most_recent = file_manager.most_recent();
     match file_manager.get_storage(most_recent) {
         Ok(storage) => {
-            let bytes = serialize_storage(&storage);
+            let compression = Compression {
+                format: ValuesFormat::Float(config.trainable_precision),
+                deflate: true,
+            };
+            let bytes = serialize_storage_compressed(&storage, compression);
+            Ok(reply::with_status(bytes, StatusCode::OK))
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            Ok(reply::with_status(vec![], StatusCode::NOT_FOUND))
+        }
+    }
+}
+
+/// Returns a checkpoint with the config and the most recent version of a specific model
+pub async fn get_checkpoint(name: String, file_managers: FileManagersDep, config: EnvConfigDep) -> EndpointResult<impl Reply> {
+    let file_manager = match file_managers.get_from_name(&name) {
+        Some(v) => v,
+        None => return Ok(reply::with_status(vec![], StatusCode::NOT_FOUND)),
+    };
+
+    let file_manager = file_manager.read().await;
+    let most_recent = file_manager.most_recent();
+    match file_manager.get_checkpoint(most_recent) {
+        Ok(checkpoint) => {
+            let bytes = serialize_checkpoint(&checkpoint, ValuesFormat::Float(config.trainable_precision));
             Ok(reply::with_status(bytes, StatusCode::OK))
         }
         Err(e) => {
@@ -62,19 +94,26 @@ pub async fn get_config(name: String, file_managers: FileManagersDep) -> Endpoin
 
 /// Replaces the config for a specific model with the one provided by the user, if it's valid
 pub async fn post_config(name: String, body: warp::hyper::body::Bytes, file_managers: FileManagersDep) -> EndpointResult<impl Reply> {
-    let file_manager = match file_managers.get_from_name(&name) {
-        Some(v) => v,
-        None => return Ok(StatusCode::NOT_FOUND)
+    let (file_manager, input_shape) = match (file_managers.get_from_name(&name), INPUT_SHAPES.get_from_name(&name)) {
+        (Some(file_manager), Some(input_shape)) => (file_manager, *input_shape),
+        _ => return Ok(reply::with_status(String::new(), StatusCode::NOT_FOUND))
     };
 
-    match load_model_xml(&body) { // Test if the xml is valid
-        Ok(_) => {
-            let file_manager = file_manager.read().await;
-            match file_manager.set_config_bytes(&body) {
-                Ok(_) => Ok(StatusCode::OK),
-                Err(e) => stderr_proc(e),
-            }
+    // Test if the xml is valid and if the model accepts the inputs of the endpoint
+    let config = match load_model_xml(&body) {
+        Ok(v) => v,
+        Err(e) => return Ok(reply::with_status(e.to_string(), StatusCode::BAD_REQUEST)),
+    };
+    if let Err(e) = validate_shapes(&config.main_layer, &config.heads, input_shape) {
+        return Ok(reply::with_status(e.to_string(), StatusCode::BAD_REQUEST));
+    }
+
+    let file_manager = file_manager.read().await;
+    match file_manager.set_config_bytes(&body) {
+        Ok(_) => Ok(reply::with_status(String::new(), StatusCode::OK)),
+        Err(e) => {
+            eprintln!("{:?}", e);
+            Ok(reply::with_status(String::new(), StatusCode::INTERNAL_SERVER_ERROR))
         }
-        Err(_) => Ok(StatusCode::BAD_REQUEST)
     }
 }